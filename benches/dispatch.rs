@@ -0,0 +1,54 @@
+use brainfudge::{
+    block::BasicBlocks,
+    ir::Ir,
+    lexer::{tokenize, JumpTable},
+    optimizer::PassManager,
+    runtime::State,
+};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+const NESTED_LOOPS: &str = "++++++++[>++++++++[>++++++++[>+>-<<-]<-]<-]";
+const STRAIGHT_LINE: &str = "++++++++[>+>++>+++>++++>+++++<<<<<-]";
+
+fn bench_program(c: &mut Criterion, name: &str, source: &str) {
+    let tokens = tokenize(source);
+    let jump_table = JumpTable::from(&tokens).unwrap();
+    let blocks = BasicBlocks::from(&tokens, &jump_table).unwrap();
+    let mut ir = Ir::lower(&tokens, &jump_table).unwrap();
+    PassManager::default().run(&mut ir);
+    let mut group = c.benchmark_group(name);
+
+    group.bench_function("run", |b| {
+        b.iter(|| {
+            let mut state = State::new();
+            state.run(black_box(&tokens), &jump_table).unwrap();
+            state
+        })
+    });
+
+    group.bench_function("run_blocks", |b| {
+        b.iter(|| {
+            let mut state = State::new();
+            state.run_blocks(black_box(&tokens), &blocks).unwrap();
+            state
+        })
+    });
+
+    group.bench_function("run_ir", |b| {
+        b.iter(|| {
+            let mut state = State::new();
+            state.run_ir(black_box(&ir)).unwrap();
+            state
+        })
+    });
+
+    group.finish();
+}
+
+fn dispatch(c: &mut Criterion) {
+    bench_program(c, "nested_loops", NESTED_LOOPS);
+    bench_program(c, "straight_line", STRAIGHT_LINE);
+}
+
+criterion_group!(benches, dispatch);
+criterion_main!(benches);
@@ -0,0 +1,615 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+};
+
+use crate::{
+    lexer::{JumpTable, Token},
+    visit::{walk, Visitor},
+};
+
+#[derive(Debug, PartialEq)]
+pub struct InvariantClear {
+    pub loop_start: usize,
+    pub position: usize,
+    pub offset: isize,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct LoopSummary {
+    pub start: usize,
+    pub end: usize,
+    pub balance: Balance,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct LoopInfo {
+    pub start: usize,
+    pub end: usize,
+    /// Number of loops enclosing this one.
+    pub depth: usize,
+    pub balance: Balance,
+    /// Whether the body, including nested loops, reads input, writes output or asserts.
+    pub contains_io: bool,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Balance {
+    Balanced {
+        deltas: BTreeMap<isize, i32>,
+        contains_io: bool,
+    },
+    Unbalanced(isize),
+    Unknown,
+}
+
+/// The value of a cell after a block: the values of cells before the block, by offset, times
+/// their coefficients plus a constant. The arithmetic wraps around like the cells do.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Affine {
+    pub coefficients: BTreeMap<isize, i64>,
+    pub constant: i64,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Effect {
+    /// The new value of every cell the block changes, by offset from the cell it starts on.
+    Known(BTreeMap<isize, Affine>),
+    /// The block does I/O, moves the pointer or contains a loop without a derivable effect.
+    Unknown,
+}
+
+enum BodyOperation {
+    Add(isize),
+    Clear(isize, usize),
+}
+
+pub fn classify_loops(tokens: &[Token], jump_table: &JumpTable) -> Vec<LoopSummary> {
+    tokens
+        .iter()
+        .enumerate()
+        .filter(|(_, token)| **token == Token::LoopStart)
+        .filter_map(|(start, _)| {
+            let end = *jump_table.resolve(&start)?;
+            Some(LoopSummary {
+                start,
+                end,
+                balance: balance(&tokens[start + 1..end]),
+            })
+        })
+        .collect()
+}
+
+/// Lists every loop in source order with its nesting depth and classification.
+pub fn loops(tokens: &[Token], jump_table: &JumpTable) -> Vec<LoopInfo> {
+    let mut collector = LoopCollector {
+        tokens,
+        depth: 0,
+        loops: Vec::new(),
+    };
+    walk(tokens, jump_table, &mut collector);
+    collector.loops
+}
+
+struct LoopCollector<'a> {
+    tokens: &'a [Token],
+    depth: usize,
+    loops: Vec<LoopInfo>,
+}
+
+impl Visitor for LoopCollector<'_> {
+    fn enter_loop(&mut self, start: usize, end: usize) {
+        let body = &self.tokens[start + 1..end];
+        self.loops.push(LoopInfo {
+            start,
+            end,
+            depth: self.depth,
+            balance: balance(body),
+            contains_io: body
+                .iter()
+                .any(|x| matches!(x, Token::Input | Token::Output | Token::Assert)),
+        });
+        self.depth += 1;
+    }
+
+    fn exit_loop(&mut self, _start: usize, _end: usize) {
+        self.depth -= 1;
+    }
+}
+
+/// Classifies a loop body by how it moves the pointer and changes cells per iteration.
+pub fn balance(body: &[Token]) -> Balance {
+    let mut deltas = BTreeMap::new();
+    let mut contains_io = false;
+    let mut offset = 0isize;
+
+    for token in body {
+        match token {
+            Token::Increment => *deltas.entry(offset).or_insert(0) += 1,
+            Token::Decrement => *deltas.entry(offset).or_insert(0) -= 1,
+            Token::PointerIncrement => offset += 1,
+            Token::PointerDecrement => offset -= 1,
+            Token::Input | Token::Output | Token::Assert => contains_io = true,
+            Token::LoopStart | Token::LoopEnd => return Balance::Unknown,
+        }
+    }
+
+    if offset != 0 {
+        return Balance::Unbalanced(offset);
+    }
+    deltas.retain(|_, delta| *delta != 0);
+    Balance::Balanced {
+        deltas,
+        contains_io,
+    }
+}
+
+/// The clear loops inside innermost loops that clear a cell the loop changes nowhere else,
+/// which `lint` reports as `Lint::InvariantClear`.
+pub fn invariant_clears(tokens: &[Token], jump_table: &JumpTable) -> Vec<InvariantClear> {
+    let mut clears = Vec::new();
+
+    for (start, token) in tokens.iter().enumerate() {
+        if *token != Token::LoopStart {
+            continue;
+        }
+        let end = match jump_table.resolve(&start) {
+            Some(x) => *x,
+            None => continue,
+        };
+        let operations = match body_operations(&tokens[start + 1..end], start + 1) {
+            Some(x) => x,
+            None => continue,
+        };
+
+        let mut writes: HashMap<isize, usize> = HashMap::new();
+        for operation in &operations {
+            let offset = match operation {
+                BodyOperation::Add(x) | BodyOperation::Clear(x, _) => *x,
+            };
+            *writes.entry(offset).or_default() += 1;
+        }
+        for operation in operations {
+            if let BodyOperation::Clear(offset, position) = operation {
+                if offset != 0 && writes[&offset] == 1 {
+                    clears.push(InvariantClear {
+                        loop_start: start,
+                        position,
+                        offset,
+                    });
+                }
+            }
+        }
+    }
+
+    clears
+}
+
+pub fn dead_loops(tokens: &[Token], jump_table: &JumpTable, zeroed_tape: bool) -> Vec<usize> {
+    loop_entry_values(tokens, jump_table, zeroed_tape)
+        .into_iter()
+        .filter(|(_, value)| *value == Some(0))
+        .map(|(position, _)| position)
+        .collect()
+}
+
+/// Returns the value of the loop cell on entry for every reachable loop, when it is known.
+/// Loops entered with a known zero are skipped, so their bodies produce no entries.
+pub(crate) fn loop_entry_values(
+    tokens: &[Token],
+    jump_table: &JumpTable,
+    zeroed_tape: bool,
+) -> Vec<(usize, Option<u8>)> {
+    let mut entries = Vec::new();
+    let mut known: HashMap<isize, Option<u8>> = HashMap::new();
+    let mut unknown_is_zero = zeroed_tape;
+    let mut pointer = 0isize;
+    let mut position = 0;
+
+    while position < tokens.len() {
+        let current = match known.get(&pointer) {
+            Some(x) => *x,
+            None if unknown_is_zero => Some(0),
+            None => None,
+        };
+
+        match tokens[position] {
+            Token::Increment | Token::Decrement => {
+                if let Some(value) = current {
+                    let value = match tokens[position] {
+                        Token::Increment => value.wrapping_add(1),
+                        _ => value.wrapping_sub(1),
+                    };
+                    known.insert(pointer, Some(value));
+                }
+            }
+            Token::PointerIncrement => pointer += 1,
+            Token::PointerDecrement => pointer -= 1,
+            Token::Input => {
+                known.insert(pointer, None);
+            }
+            Token::Output | Token::Assert => (),
+            Token::LoopStart if current == Some(0) => {
+                entries.push((position, current));
+                if let Some(end) = jump_table.resolve(&position) {
+                    position = *end;
+                }
+            }
+            Token::LoopStart => {
+                entries.push((position, current));
+                known.clear();
+                unknown_is_zero = false;
+                pointer = 0;
+            }
+            Token::LoopEnd => {
+                known.clear();
+                unknown_is_zero = false;
+                pointer = 0;
+                known.insert(pointer, Some(0));
+            }
+        }
+        position += 1;
+    }
+
+    entries
+}
+
+impl Affine {
+    /// The value the cell at `offset` had before the block.
+    pub fn cell(offset: isize) -> Self {
+        Self {
+            coefficients: BTreeMap::from([(offset, 1)]),
+            constant: 0,
+        }
+    }
+
+    pub fn constant(value: i64) -> Self {
+        Self {
+            coefficients: BTreeMap::new(),
+            constant: value,
+        }
+    }
+
+    /// Adds `factor` times `other`.
+    fn add_scaled(&mut self, other: &Affine, factor: i64) {
+        for (offset, coefficient) in &other.coefficients {
+            let sum = self.coefficients.entry(*offset).or_insert(0);
+            *sum = sum.wrapping_add(coefficient.wrapping_mul(factor));
+        }
+        self.coefficients.retain(|_, x| *x != 0);
+        self.constant = self
+            .constant
+            .wrapping_add(other.constant.wrapping_mul(factor));
+    }
+}
+
+impl fmt::Display for Affine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut terms: Vec<_> = self
+            .coefficients
+            .iter()
+            .map(|(offset, coefficient)| match coefficient.unsigned_abs() {
+                1 => (*coefficient, format!("c[{}]", offset)),
+                x => (*coefficient, format!("{}*c[{}]", x, offset)),
+            })
+            .collect();
+        if self.constant != 0 || terms.is_empty() {
+            terms.push((self.constant, self.constant.unsigned_abs().to_string()));
+        }
+        for (index, (sign, term)) in terms.iter().enumerate() {
+            match (index, *sign < 0) {
+                (0, true) => write!(f, "-{}", term)?,
+                (0, false) => write!(f, "{}", term)?,
+                (_, true) => write!(f, " - {}", term)?,
+                (_, false) => write!(f, " + {}", term)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Infers the net effect of a block that returns the pointer to where it started. Straight-line
+/// code is always derivable, and so are loops whose body changes the loop cell by one and the
+/// other cells by constants, like clear, move and multiply loops.
+pub fn block_effect(tokens: &[Token]) -> Effect {
+    let jump_table = match JumpTable::from(tokens) {
+        Ok(x) => x,
+        Err(_) => return Effect::Unknown,
+    };
+    let mut cells = BTreeMap::new();
+    let mut offset = 0isize;
+    let mut position = 0;
+
+    while position < tokens.len() {
+        match tokens[position] {
+            Token::Increment => {
+                let value = cells.entry(offset).or_insert_with(|| Affine::cell(offset));
+                value.constant = value.constant.wrapping_add(1);
+            }
+            Token::Decrement => {
+                let value = cells.entry(offset).or_insert_with(|| Affine::cell(offset));
+                value.constant = value.constant.wrapping_sub(1);
+            }
+            Token::PointerIncrement => offset += 1,
+            Token::PointerDecrement => offset -= 1,
+            Token::LoopStart => {
+                let end = *jump_table.resolve(&position).unwrap();
+                let deltas = match balance(&tokens[position + 1..end]) {
+                    Balance::Balanced {
+                        deltas,
+                        contains_io: false,
+                    } => deltas,
+                    _ => return Effect::Unknown,
+                };
+                // A loop cell stepping by -1 runs as many times as its value, one stepping by
+                // +1 as many times as its negated value.
+                let factor = match deltas.get(&0) {
+                    Some(-1) => 1,
+                    Some(1) => -1,
+                    _ => return Effect::Unknown,
+                };
+                let counter = cells.remove(&offset).unwrap_or(Affine::cell(offset));
+                for (target, delta) in deltas.iter().filter(|(x, _)| **x != 0) {
+                    let target = offset + target;
+                    cells
+                        .entry(target)
+                        .or_insert_with(|| Affine::cell(target))
+                        .add_scaled(&counter, factor * *delta as i64);
+                }
+                cells.insert(offset, Affine::constant(0));
+                position = end;
+            }
+            Token::LoopEnd | Token::Input | Token::Output | Token::Assert => {
+                return Effect::Unknown
+            }
+        }
+        position += 1;
+    }
+
+    if offset != 0 {
+        return Effect::Unknown;
+    }
+    cells.retain(|offset, value| *value != Affine::cell(*offset));
+    Effect::Known(cells)
+}
+
+/// Counts every sequence of `length` consecutive tokens across all programs, most frequent
+/// first. Sequences with the same count are ordered by their source text.
+pub fn ngram_frequencies<'a>(
+    programs: impl IntoIterator<Item = &'a [Token]>,
+    length: usize,
+) -> Vec<(Vec<Token>, usize)> {
+    let mut counts: HashMap<&[Token], usize> = HashMap::new();
+    for tokens in programs {
+        for window in tokens.windows(length.max(1)) {
+            *counts.entry(window).or_default() += 1;
+        }
+    }
+
+    let mut frequencies: Vec<_> = counts
+        .into_iter()
+        .map(|(ngram, count)| (ngram.to_vec(), count))
+        .collect();
+    frequencies.sort_by_cached_key(|(ngram, count)| {
+        let text: String = ngram.iter().map(Token::as_char).collect();
+        (std::cmp::Reverse(*count), text)
+    });
+    frequencies
+}
+
+fn body_operations(body: &[Token], first_position: usize) -> Option<Vec<BodyOperation>> {
+    let mut operations = Vec::new();
+    let mut offset = 0isize;
+    let mut index = 0;
+
+    while index < body.len() {
+        match body[index] {
+            Token::Increment | Token::Decrement => operations.push(BodyOperation::Add(offset)),
+            Token::PointerIncrement => offset += 1,
+            Token::PointerDecrement => offset -= 1,
+            Token::LoopStart => match body.get(index + 1..index + 3) {
+                Some([Token::Increment | Token::Decrement, Token::LoopEnd]) => {
+                    operations.push(BodyOperation::Clear(offset, first_position + index));
+                    index += 2;
+                }
+                _ => return None,
+            },
+            Token::LoopEnd | Token::Input | Token::Output | Token::Assert => return None,
+        }
+        index += 1;
+    }
+
+    match offset {
+        0 => Some(operations),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::tokenize;
+
+    fn analyze(source: &str) -> Vec<InvariantClear> {
+        let tokens = tokenize(source);
+        let jump_table = JumpTable::from(&tokens).unwrap();
+        invariant_clears(&tokens, &jump_table)
+    }
+
+    #[test]
+    fn detects_clear_of_untouched_cell() {
+        assert_eq!(
+            analyze("+[>[-]<-]"),
+            vec![InvariantClear {
+                loop_start: 1,
+                position: 3,
+                offset: 1
+            }]
+        );
+    }
+
+    #[test]
+    fn ignores_clear_of_modified_cell() {
+        assert_eq!(analyze("+[>[-]+<-]"), vec![]);
+    }
+
+    #[test]
+    fn ignores_clear_of_loop_cell() {
+        assert_eq!(analyze("+[[-]]"), vec![]);
+    }
+
+    #[test]
+    fn ignores_unbalanced_and_io_loops() {
+        assert_eq!(analyze("+[>[-]]"), vec![]);
+        assert_eq!(analyze("+[>[-]<.-]"), vec![]);
+    }
+
+    fn dead(source: &str, zeroed_tape: bool) -> Vec<usize> {
+        let tokens = tokenize(source);
+        let jump_table = JumpTable::from(&tokens).unwrap();
+        dead_loops(&tokens, &jump_table, zeroed_tape)
+    }
+
+    #[test]
+    fn leading_loop_is_dead_on_zeroed_tape() {
+        assert_eq!(dead("[comment.]+[-]", true), vec![0]);
+        assert_eq!(dead("[comment.]+[-]", false), vec![]);
+    }
+
+    #[test]
+    fn loop_following_loop_is_dead() {
+        assert_eq!(dead(",[-][>+<-]", false), vec![4]);
+    }
+
+    #[test]
+    fn known_values_are_tracked_through_arithmetic() {
+        assert_eq!(dead("+[-]+-[.]>[.]+[-]", true), vec![6]);
+        assert_eq!(dead("+[-]+>-<[-]", true), vec![]);
+    }
+
+    #[test]
+    fn input_makes_cell_unknown() {
+        assert_eq!(dead(",[-]>,[.]", true), vec![]);
+        assert_eq!(dead(",>[.]<[.]", true), vec![2]);
+    }
+
+    #[test]
+    fn classify_balanced_loop() {
+        let tokens = tokenize("+[->++>+++<<]");
+        let jump_table = JumpTable::from(&tokens).unwrap();
+
+        assert_eq!(
+            classify_loops(&tokens, &jump_table),
+            vec![LoopSummary {
+                start: 1,
+                end: 12,
+                balance: Balance::Balanced {
+                    deltas: BTreeMap::from([(0, -1), (1, 2), (2, 3)]),
+                    contains_io: false
+                }
+            }]
+        );
+    }
+
+    #[test]
+    fn classify_unbalanced_and_nested_loops() {
+        let tokens = tokenize("[>][.<<+>>-][[-]]");
+        let jump_table = JumpTable::from(&tokens).unwrap();
+        let balances: Vec<_> = classify_loops(&tokens, &jump_table)
+            .into_iter()
+            .map(|x| x.balance)
+            .collect();
+
+        assert_eq!(
+            balances,
+            vec![
+                Balance::Unbalanced(1),
+                Balance::Balanced {
+                    deltas: BTreeMap::from([(-2, 1), (0, -1)]),
+                    contains_io: true
+                },
+                Balance::Unknown,
+                Balance::Balanced {
+                    deltas: BTreeMap::from([(0, -1)]),
+                    contains_io: false
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn list_loops_with_depth() {
+        let tokens = tokenize("[>][-[.]]");
+        let jump_table = JumpTable::from(&tokens).unwrap();
+        let loops = loops(&tokens, &jump_table);
+
+        let summary: Vec<_> = loops
+            .iter()
+            .map(|x| (x.start, x.end, x.depth, x.contains_io))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![(0, 2, 0, false), (3, 8, 0, true), (5, 7, 1, true)]
+        );
+        assert_eq!(loops[0].balance, Balance::Unbalanced(1));
+        assert_eq!(loops[1].balance, Balance::Unknown);
+    }
+
+    fn effect(source: &str) -> Effect {
+        block_effect(&tokenize(source))
+    }
+
+    fn known(effect: Effect) -> BTreeMap<isize, Affine> {
+        match effect {
+            Effect::Known(x) => x,
+            Effect::Unknown => panic!("Expected a known effect"),
+        }
+    }
+
+    #[test]
+    fn straight_line_effects() {
+        let cells = known(effect("++>-<<+++>"));
+
+        assert_eq!(cells.len(), 3);
+        assert_eq!(cells[&-1].to_string(), "c[-1] + 3");
+        assert_eq!(cells[&0].to_string(), "c[0] + 2");
+        assert_eq!(cells[&1].to_string(), "c[1] - 1");
+        assert_eq!(effect("+-><"), Effect::Known(BTreeMap::new()));
+    }
+
+    #[test]
+    fn loop_effects_are_affine() {
+        let cells = known(effect("+[->++>-<<]"));
+        assert_eq!(cells[&0].to_string(), "0");
+        assert_eq!(cells[&1].to_string(), "2*c[0] + c[1] + 2");
+        assert_eq!(cells[&2].to_string(), "-c[0] + c[2] - 1");
+
+        let cells = known(effect("[+>-<]"));
+        assert_eq!(cells[&1].to_string(), "c[0] + c[1]");
+        assert_eq!(
+            effect("[-]"),
+            Effect::Known(BTreeMap::from([(0, Affine::constant(0))]))
+        );
+    }
+
+    #[test]
+    fn unknown_effects() {
+        assert_eq!(effect(">"), Effect::Unknown);
+        assert_eq!(effect("+."), Effect::Unknown);
+        assert_eq!(effect("[--]"), Effect::Unknown);
+        assert_eq!(effect("[>]"), Effect::Unknown);
+        assert_eq!(effect("[-[-]]"), Effect::Unknown);
+        assert_eq!(effect("-]"), Effect::Unknown);
+    }
+
+    #[test]
+    fn count_ngrams_across_programs() {
+        let first = tokenize("[-]>[-]");
+        let second = tokenize("+[-]");
+        let frequencies = ngram_frequencies([first.as_slice(), second.as_slice()], 3);
+
+        assert_eq!(frequencies[0], (tokenize("[-]"), 3));
+        assert_eq!(frequencies.len(), 5);
+        assert_eq!(frequencies[1].1, 1);
+    }
+}
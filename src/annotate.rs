@@ -0,0 +1,120 @@
+use std::fmt::Write;
+
+use crate::{
+    io::Io,
+    lexer::{JumpTable, Token},
+    runtime::{ExecutionError, State},
+    tape::Tape,
+};
+
+/// Runs a program to completion one instruction at a time and returns how often the
+/// instruction at each position was executed.
+pub fn count_executions<I: Io, T: Tape>(
+    state: &mut State<I, T>,
+    tokens: &[Token],
+    jump_table: &JumpTable,
+) -> Result<Vec<u64>, ExecutionError> {
+    let mut counts = vec![0; tokens.len()];
+    while state.can_execute(tokens) {
+        counts[state.instruction_pointer()] += 1;
+        state.execute_current_instruction(tokens, jump_table)?;
+    }
+    Ok(counts)
+}
+
+/// Renders the source line by line. Each line is followed by its instructions, grouped into runs
+/// that executed equally often, with the total number of iterations next to every loop start.
+/// `offsets` holds the byte offset of each token in the source.
+pub fn annotate(
+    source: &[u8],
+    offsets: &[usize],
+    tokens: &[Token],
+    jump_table: &JumpTable,
+    counts: &[u64],
+) -> String {
+    let mut listing = String::new();
+    let mut position = 0;
+    let mut line_start = 0;
+    let source = source.strip_suffix(b"\n").unwrap_or(source);
+
+    for (index, line) in source.split(|x| *x == b'\n').enumerate() {
+        let line_end = line_start + line.len();
+        let _ = writeln!(
+            listing,
+            "{:>4} | {}",
+            index + 1,
+            String::from_utf8_lossy(line)
+        );
+
+        let mut run: Option<(u64, String)> = None;
+        while position < tokens.len() && offsets[position] <= line_end {
+            let token = tokens[position];
+            let count = counts[position];
+            match token {
+                Token::LoopStart | Token::LoopEnd => {
+                    write_run(&mut listing, run.take());
+                    let _ = write!(listing, "     | {:>10}  {}", count, token.as_char());
+                    if let (Token::LoopStart, Some(end)) = (token, jump_table.resolve(&position)) {
+                        let _ = write!(listing, "  {} iterations", counts[*end]);
+                    }
+                    listing.push('\n');
+                }
+                _ => match &mut run {
+                    Some((x, text)) if *x == count => text.push(token.as_char()),
+                    _ => {
+                        write_run(&mut listing, run.take());
+                        run = Some((count, token.as_char().to_string()));
+                    }
+                },
+            }
+            position += 1;
+        }
+        write_run(&mut listing, run);
+        line_start = line_end + 1;
+    }
+
+    listing
+}
+
+fn write_run(listing: &mut String, run: Option<(u64, String)>) {
+    if let Some((count, text)) = run {
+        let _ = writeln!(listing, "     | {:>10}  {}", count, text);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        io::Null,
+        lexer::{token_offsets, tokenize},
+    };
+
+    #[test]
+    fn annotate_counts_and_iterations() {
+        let source = "++[>+++<-] double\n>.\n";
+        let tokens = tokenize(source);
+        let jump_table = JumpTable::from(&tokens).unwrap();
+        let mut state = State::with_device(Null);
+
+        let counts = count_executions(&mut state, &tokens, &jump_table).unwrap();
+        let listing = annotate(
+            source.as_bytes(),
+            &token_offsets(source),
+            &tokens,
+            &jump_table,
+            &counts,
+        );
+
+        assert_eq!(
+            listing,
+            "   1 | ++[>+++<-] double\n\
+             \x20    |          1  ++\n\
+             \x20    |          1  [  2 iterations\n\
+             \x20    |          2  >+++<-\n\
+             \x20    |          2  ]\n\
+             \x20  2 | >.\n\
+             \x20    |          1  >.\n"
+        );
+    }
+}
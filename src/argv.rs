@@ -0,0 +1,72 @@
+#[derive(Debug, PartialEq)]
+pub enum ArgvError {
+    TooManyArguments(usize),
+    ArgumentTooLong(usize),
+}
+
+/// Lays out the arguments as the argument count followed by the length and bytes of each one.
+pub fn encode<T: AsRef<[u8]>>(arguments: &[T]) -> Result<Vec<u8>, ArgvError> {
+    let count =
+        u8::try_from(arguments.len()).map_err(|_| ArgvError::TooManyArguments(arguments.len()))?;
+    let mut cells = vec![count];
+
+    for (index, argument) in arguments.iter().enumerate() {
+        let argument = argument.as_ref();
+        let length = u8::try_from(argument.len()).map_err(|_| ArgvError::ArgumentTooLong(index))?;
+        cells.push(length);
+        cells.extend_from_slice(argument);
+    }
+
+    Ok(cells)
+}
+
+pub fn decode(cells: &[u8]) -> Option<Vec<Vec<u8>>> {
+    let (count, mut rest) = cells.split_first()?;
+    let mut arguments = Vec::with_capacity(*count as usize);
+
+    for _ in 0..*count {
+        let (length, tail) = rest.split_first()?;
+        let length = *length as usize;
+        arguments.push(tail.get(..length)?.to_vec());
+        rest = &tail[length..];
+    }
+
+    Some(arguments)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_length_prefixed_arguments() {
+        assert_eq!(
+            encode(&["ab", "", "c"]),
+            Ok(vec![3, 2, b'a', b'b', 0, 1, b'c'])
+        );
+        assert_eq!(encode::<&str>(&[]), Ok(vec![0]));
+    }
+
+    #[test]
+    fn encode_rejects_oversized_arguments() {
+        assert_eq!(
+            encode(&["a".to_string(), "b".repeat(256)]),
+            Err(ArgvError::ArgumentTooLong(1))
+        );
+        assert_eq!(
+            encode(&vec![""; 256]),
+            Err(ArgvError::TooManyArguments(256))
+        );
+    }
+
+    #[test]
+    fn decode_round_trips() {
+        let cells = encode(&["hello", "world"]).unwrap();
+
+        assert_eq!(
+            decode(&cells),
+            Some(vec![b"hello".to_vec(), b"world".to_vec()])
+        );
+        assert_eq!(decode(&cells[..4]), None);
+    }
+}
@@ -0,0 +1,229 @@
+use std::{
+    fmt,
+    fmt::Write,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    io::Counter,
+    lexer::Token,
+    program::Program,
+    runtime::{ExecutionError, State},
+    schema::BENCH,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchResult {
+    /// Measured iterations, not counting the warmup.
+    pub iterations: u32,
+    /// Iterations run before measuring, e.g. to warm up caches.
+    pub warmup: u32,
+    /// Instructions executed by a single iteration.
+    pub instructions: u64,
+    /// Bytes written by a single iteration.
+    pub output_bytes: u64,
+    pub total: Duration,
+    pub fastest: Duration,
+    pub slowest: Duration,
+    /// The time of every measured iteration, in order.
+    pub samples: Vec<Duration>,
+}
+
+#[derive(Debug)]
+pub enum BenchError {
+    ReadsInput,
+    Execution(ExecutionError),
+}
+
+/// Runs a program that does not read input `iterations` times, discarding its output, and
+/// measures the time spent executing. The program is compiled beforehand and each iteration
+/// starts from a fresh tape.
+pub fn run_pure(program: &Program, iterations: u32) -> Result<BenchResult, BenchError> {
+    run_with_warmup(program, 0, iterations)
+}
+
+/// Runs like `run_pure`, but only measures the iterations after the first `warmup` ones, which
+/// tend to be slower while caches and branch predictors settle.
+pub fn run_with_warmup(
+    program: &Program,
+    warmup: u32,
+    iterations: u32,
+) -> Result<BenchResult, BenchError> {
+    if program.tokens().contains(&Token::Input) {
+        return Err(BenchError::ReadsInput);
+    }
+
+    let mut result = BenchResult {
+        iterations,
+        warmup,
+        instructions: 0,
+        output_bytes: 0,
+        total: Duration::ZERO,
+        fastest: Duration::MAX,
+        slowest: Duration::ZERO,
+        samples: Vec::with_capacity(iterations as usize),
+    };
+    for iteration in 0..warmup + iterations {
+        let mut state = State::with_device(Counter::default());
+        let start = Instant::now();
+        state.run_ir(program.ir()).map_err(BenchError::Execution)?;
+        let elapsed = start.elapsed();
+
+        result.instructions = state.steps();
+        result.output_bytes = state.io().bytes;
+        if iteration < warmup {
+            continue;
+        }
+        result.total += elapsed;
+        result.fastest = result.fastest.min(elapsed);
+        result.slowest = result.slowest.max(elapsed);
+        result.samples.push(elapsed);
+    }
+    if iterations == 0 {
+        result.fastest = Duration::ZERO;
+    }
+
+    Ok(result)
+}
+
+impl BenchResult {
+    pub fn mean(&self) -> Duration {
+        match self.iterations {
+            0 => Duration::ZERO,
+            x => self.total / x,
+        }
+    }
+
+    /// The middle sample, or the mean of the two middle ones for an even number of samples.
+    pub fn median(&self) -> Duration {
+        let mut samples = self.samples.clone();
+        samples.sort();
+        match samples.len() {
+            0 => Duration::ZERO,
+            x if x.is_multiple_of(2) => (samples[x / 2 - 1] + samples[x / 2]) / 2,
+            x => samples[x / 2],
+        }
+    }
+
+    /// The sample standard deviation, 0 for fewer than two samples.
+    pub fn std_dev(&self) -> Duration {
+        if self.samples.len() < 2 {
+            return Duration::ZERO;
+        }
+        let mean = self.mean().as_secs_f64();
+        let squares: f64 = self
+            .samples
+            .iter()
+            .map(|x| (x.as_secs_f64() - mean).powi(2))
+            .sum();
+        Duration::from_secs_f64((squares / (self.samples.len() - 1) as f64).sqrt())
+    }
+
+    pub fn instructions_per_second(&self) -> f64 {
+        self.instructions as f64 * self.iterations as f64 / self.total.as_secs_f64()
+    }
+
+    /// The statistics and samples for scripts, with durations in seconds.
+    pub fn to_json(&self) -> String {
+        let mut json = format!(
+            r#"{{"schema_version":{},"iterations":{},"warmup":{},"instructions":{},"output_bytes":{},"mean":{},"median":{},"std_dev":{},"min":{},"max":{},"samples":["#,
+            BENCH.version,
+            self.iterations,
+            self.warmup,
+            self.instructions,
+            self.output_bytes,
+            self.mean().as_secs_f64(),
+            self.median().as_secs_f64(),
+            self.std_dev().as_secs_f64(),
+            self.fastest.as_secs_f64(),
+            self.slowest.as_secs_f64()
+        );
+        for (index, sample) in self.samples.iter().enumerate() {
+            let _ = write!(
+                json,
+                "{}{}",
+                if index > 0 { "," } else { "" },
+                sample.as_secs_f64()
+            );
+        }
+        json.push_str("]}");
+        json
+    }
+}
+
+impl fmt::Display for BenchResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{} iterations after {} warmup, {} instructions each",
+            self.iterations, self.warmup, self.instructions
+        )?;
+        writeln!(f, "{:<8} {:>12.3?}", "mean", self.mean())?;
+        writeln!(f, "{:<8} {:>12.3?}", "median", self.median())?;
+        writeln!(f, "{:<8} {:>12.3?}", "std dev", self.std_dev())?;
+        writeln!(f, "{:<8} {:>12.3?}", "min", self.fastest)?;
+        writeln!(f, "{:<8} {:>12.3?}", "max", self.slowest)?;
+        writeln!(
+            f,
+            "{:.0} instructions per second",
+            self.instructions_per_second()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn run_pure_counts_instructions_and_output() {
+        let program = Program::parse("++[>+++<-]>.").unwrap();
+
+        let result = run_pure(&program, 3).unwrap();
+
+        assert_eq!(result.iterations, 3);
+        assert_eq!(result.instructions, 2 + 1 + 7 * 2 + 2);
+        assert_eq!(result.output_bytes, 1);
+        assert!(result.fastest <= result.mean());
+    }
+
+    #[test]
+    fn run_with_warmup_measures_the_rest() {
+        let program = Program::parse("+[>+<+]").unwrap();
+
+        let result = run_with_warmup(&program, 2, 3).unwrap();
+
+        assert_eq!(result.warmup, 2);
+        assert_eq!(result.samples.len(), 3);
+        assert_eq!(result.total, result.samples.iter().sum());
+        assert!(result.fastest <= result.median() && result.median() <= result.slowest);
+        assert!(result
+            .to_json()
+            .starts_with(r#"{"schema_version":1,"iterations":3,"warmup":2,"#));
+    }
+
+    #[test]
+    fn statistics_of_samples() {
+        let result = BenchResult {
+            iterations: 4,
+            warmup: 0,
+            instructions: 1,
+            output_bytes: 0,
+            total: Duration::from_millis(20),
+            fastest: Duration::from_millis(2),
+            slowest: Duration::from_millis(8),
+            samples: [2, 8, 4, 6].map(Duration::from_millis).to_vec(),
+        };
+
+        assert_eq!(result.mean(), Duration::from_millis(5));
+        assert_eq!(result.median(), Duration::from_millis(5));
+        assert_eq!(result.std_dev().as_micros(), 2581);
+    }
+
+    #[test]
+    fn run_pure_rejects_input() {
+        let program = Program::parse(",").unwrap();
+
+        assert!(matches!(run_pure(&program, 1), Err(BenchError::ReadsInput)));
+    }
+}
@@ -0,0 +1,273 @@
+use std::{fmt, io, str::FromStr};
+
+use crate::{
+    cell::{parse_decimal, read_word, Cell, CellIo},
+    io::Io,
+};
+
+const LIMB_BASE: u32 = 1_000_000_000;
+const LIMB_DIGITS: usize = 9;
+
+/// An unbounded signed integer cell. The magnitude is stored in base 10^9 limbs, least
+/// significant first, so that decimal conversion is cheap.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct BigCell {
+    negative: bool,
+    limbs: Vec<u32>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ParseBigCellError;
+
+impl BigCell {
+    fn increment_magnitude(&mut self) {
+        for limb in &mut self.limbs {
+            *limb += 1;
+            if *limb < LIMB_BASE {
+                return;
+            }
+            *limb = 0;
+        }
+        self.limbs.push(1);
+    }
+
+    fn decrement_magnitude(&mut self) {
+        for limb in &mut self.limbs {
+            if *limb > 0 {
+                *limb -= 1;
+                break;
+            }
+            *limb = LIMB_BASE - 1;
+        }
+        while self.limbs.last() == Some(&0) {
+            self.limbs.pop();
+        }
+        if self.limbs.is_empty() {
+            self.negative = false;
+        }
+    }
+}
+
+/// `,` reads the next whitespace separated decimal number from the input and `.` writes the
+/// cell as a decimal line, in every `CellIo` mode.
+impl Cell for BigCell {
+    const BYTE_IO: bool = false;
+
+    fn increment(&mut self) {
+        match self.negative {
+            true => self.decrement_magnitude(),
+            false => self.increment_magnitude(),
+        }
+    }
+
+    fn decrement(&mut self) {
+        match (self.negative, self.limbs.is_empty()) {
+            (false, true) => {
+                self.negative = true;
+                self.limbs.push(1);
+            }
+            (false, false) => self.decrement_magnitude(),
+            (true, _) => self.increment_magnitude(),
+        }
+    }
+
+    fn is_zero(&self) -> bool {
+        self.limbs.is_empty()
+    }
+
+    fn from_byte(byte: u8) -> Self {
+        Self {
+            negative: false,
+            limbs: match byte {
+                0 => Vec::new(),
+                x => vec![x.into()],
+            },
+        }
+    }
+
+    fn iterations_to_zero(&self, step: i32) -> Option<u64> {
+        match (self.limbs.as_slice(), self.negative == (step > 0)) {
+            ([], _) => Some(0),
+            (_, false) => None,
+            ([low], true) => Some(*low as u64),
+            ([low, high], true) => Some(*high as u64 * LIMB_BASE as u64 + *low as u64),
+            _ => None,
+        }
+    }
+
+    fn to_byte(&self) -> u8 {
+        // 10^9 is a multiple of 256, so only the lowest limb contributes.
+        let magnitude = self.limbs.first().copied().unwrap_or(0) as u8;
+        match self.negative {
+            true => magnitude.wrapping_neg(),
+            false => magnitude,
+        }
+    }
+
+    fn read(io: &mut impl Io, _mode: CellIo) -> io::Result<Option<Self>> {
+        read_word(io)?.map(|x| parse_decimal(&x)).transpose()
+    }
+
+    fn write(&self, io: &mut impl Io, _mode: CellIo) -> io::Result<u64> {
+        let line = format!("{}\n", self);
+        io.write_bytes(line.as_bytes())?;
+        Ok(line.len() as u64)
+    }
+}
+
+impl FromStr for BigCell {
+    type Err = ParseBigCellError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (negative, digits) = match value.strip_prefix('-') {
+            Some(x) => (true, x),
+            None => (false, value.strip_prefix('+').unwrap_or(value)),
+        };
+        if digits.is_empty() || !digits.bytes().all(|x| x.is_ascii_digit()) {
+            return Err(ParseBigCellError);
+        }
+
+        let mut limbs: Vec<u32> = digits
+            .as_bytes()
+            .rchunks(LIMB_DIGITS)
+            .map(|chunk| {
+                chunk
+                    .iter()
+                    .fold(0, |limb, x| limb * 10 + (x - b'0') as u32)
+            })
+            .collect();
+        while limbs.last() == Some(&0) {
+            limbs.pop();
+        }
+        Ok(Self {
+            negative: negative && !limbs.is_empty(),
+            limbs,
+        })
+    }
+}
+
+impl fmt::Display for BigCell {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut limbs = self.limbs.iter().rev();
+        match limbs.next() {
+            None => return write!(f, "0"),
+            Some(x) if self.negative => write!(f, "-{}", x)?,
+            Some(x) => write!(f, "{}", x)?,
+        }
+        for limb in limbs {
+            write!(f, "{:09}", limb)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        io::Buffer,
+        limits::Limits,
+        program::Program,
+        runtime::{EofMode, ExecutionError, State},
+        tape::VecTape,
+    };
+    use std::io::ErrorKind;
+
+    fn big(value: &str) -> BigCell {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn carry_across_limbs() {
+        let mut cell = big("999999999");
+
+        cell.increment();
+        assert_eq!(cell.to_string(), "1000000000");
+
+        cell.decrement();
+        assert_eq!(cell.to_string(), "999999999");
+    }
+
+    #[test]
+    fn cells_go_negative() {
+        let mut cell = BigCell::default();
+
+        cell.decrement();
+        assert_eq!(cell.to_string(), "-1");
+
+        cell.increment();
+        assert!(cell.is_zero());
+        assert_eq!(cell, big("-0"));
+    }
+
+    #[test]
+    fn parse_and_display_round_trip() {
+        for value in [
+            "0",
+            "42",
+            "-1000000000000000000001",
+            "123456789012345678901234567890",
+        ] {
+            assert_eq!(big(value).to_string(), value);
+        }
+        assert_eq!(big("+007").to_string(), "7");
+        assert_eq!("12a".parse::<BigCell>(), Err(ParseBigCellError));
+        assert_eq!("-".parse::<BigCell>(), Err(ParseBigCellError));
+    }
+
+    #[test]
+    fn clear_loops_only_end_towards_zero() {
+        assert_eq!(big("1000000007").iterations_to_zero(-1), Some(1000000007));
+        assert_eq!(big("-5").iterations_to_zero(1), Some(5));
+        assert_eq!(big("5").iterations_to_zero(1), None);
+        assert_eq!(big("0").iterations_to_zero(1), Some(0));
+    }
+
+    fn run(
+        source: &str,
+        input: &str,
+    ) -> (Result<(), ExecutionError>, State<Buffer, VecTape<BigCell>>) {
+        let program = Program::parse(source).unwrap();
+        let mut state = State::with_tape(Buffer::new(input), VecTape::default());
+        (state.run_ir(program.ir()), state)
+    }
+
+    #[test]
+    fn run_with_decimal_io() {
+        let (result, state) = run(",>,[-<+>]<.", "300\n  5 ");
+
+        result.unwrap();
+        assert_eq!(state.io().output(), b"305\n");
+        assert_eq!(state.memory(), vec![big("305"), big("0")]);
+        assert_eq!(state.report().output_bytes, 4);
+    }
+
+    #[test]
+    fn run_errors_on_missing_number() {
+        match run(",,", "1").0.unwrap_err() {
+            ExecutionError::InputError(1, e) => assert_eq!(e.kind(), ErrorKind::UnexpectedEof),
+            e => panic!("Unexpected error: {:?}", e),
+        };
+        match run(",", "12a").0.unwrap_err() {
+            ExecutionError::InputError(0, e) => assert_eq!(e.kind(), ErrorKind::InvalidData),
+            e => panic!("Unexpected error: {:?}", e),
+        };
+    }
+
+    #[test]
+    fn runs_honor_limits_and_eof_mode() {
+        let program = Program::parse("+[>+<]").unwrap();
+        let mut state = State::with_tape(Buffer::new(""), VecTape::<BigCell>::default());
+        state.set_limits(Limits::default().with_steps(100));
+        assert!(matches!(
+            state.run_ir(program.ir()),
+            Err(ExecutionError::LimitExceeded(..))
+        ));
+
+        let program = Program::parse(",.,.,.").unwrap();
+        let mut state = State::with_tape(Buffer::new("7 -2"), VecTape::<BigCell>::default());
+        state.set_eof(EofMode::MinusOne);
+        state.run_ir(program.ir()).unwrap();
+        assert_eq!(state.io().output(), b"7\n-2\n-1\n");
+    }
+}
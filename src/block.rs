@@ -0,0 +1,199 @@
+use std::ops::Index;
+
+use crate::lexer::{JumpTable, Token};
+
+#[derive(Debug, PartialEq)]
+pub enum Terminator {
+    JumpIfZero { target: usize, fallthrough: usize },
+    JumpIfNonZero { target: usize, fallthrough: usize },
+    End,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct BasicBlock {
+    pub start: usize,
+    pub end: usize,
+    pub terminator: Terminator,
+}
+
+pub struct BasicBlocks {
+    blocks: Vec<BasicBlock>,
+    block_starts: Vec<usize>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum BasicBlocksError {
+    UndefinedJumpTarget(usize),
+}
+
+impl BasicBlocks {
+    pub fn from(tokens: &[Token], jump_table: &JumpTable) -> Result<Self, BasicBlocksError> {
+        let mut block_starts = vec![0];
+        let mut bracket_index = vec![0; tokens.len()];
+
+        for (position, token) in tokens.iter().enumerate() {
+            if matches!(token, Token::LoopStart | Token::LoopEnd) {
+                bracket_index[position] = block_starts.len() - 1;
+                block_starts.push(position + 1);
+            }
+        }
+
+        let mut blocks = Vec::with_capacity(block_starts.len());
+        for (index, start) in block_starts.iter().enumerate() {
+            let end = match block_starts.get(index + 1) {
+                Some(x) => x - 1,
+                None => tokens.len(),
+            };
+            let terminator = match tokens.get(end) {
+                None => Terminator::End,
+                Some(token) => {
+                    let matching = match jump_table.resolve(&end) {
+                        Some(x) => *x,
+                        None => return Err(BasicBlocksError::UndefinedJumpTarget(end)),
+                    };
+                    let target = bracket_index[matching] + 1;
+                    let fallthrough = index + 1;
+                    match token {
+                        Token::LoopStart => Terminator::JumpIfZero {
+                            target,
+                            fallthrough,
+                        },
+                        _ => Terminator::JumpIfNonZero {
+                            target,
+                            fallthrough,
+                        },
+                    }
+                }
+            };
+            blocks.push(BasicBlock {
+                start: *start,
+                end,
+                terminator,
+            });
+        }
+
+        Ok(Self {
+            blocks,
+            block_starts,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&BasicBlock> {
+        self.blocks.get(index)
+    }
+
+    pub fn block_at(&self, position: usize) -> usize {
+        match self.block_starts.binary_search(&position) {
+            Ok(x) => x,
+            Err(x) => x - 1,
+        }
+    }
+}
+
+impl Index<usize> for BasicBlocks {
+    type Output = BasicBlock;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        &self.blocks[index]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blocks_without_loops() {
+        let tokens = [Token::Increment, Token::Output];
+        let jump_table = JumpTable::from(&tokens).unwrap();
+        let blocks = BasicBlocks::from(&tokens, &jump_table).unwrap();
+
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(
+            blocks[0],
+            BasicBlock {
+                start: 0,
+                end: 2,
+                terminator: Terminator::End
+            }
+        );
+    }
+
+    #[test]
+    fn blocks_split_at_brackets() {
+        let tokens = [
+            Token::Increment,
+            Token::LoopStart,
+            Token::Decrement,
+            Token::LoopEnd,
+            Token::Output,
+        ];
+        let jump_table = JumpTable::from(&tokens).unwrap();
+        let blocks = BasicBlocks::from(&tokens, &jump_table).unwrap();
+
+        assert_eq!(blocks.len(), 3);
+        assert_eq!(
+            blocks[0],
+            BasicBlock {
+                start: 0,
+                end: 1,
+                terminator: Terminator::JumpIfZero {
+                    target: 2,
+                    fallthrough: 1
+                }
+            }
+        );
+        assert_eq!(
+            blocks[1],
+            BasicBlock {
+                start: 2,
+                end: 3,
+                terminator: Terminator::JumpIfNonZero {
+                    target: 1,
+                    fallthrough: 2
+                }
+            }
+        );
+        assert_eq!(
+            blocks[2],
+            BasicBlock {
+                start: 4,
+                end: 5,
+                terminator: Terminator::End
+            }
+        );
+    }
+
+    #[test]
+    fn block_at_finds_containing_block() {
+        let tokens = [
+            Token::Increment,
+            Token::LoopStart,
+            Token::Decrement,
+            Token::LoopEnd,
+        ];
+        let jump_table = JumpTable::from(&tokens).unwrap();
+        let blocks = BasicBlocks::from(&tokens, &jump_table).unwrap();
+
+        assert_eq!(blocks.block_at(0), 0);
+        assert_eq!(blocks.block_at(1), 0);
+        assert_eq!(blocks.block_at(2), 1);
+        assert_eq!(blocks.block_at(4), 2);
+    }
+
+    #[test]
+    fn blocks_error_on_invalid_jump_table() {
+        let jump_table = JumpTable::from(&[]).unwrap();
+        let blocks = BasicBlocks::from(&[Token::LoopStart, Token::LoopEnd], &jump_table);
+
+        assert_eq!(blocks.err(), Some(BasicBlocksError::UndefinedJumpTarget(0)));
+    }
+}
@@ -0,0 +1,172 @@
+use std::{collections::HashMap, sync::Arc};
+
+use crate::{
+    lexer::tokenize,
+    program::{fingerprint, Program, ProgramError},
+};
+
+pub struct ProgramCache {
+    capacity: usize,
+    entries: HashMap<u64, Entry>,
+    clock: u64,
+    stats: CacheStats,
+}
+
+struct Entry {
+    program: Arc<Program<'static>>,
+    last_used: u64,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+}
+
+impl ProgramCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: HashMap::new(),
+            clock: 0,
+            stats: CacheStats::default(),
+        }
+    }
+
+    pub fn get_or_compile(
+        &mut self,
+        source: impl AsRef<[u8]>,
+    ) -> Result<Arc<Program<'static>>, ProgramError> {
+        let tokens = tokenize(source);
+        let key = fingerprint(&tokens);
+        self.clock += 1;
+
+        // Fingerprints can collide, and a collision must never hand out another program.
+        match self.entries.get_mut(&key) {
+            Some(entry) if entry.program.tokens() == tokens.as_slice() => {
+                entry.last_used = self.clock;
+                self.stats.hits += 1;
+                return Ok(Arc::clone(&entry.program));
+            }
+            Some(_) => {
+                self.entries.remove(&key);
+            }
+            None => (),
+        }
+
+        self.stats.misses += 1;
+        let program = Arc::new(Program::from_tokens(tokens)?);
+        if self.capacity == 0 {
+            return Ok(program);
+        }
+        if self.entries.len() == self.capacity {
+            self.evict_least_recently_used();
+        }
+        self.entries.insert(
+            key,
+            Entry {
+                program: Arc::clone(&program),
+                last_used: self.clock,
+            },
+        );
+        Ok(program)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    fn evict_least_recently_used(&mut self) {
+        let oldest = self
+            .entries
+            .iter()
+            .min_by_key(|(_, entry)| entry.last_used)
+            .map(|(key, _)| *key);
+        if let Some(key) = oldest {
+            self.entries.remove(&key);
+            self.stats.evictions += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cache_hits_on_same_program() {
+        let mut cache = ProgramCache::new(2);
+
+        let first = cache.get_or_compile("+[-]").unwrap();
+        let second = cache.get_or_compile("+ [-] comment").unwrap();
+
+        assert!(Arc::ptr_eq(&first, &second));
+        assert_eq!(
+            cache.stats(),
+            CacheStats {
+                hits: 1,
+                misses: 1,
+                evictions: 0
+            }
+        );
+    }
+
+    #[test]
+    fn cache_evicts_least_recently_used() {
+        let mut cache = ProgramCache::new(2);
+
+        cache.get_or_compile("+").unwrap();
+        cache.get_or_compile("-").unwrap();
+        cache.get_or_compile("+").unwrap();
+        cache.get_or_compile(">").unwrap();
+        cache.get_or_compile("+").unwrap();
+        cache.get_or_compile("-").unwrap();
+
+        assert_eq!(cache.len(), 2);
+        assert_eq!(
+            cache.stats(),
+            CacheStats {
+                hits: 2,
+                misses: 4,
+                evictions: 2
+            }
+        );
+    }
+
+    #[test]
+    fn cache_compares_programs_with_the_same_fingerprint() {
+        let mut cache = ProgramCache::new(2);
+        let other = cache.get_or_compile("-").unwrap();
+        let key = fingerprint(&tokenize("+"));
+        cache.entries.insert(
+            key,
+            Entry {
+                program: other,
+                last_used: 0,
+            },
+        );
+
+        let program = cache.get_or_compile("+").unwrap();
+
+        assert_eq!(program.tokens(), tokenize("+"));
+        assert_eq!(cache.stats().hits, 0);
+        assert!(Arc::ptr_eq(&program, &cache.get_or_compile("+").unwrap()));
+    }
+
+    #[test]
+    fn cache_does_not_store_invalid_programs() {
+        let mut cache = ProgramCache::new(2);
+
+        assert!(cache.get_or_compile("[").is_err());
+        assert!(cache.is_empty());
+    }
+}
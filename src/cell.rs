@@ -0,0 +1,287 @@
+use std::{
+    io::{Error, ErrorKind, Result},
+    str::FromStr,
+};
+
+use crate::io::Io;
+
+/// How `,` and `.` move the value of a cell, like `--cell-io`. The modes only differ for
+/// cells wider than a byte, or for `Decimal`.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum CellIo {
+    /// One byte, the value modulo 256 on output.
+    #[default]
+    Byte,
+    /// As many bytes as the cell is wide, least significant first.
+    LittleEndian,
+    /// As many bytes as the cell is wide, most significant first.
+    BigEndian,
+    /// A whitespace separated decimal number on input and a decimal line on output.
+    Decimal,
+}
+
+pub trait Cell: Clone + Default + PartialEq {
+    /// Whether `,` and `.` move a single byte, which lets runs copy input in chunks.
+    const BYTE_IO: bool = true;
+
+    fn increment(&mut self);
+    fn decrement(&mut self);
+
+    /// Increments the cell `amount` times, or decrements it for negative amounts.
+    fn add_signed(&mut self, amount: i32) {
+        for _ in 0..amount.unsigned_abs() {
+            match amount > 0 {
+                true => self.increment(),
+                false => self.decrement(),
+            }
+        }
+    }
+
+    /// The cell value for a byte read as input.
+    fn from_byte(byte: u8) -> Self;
+
+    /// The byte written as output, the value modulo 256.
+    fn to_byte(&self) -> u8;
+
+    /// Reads the value for `,`, or `None` at the end of input. One byte by default, for cells
+    /// that ignore `mode`.
+    fn read(io: &mut impl Io, _mode: CellIo) -> Result<Option<Self>> {
+        Ok(io.read_byte()?.map(Self::from_byte))
+    }
+
+    /// Writes the value for `.` and returns how many bytes that took. `to_byte` by default,
+    /// for cells that ignore `mode`.
+    fn write(&self, io: &mut impl Io, _mode: CellIo) -> Result<u64> {
+        io.write_byte(self.to_byte())?;
+        Ok(1)
+    }
+
+    fn is_zero(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// How many times the clear loop `[-]` for a step of -1 or `[+]` for 1 runs on the cell,
+    /// or `None` if it never ends or the cell cannot tell without running it.
+    fn iterations_to_zero(&self, _step: i32) -> Option<u64> {
+        None
+    }
+}
+
+macro_rules! wrapping_cell {
+    ($($type:ty),*) => {
+        $(
+            impl Cell for $type {
+                fn increment(&mut self) {
+                    *self = self.wrapping_add(1);
+                }
+
+                fn decrement(&mut self) {
+                    *self = self.wrapping_sub(1);
+                }
+
+                fn add_signed(&mut self, amount: i32) {
+                    *self = self.wrapping_add(amount as $type);
+                }
+
+                fn from_byte(byte: u8) -> Self {
+                    byte.into()
+                }
+
+                fn to_byte(&self) -> u8 {
+                    *self as u8
+                }
+
+                fn read(io: &mut impl Io, mode: CellIo) -> Result<Option<Self>> {
+                    let mut bytes = [0; size_of::<$type>()];
+                    match mode {
+                        CellIo::Byte => Ok(io.read_byte()?.map(Self::from_byte)),
+                        CellIo::LittleEndian => Ok(read_value(io, &mut bytes)?
+                            .then(|| <$type>::from_le_bytes(bytes))),
+                        CellIo::BigEndian => Ok(read_value(io, &mut bytes)?
+                            .then(|| <$type>::from_be_bytes(bytes))),
+                        CellIo::Decimal => read_word(io)?.map(|x| parse_decimal(&x)).transpose(),
+                    }
+                }
+
+                fn write(&self, io: &mut impl Io, mode: CellIo) -> Result<u64> {
+                    match mode {
+                        CellIo::Byte => write_bytes(io, &[self.to_byte()]),
+                        CellIo::LittleEndian => write_bytes(io, &self.to_le_bytes()),
+                        CellIo::BigEndian => write_bytes(io, &self.to_be_bytes()),
+                        CellIo::Decimal => write_bytes(io, format!("{}\n", self).as_bytes()),
+                    }
+                }
+
+                fn iterations_to_zero(&self, step: i32) -> Option<u64> {
+                    match step {
+                        -1 => Some(*self as u64),
+                        1 => Some(self.wrapping_neg() as u64),
+                        _ => None,
+                    }
+                }
+            }
+        )*
+    };
+}
+
+wrapping_cell!(u8, u16, u32);
+
+impl CellIo {
+    pub const ALL: [Self; 4] = [
+        Self::Byte,
+        Self::LittleEndian,
+        Self::BigEndian,
+        Self::Decimal,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Byte => "byte",
+            Self::LittleEndian => "le",
+            Self::BigEndian => "be",
+            Self::Decimal => "decimal",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|x| x.name() == name)
+    }
+}
+
+/// Fills `bytes` from the input. `false` at the end of input, and an error when the input
+/// ends in the middle of the value.
+fn read_value(io: &mut impl Io, bytes: &mut [u8]) -> Result<bool> {
+    for (index, byte) in bytes.iter_mut().enumerate() {
+        *byte = match (io.read_byte()?, index) {
+            (Some(x), _) => x,
+            (None, 0) => return Ok(false),
+            (None, _) => {
+                return Err(Error::new(
+                    ErrorKind::UnexpectedEof,
+                    "end of input inside a cell value",
+                ))
+            }
+        };
+    }
+    Ok(true)
+}
+
+/// Reads the next whitespace separated word, or `None` at the end of input.
+pub(crate) fn read_word(io: &mut impl Io) -> Result<Option<Vec<u8>>> {
+    let mut word = Vec::new();
+    while let Some(byte) = io.read_byte()? {
+        match (byte.is_ascii_whitespace(), word.is_empty()) {
+            (true, true) => (),
+            (true, false) => break,
+            (false, _) => word.push(byte),
+        }
+    }
+    Ok((!word.is_empty()).then_some(word))
+}
+
+pub(crate) fn parse_decimal<T: FromStr>(word: &[u8]) -> Result<T> {
+    std::str::from_utf8(word)
+        .ok()
+        .and_then(|x| x.parse().ok())
+        .ok_or(Error::new(
+            ErrorKind::InvalidData,
+            "expected a decimal number",
+        ))
+}
+
+fn write_bytes(io: &mut impl Io, bytes: &[u8]) -> Result<u64> {
+    io.write_bytes(bytes)?;
+    Ok(bytes.len() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::Buffer;
+
+    #[test]
+    fn byte_cells_wrap_around() {
+        let mut cell = 255u8;
+
+        cell.increment();
+        assert!(cell.is_zero());
+
+        cell.decrement();
+        assert_eq!(cell, 255);
+    }
+
+    #[test]
+    fn wide_cells_wrap_at_their_width() {
+        let mut cell = 255u16;
+
+        cell.increment();
+        assert_eq!(cell, 256);
+        assert_eq!(cell.to_byte(), 0);
+
+        cell = 0;
+        cell.decrement();
+        assert_eq!(cell, u16::MAX);
+        assert_eq!(u32::from_byte(7), 7);
+    }
+
+    #[test]
+    fn add_signed_wraps_like_repeated_steps() {
+        let mut cell = 250u8;
+
+        cell.add_signed(10);
+        assert_eq!(cell, 4);
+
+        cell.add_signed(-5);
+        assert_eq!(cell, 255);
+
+        let mut wide = 0u16;
+        wide.add_signed(-300);
+        assert_eq!(wide, u16::MAX - 299);
+    }
+
+    #[test]
+    fn wide_cells_move_in_the_chosen_mode() {
+        let mut io = Buffer::new(&b"\x01\x02\x01\x02 513\n70000"[..]);
+
+        assert_eq!(
+            u16::read(&mut io, CellIo::LittleEndian).unwrap(),
+            Some(0x0201)
+        );
+        assert_eq!(u16::read(&mut io, CellIo::BigEndian).unwrap(), Some(0x0102));
+        assert_eq!(u16::read(&mut io, CellIo::Decimal).unwrap(), Some(513));
+        assert_eq!(
+            u16::read(&mut io, CellIo::Decimal).unwrap_err().kind(),
+            ErrorKind::InvalidData
+        );
+        assert_eq!(u16::read(&mut io, CellIo::Decimal).unwrap(), None);
+
+        for (mode, length) in [
+            (CellIo::Byte, 1),
+            (CellIo::LittleEndian, 4),
+            (CellIo::BigEndian, 4),
+            (CellIo::Decimal, 4),
+        ] {
+            assert_eq!(513u32.write(&mut io, mode).unwrap(), length);
+        }
+        assert_eq!(io.output(), b"\x01\x01\x02\0\0\0\0\x02\x01513\n");
+    }
+
+    #[test]
+    fn values_cut_off_by_the_end_of_input_are_errors() {
+        let mut io = Buffer::new(&b"\x01"[..]);
+
+        assert_eq!(
+            u32::read(&mut io, CellIo::BigEndian).unwrap_err().kind(),
+            ErrorKind::UnexpectedEof
+        );
+        assert_eq!(u32::read(&mut io, CellIo::BigEndian).unwrap(), None);
+    }
+
+    #[test]
+    fn clear_loops_run_until_the_cell_wraps_to_zero() {
+        assert_eq!(3u8.iterations_to_zero(-1), Some(3));
+        assert_eq!(3u8.iterations_to_zero(1), Some(253));
+        assert_eq!(3u16.iterations_to_zero(1), Some(65533));
+        assert_eq!(0u32.iterations_to_zero(1), Some(0));
+    }
+}
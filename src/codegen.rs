@@ -0,0 +1,1062 @@
+use std::fmt::Write;
+
+use crate::{
+    ir::{Instruction, Ir},
+    runtime::EofMode,
+};
+
+/// The runtime behaviour the generated program copies from the interpreter.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CodegenOptions {
+    /// 8, 16 or 32. Cells are unsigned integers of this width, which wrap around like `u8`,
+    /// `u16` and `u32` cells do.
+    pub cell_bits: u32,
+    pub eof: EofMode,
+    /// The cell the memory pointer starts on.
+    pub origin: usize,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum CodegenError {
+    UnsupportedCellBits(u32),
+    /// Assertions only exist in tests and have no equivalent in generated code.
+    Assertion(usize),
+    /// The origin does not fit on the fixed size tape of the x86-64 backend.
+    OriginOutsideTape(usize),
+}
+
+/// The language specific parts of a code generator. `generate` walks the IR, opens a block at
+/// every `JumpIfZero`, closes it at the matching `JumpIfNonZero` and indents the lines between.
+trait Backend {
+    /// Everything before the first instruction, ending inside the function that runs them.
+    fn prelude(&self, ir: &Ir, options: CodegenOptions) -> String;
+
+    /// The code for the instruction at `index`, which is not an `Assert`. Every line of it is
+    /// indented.
+    fn instruction(&self, ir: &Ir, index: usize, options: CodegenOptions) -> String;
+
+    /// Everything after the last instruction.
+    fn epilogue(&self, options: CodegenOptions) -> String;
+
+    /// The indentation of the first instruction, in levels of four spaces.
+    fn depth(&self) -> usize {
+        1
+    }
+}
+
+struct C;
+
+struct Rust;
+
+struct Wat;
+
+struct X86_64;
+
+struct Python;
+
+struct JavaScript;
+
+impl Default for CodegenOptions {
+    fn default() -> Self {
+        Self {
+            cell_bits: 8,
+            eof: EofMode::Error,
+            origin: 0,
+        }
+    }
+}
+
+/// Generates a standalone C program that reads from stdin and writes to stdout. The tape grows
+/// to the right on demand, and moving left of the first cell stops the program with an error
+/// like the interpreter does.
+pub fn to_c(ir: &Ir, options: CodegenOptions) -> Result<String, CodegenError> {
+    generate(ir, options, &C)
+}
+
+/// Generates Rust source with a `State` holding the tape and a `run` function over any reader
+/// and writer, to embed the program in other Rust projects. A `main` runs it on stdin and
+/// stdout, so the file also builds on its own with `rustc`.
+pub fn to_rust(ir: &Ir, options: CodegenOptions) -> Result<String, CodegenError> {
+    generate(ir, options, &Rust)
+}
+
+/// Generates a WebAssembly module in the text format that exports its `memory` and a `run`
+/// function. It imports `env.input`, which returns the next input byte or -1 at the end of
+/// input, and `env.output`, which receives every output byte. Moving left of the first cell or
+/// reading past the end of input with `EofMode::Error` traps.
+pub fn to_wat(ir: &Ir, options: CodegenOptions) -> Result<String, CodegenError> {
+    generate(ir, options, &Wat)
+}
+
+/// Generates x86-64 assembly for Linux in the GNU assembler syntax, which builds without libc,
+/// e.g. with `as program.s -o program.o && ld program.o -o program`. The tape is a 1 GiB
+/// `mmap` region whose pages are only allocated when they are touched, and output is
+/// buffered. Errors print a message to stderr and exit with status 1.
+pub fn to_x86_64(ir: &Ir, options: CodegenOptions) -> Result<String, CodegenError> {
+    if options.origin >= TAPE_BYTES / (options.cell_bits as usize / 8).max(1) {
+        return Err(CodegenError::OriginOutsideTape(options.origin));
+    }
+    generate(ir, options, &X86_64)
+}
+
+/// Generates a readable Python script with a `State` holding the tape and a `run` function
+/// that takes the state, a `read` function returning the next input byte or -1 at the end of
+/// input, and a `write` function taking an output byte. Run directly, it uses stdin and stdout.
+pub fn to_python(ir: &Ir, options: CodegenOptions) -> Result<String, CodegenError> {
+    generate(ir, options, &Python)
+}
+
+/// Generates a readable JavaScript module with the same `State` and `run` as `to_python`, on a
+/// typed array so that cells wrap around. Run with Node.js, it uses stdin and stdout.
+pub fn to_javascript(ir: &Ir, options: CodegenOptions) -> Result<String, CodegenError> {
+    generate(ir, options, &JavaScript)
+}
+
+fn generate(
+    ir: &Ir,
+    options: CodegenOptions,
+    backend: &impl Backend,
+) -> Result<String, CodegenError> {
+    if !matches!(options.cell_bits, 8 | 16 | 32) {
+        return Err(CodegenError::UnsupportedCellBits(options.cell_bits));
+    }
+
+    let mut code = backend.prelude(ir, options);
+    let mut depth = backend.depth();
+    for index in 0..ir.len() {
+        let instruction = ir[index];
+        let position = ir.position(index);
+        match instruction {
+            Instruction::Assert => return Err(CodegenError::Assertion(position)),
+            Instruction::JumpIfNonZero(_) => depth -= 1,
+            _ => (),
+        }
+        for line in backend.instruction(ir, index, options).lines() {
+            writeln!(code, "{}{}", "    ".repeat(depth), line).unwrap();
+        }
+        if let Instruction::JumpIfZero(_) = instruction {
+            depth += 1;
+        }
+    }
+
+    code.push_str(&backend.epilogue(options));
+    Ok(code)
+}
+
+fn uses(ir: &Ir, f: fn(&Instruction) -> bool) -> bool {
+    ir.instructions().iter().any(f)
+}
+
+const INCLUDES: &str = "#include <stddef.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+";
+
+const STATE: &str = "
+static cell *tape;
+static size_t size;
+static size_t p;
+";
+
+const FAIL: &str = r#"
+static void fail(const char *message, size_t position) {
+    fflush(stdout);
+    fprintf(stderr, "error: %s at instruction %zu\n", message, position);
+    exit(1);
+}
+"#;
+
+const GROW: &str = r#"
+static void grow(size_t cells) {
+    tape = realloc(tape, cells * sizeof(cell));
+    if (!tape) {
+        fputs("error: out of memory\n", stderr);
+        exit(1);
+    }
+    memset(tape + size, 0, (cells - size) * sizeof(cell));
+    size = cells;
+}
+"#;
+
+const MOVE: &str = "
+static void move(ptrdiff_t delta, size_t position) {
+    if (delta < 0 && (size_t)-delta > p) {
+        fail(\"pointer underflow\", position);
+    }
+    p += delta;
+    if (p >= size) {
+        grow(p + 1 > size * 2 ? p + 1 : size * 2);
+    }
+}
+";
+
+impl Backend for C {
+    /// Only the helpers the program calls, so that the code compiles without warnings.
+    fn prelude(&self, ir: &Ir, options: CodegenOptions) -> String {
+        let moves = uses(ir, |x| matches!(x, Instruction::Move(_)));
+        let reads = uses(ir, |x| matches!(x, Instruction::Input));
+
+        let mut code = INCLUDES.to_string();
+        writeln!(code, "\ntypedef uint{}_t cell;", options.cell_bits).unwrap();
+        code.push_str(STATE);
+        if moves || (reads && options.eof == EofMode::Error) {
+            code.push_str(FAIL);
+        }
+        code.push_str(GROW);
+        if moves {
+            code.push_str(MOVE);
+        }
+        if reads {
+            code.push_str(&c_input(options.eof));
+        }
+        code.push_str("\nint main(void) {\n");
+        writeln!(code, "    grow({});", options.origin + 1).unwrap();
+        writeln!(code, "    p = {};", options.origin).unwrap();
+        code
+    }
+
+    fn instruction(&self, ir: &Ir, index: usize, options: CodegenOptions) -> String {
+        let position = ir.position(index);
+        match ir[index] {
+            Instruction::Add(x) if x < 0 => format!("tape[p] -= {};", x.unsigned_abs()),
+            Instruction::Add(x) => format!("tape[p] += {};", x),
+            Instruction::Move(x) => format!("move({}, {});", x, position),
+            Instruction::Set(x) => format!("tape[p] = {};", x),
+            Instruction::Clear(_) => "tape[p] = 0;".to_string(),
+            Instruction::Input if options.eof == EofMode::Error => format!("input({});", position),
+            Instruction::Input => "input();".to_string(),
+            Instruction::Output => "putchar((unsigned char)tape[p]);".to_string(),
+            Instruction::JumpIfZero(_) => "while (tape[p]) {".to_string(),
+            Instruction::JumpIfNonZero(_) => "}".to_string(),
+            Instruction::Assert => unreachable!(),
+        }
+    }
+
+    fn epilogue(&self, _options: CodegenOptions) -> String {
+        "    return 0;\n}\n".to_string()
+    }
+}
+
+fn c_input(eof: EofMode) -> String {
+    let (parameters, end_of_input) = match eof {
+        EofMode::Error => ("size_t position", "fail(\"end of input\", position);"),
+        EofMode::Zero => ("void", "tape[p] = 0;"),
+        EofMode::MinusOne => ("void", "tape[p] = (cell)-1;"),
+        EofMode::Unchanged => ("void", "return;"),
+    };
+    format!(
+        "\nstatic void input({}) {{\n    fflush(stdout);\n    int c = getchar();\n    \
+         if (c == EOF) {{\n        {}\n    }} else {{\n        tape[p] = (cell)c;\n    }}\n}}\n",
+        parameters, end_of_input
+    )
+}
+
+const RUST_STATE: &str = r#"// Generated by brainfudge.
+use std::io::{self, Read, Write};
+
+pub struct State {
+    pub mem: Vec<CELL>,
+    pub p: usize,
+}
+
+impl State {
+    pub fn new() -> Self {
+        Self {
+            mem: vec![0; CELLS],
+            p: ORIGIN,
+        }
+    }
+
+    /// Moves the memory pointer, growing the tape to the right on demand.
+    pub fn shift(&mut self, delta: isize, position: usize) -> io::Result<()> {
+        self.p = self
+            .p
+            .checked_add_signed(delta)
+            .ok_or_else(|| error("pointer underflow", position))?;
+        if self.p >= self.mem.len() {
+            let cells = (self.p + 1).max(self.mem.len() * 2);
+            self.mem.resize(cells, 0);
+        }
+        Ok(())
+    }
+
+    /// Reads a byte into the current cell, after flushing the output for interactive use.
+    pub fn input(
+        &mut self,
+        input: &mut impl Read,
+        output: &mut impl Write,
+        POSITION: usize,
+    ) -> io::Result<()> {
+        output.flush()?;
+        let mut byte = [0];
+        match input.read_exact(&mut byte) {
+            Ok(()) => self.mem[self.p] = BYTE,
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => END_OF_INPUT,
+            Err(e) => return Err(e),
+        }
+        Ok(())
+    }
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn error(message: &str, position: usize) -> io::Error {
+    io::Error::other(format!("{} at instruction {}", message, position))
+}
+"#;
+
+impl Backend for Rust {
+    fn prelude(&self, ir: &Ir, options: CodegenOptions) -> String {
+        let reads = uses(ir, |x| matches!(x, Instruction::Input));
+        let writes = uses(ir, |x| matches!(x, Instruction::Output));
+        let (position, end_of_input) = match options.eof {
+            EofMode::Error => ("position", "return Err(error(\"end of input\", position))"),
+            EofMode::Zero => ("_position", "self.mem[self.p] = 0"),
+            EofMode::MinusOne => ("_position", "self.mem[self.p] = CELL::MAX"),
+            EofMode::Unchanged => ("_position", "()"),
+        };
+        // Parameters the program never uses start with `_` so that the code builds without
+        // warnings.
+        let unused = |used: bool| if used { "" } else { "_" };
+
+        let mut code = RUST_STATE
+            .replace("POSITION", position)
+            .replace("END_OF_INPUT", end_of_input)
+            .replace(
+                "BYTE",
+                match options.cell_bits {
+                    8 => "byte[0]",
+                    _ => "byte[0].into()",
+                },
+            )
+            .replace("CELLS", &(options.origin + 1).to_string())
+            .replace("ORIGIN", &options.origin.to_string())
+            .replace("CELL", &format!("u{}", options.cell_bits));
+        writeln!(
+            code,
+            "\npub fn run(\n    {}state: &mut State,\n    {}input: &mut impl Read,\n    \
+             {}output: &mut impl Write,\n) -> io::Result<()> {{",
+            unused(!ir.is_empty()),
+            unused(reads),
+            unused(reads || writes)
+        )
+        .unwrap();
+        code
+    }
+
+    fn instruction(&self, ir: &Ir, index: usize, options: CodegenOptions) -> String {
+        let position = ir.position(index);
+        let modulus = 1i64 << options.cell_bits;
+        match ir[index] {
+            Instruction::Add(x) if x < 0 => format!(
+                "state.mem[state.p] = state.mem[state.p].wrapping_sub({});",
+                (-(x as i64)).rem_euclid(modulus)
+            ),
+            Instruction::Add(x) => format!(
+                "state.mem[state.p] = state.mem[state.p].wrapping_add({});",
+                (x as i64).rem_euclid(modulus)
+            ),
+            Instruction::Move(x) => format!("state.shift({}, {})?;", x, position),
+            Instruction::Set(x) => format!("state.mem[state.p] = {};", x),
+            Instruction::Clear(_) => "state.mem[state.p] = 0;".to_string(),
+            Instruction::Input => format!("state.input(input, output, {})?;", position),
+            Instruction::Output if options.cell_bits == 8 => {
+                "output.write_all(&[state.mem[state.p]])?;".to_string()
+            }
+            Instruction::Output => "output.write_all(&[state.mem[state.p] as u8])?;".to_string(),
+            Instruction::JumpIfZero(_) => "while state.mem[state.p] != 0 {".to_string(),
+            Instruction::JumpIfNonZero(_) => "}".to_string(),
+            Instruction::Assert => unreachable!(),
+        }
+    }
+
+    fn epilogue(&self, _options: CodegenOptions) -> String {
+        "    Ok(())\n}\n\n#[allow(dead_code)]\nfn main() -> io::Result<()> {\n    \
+         let mut output = io::stdout().lock();\n    \
+         run(&mut State::new(), &mut io::stdin().lock(), &mut output)?;\n    \
+         output.flush()\n}\n"
+            .to_string()
+    }
+}
+
+const WAT_PRELUDE: &str = r#";; Generated by brainfudge.
+(module
+    (import "env" "input" (func $input (result i32)))
+    (import "env" "output" (func $output (param i32)))
+    (memory (export "memory") PAGES)
+    (global $p (mut i32) (i32.const ORIGIN))
+
+    (func $address (result i32)
+        (i32.shl (global.get $p) (i32.const SHIFT)))
+
+    (func $get (result i32)
+        (LOAD (call $address)))
+
+    (func $set (param $value i32)
+        (STORE (call $address) (local.get $value)))
+
+    ;; Moves the memory pointer and grows the memory on demand.
+    (func $move (param $delta i32)
+        (local $pages i32)
+        (global.set $p (i32.add (global.get $p) (local.get $delta)))
+        (if (i32.lt_s (global.get $p) (i32.const 0))
+            (then unreachable))
+        (local.set $pages
+            (i32.sub
+                (i32.add
+                    (i32.shr_u
+                        (i32.add (call $address) (i32.const LAST_BYTE))
+                        (i32.const 16))
+                    (i32.const 1))
+                (memory.size)))
+        (if (i32.gt_s (local.get $pages) (i32.const 0))
+            (then
+                (if (i32.eq (memory.grow (local.get $pages)) (i32.const -1))
+                    (then unreachable)))))
+
+    (func $read
+        (local $byte i32)
+        (local.set $byte (call $input))
+        (if (i32.lt_s (local.get $byte) (i32.const 0))
+            (then END_OF_INPUT)
+            (else (call $set (local.get $byte)))))
+
+    (func (export "run")
+"#;
+
+impl Backend for Wat {
+    fn prelude(&self, _ir: &Ir, options: CodegenOptions) -> String {
+        let bytes = options.cell_bits as usize / 8;
+        let (load, store) = match options.cell_bits {
+            8 => ("i32.load8_u", "i32.store8"),
+            16 => ("i32.load16_u", "i32.store16"),
+            _ => ("i32.load", "i32.store"),
+        };
+        let end_of_input = match options.eof {
+            EofMode::Error => "unreachable",
+            EofMode::Zero => "(call $set (i32.const 0))",
+            EofMode::MinusOne => "(call $set (i32.const -1))",
+            EofMode::Unchanged => "nop",
+        };
+
+        WAT_PRELUDE
+            .replace(
+                "PAGES",
+                &((options.origin + 1) * bytes).div_ceil(1 << 16).to_string(),
+            )
+            .replace("ORIGIN", &options.origin.to_string())
+            .replace("SHIFT", &bytes.trailing_zeros().to_string())
+            .replace("LAST_BYTE", &(bytes - 1).to_string())
+            .replace("LOAD", load)
+            .replace("STORE", store)
+            .replace("END_OF_INPUT", end_of_input)
+    }
+
+    fn instruction(&self, ir: &Ir, index: usize, _options: CodegenOptions) -> String {
+        match ir[index] {
+            Instruction::Add(x) => format!("(call $set (i32.add (call $get) (i32.const {})))", x),
+            Instruction::Move(x) => format!("(call $move (i32.const {}))", x),
+            Instruction::Set(x) => format!("(call $set (i32.const {}))", x),
+            Instruction::Clear(_) => "(call $set (i32.const 0))".to_string(),
+            Instruction::Input => "(call $read)".to_string(),
+            Instruction::Output => {
+                "(call $output (i32.and (call $get) (i32.const 255)))".to_string()
+            }
+            Instruction::JumpIfZero(_) => {
+                "(block (loop (br_if 1 (i32.eqz (call $get)))".to_string()
+            }
+            Instruction::JumpIfNonZero(_) => "(br 0)))".to_string(),
+            Instruction::Assert => unreachable!(),
+        }
+    }
+
+    fn epilogue(&self, _options: CodegenOptions) -> String {
+        "    )\n)\n".to_string()
+    }
+
+    fn depth(&self) -> usize {
+        2
+    }
+}
+
+/// The size of the x86-64 tape. Pages are only allocated when the program touches them.
+const TAPE_BYTES: usize = 1 << 30;
+
+const X86_64_PRELUDE: &str = "# Generated by brainfudge.
+    .equ TAPE_BYTES, 1073741824
+    .equ BUFFER_SIZE, 4096
+
+    .bss
+buffer:
+    .skip BUFFER_SIZE
+byte:
+    .skip 1
+
+    .section .rodata
+underflow:
+    .ascii \"error: pointer underflow\\n\"
+    .equ underflow_length, . - underflow
+overflow:
+    .ascii \"error: pointer moved past the end of the tape\\n\"
+    .equ overflow_length, . - overflow
+end_of_input:
+    .ascii \"error: end of input\\n\"
+    .equ end_of_input_length, . - end_of_input
+io_error:
+    .ascii \"error: input or output failed\\n\"
+    .equ io_error_length, . - io_error
+out_of_memory:
+    .ascii \"error: cannot allocate the tape\\n\"
+    .equ out_of_memory_length, . - out_of_memory
+
+# %rbx points to the current cell, %r12 and %r13 to the start and end of the tape and %r14
+# holds the number of buffered output bytes.
+    .text
+    .globl _start
+_start:
+    mov $9, %eax                # mmap(NULL, TAPE_BYTES, PROT_READ | PROT_WRITE,
+    xor %edi, %edi              #      MAP_PRIVATE | MAP_ANONYMOUS | MAP_NORESERVE, -1, 0)
+    mov $TAPE_BYTES, %rsi
+    mov $3, %edx
+    mov $0x4022, %r10d
+    mov $-1, %r8
+    xor %r9d, %r9d
+    syscall
+    test %rax, %rax
+    js fail_out_of_memory
+    mov %rax, %r12
+    lea TAPE_BYTES(%rax), %r13
+    lea ORIGIN(%rax), %rbx
+    xor %r14d, %r14d
+";
+
+const X86_64_EPILOGUE: &str = "    call flush
+    mov $60, %eax
+    xor %edi, %edi
+    syscall
+
+# Appends the low byte of the current cell to the output buffer.
+output:
+    movzbl (%rbx), %eax
+    lea buffer(%rip), %rcx
+    mov %al, (%rcx,%r14)
+    inc %r14
+    cmp $BUFFER_SIZE, %r14
+    je flush
+    ret
+
+flush:
+    lea buffer(%rip), %rsi
+    mov %r14, %rdx
+1:
+    test %rdx, %rdx
+    jz 2f
+    mov $1, %eax
+    mov $1, %edi
+    syscall
+    test %rax, %rax
+    jle fail_io_error
+    add %rax, %rsi
+    sub %rax, %rdx
+    jmp 1b
+2:
+    xor %r14d, %r14d
+    ret
+
+# Reads a byte into the current cell, after flushing the output for interactive use.
+input:
+    call flush
+    xor %eax, %eax
+    xor %edi, %edi
+    lea byte(%rip), %rsi
+    mov $1, %edx
+    syscall
+    test %rax, %rax
+    js fail_io_error
+    jz 1f
+    movzbl byte(%rip), %eax
+    STORE_BYTE
+    ret
+1:
+    END_OF_INPUT
+    ret
+
+fail_underflow:
+    lea underflow(%rip), %rsi
+    mov $underflow_length, %edx
+    jmp fail
+fail_overflow:
+    lea overflow(%rip), %rsi
+    mov $overflow_length, %edx
+    jmp fail
+fail_end_of_input:
+    lea end_of_input(%rip), %rsi
+    mov $end_of_input_length, %edx
+    jmp fail
+fail_io_error:
+    lea io_error(%rip), %rsi
+    mov $io_error_length, %edx
+    jmp fail
+fail_out_of_memory:
+    lea out_of_memory(%rip), %rsi
+    mov $out_of_memory_length, %edx
+
+# Writes the message in %rsi and %rdx to stderr and exits with status 1.
+fail:
+    mov $1, %eax
+    mov $2, %edi
+    syscall
+    mov $60, %eax
+    mov $1, %edi
+    syscall
+";
+
+/// The instruction suffix and register for a cell.
+fn x86_64_operands(cell_bits: u32) -> (&'static str, &'static str) {
+    match cell_bits {
+        8 => ("b", "%al"),
+        16 => ("w", "%ax"),
+        _ => ("l", "%eax"),
+    }
+}
+
+impl Backend for X86_64 {
+    fn prelude(&self, _ir: &Ir, options: CodegenOptions) -> String {
+        let origin = options.origin * options.cell_bits as usize / 8;
+        X86_64_PRELUDE.replace("ORIGIN", &origin.to_string())
+    }
+
+    fn instruction(&self, ir: &Ir, index: usize, options: CodegenOptions) -> String {
+        let (suffix, _) = x86_64_operands(options.cell_bits);
+        let bytes = options.cell_bits as i64 / 8;
+        let modulus = 1i64 << options.cell_bits;
+        match ir[index] {
+            Instruction::Add(x) => {
+                format!("add{} ${}, (%rbx)", suffix, (x as i64).rem_euclid(modulus))
+            }
+            Instruction::Move(x) if x < 0 => format!(
+                "sub ${}, %rbx\ncmp %r12, %rbx\njb fail_underflow",
+                -(x as i64) * bytes
+            ),
+            Instruction::Move(x) => format!(
+                "add ${}, %rbx\ncmp %r13, %rbx\njae fail_overflow",
+                x as i64 * bytes
+            ),
+            Instruction::Set(x) => format!("mov{} ${}, (%rbx)", suffix, x),
+            Instruction::Clear(_) => format!("mov{} $0, (%rbx)", suffix),
+            Instruction::Input => "call input".to_string(),
+            Instruction::Output => "call output".to_string(),
+            Instruction::JumpIfZero(end) => format!(
+                "cmp{} $0, (%rbx)\nje .L{}_end\n.L{}_body:",
+                suffix, end, end
+            ),
+            Instruction::JumpIfNonZero(_) => format!(
+                "cmp{} $0, (%rbx)\njne .L{}_body\n.L{}_end:",
+                suffix, index, index
+            ),
+            Instruction::Assert => unreachable!(),
+        }
+    }
+
+    fn epilogue(&self, options: CodegenOptions) -> String {
+        let (suffix, register) = x86_64_operands(options.cell_bits);
+        let end_of_input = match options.eof {
+            EofMode::Error => "jmp fail_end_of_input".to_string(),
+            EofMode::Zero => format!("mov{} $0, (%rbx)", suffix),
+            EofMode::MinusOne => format!("mov{} $-1, (%rbx)", suffix),
+            EofMode::Unchanged => "nop".to_string(),
+        };
+        X86_64_EPILOGUE
+            .replace("STORE_BYTE", &format!("mov {}, (%rbx)", register))
+            .replace("END_OF_INPUT", &end_of_input)
+    }
+}
+
+const PYTHON_STATE: &str = r#"# Generated by brainfudge.
+import sys
+
+MASK = MAXIMUM
+
+
+class State:
+    def __init__(self):
+        self.mem = [0] * CELLS
+        self.p = ORIGIN
+
+    def move(self, delta, position):
+        """Moves the memory pointer, growing the tape to the right on demand."""
+        self.p += delta
+        if self.p < 0:
+            raise RuntimeError(f"pointer underflow at instruction {position}")
+        if self.p >= len(self.mem):
+            self.mem.extend([0] * max(self.p + 1 - len(self.mem), len(self.mem)))
+
+    def input(self, read, position):
+        byte = read()
+        if byte >= 0:
+            self.mem[self.p] = byte
+        else:
+            END_OF_INPUT
+
+
+def run(state, read, write):
+    """Runs the program. read() returns the next input byte or -1, write(byte) outputs one."""
+"#;
+
+const PYTHON_MAIN: &str = r#"
+
+def main():
+    stdin, stdout = sys.stdin.buffer, sys.stdout.buffer
+
+    def read():
+        stdout.flush()
+        byte = stdin.read(1)
+        return byte[0] if byte else -1
+
+    try:
+        run(State(), read, lambda byte: stdout.write(bytes([byte])))
+    except RuntimeError as e:
+        stdout.flush()
+        sys.exit(f"error: {e}")
+    stdout.flush()
+
+
+if __name__ == "__main__":
+    main()
+"#;
+
+impl Backend for Python {
+    fn prelude(&self, _ir: &Ir, options: CodegenOptions) -> String {
+        let end_of_input = match options.eof {
+            EofMode::Error => "raise RuntimeError(f\"end of input at instruction {position}\")",
+            EofMode::Zero => "self.mem[self.p] = 0",
+            EofMode::MinusOne => "self.mem[self.p] = MASK",
+            EofMode::Unchanged => "pass",
+        };
+        PYTHON_STATE
+            .replace(
+                "MAXIMUM",
+                &format!("{:#x}", u32::MAX >> (32 - options.cell_bits)),
+            )
+            .replace("END_OF_INPUT", end_of_input)
+            .replace("CELLS", &(options.origin + 1).to_string())
+            .replace("ORIGIN", &options.origin.to_string())
+    }
+
+    fn instruction(&self, ir: &Ir, index: usize, options: CodegenOptions) -> String {
+        let position = ir.position(index);
+        match ir[index] {
+            Instruction::Add(x) if x < 0 => format!(
+                "state.mem[state.p] = (state.mem[state.p] - {}) & MASK",
+                x.unsigned_abs()
+            ),
+            Instruction::Add(x) => {
+                format!("state.mem[state.p] = (state.mem[state.p] + {}) & MASK", x)
+            }
+            Instruction::Move(x) => format!("state.move({}, {})", x, position),
+            Instruction::Set(x) => format!("state.mem[state.p] = {}", x),
+            Instruction::Clear(_) => "state.mem[state.p] = 0".to_string(),
+            Instruction::Input => format!("state.input(read, {})", position),
+            Instruction::Output if options.cell_bits == 8 => {
+                "write(state.mem[state.p])".to_string()
+            }
+            Instruction::Output => "write(state.mem[state.p] & 0xff)".to_string(),
+            Instruction::JumpIfZero(_) => "while state.mem[state.p]:".to_string(),
+            // A loop without a body still needs a statement. The loop is closed by dedenting.
+            Instruction::JumpIfNonZero(start) if start + 1 == index => "    pass".to_string(),
+            Instruction::JumpIfNonZero(_) => String::new(),
+            Instruction::Assert => unreachable!(),
+        }
+    }
+
+    fn epilogue(&self, _options: CodegenOptions) -> String {
+        PYTHON_MAIN.to_string()
+    }
+}
+
+const JAVASCRIPT_STATE: &str = r#"// Generated by brainfudge.
+"use strict";
+
+class State {
+    constructor() {
+        this.mem = new ARRAY(CELLS);
+        this.p = ORIGIN;
+    }
+
+    /** Moves the memory pointer, growing the tape to the right on demand. */
+    move(delta, position) {
+        this.p += delta;
+        if (this.p < 0) {
+            throw new Error(`pointer underflow at instruction ${position}`);
+        }
+        if (this.p >= this.mem.length) {
+            const mem = new ARRAY(Math.max(this.p + 1, this.mem.length * 2));
+            mem.set(this.mem);
+            this.mem = mem;
+        }
+    }
+
+    input(read, position) {
+        const byte = read();
+        if (byte >= 0) {
+            this.mem[this.p] = byte;
+        } else {
+            END_OF_INPUT
+        }
+    }
+}
+
+/** Runs the program. read() returns the next input byte or -1, write(byte) outputs one. */
+function run(state, read, write) {
+"#;
+
+const JAVASCRIPT_MAIN: &str = r#"}
+
+function main() {
+    const fs = require("fs");
+    const byte = Buffer.alloc(1);
+    const output = [];
+    const flush = () => {
+        fs.writeSync(1, Buffer.from(output));
+        output.length = 0;
+    };
+    const read = () => {
+        flush();
+        return fs.readSync(0, byte, 0, 1, null) === 1 ? byte[0] : -1;
+    };
+    const write = (x) => {
+        output.push(x);
+        if (output.length === 4096) {
+            flush();
+        }
+    };
+
+    try {
+        run(new State(), read, write);
+    } catch (e) {
+        flush();
+        process.stderr.write(`error: ${e.message}\n`);
+        process.exitCode = 1;
+        return;
+    }
+    flush();
+}
+
+if (typeof module !== "undefined") {
+    module.exports = { State, run };
+    if (require.main === module) {
+        main();
+    }
+}
+"#;
+
+impl Backend for JavaScript {
+    fn prelude(&self, _ir: &Ir, options: CodegenOptions) -> String {
+        let end_of_input = match options.eof {
+            EofMode::Error => "throw new Error(`end of input at instruction ${position}`);",
+            EofMode::Zero => "this.mem[this.p] = 0;",
+            EofMode::MinusOne => "this.mem[this.p] = -1;",
+            EofMode::Unchanged => "return;",
+        };
+        JAVASCRIPT_STATE
+            .replace("ARRAY", &format!("Uint{}Array", options.cell_bits))
+            .replace("END_OF_INPUT", end_of_input)
+            .replace("CELLS", &(options.origin + 1).to_string())
+            .replace("ORIGIN", &options.origin.to_string())
+    }
+
+    fn instruction(&self, ir: &Ir, index: usize, options: CodegenOptions) -> String {
+        let position = ir.position(index);
+        match ir[index] {
+            Instruction::Add(x) if x < 0 => format!("state.mem[state.p] -= {};", x.unsigned_abs()),
+            Instruction::Add(x) => format!("state.mem[state.p] += {};", x),
+            Instruction::Move(x) => format!("state.move({}, {});", x, position),
+            Instruction::Set(x) => format!("state.mem[state.p] = {};", x),
+            Instruction::Clear(_) => "state.mem[state.p] = 0;".to_string(),
+            Instruction::Input => format!("state.input(read, {});", position),
+            Instruction::Output if options.cell_bits == 8 => {
+                "write(state.mem[state.p]);".to_string()
+            }
+            Instruction::Output => "write(state.mem[state.p] & 0xff);".to_string(),
+            Instruction::JumpIfZero(_) => "while (state.mem[state.p]) {".to_string(),
+            Instruction::JumpIfNonZero(_) => "}".to_string(),
+            Instruction::Assert => unreachable!(),
+        }
+    }
+
+    fn epilogue(&self, _options: CodegenOptions) -> String {
+        JAVASCRIPT_MAIN.to_string()
+    }
+}
+
+#[cfg(all(test, feature = "optimizer"))]
+mod tests {
+    use super::*;
+    use crate::{lexer::Token, program::Program};
+
+    #[test]
+    fn generate_c_program() {
+        let program = Program::parse("++[->+<]>.,").unwrap();
+        let code = to_c(program.ir(), CodegenOptions::default()).unwrap();
+
+        assert!(code.contains("#include <string.h>\n\ntypedef uint8_t cell;\n"));
+        assert!(code.ends_with(
+            "int main(void) {\n    grow(1);\n    p = 0;\n    tape[p] += 2;\n    \
+             while (tape[p]) {\n        tape[p] -= 1;\n        move(1, 4);\n        \
+             tape[p] += 1;\n        move(-1, 6);\n    }\n    move(1, 8);\n    \
+             putchar((unsigned char)tape[p]);\n    input(10);\n    return 0;\n}\n"
+        ));
+        assert!(code.contains("fail(\"end of input\", position);"));
+    }
+
+    #[test]
+    fn generate_c_with_options() {
+        let program = Program::parse("[-],").unwrap();
+        let options = CodegenOptions {
+            cell_bits: 16,
+            eof: EofMode::MinusOne,
+            origin: 4,
+        };
+        let code = to_c(program.ir(), options).unwrap();
+
+        assert!(code.contains("typedef uint16_t cell;"));
+        assert!(code.contains("tape[p] = (cell)-1;"));
+        assert!(code.contains("    grow(5);\n    p = 4;\n    tape[p] = 0;\n"));
+        assert_eq!(
+            to_c(
+                program.ir(),
+                CodegenOptions {
+                    cell_bits: 64,
+                    ..options
+                }
+            ),
+            Err(CodegenError::UnsupportedCellBits(64))
+        );
+    }
+
+    #[test]
+    fn generate_rust_program() {
+        let program = Program::parse("-[>+<-]>.").unwrap();
+        let options = CodegenOptions {
+            cell_bits: 16,
+            ..CodegenOptions::default()
+        };
+        let code = to_rust(program.ir(), options).unwrap();
+
+        assert!(code.contains("pub mem: Vec<u16>,"));
+        assert!(code.contains(
+            "pub fn run(\n    state: &mut State,\n    _input: &mut impl Read,\n    \
+             output: &mut impl Write,\n) -> io::Result<()> {\n    \
+             state.mem[state.p] = state.mem[state.p].wrapping_sub(1);\n    \
+             while state.mem[state.p] != 0 {\n        state.shift(1, 2)?;\n        \
+             state.mem[state.p] = state.mem[state.p].wrapping_add(1);\n        \
+             state.shift(-1, 4)?;\n        \
+             state.mem[state.p] = state.mem[state.p].wrapping_sub(1);\n    }\n    \
+             state.shift(1, 7)?;\n    output.write_all(&[state.mem[state.p] as u8])?;\n    \
+             Ok(())\n}\n"
+        ));
+
+        let program = Program::from_tokens(vec![Token::Increment, Token::Assert]).unwrap();
+        assert_eq!(
+            to_rust(program.ir(), options),
+            Err(CodegenError::Assertion(1))
+        );
+    }
+
+    #[test]
+    fn generate_wat_module() {
+        let program = Program::parse("+[-],[.>]").unwrap();
+        let options = CodegenOptions {
+            cell_bits: 32,
+            eof: EofMode::Zero,
+            origin: 20000,
+        };
+        let code = to_wat(program.ir(), options).unwrap();
+
+        assert!(code.contains("(memory (export \"memory\") 2)"));
+        assert!(code.contains("(global $p (mut i32) (i32.const 20000))"));
+        assert!(code.contains("(i32.load (call $address))"));
+        assert!(code.contains("(then (call $set (i32.const 0)))"));
+        assert!(code.ends_with(
+            "    (func (export \"run\")\n        \
+             (call $set (i32.add (call $get) (i32.const 1)))\n        \
+             (call $set (i32.const 0))\n        (call $read)\n        \
+             (block (loop (br_if 1 (i32.eqz (call $get)))\n            \
+             (call $output (i32.and (call $get) (i32.const 255)))\n            \
+             (call $move (i32.const 1))\n        (br 0)))\n    )\n)\n"
+        ));
+    }
+
+    #[test]
+    fn generate_x86_64_assembly() {
+        let program = Program::parse("+[-]<,[.>]").unwrap();
+        let options = CodegenOptions {
+            cell_bits: 16,
+            eof: EofMode::MinusOne,
+            origin: 4,
+        };
+        let code = to_x86_64(program.ir(), options).unwrap();
+
+        assert!(code.contains("    lea 8(%rax), %rbx\n"));
+        assert!(code.contains(
+            "    addw $1, (%rbx)\n    movw $0, (%rbx)\n    \
+             sub $2, %rbx\n    cmp %r12, %rbx\n    jb fail_underflow\n    call input\n    \
+             cmpw $0, (%rbx)\n    je .L7_end\n    .L7_body:\n        call output\n        \
+             add $2, %rbx\n        cmp %r13, %rbx\n        jae fail_overflow\n    \
+             cmpw $0, (%rbx)\n    jne .L7_body\n    .L7_end:\n    call flush\n"
+        ));
+        assert!(code.contains("    mov %ax, (%rbx)\n"));
+        assert!(code.contains("    movw $-1, (%rbx)\n"));
+        assert_eq!(
+            to_x86_64(
+                program.ir(),
+                CodegenOptions {
+                    origin: 1 << 29,
+                    ..options
+                }
+            ),
+            Err(CodegenError::OriginOutsideTape(1 << 29))
+        );
+    }
+
+    #[test]
+    fn generate_python_script() {
+        let program = Program::parse("-[]>,[.<]").unwrap();
+        let options = CodegenOptions {
+            cell_bits: 16,
+            eof: EofMode::Unchanged,
+            origin: 1,
+        };
+        let code = to_python(program.ir(), options).unwrap();
+
+        assert!(code.contains("MASK = 0xffff\n"));
+        assert!(code.contains("            pass\n"));
+        assert!(code.contains(
+            "    state.mem[state.p] = (state.mem[state.p] - 1) & MASK\n    \
+             while state.mem[state.p]:\n        pass\n    state.move(1, 3)\n    \
+             state.input(read, 4)\n    while state.mem[state.p]:\n        \
+             write(state.mem[state.p] & 0xff)\n        state.move(-1, 7)\n\n\ndef main():"
+        ));
+    }
+
+    #[test]
+    fn generate_javascript_module() {
+        let program = Program::parse("+[-]<,[.>]").unwrap();
+        let code = to_javascript(program.ir(), CodegenOptions::default()).unwrap();
+
+        assert!(code.contains("this.mem = new Uint8Array(1);"));
+        assert!(code.contains("throw new Error(`end of input at instruction ${position}`);"));
+        assert!(code.contains(
+            "function run(state, read, write) {\n    state.mem[state.p] += 1;\n    \
+             state.mem[state.p] = 0;\n    state.move(-1, 4);\n    state.input(read, 5);\n    \
+             while (state.mem[state.p]) {\n        write(state.mem[state.p]);\n        \
+             state.move(1, 8);\n    }\n}\n"
+        ));
+    }
+}
@@ -0,0 +1,252 @@
+pub use crate::ir::CellKind;
+use crate::{
+    lexer::CountSyntax,
+    request::{Engine, TapeKind},
+    runtime::EofMode,
+};
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Config {
+    pub engine: Option<Engine>,
+    pub lenient: Option<bool>,
+    pub eof: Option<EofMode>,
+    pub cells: Option<CellKind>,
+    pub fuel: Option<u64>,
+    pub origin: Option<u64>,
+    pub input: Option<String>,
+    pub output: Option<String>,
+    pub aliases: Option<String>,
+    pub counts: Option<CountSyntax>,
+    pub timings: Option<bool>,
+    pub tape: Option<TapeKind>,
+    /// The optimization level, 0 to 2 like `-O0` to `-O2`.
+    pub optimize: Option<u8>,
+    /// Names of passes to run on top of the optimization level.
+    pub enable_passes: Vec<String>,
+    /// Names of passes to skip.
+    pub disable_passes: Vec<String>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ConfigError {
+    InvalidLine(usize),
+    UnknownKey(usize, String),
+    InvalidValue(usize, String),
+}
+
+enum Value {
+    String(String),
+    Integer(u64),
+    Boolean(bool),
+}
+
+impl Config {
+    /// Parses the flat subset of TOML used by `brainfudge.toml`: one `key = value` pair per line,
+    /// where values are strings, non-negative integers or booleans.
+    pub fn parse(text: &str) -> Result<Self, ConfigError> {
+        let mut config = Self::default();
+
+        for (index, line) in text.lines().enumerate() {
+            let number = index + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or(ConfigError::InvalidLine(number))?;
+            let key = key.trim();
+            let value = parse_value(value.trim())
+                .ok_or(ConfigError::InvalidValue(number, key.to_string()))?;
+            let invalid = || ConfigError::InvalidValue(number, key.to_string());
+
+            match (key, value) {
+                ("engine", Value::String(x)) => {
+                    config.engine = Some(Engine::from_name(&x).ok_or_else(invalid)?)
+                }
+                ("lenient", Value::Boolean(x)) => config.lenient = Some(x),
+                ("eof", Value::Integer(0)) => config.eof = Some(EofMode::Zero),
+                ("eof", Value::Integer(255)) => config.eof = Some(EofMode::MinusOne),
+                ("eof", Value::String(x)) if x == "error" => config.eof = Some(EofMode::Error),
+                ("eof", Value::String(x)) if x == "unchanged" => {
+                    config.eof = Some(EofMode::Unchanged)
+                }
+                ("cells", Value::Integer(8)) => config.cells = Some(CellKind::Byte),
+                ("cells", Value::Integer(16)) => config.cells = Some(CellKind::U16),
+                ("cells", Value::Integer(32)) => config.cells = Some(CellKind::U32),
+                ("cells", Value::String(x)) if x == "bignum" => {
+                    config.cells = Some(CellKind::Bignum)
+                }
+                ("fuel", Value::Integer(x)) => config.fuel = Some(x),
+                ("origin", Value::Integer(x)) => config.origin = Some(x),
+                ("input", Value::String(x)) => config.input = Some(x),
+                ("output", Value::String(x)) => config.output = Some(x),
+                ("aliases", Value::String(x)) => config.aliases = Some(x),
+                ("counts", Value::String(x)) if x == "prefix" => {
+                    config.counts = Some(CountSyntax::Prefix)
+                }
+                ("counts", Value::String(x)) if x == "postfix" => {
+                    config.counts = Some(CountSyntax::Postfix)
+                }
+                ("timings", Value::Boolean(x)) => config.timings = Some(x),
+                ("tape", Value::String(x)) => {
+                    config.tape = Some(parse_tape(&x).ok_or_else(invalid)?)
+                }
+                ("optimize", Value::Integer(x @ 0..=2)) => config.optimize = Some(x as u8),
+                ("enable_passes", Value::String(x)) => config.enable_passes = names(&x),
+                ("disable_passes", Value::String(x)) => config.disable_passes = names(&x),
+                (
+                    "engine" | "lenient" | "eof" | "cells" | "fuel" | "origin" | "input" | "output"
+                    | "aliases" | "counts" | "timings" | "tape" | "optimize" | "enable_passes"
+                    | "disable_passes",
+                    _,
+                ) => return Err(invalid()),
+                _ => return Err(ConfigError::UnknownKey(number, key.to_string())),
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+/// Reads the tape models of `--grow-left` and `--tape`: `"growing"`, `"grow-left"` or
+/// `"wrap:<cells>"`.
+fn parse_tape(value: &str) -> Option<TapeKind> {
+    match value {
+        "growing" => Some(TapeKind::Growing),
+        "grow-left" => Some(TapeKind::GrowLeft),
+        _ => match value.strip_prefix("wrap:")?.parse() {
+            Ok(x) if x > 0 => Some(TapeKind::Wrap(x)),
+            _ => None,
+        },
+    }
+}
+
+/// Splits a comma separated list of pass names, e.g. `"runs, clear-loops"`.
+fn names(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|x| !x.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+fn parse_value(value: &str) -> Option<Value> {
+    let value = without_comment(value);
+    match value {
+        "true" => return Some(Value::Boolean(true)),
+        "false" => return Some(Value::Boolean(false)),
+        _ => (),
+    }
+    if let Some(x) = value.strip_prefix('"').and_then(|x| x.strip_suffix('"')) {
+        return unescape(x).map(Value::String);
+    }
+    value.replace('_', "").parse().ok().map(Value::Integer)
+}
+
+fn without_comment(value: &str) -> &str {
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for (index, c) in value.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            '#' if !in_string => return value[..index].trim(),
+            _ => (),
+        }
+    }
+
+    value
+}
+
+fn unescape(value: &str) -> Option<String> {
+    let mut result = String::new();
+    let mut chars = value.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next()? {
+                '"' => result.push('"'),
+                '\\' => result.push('\\'),
+                'n' => result.push('\n'),
+                't' => result.push('\t'),
+                _ => return None,
+            },
+            '"' => return None,
+            _ => result.push(c),
+        }
+    }
+
+    Some(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_config() {
+        let text = "# project defaults\n\
+                    engine = \"blocks\"\n\
+                    lenient = true\n\
+                    eof = \"unchanged\"\n\
+                    cells = \"bignum\"\n\
+                    fuel = 1_000 # instructions\n\
+                    origin = 16\n\
+                    counts = \"postfix\"\n\
+                    tape = \"wrap:300\"\n\
+                    optimize = 1\n\
+                    enable_passes = \"constants, unroll\"\n\
+                    input = \"data/in \\\"#a\\\".txt\" # quoted\n";
+
+        assert_eq!(
+            Config::parse(text),
+            Ok(Config {
+                engine: Some(Engine::Blocks),
+                lenient: Some(true),
+                eof: Some(EofMode::Unchanged),
+                cells: Some(CellKind::Bignum),
+                fuel: Some(1000),
+                origin: Some(16),
+                counts: Some(CountSyntax::Postfix),
+                tape: Some(TapeKind::Wrap(300)),
+                optimize: Some(1),
+                enable_passes: vec!["constants".to_string(), "unroll".to_string()],
+                input: Some("data/in \"#a\".txt".to_string()),
+                ..Config::default()
+            })
+        );
+    }
+
+    #[test]
+    fn parse_config_errors() {
+        assert_eq!(Config::parse("lenient"), Err(ConfigError::InvalidLine(1)));
+        assert_eq!(
+            Config::parse("\nbackend = \"jit\""),
+            Err(ConfigError::UnknownKey(2, "backend".to_string()))
+        );
+        assert_eq!(
+            Config::parse("engine = \"jit\""),
+            Err(ConfigError::InvalidValue(1, "engine".to_string()))
+        );
+        assert_eq!(
+            Config::parse("cells = 12"),
+            Err(ConfigError::InvalidValue(1, "cells".to_string()))
+        );
+        assert_eq!(
+            Config::parse("fuel = \"a lot\""),
+            Err(ConfigError::InvalidValue(1, "fuel".to_string()))
+        );
+        assert_eq!(
+            Config::parse("tape = \"wrap:0\""),
+            Err(ConfigError::InvalidValue(1, "tape".to_string()))
+        );
+        assert_eq!(
+            Config::parse("optimize = 3"),
+            Err(ConfigError::InvalidValue(1, "optimize".to_string()))
+        );
+    }
+}
@@ -0,0 +1,411 @@
+use std::{
+    env, fs,
+    io::Write,
+    path::{Path, PathBuf},
+    process::{self, Command, Stdio},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::{
+    codegen::{to_c, CodegenOptions},
+    program::Program,
+    request::{execute, Engine, RunOptions, RunRequest},
+    runtime::{EofMode, State},
+};
+
+pub struct TestVector {
+    pub name: &'static str,
+    pub program: &'static str,
+    pub input: &'static [u8],
+    pub output: &'static [u8],
+    pub tape: &'static [u8],
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Outcome {
+    pub output: Vec<u8>,
+    pub tape: Vec<u8>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Failure {
+    EngineError(&'static str, String),
+    OutputMismatch(&'static str, Vec<u8>),
+    TapeMismatch(&'static str, Vec<u8>),
+}
+
+/// Generates random programs that always end, with inputs for them. Every loop steps its cell
+/// down by one per iteration and its body only touches cells to the right of it, and the
+/// pointer never moves left of the first cell.
+pub struct RandomPrograms {
+    state: u64,
+}
+
+/// A run of `differential` where an engine did not write what the oracle wrote.
+#[derive(Debug, PartialEq)]
+pub struct Mismatch {
+    pub program: String,
+    pub input: Vec<u8>,
+    pub engine: Engine,
+    pub expected: Vec<u8>,
+    pub actual: Result<Vec<u8>, String>,
+}
+
+pub const VECTORS: &[TestVector] = &[
+    TestVector {
+        name: "empty program",
+        program: "",
+        input: b"",
+        output: b"",
+        tape: &[0],
+    },
+    TestVector {
+        name: "comments are ignored",
+        program: "this is + a comment",
+        input: b"",
+        output: b"",
+        tape: &[1],
+    },
+    TestVector {
+        name: "increment wraps around",
+        program: "-+",
+        input: b"",
+        output: b"",
+        tape: &[0],
+    },
+    TestVector {
+        name: "decrement wraps around",
+        program: "-",
+        input: b"",
+        output: b"",
+        tape: &[255],
+    },
+    TestVector {
+        name: "tape grows to the right",
+        program: ">>>+",
+        input: b"",
+        output: b"",
+        tape: &[0, 0, 0, 1],
+    },
+    TestVector {
+        name: "loop is skipped on zero",
+        program: "[+.]>+",
+        input: b"",
+        output: b"",
+        tape: &[0, 1],
+    },
+    TestVector {
+        name: "nested loops multiply",
+        program: "+++[>++[>+++<-]<-]",
+        input: b"",
+        output: b"",
+        tape: &[0, 0, 18],
+    },
+    TestVector {
+        name: "output writes raw bytes",
+        program: "-.",
+        input: b"",
+        output: &[255],
+        tape: &[255],
+    },
+    TestVector {
+        name: "input reads raw bytes",
+        program: ",>,>,",
+        input: &[0, 128, 255],
+        output: b"",
+        tape: &[0, 128, 255],
+    },
+    TestVector {
+        name: "echo until zero byte",
+        program: ",[.,]",
+        input: b"abc\0",
+        output: b"abc",
+        tape: &[0],
+    },
+    TestVector {
+        name: "hello world",
+        program: include_str!("../examples/hello_world.bf"),
+        input: b"",
+        output: b"Hello world!\n",
+        tape: &[],
+    },
+];
+
+pub fn reference_engine(program: &str, input: &[u8]) -> Result<Outcome, String> {
+    let program = Program::parse(program).map_err(|e| format!("{:?}", e))?;
+    let mut state = State::with_io(input, Vec::new());
+
+    state
+        .run_blocks(program.tokens(), program.blocks())
+        .map_err(|e| format!("{:?}", e))?;
+
+    Ok(Outcome {
+        tape: state.memory(),
+        output: state.into_output(),
+    })
+}
+
+pub fn verify<F>(mut engine: F) -> Vec<Failure>
+where
+    F: FnMut(&str, &[u8]) -> Result<Outcome, String>,
+{
+    let mut failures = Vec::new();
+
+    for vector in VECTORS {
+        let outcome = match engine(vector.program, vector.input) {
+            Ok(x) => x,
+            Err(e) => {
+                failures.push(Failure::EngineError(vector.name, e));
+                continue;
+            }
+        };
+        if outcome.output != vector.output {
+            failures.push(Failure::OutputMismatch(vector.name, outcome.output));
+            continue;
+        }
+        let tape_matches = vector
+            .tape
+            .iter()
+            .enumerate()
+            .all(|(index, cell)| outcome.tape.get(index).copied().unwrap_or(0) == *cell);
+        if !tape_matches {
+            failures.push(Failure::TapeMismatch(vector.name, outcome.tape));
+        }
+    }
+
+    failures
+}
+
+/// Runs `count` random programs from `seed` on every engine and on `oracle`, with reads at the
+/// end of input storing 0, and lists the runs where an engine wrote something else than the
+/// oracle. Fails when the oracle does.
+pub fn differential<F>(seed: u64, count: usize, mut oracle: F) -> Result<Vec<Mismatch>, String>
+where
+    F: FnMut(&str, &[u8]) -> Result<Vec<u8>, String>,
+{
+    let mut programs = RandomPrograms::new(seed);
+    let mut mismatches = Vec::new();
+
+    for _ in 0..count {
+        let program = programs.program();
+        let input = programs.input();
+        let expected = oracle(&program, &input).map_err(|e| format!("{}: {}", program, e))?;
+        for engine in Engine::ALL {
+            let options = RunOptions {
+                engine: *engine,
+                eof: EofMode::Zero,
+                ..RunOptions::default()
+            };
+            let outcome = execute(
+                RunRequest::new(program.as_str())
+                    .input(input.as_slice())
+                    .options(options),
+            );
+            let actual = match outcome.result {
+                Ok(()) => Ok(outcome.output),
+                Err(e) => Err(format!("{:?}", e)),
+            };
+            if actual.as_ref() != Ok(&expected) {
+                mismatches.push(Mismatch {
+                    program: program.clone(),
+                    input: input.clone(),
+                    engine: *engine,
+                    expected: expected.clone(),
+                    actual,
+                });
+            }
+        }
+    }
+
+    Ok(mismatches)
+}
+
+/// An oracle for `differential`: translates the program with `codegen::to_c`, builds it with
+/// the system compiler `cc` and runs the executable on `input`. Every call builds in a
+/// directory of its own that only the current user can access.
+pub fn c_oracle(program: &str, input: &[u8]) -> Result<Vec<u8>, String> {
+    let program = Program::parse(program).map_err(|e| format!("{:?}", e))?;
+    let options = CodegenOptions {
+        eof: EofMode::Zero,
+        ..CodegenOptions::default()
+    };
+    let code = to_c(program.ir(), options).map_err(|e| format!("{:?}", e))?;
+
+    let directory = private_directory()?;
+    let result = compile_and_run(&directory, &code, input);
+    let _ = fs::remove_dir_all(&directory);
+    result
+}
+
+fn compile_and_run(directory: &Path, code: &str, input: &[u8]) -> Result<Vec<u8>, String> {
+    let source = directory.join("program.c");
+    let executable = directory.join("program");
+    fs::write(&source, code).map_err(|e| e.to_string())?;
+
+    let compiled = Command::new("cc")
+        .arg("-o")
+        .arg(&executable)
+        .arg(&source)
+        .output()
+        .map_err(|e| format!("cc: {}", e))?;
+    if !compiled.status.success() {
+        return Err(format!("cc: {}", String::from_utf8_lossy(&compiled.stderr)));
+    }
+
+    let mut child = Command::new(&executable)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| e.to_string())?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(input).map_err(|e| e.to_string())?;
+    }
+    let output = child.wait_with_output().map_err(|e| e.to_string())?;
+    match output.status.success() {
+        true => Ok(output.stdout),
+        false => Err(String::from_utf8_lossy(&output.stderr).into_owned()),
+    }
+}
+
+/// Creates a new directory in the temporary directory, failing rather than reusing a path
+/// that already exists.
+fn private_directory() -> Result<PathBuf, String> {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+    let name = format!(
+        "brainfudge-oracle-{}-{}",
+        process::id(),
+        NEXT.fetch_add(1, Ordering::Relaxed)
+    );
+    let path = env::temp_dir().join(name);
+    let mut builder = fs::DirBuilder::new();
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+    builder
+        .create(&path)
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+    Ok(path)
+}
+
+impl RandomPrograms {
+    pub fn new(seed: u64) -> Self {
+        // xorshift never leaves a state of 0.
+        Self {
+            state: seed.wrapping_mul(2).wrapping_add(1),
+        }
+    }
+
+    pub fn program(&mut self) -> String {
+        let mut source = String::new();
+        self.block(&mut source, 0);
+        source
+    }
+
+    /// Up to 8 input bytes, so that some programs read past the end.
+    pub fn input(&mut self) -> Vec<u8> {
+        (0..self.below(9)).map(|_| self.next() as u8).collect()
+    }
+
+    /// Appends instructions that end on the cell they start on and never move left of it.
+    fn block(&mut self, source: &mut String, depth: usize) {
+        let mut offset = 0;
+        for _ in 0..=self.below(12) {
+            match self.below(if depth < 2 { 9 } else { 8 }) {
+                0 | 1 => source.push('+'),
+                2 => source.push('-'),
+                4 if offset > 0 => {
+                    source.push('<');
+                    offset -= 1;
+                }
+                3 | 4 => {
+                    source.push('>');
+                    offset += 1;
+                }
+                5 | 6 => source.push('.'),
+                7 => source.push(','),
+                _ => {
+                    source.push_str("[>");
+                    self.block(source, depth + 1);
+                    source.push_str("<-]");
+                }
+            }
+        }
+        source.extend((0..offset).map(|_| '<'));
+    }
+
+    fn below(&mut self, bound: u64) -> u64 {
+        self.next() % bound
+    }
+
+    fn next(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reference_engine_conforms() {
+        assert_eq!(verify(reference_engine), vec![]);
+    }
+
+    #[test]
+    fn verify_reports_mismatches() {
+        let failures = verify(|_, _| {
+            Ok(Outcome {
+                output: vec![],
+                tape: vec![],
+            })
+        });
+
+        assert!(failures.contains(&Failure::TapeMismatch("decrement wraps around", vec![])));
+        assert!(failures.contains(&Failure::OutputMismatch("hello world", vec![])));
+        assert!(!failures
+            .iter()
+            .any(|x| matches!(x, Failure::TapeMismatch("empty program", _))));
+    }
+
+    #[test]
+    fn random_programs_end_on_the_tape() {
+        let mut programs = RandomPrograms::new(7);
+
+        for _ in 0..50 {
+            let program = programs.program();
+            let options = RunOptions {
+                eof: EofMode::Zero,
+                ..RunOptions::default()
+            };
+            let outcome = execute(
+                RunRequest::new(program.as_str())
+                    .input(programs.input())
+                    .options(options),
+            );
+            assert!(outcome.result.is_ok(), "{}", program);
+        }
+    }
+
+    #[test]
+    fn differential_reports_engines_that_disagree() {
+        let mismatches = differential(1, 20, |_, _| Ok(vec![0])).unwrap();
+
+        assert!(!mismatches.is_empty());
+        assert!(mismatches.iter().all(|x| x.expected == [0]));
+        assert!(differential(1, 20, |_, _| Err("down".to_string())).is_err());
+    }
+
+    #[test]
+    fn engines_agree_with_compiled_c() {
+        // Systems without a C compiler have no oracle.
+        if Command::new("cc").arg("--version").output().is_err() {
+            return;
+        }
+
+        assert_eq!(differential(0, 20, c_oracle), Ok(Vec::new()));
+    }
+}
@@ -0,0 +1,118 @@
+use std::{
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    time::Duration,
+};
+
+/// Options that can be changed from another thread while a program runs.
+#[derive(Debug, Default)]
+pub struct Controls {
+    trace: AtomicBool,
+    throttle: AtomicU64,
+    flush: AtomicBool,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ControlError {
+    UnknownCommand(String),
+    InvalidValue(String),
+}
+
+impl Controls {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn trace(&self) -> bool {
+        self.trace.load(Ordering::Relaxed)
+    }
+
+    pub fn set_trace(&self, trace: bool) {
+        self.trace.store(trace, Ordering::Relaxed);
+    }
+
+    /// Pause after every instruction.
+    pub fn throttle(&self) -> Duration {
+        Duration::from_micros(self.throttle.load(Ordering::Relaxed))
+    }
+
+    pub fn set_throttle(&self, throttle: Duration) {
+        let micros = u64::try_from(throttle.as_micros()).unwrap_or(u64::MAX);
+        self.throttle.store(micros, Ordering::Relaxed);
+    }
+
+    /// Whether output is flushed after every byte.
+    pub fn flush(&self) -> bool {
+        self.flush.load(Ordering::Relaxed)
+    }
+
+    pub fn set_flush(&self, flush: bool) {
+        self.flush.store(flush, Ordering::Relaxed);
+    }
+
+    /// Applies a command: `trace on|off`, `throttle <microseconds>` or `flush on|off`.
+    pub fn apply(&self, command: &str) -> Result<(), ControlError> {
+        let invalid = || ControlError::InvalidValue(command.to_string());
+        let switch = |value: Option<&str>| match value {
+            Some("on") => Ok(true),
+            Some("off") => Ok(false),
+            _ => Err(invalid()),
+        };
+
+        let mut words = command.split_whitespace();
+        match words.next() {
+            Some("trace") => self.set_trace(switch(words.next())?),
+            Some("flush") => self.set_flush(switch(words.next())?),
+            Some("throttle") => {
+                let micros = words
+                    .next()
+                    .and_then(|x| x.parse().ok())
+                    .ok_or_else(invalid)?;
+                self.set_throttle(Duration::from_micros(micros));
+            }
+            _ => return Err(ControlError::UnknownCommand(command.to_string())),
+        }
+        match words.next() {
+            Some(_) => Err(invalid()),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn apply_commands() {
+        let controls = Controls::new();
+
+        controls.apply("trace on").unwrap();
+        controls.apply("throttle 250").unwrap();
+        controls.apply(" flush  on ").unwrap();
+
+        assert!(controls.trace());
+        assert_eq!(controls.throttle(), Duration::from_micros(250));
+        assert!(controls.flush());
+
+        controls.apply("trace off").unwrap();
+        assert!(!controls.trace());
+    }
+
+    #[test]
+    fn reject_invalid_commands() {
+        let controls = Controls::new();
+
+        assert_eq!(
+            controls.apply("pause"),
+            Err(ControlError::UnknownCommand("pause".to_string()))
+        );
+        assert_eq!(
+            controls.apply("trace maybe"),
+            Err(ControlError::InvalidValue("trace maybe".to_string()))
+        );
+        assert_eq!(
+            controls.apply("throttle 1 2"),
+            Err(ControlError::InvalidValue("throttle 1 2".to_string()))
+        );
+    }
+}
@@ -0,0 +1,177 @@
+use std::io::{self, Read, Write};
+
+use crate::{lexer::Token, trace::TraceEvent};
+
+const MAGIC: &[u8; 7] = b"BFCORE\0";
+const VERSION: u8 = 1;
+
+/// The state of a program that stopped with an error, written by `--core-dump` and opened with
+/// `brainfudge debug --core`. Numbers are stored little-endian, texts and the tape with their
+/// length in front.
+#[derive(Debug, PartialEq)]
+pub struct CoreDump {
+    /// `Program::fingerprint` of the program that stopped.
+    pub fingerprint: u64,
+    pub error: String,
+    pub instruction_pointer: usize,
+    pub memory_pointer: usize,
+    pub steps: u64,
+    pub tape: Vec<u8>,
+    /// The last executed instructions, oldest first.
+    pub trace: Vec<TraceEvent>,
+}
+
+#[derive(Debug)]
+pub enum CoreError {
+    Io(io::Error),
+    NotACoreDump,
+    UnsupportedVersion(u8),
+    InvalidToken(u8),
+    InvalidText,
+}
+
+impl CoreDump {
+    pub fn write_to(&self, mut writer: impl Write) -> io::Result<()> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&[VERSION])?;
+        writer.write_all(&self.fingerprint.to_le_bytes())?;
+        write_bytes(&mut writer, self.error.as_bytes())?;
+        write_number(&mut writer, self.instruction_pointer)?;
+        write_number(&mut writer, self.memory_pointer)?;
+        writer.write_all(&self.steps.to_le_bytes())?;
+        write_bytes(&mut writer, &self.tape)?;
+
+        write_number(&mut writer, self.trace.len())?;
+        for event in &self.trace {
+            write_number(&mut writer, event.position)?;
+            writer.write_all(&[event.token.as_char() as u8])?;
+            write_number(&mut writer, event.memory_pointer)?;
+            writer.write_all(&[event.before, event.after])?;
+        }
+        writer.flush()
+    }
+
+    pub fn read_from(mut reader: impl Read) -> Result<Self, CoreError> {
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic).map_err(CoreError::Io)?;
+        if magic[..7] != MAGIC[..] {
+            return Err(CoreError::NotACoreDump);
+        }
+        if magic[7] != VERSION {
+            return Err(CoreError::UnsupportedVersion(magic[7]));
+        }
+
+        let fingerprint = read_u64(&mut reader)?;
+        let error =
+            String::from_utf8(read_bytes(&mut reader)?).map_err(|_| CoreError::InvalidText)?;
+        let instruction_pointer = read_number(&mut reader)?;
+        let memory_pointer = read_number(&mut reader)?;
+        let steps = read_u64(&mut reader)?;
+        let tape = read_bytes(&mut reader)?;
+
+        let length = read_number(&mut reader)?;
+        let mut trace = Vec::new();
+        for _ in 0..length {
+            let position = read_number(&mut reader)?;
+            let [token] = read_array(&mut reader)?;
+            let token = match token {
+                b'=' => Token::Assert,
+                x => Token::parse_byte(&x).ok_or(CoreError::InvalidToken(x))?,
+            };
+            let memory_pointer = read_number(&mut reader)?;
+            let [before, after] = read_array(&mut reader)?;
+            trace.push(TraceEvent {
+                position,
+                token,
+                memory_pointer,
+                before,
+                after,
+            });
+        }
+
+        Ok(Self {
+            fingerprint,
+            error,
+            instruction_pointer,
+            memory_pointer,
+            steps,
+            tape,
+            trace,
+        })
+    }
+}
+
+fn write_number(writer: &mut impl Write, number: usize) -> io::Result<()> {
+    writer.write_all(&(number as u64).to_le_bytes())
+}
+
+fn write_bytes(writer: &mut impl Write, bytes: &[u8]) -> io::Result<()> {
+    write_number(writer, bytes.len())?;
+    writer.write_all(bytes)
+}
+
+fn read_array<const N: usize>(reader: &mut impl Read) -> Result<[u8; N], CoreError> {
+    let mut buffer = [0; N];
+    reader.read_exact(&mut buffer).map_err(CoreError::Io)?;
+    Ok(buffer)
+}
+
+fn read_u64(reader: &mut impl Read) -> Result<u64, CoreError> {
+    read_array(reader).map(u64::from_le_bytes)
+}
+
+fn read_number(reader: &mut impl Read) -> Result<usize, CoreError> {
+    let number = read_u64(reader)?;
+    usize::try_from(number).map_err(|_| CoreError::Io(io::ErrorKind::InvalidData.into()))
+}
+
+fn read_bytes(reader: &mut impl Read) -> Result<Vec<u8>, CoreError> {
+    let length = read_u64(reader)?;
+    let mut bytes = Vec::new();
+    reader
+        .take(length)
+        .read_to_end(&mut bytes)
+        .map_err(CoreError::Io)?;
+    match bytes.len() as u64 == length {
+        true => Ok(bytes),
+        false => Err(CoreError::Io(io::ErrorKind::UnexpectedEof.into())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_and_read_core_dump() {
+        let core = CoreDump {
+            fingerprint: 0x1234,
+            error: "PointerUnderflow(3)".to_string(),
+            instruction_pointer: 3,
+            memory_pointer: 0,
+            steps: 3,
+            tape: vec![2, 0, 7],
+            trace: vec![TraceEvent {
+                position: 2,
+                token: Token::Assert,
+                memory_pointer: 0,
+                before: 1,
+                after: 2,
+            }],
+        };
+        let mut bytes = Vec::new();
+
+        core.write_to(&mut bytes).unwrap();
+
+        assert!(bytes.starts_with(b"BFCORE\0\x01"));
+        assert_eq!(CoreDump::read_from(bytes.as_slice()).unwrap(), core);
+        assert!(matches!(
+            CoreDump::read_from(&bytes[..bytes.len() - 1]),
+            Err(CoreError::Io(_))
+        ));
+        assert!(matches!(
+            CoreDump::read_from(&b"BFDUMP\0\x01"[..]),
+            Err(CoreError::NotACoreDump)
+        ));
+    }
+}
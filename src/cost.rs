@@ -0,0 +1,105 @@
+use crate::lexer::Token;
+
+/// What every executed instruction costs, for grading programs on efficiency. Every
+/// instruction costs 1 unless the model says otherwise.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CostModel {
+    costs: [u64; 9],
+}
+
+#[derive(Debug, PartialEq)]
+pub enum CostModelError {
+    /// A group without a `:` between instructions and cost.
+    InvalidGroup(String),
+    InvalidCost(String),
+    UnknownInstruction(char),
+}
+
+impl Default for CostModel {
+    fn default() -> Self {
+        Self { costs: [1; 9] }
+    }
+}
+
+impl CostModel {
+    /// Parses space separated groups of instructions and their cost, e.g. `.,:10 []:2`.
+    pub fn parse(spec: &str) -> Result<Self, CostModelError> {
+        let mut model = Self::default();
+        for group in spec.split_whitespace() {
+            let (instructions, cost) = group
+                .rsplit_once(':')
+                .ok_or(CostModelError::InvalidGroup(group.to_string()))?;
+            let cost = cost
+                .parse()
+                .map_err(|_| CostModelError::InvalidCost(cost.to_string()))?;
+            if instructions.is_empty() {
+                return Err(CostModelError::InvalidGroup(group.to_string()));
+            }
+            for c in instructions.chars() {
+                let token = Token::parse(&c).ok_or(CostModelError::UnknownInstruction(c))?;
+                model = model.with_cost(token, cost);
+            }
+        }
+        Ok(model)
+    }
+
+    pub fn with_cost(mut self, token: Token, cost: u64) -> Self {
+        self.costs[index(token)] = cost;
+        self
+    }
+
+    pub fn cost(&self, token: Token) -> u64 {
+        self.costs[index(token)]
+    }
+}
+
+fn index(token: Token) -> usize {
+    match token {
+        Token::Increment => 0,
+        Token::Decrement => 1,
+        Token::PointerIncrement => 2,
+        Token::PointerDecrement => 3,
+        Token::LoopStart => 4,
+        Token::LoopEnd => 5,
+        Token::Input => 6,
+        Token::Output => 7,
+        Token::Assert => 8,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_cost_model() {
+        let model = CostModel::parse(".,:10 []:2 +:0").unwrap();
+
+        assert_eq!(model.cost(Token::Output), 10);
+        assert_eq!(model.cost(Token::Input), 10);
+        assert_eq!(model.cost(Token::LoopEnd), 2);
+        assert_eq!(model.cost(Token::Increment), 0);
+        assert_eq!(model.cost(Token::Decrement), 1);
+        assert_eq!(CostModel::parse(""), Ok(CostModel::default()));
+    }
+
+    #[test]
+    fn reject_invalid_cost_models() {
+        assert_eq!(
+            CostModel::parse(".10"),
+            Err(CostModelError::InvalidGroup(".10".to_string()))
+        );
+        assert_eq!(
+            CostModel::parse(":3"),
+            Err(CostModelError::InvalidGroup(":3".to_string()))
+        );
+        assert_eq!(
+            CostModel::parse(".:x"),
+            Err(CostModelError::InvalidCost("x".to_string()))
+        );
+        assert_eq!(
+            CostModel::parse("a:1"),
+            Err(CostModelError::UnknownInstruction('a'))
+        );
+    }
+}
@@ -0,0 +1,176 @@
+use crate::{
+    block::BasicBlocks,
+    ir::Ir,
+    lexer::{JumpTable, Token},
+    program::{self, Program, ProgramError},
+};
+
+const NO_TARGET: usize = usize::MAX;
+
+#[macro_export]
+macro_rules! include_bf {
+    ($path:expr) => {{
+        const SOURCE: &[u8] = include_bytes!($path);
+        const PROGRAM: $crate::embed::StaticProgram<{ $crate::embed::count_tokens(SOURCE) }> =
+            $crate::embed::StaticProgram::from_source(SOURCE);
+        &PROGRAM
+    }};
+}
+
+pub struct StaticProgram<const N: usize> {
+    tokens: [Token; N],
+    targets: [usize; N],
+}
+
+impl<const N: usize> StaticProgram<N> {
+    pub const fn from_source(source: &[u8]) -> Self {
+        let mut tokens = [Token::Increment; N];
+        let mut targets = [NO_TARGET; N];
+        let mut loop_starts = [0; N];
+        let mut depth = 0;
+        let mut count = 0;
+        let mut offset = 0;
+
+        while offset < source.len() {
+            if let Some(token) = parse(source[offset]) {
+                if count == N {
+                    panic!("More tokens in brainfuck source than expected");
+                }
+                tokens[count] = token;
+                match token {
+                    Token::LoopStart => {
+                        loop_starts[depth] = count;
+                        depth += 1;
+                    }
+                    Token::LoopEnd => {
+                        if depth == 0 {
+                            panic!("Brainfuck source has a ] without matching [");
+                        }
+                        depth -= 1;
+                        targets[loop_starts[depth]] = count;
+                        targets[count] = loop_starts[depth];
+                    }
+                    _ => (),
+                }
+                count += 1;
+            }
+            offset += 1;
+        }
+
+        if depth != 0 {
+            panic!("Brainfuck source has a [ without matching ]");
+        }
+        if count != N {
+            panic!("Fewer tokens in brainfuck source than expected");
+        }
+        Self { tokens, targets }
+    }
+
+    pub fn tokens(&self) -> &[Token] {
+        &self.tokens
+    }
+
+    pub fn jump_table(&self) -> JumpTable {
+        JumpTable::from_pairs(
+            self.targets
+                .iter()
+                .enumerate()
+                .filter(|(_, target)| **target != NO_TARGET)
+                .map(|(position, target)| (position, *target)),
+        )
+    }
+
+    /// A program that borrows the embedded tokens instead of copying them.
+    pub fn to_program(&self) -> Result<Program<'_>, ProgramError> {
+        let jump_table = self.jump_table();
+        let blocks =
+            BasicBlocks::from(&self.tokens, &jump_table).map_err(ProgramError::BasicBlocks)?;
+        let ir = Ir::lower(&self.tokens, &jump_table).map_err(ProgramError::Ir)?;
+        Ok(Program::from_parts(
+            &self.tokens[..],
+            jump_table,
+            blocks,
+            program::optimized(ir),
+        ))
+    }
+}
+
+pub const fn count_tokens(source: &[u8]) -> usize {
+    let mut count = 0;
+    let mut offset = 0;
+
+    while offset < source.len() {
+        if parse(source[offset]).is_some() {
+            count += 1;
+        }
+        offset += 1;
+    }
+
+    count
+}
+
+const fn parse(byte: u8) -> Option<Token> {
+    match byte {
+        b'+' => Some(Token::Increment),
+        b'-' => Some(Token::Decrement),
+        b'>' => Some(Token::PointerIncrement),
+        b'<' => Some(Token::PointerDecrement),
+        b'[' => Some(Token::LoopStart),
+        b']' => Some(Token::LoopEnd),
+        b',' => Some(Token::Input),
+        b'.' => Some(Token::Output),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer::tokenize, runtime::State};
+
+    #[test]
+    fn include_bf_embeds_program() {
+        let program = include_bf!("../examples/hello_world.bf")
+            .to_program()
+            .unwrap();
+        let mut state = State::with_io(std::io::empty(), Vec::new());
+
+        state
+            .run_blocks(program.tokens(), program.blocks())
+            .unwrap();
+
+        assert_eq!(state.output(), b"Hello world!\n");
+    }
+
+    #[test]
+    fn to_program_borrows_embedded_tokens() {
+        let embedded = include_bf!("../examples/hello_world.bf");
+        let program = embedded.to_program().unwrap();
+
+        assert!(std::ptr::eq(program.tokens(), embedded.tokens()));
+    }
+
+    #[test]
+    fn static_program_precomputes_jumps() {
+        const PROGRAM: StaticProgram<6> = StaticProgram::from_source(b"+[-[]]");
+
+        let jump_table = PROGRAM.jump_table();
+
+        assert_eq!(PROGRAM.tokens(), tokenize("+[-[]]"));
+        assert_eq!(jump_table.resolve(&1), Some(&5));
+        assert_eq!(jump_table.resolve(&3), Some(&4));
+        assert_eq!(jump_table.resolve(&0), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "[ without matching ]")]
+    fn static_program_rejects_unmatched_loop_start() {
+        StaticProgram::<1>::from_source(b"[");
+    }
+
+    #[test]
+    #[should_panic(expected = "] without matching [")]
+    fn static_program_rejects_unmatched_loop_end() {
+        StaticProgram::<1>::from_source(b"]");
+    }
+}
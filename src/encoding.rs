@@ -0,0 +1,135 @@
+/// How a program is encoded when it is stored as text, e.g. in a JSON config, a URL or an
+/// environment variable. Decode it before lexing.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProgramEncoding {
+    /// Standard or URL-safe base64. Padding is optional.
+    Base64,
+    /// Two hexadecimal digits per byte, in either case.
+    Hex,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum DecodeError {
+    /// A byte that is not part of the encoding, at this offset.
+    InvalidCharacter(usize),
+    /// The number of digits does not add up to whole bytes.
+    InvalidLength(usize),
+}
+
+impl ProgramEncoding {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "base64" => Some(Self::Base64),
+            "hex" => Some(Self::Hex),
+            _ => None,
+        }
+    }
+
+    /// Decodes the program, skipping whitespace so that encoded text can be wrapped.
+    pub fn decode(&self, text: impl AsRef<[u8]>) -> Result<Vec<u8>, DecodeError> {
+        match self {
+            Self::Base64 => decode_base64(text.as_ref()),
+            Self::Hex => decode_hex(text.as_ref()),
+        }
+    }
+}
+
+fn decode_base64(text: &[u8]) -> Result<Vec<u8>, DecodeError> {
+    let mut bytes = Vec::with_capacity(text.len() / 4 * 3);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    let mut digits = 0;
+    let mut padded = false;
+
+    for (offset, c) in text.iter().enumerate() {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            b'=' => {
+                padded = true;
+                continue;
+            }
+            _ if c.is_ascii_whitespace() => continue,
+            _ => return Err(DecodeError::InvalidCharacter(offset)),
+        };
+        if padded {
+            return Err(DecodeError::InvalidCharacter(offset));
+        }
+        buffer = (buffer << 6 | value as u32) & 0xfff;
+        bits += 6;
+        digits += 1;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buffer >> bits) as u8);
+        }
+    }
+    if digits % 4 == 1 {
+        return Err(DecodeError::InvalidLength(digits));
+    }
+
+    Ok(bytes)
+}
+
+fn decode_hex(text: &[u8]) -> Result<Vec<u8>, DecodeError> {
+    let mut bytes = Vec::with_capacity(text.len() / 2);
+    let mut high = None;
+
+    for (offset, c) in text.iter().enumerate() {
+        if c.is_ascii_whitespace() {
+            continue;
+        }
+        let value = (*c as char)
+            .to_digit(16)
+            .ok_or(DecodeError::InvalidCharacter(offset))? as u8;
+        match high.take() {
+            Some(x) => bytes.push(x << 4 | value),
+            None => high = Some(value),
+        }
+    }
+    if high.is_some() {
+        return Err(DecodeError::InvalidLength(bytes.len() * 2 + 1));
+    }
+
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_base64_programs() {
+        let encoding = ProgramEncoding::Base64;
+
+        assert_eq!(encoding.decode("KytbLT4uXQ=="), Ok(b"++[->.]".to_vec()));
+        assert_eq!(encoding.decode("KytbLT4uXQ"), Ok(b"++[->.]".to_vec()));
+        assert_eq!(encoding.decode("Pz8-\nPz8_"), Ok(b"??>??\x3f".to_vec()));
+        assert_eq!(encoding.decode(""), Ok(Vec::new()));
+        assert_eq!(encoding.decode("KytbL"), Err(DecodeError::InvalidLength(5)));
+        assert_eq!(
+            encoding.decode("Kyt!"),
+            Err(DecodeError::InvalidCharacter(3))
+        );
+        assert_eq!(
+            encoding.decode("Kw==Kw"),
+            Err(DecodeError::InvalidCharacter(4))
+        );
+    }
+
+    #[test]
+    fn decode_hex_programs() {
+        let encoding = ProgramEncoding::Hex;
+
+        assert_eq!(encoding.decode("2b2B 5b2d5d\n"), Ok(b"++[-]".to_vec()));
+        assert_eq!(encoding.decode("2b2"), Err(DecodeError::InvalidLength(3)));
+        assert_eq!(encoding.decode("2g"), Err(DecodeError::InvalidCharacter(1)));
+        assert_eq!(
+            ProgramEncoding::from_name("hex"),
+            Some(ProgramEncoding::Hex)
+        );
+        assert_eq!(ProgramEncoding::from_name("utf-8"), None);
+    }
+}
@@ -0,0 +1,143 @@
+use std::fmt::Write;
+
+use crate::{
+    analysis::{balance, Balance},
+    lexer::{detokenize, JumpTable, Token},
+    schema::SNIPPET,
+};
+
+/// A loop cut out of a program, to be saved as a standalone file.
+#[derive(Debug, PartialEq)]
+pub struct Snippet {
+    /// Positions of the loop's brackets in the original program.
+    pub start: usize,
+    pub end: usize,
+    /// The loop, brackets included.
+    pub tokens: Vec<Token>,
+    /// The effect of one iteration relative to the cell the loop starts on.
+    pub balance: Balance,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ExtractError {
+    PositionOutOfRange(usize),
+    NotInLoop(usize),
+}
+
+/// Extracts the innermost loop containing the instruction at `position`, brackets included.
+pub fn extract_loop(
+    tokens: &[Token],
+    jump_table: &JumpTable,
+    position: usize,
+) -> Result<Snippet, ExtractError> {
+    if position >= tokens.len() {
+        return Err(ExtractError::PositionOutOfRange(position));
+    }
+
+    let mut open = Vec::new();
+    for (index, token) in tokens.iter().enumerate().take(position + 1) {
+        match token {
+            Token::LoopStart => open.push(index),
+            Token::LoopEnd if index < position => {
+                open.pop();
+            }
+            _ => (),
+        }
+    }
+    let start = match (tokens[position], open.last()) {
+        (Token::LoopEnd, _) => jump_table.resolve(&position).copied(),
+        (_, start) => start.copied(),
+    }
+    .ok_or(ExtractError::NotInLoop(position))?;
+    let end = *jump_table
+        .resolve(&start)
+        .ok_or(ExtractError::NotInLoop(position))?;
+
+    Ok(Snippet {
+        start,
+        end,
+        tokens: tokens[start..=end].to_vec(),
+        balance: balance(&tokens[start + 1..end]),
+    })
+}
+
+impl Snippet {
+    pub fn source(&self) -> String {
+        detokenize(&self.tokens)
+    }
+
+    /// The span and effects of the loop, written next to the extracted file.
+    pub fn to_json(&self) -> String {
+        let mut json = format!(
+            r#"{{"schema_version":{},"start":{},"end":{},"effects":"#,
+            SNIPPET.version, self.start, self.end
+        );
+        match &self.balance {
+            Balance::Balanced {
+                deltas,
+                contains_io,
+            } => {
+                json.push_str(r#"{"kind":"balanced","deltas":["#);
+                for (index, (offset, delta)) in deltas.iter().enumerate() {
+                    let _ = write!(
+                        json,
+                        r#"{}{{"offset":{},"delta":{}}}"#,
+                        if index > 0 { "," } else { "" },
+                        offset,
+                        delta
+                    );
+                }
+                let _ = write!(json, r#"],"contains_io":{}}}"#, contains_io);
+            }
+            Balance::Unbalanced(shift) => {
+                let _ = write!(json, r#"{{"kind":"unbalanced","shift":{}}}"#, shift);
+            }
+            Balance::Unknown => json.push_str(r#"{"kind":"unknown"}"#),
+        }
+        json.push('}');
+        json
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::tokenize;
+
+    #[test]
+    fn extract_innermost_loop() {
+        let tokens = tokenize("+[>++[->+<]<-]");
+        let jump_table = JumpTable::from(&tokens).unwrap();
+
+        let snippet = extract_loop(&tokens, &jump_table, 6).unwrap();
+        assert_eq!((snippet.start, snippet.end), (5, 10));
+        assert_eq!(snippet.source(), "[->+<]");
+        assert_eq!(
+            snippet.to_json(),
+            r#"{"schema_version":1,"start":5,"end":10,"effects":{"kind":"balanced","deltas":[{"offset":0,"delta":-1},{"offset":1,"delta":1}],"contains_io":false}}"#
+        );
+
+        let snippet = extract_loop(&tokens, &jump_table, 11).unwrap();
+        assert_eq!((snippet.start, snippet.end), (1, 13));
+        assert_eq!(
+            snippet.to_json(),
+            r#"{"schema_version":1,"start":1,"end":13,"effects":{"kind":"unknown"}}"#
+        );
+        assert_eq!(extract_loop(&tokens, &jump_table, 10).unwrap().start, 5);
+    }
+
+    #[test]
+    fn extract_errors_outside_loops() {
+        let tokens = tokenize("+[-]>");
+        let jump_table = JumpTable::from(&tokens).unwrap();
+
+        assert_eq!(
+            extract_loop(&tokens, &jump_table, 4),
+            Err(ExtractError::NotInLoop(4))
+        );
+        assert_eq!(
+            extract_loop(&tokens, &jump_table, 5),
+            Err(ExtractError::PositionOutOfRange(5))
+        );
+    }
+}
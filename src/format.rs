@@ -0,0 +1,228 @@
+use crate::lexer::{detokenize, tokenize_with_trivia, JumpTable, JumpTableError, Lexeme, Token};
+
+const INDENT: &str = "    ";
+
+/// A program as a tree of loops, with the comments that sit between its instructions.
+enum Node {
+    Token(Token),
+    Loop(Vec<Node>),
+    /// A comment on its own line.
+    Comment(String),
+    /// A comment behind the instruction before it, on the same line.
+    TrailingComment(String),
+    /// At least one empty line.
+    Blank,
+}
+
+/// Lays out a program canonically: instructions are packed into lines of at most `width`
+/// columns, and every loop that contains a comment or does not fit on one line gets its
+/// brackets on their own lines and its body indented by one level. Comments are kept, on
+/// their own lines or behind the instruction they followed, and so is one of every run of
+/// empty lines. Formatting the result again does not change it.
+pub fn format(source: impl AsRef<[u8]>, width: usize) -> Result<String, JumpTableError> {
+    let lexemes = tokenize_with_trivia(source);
+    let tokens: Vec<Token> = lexemes
+        .iter()
+        .filter_map(|x| match x {
+            Lexeme::Token(token) => Some(*token),
+            Lexeme::Trivia(_) => None,
+        })
+        .collect();
+    JumpTable::from(&tokens)?;
+
+    let mut layout = Layout {
+        width,
+        lines: Vec::new(),
+        line: String::new(),
+        attach: false,
+        block_start: 0,
+    };
+    layout.block(&parse(lexemes), 0);
+    layout.flush(0);
+    while layout.lines.last().is_some_and(|x| x.is_empty()) {
+        layout.lines.pop();
+    }
+
+    let mut formatted = layout.lines.join("\n");
+    if !formatted.is_empty() {
+        formatted.push('\n');
+    }
+    Ok(formatted)
+}
+
+/// Builds the tree of a balanced program.
+fn parse(lexemes: Vec<Lexeme>) -> Vec<Node> {
+    let mut blocks = vec![Vec::new()];
+    let mut after_token = false;
+
+    for lexeme in lexemes {
+        let is_token = matches!(lexeme, Lexeme::Token(_));
+        match lexeme {
+            Lexeme::Token(Token::LoopStart) => blocks.push(Vec::new()),
+            Lexeme::Token(Token::LoopEnd) => {
+                let body = blocks.pop().unwrap();
+                blocks.last_mut().unwrap().push(Node::Loop(body));
+            }
+            Lexeme::Token(token) => blocks.last_mut().unwrap().push(Node::Token(token)),
+            Lexeme::Trivia(text) => {
+                let block = blocks.last_mut().unwrap();
+                let lines: Vec<&str> = text.split('\n').collect();
+                for (index, line) in lines.iter().enumerate() {
+                    let line = line.trim();
+                    let full_line = index > 0 && index + 1 < lines.len();
+                    match (index, line.is_empty()) {
+                        (0, false) if after_token => {
+                            block.push(Node::TrailingComment(line.to_string()))
+                        }
+                        (_, false) => block.push(Node::Comment(line.to_string())),
+                        (_, true) if full_line => block.push(Node::Blank),
+                        (_, true) => (),
+                    }
+                }
+            }
+        }
+        after_token = is_token;
+    }
+
+    blocks.pop().unwrap()
+}
+
+struct Layout {
+    width: usize,
+    lines: Vec<String>,
+    /// The instructions of the line being packed.
+    line: String,
+    /// Whether a trailing comment goes behind the last line, which holds a bracket.
+    attach: bool,
+    /// The first line of the innermost loop, which never starts with an empty line.
+    block_start: usize,
+}
+
+impl Layout {
+    fn block(&mut self, nodes: &[Node], depth: usize) {
+        for node in nodes {
+            match node {
+                Node::Token(token) => self.push(&token.as_char().to_string(), depth),
+                Node::Loop(body) => {
+                    let inline = flatten(body).map(|x| format!("[{}]", x));
+                    match inline {
+                        Some(x) if INDENT.len() * depth + x.len() <= self.width => {
+                            self.push(&x, depth)
+                        }
+                        _ => {
+                            self.flush(depth);
+                            self.emit("[".to_string(), depth);
+                            self.attach = true;
+                            let block_start =
+                                std::mem::replace(&mut self.block_start, self.lines.len());
+                            self.block(body, depth + 1);
+                            self.block_start = block_start;
+                            self.flush(depth + 1);
+                            while self.lines.last().is_some_and(|x| x.is_empty()) {
+                                self.lines.pop();
+                            }
+                            self.emit("]".to_string(), depth);
+                            self.attach = true;
+                        }
+                    }
+                }
+                Node::TrailingComment(text) if !self.line.is_empty() => {
+                    let line = std::mem::take(&mut self.line);
+                    self.emit(format!("{} {}", line, text), depth);
+                }
+                Node::TrailingComment(text) if self.attach => {
+                    let last = self.lines.last_mut().unwrap();
+                    last.push(' ');
+                    last.push_str(text);
+                    self.attach = false;
+                }
+                Node::TrailingComment(text) | Node::Comment(text) => {
+                    self.flush(depth);
+                    self.emit(text.clone(), depth);
+                }
+                Node::Blank => {
+                    self.flush(depth);
+                    if self.lines.len() > self.block_start
+                        && self.lines.last().is_some_and(|x| !x.is_empty())
+                    {
+                        self.lines.push(String::new());
+                    }
+                    self.attach = false;
+                }
+            }
+        }
+    }
+
+    /// Adds instructions to the current line, starting a new one when they do not fit.
+    fn push(&mut self, code: &str, depth: usize) {
+        let used = INDENT.len() * depth + self.line.len();
+        if !self.line.is_empty() && used + code.len() > self.width {
+            self.flush(depth);
+        }
+        self.line.push_str(code);
+        self.attach = false;
+    }
+
+    fn flush(&mut self, depth: usize) {
+        if !self.line.is_empty() {
+            let line = std::mem::take(&mut self.line);
+            self.emit(line, depth);
+        }
+    }
+
+    fn emit(&mut self, line: String, depth: usize) {
+        self.lines.push(INDENT.repeat(depth) + &line);
+        self.attach = false;
+    }
+}
+
+/// The instructions of a loop body on one line, unless it holds a comment.
+fn flatten(nodes: &[Node]) -> Option<String> {
+    let mut tokens = Vec::new();
+    collect(nodes, &mut tokens)?;
+    Some(detokenize(&tokens))
+}
+
+fn collect(nodes: &[Node], tokens: &mut Vec<Token>) -> Option<()> {
+    for node in nodes {
+        match node {
+            Node::Token(token) => tokens.push(*token),
+            Node::Loop(body) => {
+                tokens.push(Token::LoopStart);
+                collect(body, tokens)?;
+                tokens.push(Token::LoopEnd);
+            }
+            Node::Comment(_) | Node::TrailingComment(_) => return None,
+            Node::Blank => (),
+        }
+    }
+    Some(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_indents_loops() {
+        let source = "read ,[\n\n  print .,  next\n]\n\n\n+++[->+<]";
+
+        assert_eq!(
+            format(source, 80),
+            Ok("read\n,\n[\n    print\n    ., next\n]\n\n+++[->+<]\n".to_string())
+        );
+        assert_eq!(
+            format("++++++++[>++++[>++>+++<<-]>+<<-]>>.", 16),
+            Ok("++++++++\n[\n    >++++\n    [>++>+++<<-]\n    >+<<-\n]\n>>.\n".to_string())
+        );
+        assert_eq!(format("[", 80), Err(JumpTableError::TooManyLoopStarts(1)));
+    }
+
+    #[test]
+    fn format_is_idempotent() {
+        let source = "x +++ [ outer\n>++[>+\n\n<-]  inner\n]\n< done .";
+        let formatted = format(source, 12).unwrap();
+
+        assert_eq!(format(&formatted, 12).unwrap(), formatted);
+    }
+}
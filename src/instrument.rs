@@ -0,0 +1,94 @@
+use crate::{
+    io::Io,
+    lexer::{JumpTable, Token},
+    program::Program,
+    runtime::{ExecutionError, State},
+    tape::Tape,
+    visit::{walk, Visitor},
+};
+
+/// Pseudo-instructions at the head of loop bodies. They are not part of the token stream that
+/// runs: `State::run_probed` fires a hook with the index of the probe whenever execution enters
+/// a marked loop body, which costs far less than a hook after every instruction.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Probes {
+    loop_starts: Vec<usize>,
+}
+
+/// Loop events of `State::run_with_loop_hooks`, where a loop is identified by its position in
+/// source order. Both events do nothing unless implemented.
+pub trait LoopHooks {
+    /// Execution reached the `[` of the loop from outside of it.
+    fn on_loop_enter(&mut self, _loop_id: usize) {}
+
+    /// Execution left the loop after running its body `iterations` times since it entered.
+    fn on_loop_exit(&mut self, _loop_id: usize, _iterations: u64) {}
+}
+
+struct LoopHeads(Vec<usize>);
+
+impl Visitor for LoopHeads {
+    fn enter_loop(&mut self, start: usize, _end: usize) {
+        self.0.push(start);
+    }
+}
+
+impl Probes {
+    /// A probe for every loop, in source order.
+    pub fn loop_heads(tokens: &[Token], jump_table: &JumpTable) -> Self {
+        let mut heads = LoopHeads(Vec::new());
+        walk(tokens, jump_table, &mut heads);
+        Self {
+            loop_starts: heads.0,
+        }
+    }
+
+    /// The position of the `[` whose body the probe marks.
+    pub fn loop_start(&self, probe: usize) -> usize {
+        self.loop_starts[probe]
+    }
+
+    pub fn loop_starts(&self) -> &[usize] {
+        &self.loop_starts
+    }
+
+    pub fn len(&self) -> usize {
+        self.loop_starts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.loop_starts.is_empty()
+    }
+}
+
+/// Runs a program to completion and returns the start of every loop with the number of
+/// iterations it ran in total.
+pub fn loop_iterations<I: Io, T: Tape>(
+    state: &mut State<I, T>,
+    program: &Program,
+) -> Result<Vec<(usize, u64)>, ExecutionError> {
+    let probes = Probes::loop_heads(program.tokens(), program.jump_table());
+    let mut counts = vec![0; probes.len()];
+    state.run_probed(program.tokens(), program.blocks(), &probes, |probe| {
+        counts[probe] += 1
+    })?;
+    Ok(probes.loop_starts().iter().copied().zip(counts).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::Null;
+
+    #[test]
+    fn count_loop_iterations() {
+        let program = Program::parse("++[>+++[-]<-]>[]").unwrap();
+        let mut state = State::with_device(Null);
+
+        assert_eq!(
+            loop_iterations(&mut state, &program).unwrap(),
+            vec![(2, 2), (7, 6), (14, 0)]
+        );
+        assert_eq!(state.memory(), vec![0, 0]);
+    }
+}
@@ -0,0 +1,185 @@
+use crate::{
+    io::{Io, Stdio},
+    limits::Limits,
+    program::Program,
+    runtime::{EofMode, ExecutionError, State},
+    tape::{Tape, VecTape},
+};
+
+/// A configured runtime. Cells are bytes that wrap around; see `bignum` for unbounded cells.
+pub struct Interpreter<I = Stdio, T = VecTape> {
+    state: State<I, T>,
+}
+
+pub struct Builder<I = Stdio, T = VecTape> {
+    io: I,
+    memory: T,
+    tape: Vec<u8>,
+    origin: usize,
+    eof: EofMode,
+    lenient: bool,
+    limits: Limits,
+}
+
+impl Interpreter {
+    pub fn builder() -> Builder {
+        Builder {
+            io: Stdio::stdio(),
+            memory: VecTape::new(),
+            tape: Vec::new(),
+            origin: 0,
+            eof: EofMode::Error,
+            lenient: false,
+            limits: Limits::default(),
+        }
+    }
+}
+
+impl<I: Io, T: Tape<Cell = u8>> Builder<I, T> {
+    pub fn io<J: Io>(self, io: J) -> Builder<J, T> {
+        Builder {
+            io,
+            memory: self.memory,
+            tape: self.tape,
+            origin: self.origin,
+            eof: self.eof,
+            lenient: self.lenient,
+            limits: self.limits,
+        }
+    }
+
+    /// The tape implementation, e.g. `CircularTape::new(30_000)` for a tape that wraps around.
+    pub fn memory<U: Tape<Cell = u8>>(self, memory: U) -> Builder<I, U> {
+        Builder {
+            io: self.io,
+            memory,
+            tape: self.tape,
+            origin: self.origin,
+            eof: self.eof,
+            lenient: self.lenient,
+            limits: self.limits,
+        }
+    }
+
+    /// Bytes written to the first cells before the program starts.
+    pub fn tape(mut self, tape: impl Into<Vec<u8>>) -> Self {
+        self.tape = tape.into();
+        self
+    }
+
+    /// Caps the tape at the given number of cells. Moving past the last cell fails with
+    /// `Limit::Cells`.
+    pub fn tape_size(mut self, cells: usize) -> Self {
+        self.limits.cells = Some(cells);
+        self
+    }
+
+    pub fn origin(mut self, origin: usize) -> Self {
+        self.origin = origin;
+        self
+    }
+
+    pub fn eof(mut self, eof: EofMode) -> Self {
+        self.eof = eof;
+        self
+    }
+
+    /// Clamps pointer underflows to cell 0 and reads end of input as 0, recording a warning.
+    pub fn lenient(mut self, lenient: bool) -> Self {
+        self.lenient = lenient;
+        self
+    }
+
+    /// Replaces all limits, including a previously set tape size.
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn build(self) -> Result<Interpreter<I, T>, ExecutionError> {
+        let mut state = State::with_tape(self.io, self.memory);
+        state.write_arguments(&self.tape);
+        state.set_limits(self.limits);
+        state.set_origin(self.origin)?;
+        state.set_eof(self.eof);
+        state.set_lenient(self.lenient);
+        Ok(Interpreter { state })
+    }
+}
+
+impl<I: Io, T: Tape<Cell = u8>> Interpreter<I, T> {
+    /// Runs the program. Errors are `located` when the program has spans.
+    pub fn run(&mut self, program: &Program) -> Result<(), ExecutionError> {
+        self.state
+            .run_ir(program.ir())
+            .map_err(|e| e.located(program.spans()))
+    }
+
+    pub fn state(&self) -> &State<I, T> {
+        &self.state
+    }
+
+    pub fn state_mut(&mut self) -> &mut State<I, T> {
+        &mut self.state
+    }
+
+    pub fn into_state(self) -> State<I, T> {
+        self.state
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        io::Buffer,
+        lexer::{tokenize_with_spans, Aliases},
+        limits::Limit,
+        tape::CircularTape,
+    };
+
+    #[test]
+    fn build_configured_interpreter() {
+        let program = Program::parse("<,.>>").unwrap();
+        let mut interpreter = Interpreter::builder()
+            .io(Buffer::new(""))
+            .tape([1, 2])
+            .origin(1)
+            .eof(EofMode::Unchanged)
+            .tape_size(2)
+            .build()
+            .unwrap();
+
+        match interpreter.run(&program).unwrap_err() {
+            ExecutionError::LimitExceeded(4, Limit::Cells(2)) => (),
+            e => panic!("Unexpected error: {:?}", e),
+        }
+        assert_eq!(interpreter.state().io().output(), &[1]);
+    }
+
+    #[test]
+    fn run_locates_errors_of_spanned_programs() {
+        let tokens = tokenize_with_spans("+\n  ,", &Aliases::new(), None);
+        let program = Program::from_spanned_tokens(tokens).unwrap();
+        let mut interpreter = Interpreter::builder().io(Buffer::new("")).build().unwrap();
+
+        let error = interpreter.run(&program).unwrap_err();
+        assert!(matches!(error.error(), ExecutionError::InputError(1, _)));
+        assert_eq!(error.span().map(|x| (x.line, x.column)), Some((2, 3)));
+    }
+
+    #[test]
+    fn build_with_circular_tape() {
+        let program = Program::parse("<+>>>+.").unwrap();
+        let mut interpreter = Interpreter::builder()
+            .io(Buffer::new(""))
+            .memory(CircularTape::new(3))
+            .build()
+            .unwrap();
+
+        interpreter.run(&program).unwrap();
+
+        assert_eq!(interpreter.state().memory(), vec![0, 0, 2]);
+        assert_eq!(interpreter.state().io().output(), &[2]);
+    }
+}
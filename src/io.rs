@@ -0,0 +1,284 @@
+use std::{
+    collections::VecDeque,
+    io::{stdin, stdout, Error, ErrorKind, Read, Result, Stdin, Stdout, Write},
+    sync::mpsc::{Receiver, Sender},
+};
+
+pub trait Io {
+    /// Reads the next input byte, or `None` at the end of input.
+    fn read_byte(&mut self) -> Result<Option<u8>>;
+    fn write_byte(&mut self, byte: u8) -> Result<()>;
+
+    /// Reads up to `buffer.len()` input bytes at once and returns how many, 0 at the end of
+    /// input. Devices that can read ahead cheaply override the default of one byte per call.
+    fn read_bytes(&mut self, buffer: &mut [u8]) -> Result<usize> {
+        match (buffer.first_mut(), self.read_byte()?) {
+            (Some(x), Some(byte)) => {
+                *x = byte;
+                Ok(1)
+            }
+            _ => Ok(0),
+        }
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<()> {
+        for byte in bytes {
+            self.write_byte(*byte)?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+pub struct Streams<R, W> {
+    input: R,
+    output: W,
+}
+
+pub type Stdio = Streams<Stdin, Stdout>;
+
+#[derive(Debug, Default)]
+pub struct Buffer {
+    input: VecDeque<u8>,
+    output: Vec<u8>,
+}
+
+pub struct Channels {
+    input: Receiver<u8>,
+    output: Sender<u8>,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Null;
+
+/// Has no input and discards output, counting the written bytes.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Counter {
+    pub bytes: u64,
+}
+
+impl<R: Read, W: Write> Streams<R, W> {
+    pub fn new(input: R, output: W) -> Self {
+        Self { input, output }
+    }
+
+    pub fn output(&self) -> &W {
+        &self.output
+    }
+
+    pub fn into_output(self) -> W {
+        self.output
+    }
+}
+
+impl Stdio {
+    pub fn stdio() -> Self {
+        Self::new(stdin(), stdout())
+    }
+}
+
+impl<R: Read, W: Write> Io for Streams<R, W> {
+    fn read_byte(&mut self) -> Result<Option<u8>> {
+        let mut buffer = [0];
+        loop {
+            match self.input.read(&mut buffer) {
+                Ok(0) => return Ok(None),
+                Ok(_) => return Ok(Some(buffer[0])),
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn read_bytes(&mut self, buffer: &mut [u8]) -> Result<usize> {
+        loop {
+            match self.input.read(buffer) {
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                result => return result,
+            }
+        }
+    }
+
+    fn write_byte(&mut self, byte: u8) -> Result<()> {
+        self.output.write_all(&[byte])
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<()> {
+        self.output.write_all(bytes)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.output.flush()
+    }
+}
+
+impl Buffer {
+    pub fn new(input: impl Into<Vec<u8>>) -> Self {
+        Self {
+            input: input.into().into(),
+            output: Vec::new(),
+        }
+    }
+
+    pub fn output(&self) -> &[u8] {
+        &self.output
+    }
+
+    pub fn into_output(self) -> Vec<u8> {
+        self.output
+    }
+}
+
+impl Io for Buffer {
+    fn read_byte(&mut self) -> Result<Option<u8>> {
+        Ok(self.input.pop_front())
+    }
+
+    fn read_bytes(&mut self, buffer: &mut [u8]) -> Result<usize> {
+        let length = buffer.len().min(self.input.len());
+        for (x, byte) in buffer.iter_mut().zip(self.input.drain(..length)) {
+            *x = byte;
+        }
+        Ok(length)
+    }
+
+    fn write_byte(&mut self, byte: u8) -> Result<()> {
+        self.output.push(byte);
+        Ok(())
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<()> {
+        self.output.extend_from_slice(bytes);
+        Ok(())
+    }
+}
+
+impl Channels {
+    pub fn new(input: Receiver<u8>, output: Sender<u8>) -> Self {
+        Self { input, output }
+    }
+}
+
+impl Io for Channels {
+    /// Blocks until a byte arrives. A disconnected sender ends the input.
+    fn read_byte(&mut self) -> Result<Option<u8>> {
+        Ok(self.input.recv().ok())
+    }
+
+    fn write_byte(&mut self, byte: u8) -> Result<()> {
+        self.output
+            .send(byte)
+            .map_err(|_| Error::from(ErrorKind::BrokenPipe))
+    }
+}
+
+impl Io for Null {
+    fn read_byte(&mut self) -> Result<Option<u8>> {
+        Ok(None)
+    }
+
+    fn write_byte(&mut self, _byte: u8) -> Result<()> {
+        Ok(())
+    }
+
+    fn write_bytes(&mut self, _bytes: &[u8]) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl Io for Counter {
+    fn read_byte(&mut self) -> Result<Option<u8>> {
+        Ok(None)
+    }
+
+    fn write_byte(&mut self, _byte: u8) -> Result<()> {
+        self.bytes += 1;
+        Ok(())
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<()> {
+        self.bytes += bytes.len() as u64;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::channel;
+
+    #[test]
+    fn streams_read_until_end_of_input() {
+        let mut io = Streams::new("ab".as_bytes(), Vec::new());
+
+        assert_eq!(io.read_byte().unwrap(), Some(b'a'));
+        assert_eq!(io.read_byte().unwrap(), Some(b'b'));
+        assert_eq!(io.read_byte().unwrap(), None);
+    }
+
+    #[test]
+    fn read_bytes_reads_ahead() {
+        let mut buffer = [0; 2];
+        let mut streams = Streams::new("abc".as_bytes(), Vec::new());
+        let mut io = Buffer::new("xyz");
+
+        assert_eq!(streams.read_bytes(&mut buffer).unwrap(), 2);
+        assert_eq!(streams.read_byte().unwrap(), Some(b'c'));
+        assert_eq!(io.read_bytes(&mut buffer).unwrap(), 2);
+        assert_eq!(&buffer, b"xy");
+        assert_eq!(io.read_bytes(&mut buffer).unwrap(), 1);
+        assert_eq!(Null.read_bytes(&mut buffer).unwrap(), 0);
+    }
+
+    #[test]
+    fn buffer_collects_output() {
+        let mut io = Buffer::new("x");
+
+        io.write_byte(b'a').unwrap();
+        io.write_bytes(b"bc").unwrap();
+
+        assert_eq!(io.read_byte().unwrap(), Some(b'x'));
+        assert_eq!(io.read_byte().unwrap(), None);
+        assert_eq!(io.output(), b"abc");
+    }
+
+    #[test]
+    fn counter_counts_discarded_output() {
+        let mut io = Counter::default();
+
+        io.write_byte(b'a').unwrap();
+        io.write_bytes(b"bc").unwrap();
+
+        assert_eq!(io.read_byte().unwrap(), None);
+        assert_eq!(io.bytes, 3);
+    }
+
+    #[test]
+    fn channels_end_input_on_disconnect() {
+        let (input, receiver) = channel();
+        let (sender, output) = channel();
+        let mut io = Channels::new(receiver, sender);
+
+        input.send(7).unwrap();
+        drop(input);
+        io.write_byte(9).unwrap();
+
+        assert_eq!(io.read_byte().unwrap(), Some(7));
+        assert_eq!(io.read_byte().unwrap(), None);
+        assert_eq!(output.recv().unwrap(), 9);
+    }
+
+    #[test]
+    fn channels_error_on_closed_output() {
+        let (_input, receiver) = channel();
+        let (sender, output) = channel();
+        let mut io = Channels::new(receiver, sender);
+
+        drop(output);
+
+        assert_eq!(io.write_byte(1).unwrap_err().kind(), ErrorKind::BrokenPipe);
+    }
+}
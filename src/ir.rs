@@ -0,0 +1,251 @@
+use std::{ops::Index, sync::OnceLock};
+
+use crate::lexer::{JumpTable, Token};
+
+/// An instruction of the lowered program. An `Add` or `Move` covers one token per unit, so
+/// the runs pass can merge runs of the same `+`, `-`, `>` or `<` token into one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Instruction {
+    Add(i32),
+    Move(isize),
+    /// Stores a value in the current cell.
+    Set(u8),
+    /// The loop `[-]` or `[+]`, which adds the step of -1 or 1 to the current cell until it is
+    /// zero.
+    Clear(i32),
+    Input,
+    Output,
+    Assert,
+    /// Continues after the instruction at the target when the current cell is zero.
+    JumpIfZero(usize),
+    /// Continues after the instruction at the target when the current cell is not zero.
+    JumpIfNonZero(usize),
+}
+
+/// The cells a program runs on, which decides how values wrap around.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum CellKind {
+    Byte,
+    U16,
+    U32,
+    Bignum,
+}
+
+pub struct Ir {
+    instructions: Vec<Instruction>,
+    /// The position of the first token of every instruction, followed by the number of tokens.
+    positions: Vec<usize>,
+    /// The steps of every instruction, see `steps`.
+    steps: Vec<u64>,
+    /// The index of the instruction starting at a position, for positions where exactly one
+    /// does, sorted by position.
+    starts: Vec<(usize, usize)>,
+    /// The lowered tokens, for running the program token by token where instructions cannot
+    /// stop or resume exactly. Their jump table is only built once that happens.
+    tokens: Vec<Token>,
+    jump_table: OnceLock<Option<JumpTable>>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum IrError {
+    UndefinedJumpTarget(usize),
+}
+
+impl CellKind {
+    /// The width of a cell, or `None` for cells that never wrap around.
+    pub fn bits(&self) -> Option<u32> {
+        match self {
+            Self::Byte => Some(8),
+            Self::U16 => Some(16),
+            Self::U32 => Some(32),
+            Self::Bignum => None,
+        }
+    }
+}
+
+impl Ir {
+    /// Lowers every token to one instruction. `optimizer::PassManager` optimizes the result.
+    pub fn lower(tokens: &[Token], jump_table: &JumpTable) -> Result<Self, IrError> {
+        let mut instructions = Vec::with_capacity(tokens.len());
+
+        for (position, token) in tokens.iter().enumerate() {
+            instructions.push(match token {
+                Token::Increment => Instruction::Add(1),
+                Token::Decrement => Instruction::Add(-1),
+                Token::PointerIncrement => Instruction::Move(1),
+                Token::PointerDecrement => Instruction::Move(-1),
+                Token::Input => Instruction::Input,
+                Token::Output => Instruction::Output,
+                Token::Assert => Instruction::Assert,
+                Token::LoopStart | Token::LoopEnd => {
+                    let target = *jump_table
+                        .resolve(&position)
+                        .ok_or(IrError::UndefinedJumpTarget(position))?;
+                    match token {
+                        Token::LoopStart => Instruction::JumpIfZero(target),
+                        _ => Instruction::JumpIfNonZero(target),
+                    }
+                }
+            });
+        }
+        let mut ir = Self {
+            instructions,
+            positions: (0..=tokens.len()).collect(),
+            steps: vec![1; tokens.len()],
+            starts: Vec::new(),
+            tokens: tokens.to_vec(),
+            jump_table: OnceLock::new(),
+        };
+        ir.index_starts();
+        Ok(ir)
+    }
+
+    /// Replaces the instructions, keeping the position of the first token and the steps of
+    /// every new instruction, and points the jumps at their new matching instruction.
+    #[cfg(feature = "optimizer")]
+    pub(crate) fn replace(&mut self, instructions: Vec<(Instruction, usize, u64)>) {
+        let end = self.positions[self.instructions.len()];
+        self.positions = instructions.iter().map(|(_, x, _)| *x).collect();
+        self.positions.push(end);
+        self.steps = instructions.iter().map(|(_, _, x)| *x).collect();
+        self.instructions = instructions.into_iter().map(|(x, _, _)| x).collect();
+        self.index_starts();
+
+        let mut open = Vec::new();
+        for index in 0..self.instructions.len() {
+            match self.instructions[index] {
+                Instruction::JumpIfZero(_) => open.push(index),
+                Instruction::JumpIfNonZero(_) => {
+                    let start = open.pop().unwrap();
+                    self.instructions[start] = Instruction::JumpIfZero(index);
+                    self.instructions[index] = Instruction::JumpIfNonZero(start);
+                }
+                _ => (),
+            }
+        }
+    }
+
+    fn index_starts(&mut self) {
+        let mut starts: Vec<_> = self.positions.iter().copied().zip(0..).collect();
+        starts.sort_by_key(|(position, _)| *position);
+        self.starts = starts
+            .chunk_by(|a, b| a.0 == b.0)
+            .filter(|x| x.len() == 1)
+            .map(|x| x[0])
+            .collect();
+    }
+
+    pub fn len(&self) -> usize {
+        self.instructions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.instructions.is_empty()
+    }
+
+    pub fn instructions(&self) -> &[Instruction] {
+        &self.instructions
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Instruction> {
+        self.instructions.get(index)
+    }
+
+    /// The position of the first token of an instruction, or the number of tokens for the
+    /// index after the last instruction.
+    pub fn position(&self, index: usize) -> usize {
+        self.positions[index]
+    }
+
+    /// The index of the instruction whose first token is at `position`, or the index after
+    /// the last instruction for the number of tokens. `None` when no instruction or more than
+    /// one starts there, e.g. inside a merged run or a loop the optimizer unrolled.
+    pub fn instruction_at(&self, position: usize) -> Option<usize> {
+        self.starts
+            .binary_search_by_key(&position, |(x, _)| *x)
+            .ok()
+            .map(|x| self.starts[x].1)
+    }
+
+    /// The steps running an instruction counts, the same as running its tokens one by one:
+    /// one per token, including the tokens of code an optimization removed after it because
+    /// it would only have run one of them. A `Clear` counts two more per iteration and a jump
+    /// that is taken counts the steps of its target instead.
+    pub fn steps(&self, index: usize) -> u64 {
+        self.steps[index]
+    }
+
+    /// The tokens the instructions were lowered from.
+    pub fn tokens(&self) -> &[Token] {
+        &self.tokens
+    }
+
+    /// The jump table of `tokens`, built the first time it is needed, or `None` if their
+    /// brackets do not match.
+    pub fn jump_table(&self) -> Option<&JumpTable> {
+        self.jump_table
+            .get_or_init(|| JumpTable::from(&self.tokens).ok())
+            .as_ref()
+    }
+}
+
+impl Index<usize> for Ir {
+    type Output = Instruction;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        &self.instructions[index]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::tokenize;
+
+    #[test]
+    fn lower_keeps_one_instruction_per_token() {
+        let tokens = tokenize("+>[-]");
+        let jump_table = JumpTable::from(&tokens).unwrap();
+        let ir = Ir::lower(&tokens, &jump_table).unwrap();
+
+        assert_eq!(
+            ir.instructions,
+            vec![
+                Instruction::Add(1),
+                Instruction::Move(1),
+                Instruction::JumpIfZero(4),
+                Instruction::Add(-1),
+                Instruction::JumpIfNonZero(2),
+            ]
+        );
+        assert_eq!(ir.positions, vec![0, 1, 2, 3, 4, 5]);
+        assert_eq!(ir.instruction_at(3), Some(3));
+        assert_eq!(ir.instruction_at(5), Some(5));
+        assert_eq!(ir.instruction_at(6), None);
+    }
+
+    #[test]
+    fn lower_keeps_adjacent_brackets_apart() {
+        let tokens = tokenize("[[]]");
+        let jump_table = JumpTable::from(&tokens).unwrap();
+        let ir = Ir::lower(&tokens, &jump_table).unwrap();
+
+        assert_eq!(
+            ir.instructions,
+            vec![
+                Instruction::JumpIfZero(3),
+                Instruction::JumpIfZero(2),
+                Instruction::JumpIfNonZero(1),
+                Instruction::JumpIfNonZero(0),
+            ]
+        );
+    }
+
+    #[test]
+    fn lower_errors_on_invalid_jump_table() {
+        let jump_table = JumpTable::from(&[]).unwrap();
+        let ir = Ir::lower(&[Token::LoopStart, Token::LoopEnd], &jump_table);
+
+        assert_eq!(ir.err(), Some(IrError::UndefinedJumpTarget(0)));
+    }
+}
@@ -1,6 +1,6 @@
 use std::collections::HashMap;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
 pub enum Token {
     Increment,
     Decrement,
@@ -10,6 +10,7 @@ pub enum Token {
     LoopEnd,
     Input,
     Output,
+    Assert,
 }
 
 impl Token {
@@ -26,21 +27,373 @@ impl Token {
             _ => None,
         }
     }
+
+    pub fn parse_byte(byte: &u8) -> Option<Self> {
+        match byte.is_ascii() {
+            true => Self::parse(&(*byte as char)),
+            false => None,
+        }
+    }
+
+    pub fn as_char(&self) -> char {
+        match self {
+            Self::Increment => '+',
+            Self::Decrement => '-',
+            Self::PointerIncrement => '>',
+            Self::PointerDecrement => '<',
+            Self::LoopStart => '[',
+            Self::LoopEnd => ']',
+            Self::Input => ',',
+            Self::Output => '.',
+            Self::Assert => '=',
+        }
+    }
+}
+
+pub fn tokenize(source: impl AsRef<[u8]>) -> Vec<Token> {
+    source
+        .as_ref()
+        .iter()
+        .filter_map(Token::parse_byte)
+        .collect()
+}
+
+/// Writes tokens back as source, one character per token, e.g. to print a program without its
+/// comments.
+pub fn detokenize(tokens: &[Token]) -> String {
+    tokens.iter().map(Token::as_char).collect()
+}
+
+/// A token or the text between two tokens, which keeps comments and whitespace.
+#[derive(Debug, PartialEq)]
+pub enum Lexeme {
+    Token(Token),
+    /// Bytes that are not instructions, decoded lossily as UTF-8.
+    Trivia(String),
+}
+
+/// Tokenizes a source without dropping anything, for tools that rewrite the source and keep
+/// its comments. Joining the lexemes gives back the source, apart from invalid UTF-8.
+pub fn tokenize_with_trivia(source: impl AsRef<[u8]>) -> Vec<Lexeme> {
+    let mut lexemes = Vec::new();
+    let mut trivia = Vec::new();
+
+    for byte in source.as_ref() {
+        match Token::parse_byte(byte) {
+            Some(token) => {
+                if !trivia.is_empty() {
+                    lexemes.push(Lexeme::Trivia(
+                        String::from_utf8_lossy(&trivia).into_owned(),
+                    ));
+                    trivia.clear();
+                }
+                lexemes.push(Lexeme::Token(token));
+            }
+            None => trivia.push(*byte),
+        }
+    }
+    if !trivia.is_empty() {
+        lexemes.push(Lexeme::Trivia(
+            String::from_utf8_lossy(&trivia).into_owned(),
+        ));
+    }
+
+    lexemes
+}
+
+pub fn tokenize_with_assertions(source: impl AsRef<[u8]>) -> Vec<Token> {
+    source
+        .as_ref()
+        .iter()
+        .filter_map(|x| match x {
+            b'=' => Some(Token::Assert),
+            _ => Token::parse_byte(x),
+        })
+        .collect()
+}
+
+/// Where a token is in its source. Lines and columns start at 1 and columns count bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub offset: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+/// Tokenizes a source like `tokenize`, `tokenize_with_aliases` or, given a count syntax,
+/// `tokenize_with_counts`, keeping where every token is. Every token expanded from a count
+/// points at its instruction. Offsets are into the source as `token_offsets_with_aliases`
+/// counts them, which differs from the bytes only after invalid UTF-8.
+pub fn tokenize_with_spans(
+    source: impl AsRef<[u8]>,
+    aliases: &Aliases,
+    counts: Option<CountSyntax>,
+) -> Vec<(Token, Span)> {
+    let source = source.as_ref();
+    let tokens = match (counts, aliases.is_empty()) {
+        (None, true) => source
+            .iter()
+            .enumerate()
+            .filter_map(|(offset, x)| Some((offset, Token::parse_byte(x)?)))
+            .collect(),
+        (Some(syntax), _) => expand_counts(source, syntax, aliases),
+        (None, false) => resolve_aliases(source, aliases),
+    };
+
+    let mut spanned = Vec::with_capacity(tokens.len());
+    let mut line = 1;
+    let mut line_start = 0;
+    let mut scanned = 0;
+    for (offset, token) in tokens {
+        for (index, byte) in source[scanned..offset].iter().enumerate() {
+            if *byte == b'\n' {
+                line += 1;
+                line_start = scanned + index + 1;
+            }
+        }
+        scanned = offset;
+        let span = Span {
+            offset,
+            line,
+            column: offset - line_start + 1,
+        };
+        spanned.push((token, span));
+    }
+
+    spanned
 }
 
+/// The characters of a source with their byte offsets, reading it as UTF-8 like
+/// `String::from_utf8_lossy` does, but with offsets into the original bytes.
+fn char_offsets(source: &[u8]) -> impl Iterator<Item = (usize, char)> + '_ {
+    source
+        .utf8_chunks()
+        .scan(0, |start, chunk| {
+            let offset = *start;
+            *start += chunk.valid().len() + chunk.invalid().len();
+            let replacement = (!chunk.invalid().is_empty())
+                .then_some((offset + chunk.valid().len(), char::REPLACEMENT_CHARACTER));
+            Some(
+                chunk
+                    .valid()
+                    .char_indices()
+                    .map(move |(index, x)| (offset + index, x))
+                    .chain(replacement),
+            )
+        })
+        .flatten()
+}
+
+pub fn token_offsets(source: impl AsRef<[u8]>) -> Vec<usize> {
+    source
+        .as_ref()
+        .iter()
+        .enumerate()
+        .filter(|(_, x)| Token::parse_byte(x).is_some())
+        .map(|(offset, _)| offset)
+        .collect()
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Aliases {
+    tokens: HashMap<char, Token>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum AliasError {
+    InvalidDefinition(String),
+    UnknownInstruction(char),
+}
+
+impl Aliases {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parses one alias per line as the alias character followed by the instruction it stands
+    /// for, e.g. `→ >`. Empty lines and lines starting with `#` are skipped.
+    pub fn parse(text: &str) -> Result<Self, AliasError> {
+        let mut aliases = Self::new();
+        for line in text.lines().map(str::trim) {
+            if !line.is_empty() && !line.starts_with('#') {
+                aliases.define(line)?;
+            }
+        }
+        Ok(aliases)
+    }
+
+    pub fn define(&mut self, definition: &str) -> Result<(), AliasError> {
+        let characters: Vec<_> = definition.chars().filter(|x| !x.is_whitespace()).collect();
+        let (alias, instruction) = match characters[..] {
+            [alias, instruction] => (alias, instruction),
+            _ => return Err(AliasError::InvalidDefinition(definition.to_string())),
+        };
+        let token = match instruction {
+            '=' => Token::Assert,
+            _ => Token::parse(&instruction).ok_or(AliasError::UnknownInstruction(instruction))?,
+        };
+        self.insert(alias, token);
+        Ok(())
+    }
+
+    pub fn insert(&mut self, alias: char, token: Token) {
+        self.tokens.insert(alias, token);
+    }
+
+    pub fn extend(&mut self, other: Aliases) {
+        self.tokens.extend(other.tokens);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+
+    pub fn resolve(&self, character: &char) -> Option<Token> {
+        self.tokens
+            .get(character)
+            .copied()
+            .or_else(|| Token::parse(character))
+    }
+}
+
+pub fn tokenize_with_aliases(source: impl AsRef<[u8]>, aliases: &Aliases) -> Vec<Token> {
+    resolve_aliases(source.as_ref(), aliases)
+        .into_iter()
+        .map(|(_, token)| token)
+        .collect()
+}
+
+pub fn token_offsets_with_aliases(source: impl AsRef<[u8]>, aliases: &Aliases) -> Vec<usize> {
+    resolve_aliases(source.as_ref(), aliases)
+        .into_iter()
+        .map(|(offset, _)| offset)
+        .collect()
+}
+
+fn resolve_aliases(source: &[u8], aliases: &Aliases) -> Vec<(usize, Token)> {
+    char_offsets(source)
+        .filter_map(|(offset, x)| Some((offset, aliases.resolve(&x)?)))
+        .collect()
+}
+
+/// How repeat counts are written in the numbers shorthand, `5+` or `+5`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CountSyntax {
+    Prefix,
+    Postfix,
+}
+
+/// Tokenizes a source in the numbers shorthand, in which a count next to `+`, `-`, `>` or `<`
+/// repeats the instruction, e.g. `12>` with `CountSyntax::Prefix`. Digits anywhere else are
+/// comments.
+pub fn tokenize_with_counts(
+    source: impl AsRef<[u8]>,
+    syntax: CountSyntax,
+    aliases: &Aliases,
+) -> Vec<Token> {
+    expand_counts(source.as_ref(), syntax, aliases)
+        .into_iter()
+        .map(|(_, token)| token)
+        .collect()
+}
+
+/// The offset of the instruction every token was expanded from.
+pub fn token_offsets_with_counts(
+    source: impl AsRef<[u8]>,
+    syntax: CountSyntax,
+    aliases: &Aliases,
+) -> Vec<usize> {
+    expand_counts(source.as_ref(), syntax, aliases)
+        .into_iter()
+        .map(|(offset, _)| offset)
+        .collect()
+}
+
+fn expand_counts(source: &[u8], syntax: CountSyntax, aliases: &Aliases) -> Vec<(usize, Token)> {
+    let mut tokens = Vec::new();
+    // The digits read so far, and for postfix counts the instruction they follow.
+    let mut count: Option<usize> = None;
+    let mut repeated: Option<(usize, Token)> = None;
+
+    for (offset, character) in char_offsets(source) {
+        let token = aliases.resolve(&character);
+        if let (None, Some(digit)) = (token, character.to_digit(10)) {
+            count = Some(
+                count
+                    .unwrap_or(0)
+                    .saturating_mul(10)
+                    .saturating_add(digit as usize),
+            );
+            continue;
+        }
+
+        if let (CountSyntax::Postfix, Some((offset, token))) = (syntax, repeated.take()) {
+            let times = count.unwrap_or(1);
+            tokens.extend(std::iter::repeat_n((offset, token), times));
+        }
+        let repeatable = matches!(
+            token,
+            Some(
+                Token::Increment
+                    | Token::Decrement
+                    | Token::PointerIncrement
+                    | Token::PointerDecrement
+            )
+        );
+        match (token, syntax) {
+            (Some(token), CountSyntax::Prefix) if repeatable => {
+                let times = count.unwrap_or(1);
+                tokens.extend(std::iter::repeat_n((offset, token), times));
+            }
+            (Some(token), CountSyntax::Postfix) if repeatable => repeated = Some((offset, token)),
+            (Some(token), _) => tokens.push((offset, token)),
+            (None, _) => (),
+        }
+        count = None;
+    }
+    if let Some((offset, token)) = repeated {
+        tokens.extend(std::iter::repeat_n((offset, token), count.unwrap_or(1)));
+    }
+
+    tokens
+}
+
+/// The matching bracket of every loop bracket, stored by instruction position so that a jump is
+/// a single array load.
+///
+/// The table takes one `usize` per instruction, brackets or not, so 8 bytes per instruction on
+/// 64-bit targets however many loops a program has, and building it needs one more `usize` per
+/// open loop at the deepest nesting. A generated program of 100 million instructions needs
+/// 800 MB, where a map with an entry per bracket would need about 48 bytes per bracket.
 pub struct JumpTable {
-    jumps: HashMap<usize, usize>,
+    jumps: Vec<usize>,
 }
 
+/// Marks positions without a jump target.
+const NO_JUMP: usize = usize::MAX;
+
 #[derive(Debug, PartialEq)]
 pub enum JumpTableError {
+    /// How many `[` are never closed.
     TooManyLoopStarts(usize),
+    /// A `]` that closes no loop, by position.
     NoMatchingLoopEnd(usize),
 }
 
+impl JumpTableError {
+    /// The position of the bracket without a partner in the tokens the table was built from,
+    /// the first `[` that is never closed for `TooManyLoopStarts`.
+    pub fn position(&self, tokens: &[Token]) -> Option<usize> {
+        match self {
+            Self::TooManyLoopStarts(_) => unmatched_brackets(tokens).first().copied(),
+            Self::NoMatchingLoopEnd(x) => Some(*x),
+        }
+    }
+}
+
 impl JumpTable {
     pub fn from(tokens: &[Token]) -> Result<JumpTable, JumpTableError> {
-        let mut jumps = HashMap::new();
+        let mut jumps = vec![NO_JUMP; tokens.len()];
         let mut start_loop_stack = Vec::new();
 
         for (position, token) in tokens.iter().enumerate() {
@@ -51,8 +404,8 @@ impl JumpTable {
                         Some(x) => x,
                         None => return Err(JumpTableError::NoMatchingLoopEnd(position)),
                     };
-                    jumps.insert(start, position);
-                    jumps.insert(position, start);
+                    jumps[start] = position;
+                    jumps[position] = start;
                 }
                 _ => (),
             }
@@ -64,8 +417,72 @@ impl JumpTable {
         }
     }
 
+    pub fn from_pairs(pairs: impl IntoIterator<Item = (usize, usize)>) -> JumpTable {
+        let mut jumps = Vec::new();
+        for (from, to) in pairs {
+            if from >= jumps.len() {
+                jumps.resize(from + 1, NO_JUMP);
+            }
+            jumps[from] = to;
+        }
+        Self { jumps }
+    }
+
     pub fn resolve(&self, position: &usize) -> Option<&usize> {
-        self.jumps.get(position)
+        self.jumps.get(*position).filter(|x| **x != NO_JUMP)
+    }
+}
+
+/// The positions of all brackets without a partner, in order. `JumpTable::from` stops at the
+/// first problem, this finds every one for diagnostics.
+pub fn unmatched_brackets(tokens: &[Token]) -> Vec<usize> {
+    let mut unmatched = Vec::new();
+    let mut open = Vec::new();
+
+    for (position, token) in tokens.iter().enumerate() {
+        match token {
+            Token::LoopStart => open.push(position),
+            Token::LoopEnd if open.pop().is_none() => unmatched.push(position),
+            _ => (),
+        }
+    }
+    unmatched.extend(open);
+    unmatched.sort_unstable();
+    unmatched
+}
+
+pub fn matching_bracket(source: &str, byte_offset: usize) -> Option<usize> {
+    let bytes = source.as_bytes();
+    let mut depth = 0usize;
+
+    match bytes.get(byte_offset)? {
+        b'[' => {
+            for (position, byte) in bytes.iter().enumerate().skip(byte_offset) {
+                match byte {
+                    b'[' => depth += 1,
+                    b']' => depth -= 1,
+                    _ => continue,
+                }
+                if depth == 0 {
+                    return Some(position);
+                }
+            }
+            None
+        }
+        b']' => {
+            for (position, byte) in bytes.iter().enumerate().take(byte_offset + 1).rev() {
+                match byte {
+                    b']' => depth += 1,
+                    b'[' => depth -= 1,
+                    _ => continue,
+                }
+                if depth == 0 {
+                    return Some(position);
+                }
+            }
+            None
+        }
+        _ => None,
     }
 }
 
@@ -94,6 +511,171 @@ mod tests {
         assert_eq!(tokens, expected);
     }
 
+    #[test]
+    fn token_as_char_round_trips() {
+        for character in ['+', '-', '>', '<', '[', ']', ',', '.'] {
+            assert_eq!(Token::parse(&character).unwrap().as_char(), character);
+        }
+    }
+
+    #[test]
+    fn tokenize_skips_comments() {
+        let tokens = tokenize("+ a [-]");
+
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Increment,
+                Token::LoopStart,
+                Token::Decrement,
+                Token::LoopEnd
+            ]
+        );
+    }
+
+    #[test]
+    fn detokenize_drops_comments() {
+        let source = "Print a:\n  ++++++++[>++++++++<-]>+ .  # done\n";
+
+        assert_eq!(detokenize(&tokenize(source)), "++++++++[>++++++++<-]>+.");
+    }
+
+    #[test]
+    fn tokenize_with_trivia_keeps_comments() {
+        let lexemes = tokenize_with_trivia("a+ b\n-");
+
+        assert_eq!(
+            lexemes,
+            vec![
+                Lexeme::Trivia("a".to_string()),
+                Lexeme::Token(Token::Increment),
+                Lexeme::Trivia(" b\n".to_string()),
+                Lexeme::Token(Token::Decrement),
+            ]
+        );
+    }
+
+    #[test]
+    fn tokenize_with_spans_points_into_source() {
+        let source = "+ a\n  [\n]";
+        let tokens = tokenize_with_spans(source, &Aliases::new(), None);
+
+        assert_eq!(
+            tokens.iter().map(|(x, _)| *x).collect::<Vec<_>>(),
+            tokenize(source)
+        );
+        assert_eq!(
+            tokens[1].1,
+            Span {
+                offset: 6,
+                line: 2,
+                column: 3
+            }
+        );
+    }
+
+    #[test]
+    fn offsets_count_bytes_of_sources_that_are_not_utf8() {
+        let aliases = Aliases::parse("i +").unwrap();
+        let source = b"\xe9\xe9i\xff+";
+
+        assert_eq!(token_offsets(source), [4]);
+        assert_eq!(token_offsets_with_aliases(source, &aliases), [2, 4]);
+        assert_eq!(
+            token_offsets_with_counts(source, CountSyntax::Prefix, &aliases),
+            [2, 4]
+        );
+    }
+
+    #[test]
+    fn tokenize_with_spans_resolves_aliases_and_counts() {
+        let aliases = Aliases::parse("i +").unwrap();
+        let source = "i\n 2>[";
+
+        assert_eq!(
+            tokenize_with_spans(source, &aliases, Some(CountSyntax::Prefix)),
+            vec![
+                (
+                    Token::Increment,
+                    Span {
+                        offset: 0,
+                        line: 1,
+                        column: 1
+                    }
+                ),
+                (
+                    Token::PointerIncrement,
+                    Span {
+                        offset: 4,
+                        line: 2,
+                        column: 3
+                    }
+                ),
+                (
+                    Token::PointerIncrement,
+                    Span {
+                        offset: 4,
+                        line: 2,
+                        column: 3
+                    }
+                ),
+                (
+                    Token::LoopStart,
+                    Span {
+                        offset: 5,
+                        line: 2,
+                        column: 4
+                    }
+                ),
+            ]
+        );
+        assert_eq!(
+            tokenize_with_spans("ä\ni", &aliases, None)
+                .into_iter()
+                .map(|(_, x)| x)
+                .collect::<Vec<_>>(),
+            vec![Span {
+                offset: 3,
+                line: 2,
+                column: 1
+            }]
+        );
+    }
+
+    #[test]
+    fn jump_table_errors_point_at_the_bracket() {
+        let tokens = tokenize("+[[]][");
+        let error = JumpTable::from(&tokens[..4]).err().unwrap();
+
+        assert_eq!(error, JumpTableError::TooManyLoopStarts(1));
+        assert_eq!(error.position(&tokens[..4]), Some(1));
+        assert_eq!(
+            JumpTableError::NoMatchingLoopEnd(4).position(&tokens),
+            Some(4)
+        );
+    }
+
+    #[test]
+    fn tokenize_ignores_non_utf8_bytes() {
+        let tokens = tokenize(b"+\xe9\xff-");
+
+        assert_eq!(tokens, vec![Token::Increment, Token::Decrement]);
+    }
+
+    #[test]
+    fn tokenize_assertions_only_when_enabled() {
+        assert_eq!(tokenize("+="), vec![Token::Increment]);
+        assert_eq!(
+            tokenize_with_assertions("+="),
+            vec![Token::Increment, Token::Assert]
+        );
+    }
+
+    #[test]
+    fn token_offsets_point_into_source() {
+        assert_eq!(token_offsets("+ ä[-]"), vec![0, 4, 5, 6]);
+    }
+
     #[test]
     fn create_jump_table_more_end_loops() {
         let tokens = [Token::LoopEnd];
@@ -136,5 +718,104 @@ mod tests {
         assert_eq!(table.resolve(&4), Some(&2));
         assert_eq!(table.resolve(&5), None);
         assert_eq!(table.resolve(&6), Some(&0));
+        assert_eq!(table.resolve(&7), None);
+    }
+
+    #[test]
+    fn jump_table_from_pairs() {
+        let table = JumpTable::from_pairs([(3, 1), (1, 3)]);
+
+        assert_eq!(table.resolve(&1), Some(&3));
+        assert_eq!(table.resolve(&3), Some(&1));
+        assert_eq!(table.resolve(&2), None);
+        assert_eq!(table.resolve(&4), None);
+    }
+
+    #[test]
+    fn unmatched_brackets_lists_every_bracket() {
+        assert_eq!(unmatched_brackets(&tokenize("][[]+[")), vec![0, 1, 5]);
+        assert!(unmatched_brackets(&tokenize("[[]]")).is_empty());
+    }
+
+    #[test]
+    fn matching_bracket_in_raw_source() {
+        let source = "a[b[c]d]e";
+
+        assert_eq!(matching_bracket(source, 1), Some(7));
+        assert_eq!(matching_bracket(source, 3), Some(5));
+        assert_eq!(matching_bracket(source, 5), Some(3));
+        assert_eq!(matching_bracket(source, 7), Some(1));
+        assert_eq!(matching_bracket(source, 0), None);
+        assert_eq!(matching_bracket(source, 100), None);
+    }
+
+    #[test]
+    fn matching_bracket_unbalanced() {
+        assert_eq!(matching_bracket("[[]", 0), None);
+        assert_eq!(matching_bracket("[]]", 2), None);
+    }
+
+    #[test]
+    fn tokenize_unicode_aliases() {
+        let aliases = Aliases::parse("# arrows\n→ >\n← <\n\n").unwrap();
+
+        assert_eq!(
+            token_offsets_with_aliases("+→+←.", &aliases),
+            vec![0, 1, 4, 5, 8]
+        );
+        assert_eq!(
+            tokenize_with_aliases("+→+←.", &aliases),
+            vec![
+                Token::Increment,
+                Token::PointerIncrement,
+                Token::Increment,
+                Token::PointerDecrement,
+                Token::Output
+            ]
+        );
+    }
+
+    #[test]
+    fn alias_definitions_are_validated() {
+        let mut aliases = Aliases::new();
+
+        assert_eq!(aliases.define("a="), Ok(()));
+        assert_eq!(aliases.resolve(&'a'), Some(Token::Assert));
+        assert_eq!(
+            aliases.define("ab"),
+            Err(AliasError::UnknownInstruction('b'))
+        );
+        assert_eq!(
+            aliases.define("abc"),
+            Err(AliasError::InvalidDefinition("abc".to_string()))
+        );
+    }
+
+    #[test]
+    fn tokenize_prefix_counts() {
+        let aliases = Aliases::new();
+
+        assert_eq!(
+            tokenize_with_counts("3+ 2>0-.5.[12<]", CountSyntax::Prefix, &aliases),
+            tokenize("+++>>..[<<<<<<<<<<<<]")
+        );
+        assert_eq!(
+            token_offsets_with_counts("2+ 10.", CountSyntax::Prefix, &aliases),
+            vec![1, 1, 5]
+        );
+    }
+
+    #[test]
+    fn tokenize_postfix_counts() {
+        let aliases = Aliases::parse("→ >").unwrap();
+
+        assert_eq!(
+            tokenize_with_counts("+3 →2-0.[<12]-", CountSyntax::Postfix, &aliases),
+            tokenize("+++>>.[<<<<<<<<<<<<]-")
+        );
+        assert_eq!(
+            token_offsets_with_counts("+2 .10", CountSyntax::Postfix, &aliases),
+            vec![0, 0, 3]
+        );
     }
 }
@@ -1,2 +1,179 @@
+#[cfg(feature = "lexer")]
+pub mod analysis;
+#[cfg(feature = "runtime")]
+pub mod annotate;
+pub mod argv;
+#[cfg(feature = "runtime")]
+pub mod bench;
+#[cfg(feature = "bignum")]
+pub mod bignum;
+#[cfg(feature = "lexer")]
+pub mod block;
+#[cfg(feature = "runtime")]
+pub mod cache;
+#[cfg(feature = "runtime")]
+pub mod cell;
+#[cfg(feature = "runtime")]
+pub mod codegen;
+#[cfg(feature = "runtime")]
+pub mod config;
+#[cfg(feature = "runtime")]
+pub mod conformance;
+#[cfg(feature = "runtime")]
+pub mod control;
+#[cfg(feature = "runtime")]
+pub mod coredump;
+#[cfg(feature = "runtime")]
+pub mod cost;
+#[cfg(feature = "runtime")]
+pub mod embed;
+pub mod encoding;
+#[cfg(feature = "lexer")]
+pub mod extract;
+#[cfg(feature = "lexer")]
+pub mod format;
+#[cfg(feature = "runtime")]
+pub mod instrument;
+#[cfg(feature = "runtime")]
+pub mod interpreter;
+#[cfg(feature = "runtime")]
+pub mod io;
+#[cfg(feature = "lexer")]
+pub mod ir;
+#[cfg(feature = "lexer")]
 pub mod lexer;
+#[cfg(feature = "runtime")]
+pub mod limits;
+#[cfg(feature = "lexer")]
+pub mod lint;
+#[cfg(feature = "optimizer")]
+pub mod optimizer;
+#[cfg(feature = "runtime")]
+pub mod profile;
+#[cfg(feature = "runtime")]
+pub mod program;
+#[cfg(feature = "lexer")]
+pub mod reduce;
+#[cfg(feature = "runtime")]
+pub mod report;
+#[cfg(feature = "runtime")]
+pub mod request;
+#[cfg(feature = "optimizer")]
+pub mod rewrite;
+#[cfg(feature = "runtime")]
 pub mod runtime;
+pub mod schema;
+#[cfg(feature = "lexer")]
+pub mod slice;
+pub mod source;
+#[cfg(feature = "runtime")]
+pub mod stats;
+#[cfg(feature = "runtime")]
+pub mod tape;
+#[cfg(feature = "runtime")]
+pub mod trace;
+#[cfg(feature = "lexer")]
+pub mod visit;
+#[cfg(feature = "runtime")]
+pub mod watchdog;
+
+#[cfg(feature = "runtime")]
+use std::time::Duration;
+
+#[cfg(feature = "runtime")]
+use crate::{
+    io::Buffer,
+    limits::Limits,
+    program::{Program, ProgramError},
+    runtime::{ExecutionError, State},
+};
+
+#[cfg(feature = "runtime")]
+#[derive(Debug)]
+pub enum InterpreterError {
+    Program(ProgramError),
+    Execution(ExecutionError),
+    /// Unbounded cells were requested from a build without the `bignum` feature.
+    BignumUnavailable,
+    /// A wrapping tape without cells was requested.
+    EmptyTape,
+}
+
+/// The output a program wrote before it stopped with an error.
+#[cfg(feature = "runtime")]
+#[derive(Debug)]
+pub struct PartialOutput {
+    pub output: Vec<u8>,
+    pub error: InterpreterError,
+}
+
+/// Runs a program to completion on the given input and returns its output.
+#[cfg(feature = "runtime")]
+pub fn run(source: &str, input: &[u8]) -> Result<Vec<u8>, InterpreterError> {
+    let program = Program::parse(source).map_err(InterpreterError::Program)?;
+    let mut state = State::with_device(Buffer::new(input));
+
+    state
+        .run_ir(program.ir())
+        .map_err(InterpreterError::Execution)?;
+
+    Ok(state.into_io().into_output())
+}
+
+/// Runs a program like `run`, but stops it with `Limit::Time` once it runs longer than
+/// `timeout`. On errors the output written so far is returned as well.
+#[cfg(feature = "runtime")]
+pub fn run_with_timeout(
+    source: &str,
+    input: &[u8],
+    timeout: Duration,
+) -> Result<Vec<u8>, PartialOutput> {
+    let program = Program::parse(source).map_err(|e| PartialOutput {
+        output: Vec::new(),
+        error: InterpreterError::Program(e),
+    })?;
+    let mut state = State::with_device(Buffer::new(input));
+    state.set_limits(Limits::default().with_time(timeout));
+
+    let result = state.run_ir(program.ir());
+    let output = state.into_io().into_output();
+    match result {
+        Ok(()) => Ok(output),
+        Err(e) => Err(PartialOutput {
+            output,
+            error: InterpreterError::Execution(e),
+        }),
+    }
+}
+
+#[cfg(all(test, feature = "runtime"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn run_returns_output() {
+        assert_eq!(run(",[.,]", b"abc\0").unwrap(), b"abc");
+        assert!(matches!(run("[", b""), Err(InterpreterError::Program(_))));
+        assert!(matches!(
+            run(",", b""),
+            Err(InterpreterError::Execution(ExecutionError::InputError(
+                0,
+                _
+            )))
+        ));
+    }
+
+    #[test]
+    fn run_with_timeout_returns_partial_output() {
+        let timeout = Duration::from_millis(20);
+
+        assert_eq!(run_with_timeout(",.", b"a", timeout).unwrap(), b"a");
+
+        let partial = run_with_timeout("+.[]", b"", timeout).unwrap_err();
+        assert_eq!(partial.output, [1]);
+        assert!(matches!(
+            partial.error,
+            InterpreterError::Execution(ExecutionError::LimitExceeded(_, limits::Limit::Time(_)))
+        ));
+    }
+}
@@ -0,0 +1,64 @@
+use std::{fmt, time::Duration};
+
+/// Resource caps for a single run. `None` leaves a resource unlimited.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Limits {
+    /// Number of executed instructions.
+    pub steps: Option<u64>,
+    /// Number of cells the memory pointer can reach, counted from cell 0.
+    pub cells: Option<usize>,
+    /// Number of bytes written as output.
+    pub output: Option<u64>,
+    /// Wall-clock time, counted from when the limits are set.
+    pub time: Option<Duration>,
+}
+
+/// The limit that stopped a run, with its configured value.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Limit {
+    Steps(u64),
+    Cells(usize),
+    Output(u64),
+    Time(Duration),
+}
+
+impl Limits {
+    pub fn with_steps(self, steps: u64) -> Self {
+        Self {
+            steps: Some(steps),
+            ..self
+        }
+    }
+
+    pub fn with_cells(self, cells: usize) -> Self {
+        Self {
+            cells: Some(cells),
+            ..self
+        }
+    }
+
+    pub fn with_output(self, bytes: u64) -> Self {
+        Self {
+            output: Some(bytes),
+            ..self
+        }
+    }
+
+    pub fn with_time(self, time: Duration) -> Self {
+        Self {
+            time: Some(time),
+            ..self
+        }
+    }
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Steps(x) => write!(f, "step limit of {} instructions", x),
+            Self::Cells(x) => write!(f, "tape limit of {} cells", x),
+            Self::Output(x) => write!(f, "output limit of {} bytes", x),
+            Self::Time(x) => write!(f, "time limit of {:?}", x),
+        }
+    }
+}
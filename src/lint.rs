@@ -0,0 +1,283 @@
+use std::{collections::HashSet, fmt};
+
+use crate::{
+    analysis::{balance, invariant_clears, loop_entry_values, Balance},
+    lexer::{JumpTable, Token},
+};
+
+/// A suspicious pattern in a program, by the positions of its instructions.
+#[derive(Debug, PartialEq)]
+pub enum Lint {
+    /// Two instructions that undo each other, e.g. `+-` or `><`, at the position of the first.
+    Cancelling(usize, Token, Token),
+    /// A loop `[]` that can be entered with a nonzero cell and then never ends.
+    EmptyLoop(usize),
+    /// A loop right after `[-]` or `[+]`, which leaves the cell zero, so the loop never runs.
+    LoopAfterClear(usize),
+    /// Instructions after a loop that is entered with a nonzero cell it never changes, from
+    /// the first one after the loop.
+    Unreachable { loop_start: usize, position: usize },
+    /// A clear loop `[-]` or `[+]` inside a loop, of a cell that the loop changes nowhere else,
+    /// so clearing it once before the loop would do.
+    InvariantClear { loop_start: usize, position: usize },
+    /// The first `.` or `,` of a loop nested in at least `HOT_LOOP_DEPTH` loops, where every
+    /// byte costs a call into the I/O device.
+    IoInHotLoop {
+        position: usize,
+        token: Token,
+        depth: usize,
+    },
+}
+
+/// The number of loops around an instruction from which on it counts as hot.
+pub const HOT_LOOP_DEPTH: usize = 3;
+
+impl Lint {
+    /// A name for the kind of lint, for scripts.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Cancelling(..) => "cancelling",
+            Self::EmptyLoop(_) => "empty-loop",
+            Self::LoopAfterClear(_) => "loop-after-clear",
+            Self::Unreachable { .. } => "unreachable",
+            Self::InvariantClear { .. } => "invariant-clear",
+            Self::IoInHotLoop { .. } => "io-in-hot-loop",
+        }
+    }
+
+    /// The position of the instruction the lint points at.
+    pub fn position(&self) -> usize {
+        match self {
+            Self::Cancelling(x, _, _) | Self::EmptyLoop(x) | Self::LoopAfterClear(x) => *x,
+            Self::Unreachable { position, .. }
+            | Self::InvariantClear { position, .. }
+            | Self::IoInHotLoop { position, .. } => *position,
+        }
+    }
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Cancelling(_, a, b) => {
+                write!(f, "`{}{}` cancel each other out", a.as_char(), b.as_char())
+            }
+            Self::EmptyLoop(_) => write!(f, "empty loop never ends if the cell is not zero"),
+            Self::LoopAfterClear(_) => write!(f, "loop right after a clear loop never runs"),
+            Self::Unreachable { loop_start, .. } => write!(
+                f,
+                "unreachable, the loop at instruction {} never ends",
+                loop_start
+            ),
+            Self::InvariantClear { loop_start, .. } => write!(
+                f,
+                "clears the same cell on every iteration of the loop at instruction {}, \
+                 consider clearing it once before the loop",
+                loop_start
+            ),
+            Self::IoInHotLoop {
+                token: Token::Input,
+                depth,
+                ..
+            } => write!(
+                f,
+                "input inside {} nested loops, consider reading it into cells before the loops",
+                depth
+            ),
+            Self::IoInHotLoop { token, depth, .. } => write!(
+                f,
+                "`{}` inside {} nested loops, consider collecting the bytes in cells and \
+                 writing them after the loops",
+                token.as_char(),
+                depth
+            ),
+        }
+    }
+}
+
+/// Looks for suspicious patterns in a program on a zeroed tape, ordered by position.
+pub fn lint(tokens: &[Token], jump_table: &JumpTable) -> Vec<Lint> {
+    let mut lints = Vec::new();
+
+    let mut position = 0;
+    while position + 1 < tokens.len() {
+        match (tokens[position], tokens[position + 1]) {
+            (a @ Token::Increment, b @ Token::Decrement)
+            | (a @ Token::Decrement, b @ Token::Increment)
+            | (a @ Token::PointerIncrement, b @ Token::PointerDecrement)
+            | (a @ Token::PointerDecrement, b @ Token::PointerIncrement) => {
+                lints.push(Lint::Cancelling(position, a, b));
+                position += 2;
+            }
+            _ => position += 1,
+        }
+    }
+
+    for (start, token) in tokens.iter().enumerate() {
+        if *token == Token::LoopStart
+            && start >= 3
+            && matches!(
+                tokens[start - 3..start],
+                [
+                    Token::LoopStart,
+                    Token::Increment | Token::Decrement,
+                    Token::LoopEnd
+                ]
+            )
+        {
+            lints.push(Lint::LoopAfterClear(start));
+        }
+    }
+
+    for (start, value) in loop_entry_values(tokens, jump_table, true) {
+        let end = match (value, jump_table.resolve(&start)) {
+            (Some(0), _) | (_, None) => continue,
+            (_, Some(x)) => *x,
+        };
+        if end == start + 1 {
+            lints.push(Lint::EmptyLoop(start));
+        }
+        let endless = match balance(&tokens[start + 1..end]) {
+            Balance::Balanced {
+                deltas,
+                contains_io,
+            } => !contains_io && !deltas.contains_key(&0),
+            _ => false,
+        };
+        // Nothing after the loop runs, up to the end of the loop around it.
+        let scope_end = enclosing_end(tokens, jump_table, start);
+        if value.is_some() && endless && end + 1 < scope_end {
+            lints.push(Lint::Unreachable {
+                loop_start: start,
+                position: end + 1,
+            });
+        }
+    }
+
+    for clear in invariant_clears(tokens, jump_table) {
+        lints.push(Lint::InvariantClear {
+            loop_start: clear.loop_start,
+            position: clear.position,
+        });
+    }
+
+    // Only the first I/O instruction of every hot loop, the others share its advice.
+    let mut open = Vec::new();
+    let mut reported = HashSet::new();
+    for (position, token) in tokens.iter().enumerate() {
+        match token {
+            Token::LoopStart => open.push(position),
+            Token::LoopEnd => {
+                open.pop();
+            }
+            Token::Input | Token::Output
+                if open.len() >= HOT_LOOP_DEPTH && reported.insert(open[open.len() - 1]) =>
+            {
+                lints.push(Lint::IoInHotLoop {
+                    position,
+                    token: *token,
+                    depth: open.len(),
+                });
+            }
+            _ => (),
+        }
+    }
+
+    lints.sort_by_key(Lint::position);
+    lints
+}
+
+/// The position of the `]` of the innermost loop around `position`, or the end of the program.
+fn enclosing_end(tokens: &[Token], jump_table: &JumpTable, position: usize) -> usize {
+    let mut depth = 0;
+    for (index, token) in tokens.iter().enumerate().take(position).rev() {
+        match token {
+            Token::LoopEnd => depth += 1,
+            Token::LoopStart if depth == 0 => {
+                return jump_table.resolve(&index).copied().unwrap_or(tokens.len())
+            }
+            Token::LoopStart => depth -= 1,
+            _ => (),
+        }
+    }
+    tokens.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::tokenize;
+
+    fn linted(source: &str) -> Vec<Lint> {
+        let tokens = tokenize(source);
+        let jump_table = JumpTable::from(&tokens).unwrap();
+        lint(&tokens, &jump_table)
+    }
+
+    #[test]
+    fn lint_finds_cancelling_instructions_and_dead_loops() {
+        assert_eq!(
+            linted("++-><[-][.]"),
+            vec![
+                Lint::Cancelling(1, Token::Increment, Token::Decrement),
+                Lint::Cancelling(3, Token::PointerIncrement, Token::PointerDecrement),
+                Lint::LoopAfterClear(8),
+            ]
+        );
+        assert!(linted("+[->+<]>.").is_empty());
+    }
+
+    #[test]
+    fn lint_finds_endless_loops() {
+        assert_eq!(
+            linted("[]+[]>.,[[]]"),
+            vec![
+                Lint::EmptyLoop(3),
+                Lint::Unreachable {
+                    loop_start: 3,
+                    position: 5
+                },
+                Lint::EmptyLoop(9),
+            ]
+        );
+        assert_eq!(
+            linted(",[[-]+[>+<].]."),
+            vec![Lint::Unreachable {
+                loop_start: 6,
+                position: 11
+            }]
+        );
+    }
+
+    #[test]
+    fn lint_finds_invariant_clears() {
+        assert_eq!(
+            linted("+[>[-]<-]>[-]"),
+            vec![Lint::InvariantClear {
+                loop_start: 1,
+                position: 3
+            }]
+        );
+        assert!(linted("+[>[-]+<-]").is_empty());
+    }
+
+    #[test]
+    fn lint_finds_io_in_hot_loops() {
+        assert_eq!(
+            linted(",[[[.,]>[,]]]"),
+            vec![
+                Lint::IoInHotLoop {
+                    position: 4,
+                    token: Token::Output,
+                    depth: 3
+                },
+                Lint::IoInHotLoop {
+                    position: 9,
+                    token: Token::Input,
+                    depth: 3
+                },
+            ]
+        );
+        assert!(linted(",[[.]]").is_empty());
+    }
+}
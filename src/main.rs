@@ -1,32 +1,2368 @@
-use std::{env, fs};
+use std::{
+    collections::VecDeque,
+    env,
+    fs::{self, File},
+    io::{empty, stdin, stdout, BufRead, BufReader, BufWriter, ErrorKind, Read, Write},
+    path::{Path, PathBuf},
+    process,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
 use brainfudge::{
-    lexer::{JumpTable, Token},
-    runtime::State,
+    analysis::ngram_frequencies,
+    annotate::{annotate, count_executions},
+    argv,
+    bench::run_with_warmup,
+    cell::{Cell, CellIo},
+    codegen::{to_c, to_javascript, to_python, to_rust, to_wat, to_x86_64, CodegenOptions},
+    config::{CellKind, Config},
+    control::Controls,
+    coredump::CoreDump,
+    cost::CostModel,
+    encoding::ProgramEncoding,
+    extract::extract_loop,
+    format::format,
+    io::{Io, Streams},
+    lexer::{
+        detokenize, token_offsets, token_offsets_with_aliases, token_offsets_with_counts, tokenize,
+        tokenize_with_aliases, tokenize_with_counts, tokenize_with_spans, unmatched_brackets,
+        Aliases, CountSyntax, JumpTableError, Token,
+    },
+    limits::{Limit, Limits},
+    lint::lint,
+    optimizer::{evaluate, Evaluation, Pass, PassManager},
+    profile::SampleProfile,
+    program::{Program, ProgramError},
+    reduce::reduce,
+    report::Timings,
+    request::{Engine, TapeKind},
+    rewrite::rewrite,
+    runtime::{EofMode, ExecutionError, State},
+    schema::diagnostic_json,
+    slice::{slice, Target},
+    source::Sources,
+    tape::{BidirectionalTape, CircularTape, Tape, VecTape},
+    trace::{Event, Filter, TraceEvent},
+    watchdog::Watchdog,
 };
 
+const DEFAULT_FUEL: u64 = 10_000_000;
+/// The exit status of a run stopped by `--break-on-output`, to tell it from a finished run.
+const BREAKPOINT_STATUS: i32 = 3;
+const CONFIG_FILE: &str = "brainfudge.toml";
+const DEFAULT_NGRAM_LENGTH: usize = 3;
+const DEFAULT_TOP: usize = 20;
+const DEFAULT_WARMUP: u32 = 1;
+const DEFAULT_WIDTH: usize = 80;
+/// Executed instructions kept for a core dump.
+const CORE_TRACE_LENGTH: usize = 64;
+/// Cells shown on either side of the memory pointer when opening a core dump.
+const CORE_TAPE_CONTEXT: usize = 8;
+
+enum Stream {
+    Path(String),
+    Fd(i32),
+}
+
+enum ReportFormat {
+    Text,
+    Json,
+    Toml,
+}
+
+enum Command {
+    Run,
+    Eval,
+    Test,
+    Analyze,
+    Slice,
+    Reduce,
+    Annotate,
+    Debug,
+    Extract,
+    Compile,
+    Rewrite,
+    Fmt,
+    Lint,
+    Check,
+}
+
+enum CompileTarget {
+    C,
+    Rust,
+    Wat,
+    X86_64,
+    Python,
+    JavaScript,
+}
+
+struct Options {
+    command: Command,
+    source_path: String,
+    additional_source_paths: Vec<String>,
+    jobs: Option<usize>,
+    arguments: Vec<u8>,
+    exit_cell: Option<usize>,
+    origin: usize,
+    input: Option<Vec<u8>>,
+    input_stream: Option<Stream>,
+    output_stream: Option<Stream>,
+    lenient: bool,
+    eof: EofMode,
+    /// `--pure`: refuse programs that read input.
+    no_input: bool,
+    fuel: Option<u64>,
+    max_cells: Option<usize>,
+    max_output: Option<u64>,
+    timeout: Option<Duration>,
+    stall_timeout: Option<Duration>,
+    expected_tape_path: Option<String>,
+    tape_from_path: Option<String>,
+    dump_tape_path: Option<String>,
+    sample_interval: Option<u64>,
+    trace: Option<Option<Filter>>,
+    control_path: Option<String>,
+    assertions_path: Option<String>,
+    timings: bool,
+    events: Option<Stream>,
+    events_every: u64,
+    aliases: Aliases,
+    counts: Option<CountSyntax>,
+    bignum: bool,
+    cell_bits: u32,
+    cell_io: CellIo,
+    corpus_path: Option<String>,
+    ngram_length: usize,
+    top: usize,
+    slice_target: Option<Target>,
+    check: Option<String>,
+    loop_at: Option<usize>,
+    /// Where `extract`, `compile`, `rewrite` and `fmt` write their result.
+    emit_path: Option<String>,
+    target: Option<CompileTarget>,
+    after_run: bool,
+    core_dump_path: Option<String>,
+    core_path: Option<String>,
+    filter: bool,
+    tape: TapeKind,
+    engine: Engine,
+    report_format: ReportFormat,
+    costs: Option<CostModel>,
+    /// Output that stops an instrumented run, e.g. to write a core dump right there.
+    break_on_output: Option<Vec<u8>>,
+    passes: PassManager,
+    /// Measured iterations of `--bench`, after `warmup` ones that are not.
+    bench: Option<u32>,
+    warmup: u32,
+    program_encoding: Option<ProgramEncoding>,
+    minify: bool,
+    /// Columns `fmt` packs instructions into.
+    width: usize,
+}
+
 fn main() {
-    let source_path = env::args()
-        .nth(1)
-        .expect("No path the source file was given");
-    let source = fs::read_to_string(source_path).unwrap();
+    let options = match parse_options(env::args().skip(1)) {
+        Ok(x) => x,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(2);
+        }
+    };
+    if let (Command::Analyze, Some(path)) = (&options.command, &options.corpus_path) {
+        if let Err(e) = analyze(path, options.ngram_length, options.top) {
+            eprintln!("error: {}", e);
+            process::exit(1);
+        }
+        return;
+    }
+    if let Some(jobs) = options.jobs {
+        if !run_jobs(&options, jobs) {
+            process::exit(1);
+        }
+        return;
+    }
+
+    let mut sources = Sources::new();
+    for path in std::iter::once(&options.source_path).chain(&options.additional_source_paths) {
+        match read_source(path, options.program_encoding) {
+            Ok(x) => sources.add(path.as_str(), &x),
+            Err(e) => {
+                eprintln!("{}: {}", path, e);
+                process::exit(1);
+            }
+        }
+    }
+    let offsets = offsets(&options, &sources);
+    sources.set_instructions(offsets);
+    if let Command::Check = options.command {
+        if let Err(e) = check_program(&options, &sources) {
+            eprintln!("error: {}", e);
+            process::exit(1);
+        }
+        return;
+    }
+    let source = sources.source();
+    let mut timings = Timings::default();
+    let aliases = command_aliases(&options);
+    let tokens = timings.measure("lex", || {
+        tokenize_with_spans(source, &aliases, options.counts)
+    });
+    let mut program = match Program::from_spanned_tokens_with_timings(tokens, &mut timings) {
+        Ok(x) => x,
+        Err(e) => {
+            eprintln!("error: {}", program_error(e, &sources));
+            process::exit(1);
+        }
+    };
+    if options.passes != PassManager::default() {
+        if let Err(e) = program.set_passes(&options.passes) {
+            eprintln!("error: {}", program_error(e, &sources));
+            process::exit(1);
+        }
+    }
+    if options.timings {
+        eprint!("{}", timings);
+    }
+
+    let result = match options.command {
+        Command::Run => run(&options, &sources, &program, timings),
+        Command::Eval => eval(&options, &sources, &program),
+        Command::Test => test(&options, &sources, &program),
+        Command::Analyze => unreachable!(),
+        Command::Slice => slice_program(&options, &program),
+        Command::Reduce => reduce_program(&options, &program),
+        Command::Annotate => annotate_program(&options, &sources, &program),
+        Command::Debug => debug(&options, &sources, &program),
+        Command::Extract => extract_snippet(&options, &sources, &program),
+        Command::Compile => compile(&options, &program),
+        Command::Rewrite => rewrite_program(&options, &program),
+        Command::Fmt => format_program(&options, &sources, &program),
+        Command::Lint => lint_program(&options, &sources, &program),
+        Command::Check => unreachable!(),
+    };
+    if let Err(e) = result {
+        eprintln!("error: {}", e);
+        process::exit(1);
+    }
+}
+
+fn run(
+    options: &Options,
+    sources: &Sources,
+    program: &Program,
+    timings: Timings,
+) -> Result<(), String> {
+    if options.bignum {
+        return run_bignum(options, sources, program, timings);
+    }
+    if let Some(iterations) = options.bench {
+        return bench(options, program, iterations);
+    }
+    match options.cell_bits {
+        16 => run_wide_cells::<u16>(options, sources, program, timings),
+        32 => run_wide_cells::<u32>(options, sources, program, timings),
+        _ => match options.tape {
+            TapeKind::Growing => run_on(options, sources, program, timings, VecTape::new()),
+            TapeKind::GrowLeft => {
+                run_on(options, sources, program, timings, BidirectionalTape::new())
+            }
+            TapeKind::Wrap(cells) => {
+                run_on(options, sources, program, timings, CircularTape::new(cells))
+            }
+        },
+    }
+}
+
+fn bench(options: &Options, program: &Program, iterations: u32) -> Result<(), String> {
+    let result =
+        run_with_warmup(program, options.warmup, iterations).map_err(|e| format!("{:?}", e))?;
+
+    match options.report_format {
+        ReportFormat::Json => println!("{}", result.to_json()),
+        _ => print!("{}", result),
+    }
+    Ok(())
+}
+
+fn run_wide_cells<C: Cell>(
+    options: &Options,
+    sources: &Sources,
+    program: &Program,
+    timings: Timings,
+) -> Result<(), String> {
+    match options.tape {
+        TapeKind::Growing => run_wide(options, sources, program, timings, VecTape::<C>::default()),
+        TapeKind::GrowLeft => run_wide(
+            options,
+            sources,
+            program,
+            timings,
+            BidirectionalTape::<C>::default(),
+        ),
+        TapeKind::Wrap(cells) => run_wide(
+            options,
+            sources,
+            program,
+            timings,
+            CircularTape::<C>::new(cells),
+        ),
+    }
+}
+
+/// Applies the options that shape a run, from limits and EOF handling to the initial tape,
+/// so that every way of running a program behaves the same.
+fn configure<I: Io, T: Tape>(
+    state: &mut State<I, T>,
+    options: &Options,
+    timings: Timings,
+) -> Result<(), String> {
+    state.set_timings(timings);
+    state.write_arguments(&initial_tape(options)?);
+    state.set_limits(limits(options));
+    state
+        .set_origin(options.origin)
+        .map_err(|e| format!("{:?}", e))?;
+    state.set_lenient(options.lenient);
+    state.set_eof(options.eof);
+    state.set_cell_io(options.cell_io);
+    if let Some(costs) = options.costs {
+        state.set_costs(costs);
+    }
+    Ok(())
+}
+
+fn run_on<T: Tape<Cell = u8>>(
+    options: &Options,
+    sources: &Sources,
+    program: &Program,
+    timings: Timings,
+    tape: T,
+) -> Result<(), String> {
+    let io = Streams::new(input(options)?, output(options)?);
+    let mut state = State::with_tape(io, tape);
+    configure(&mut state, options, timings)?;
+
+    let controls = Arc::new(Controls::new());
+    controls.set_trace(options.trace.is_some());
+    if let Some(path) = &options.control_path {
+        read_controls(path.clone(), Arc::clone(&controls));
+    }
+
+    let mut profile = options.sample_interval.map(SampleProfile::new);
+    let mut breakpoint = false;
+    if profile.is_some()
+        || options.trace.is_some()
+        || options.events.is_some()
+        || options.control_path.is_some()
+        || options.core_dump_path.is_some()
+        || options.break_on_output.is_some()
+    {
+        let mut recent = VecDeque::new();
+        let mut output_tail = VecDeque::new();
+        let mut events = match &options.events {
+            Some(stream) => Some(BufWriter::new(open_stream(stream)?)),
+            None => None,
+        };
+        let mut step = 0u64;
+        while let Some(token) = program.tokens().get(state.instruction_pointer()) {
+            let position = state.instruction_pointer();
+            let memory_pointer = state.memory_pointer();
+            let before = state.cell(memory_pointer);
+            if let Some(profile) = &mut profile {
+                profile.tick(position);
+            }
+            match state.execute_current_instruction(program.tokens(), program.jump_table()) {
+                Err(e) if closed_pipe(options, &e) => return Ok(()),
+                Err(e) => {
+                    let _ = state.io_mut().flush();
+                    print_report(&state, options, sources, profile.as_ref());
+                    let message = execution_error(e, options, sources);
+                    if let Some(path) = &options.core_dump_path {
+                        write_core(path, &state, program, &message, recent)?;
+                    }
+                    return Err(message);
+                }
+                Ok(()) => (),
+            }
+            let event = TraceEvent {
+                position,
+                token: *token,
+                memory_pointer,
+                before,
+                after: state.cell(memory_pointer),
+            };
+            if controls.trace() {
+                let filter = options.trace.as_ref().and_then(Option::as_ref);
+                if filter.is_none_or(|x| x.matches(&event, program.jump_table())) {
+                    eprintln!("{}", event);
+                }
+            }
+            if *token == Token::Output && controls.flush() {
+                state.io_mut().flush().map_err(|e| e.to_string())?;
+            }
+            let throttle = controls.throttle();
+            if !throttle.is_zero() {
+                thread::sleep(throttle);
+            }
+            if let Some(writer) = &mut events {
+                if step.is_multiple_of(options.events_every) {
+                    let instruction = Event::Instruction {
+                        step,
+                        position,
+                        token: *token,
+                    };
+                    writeln!(writer, "{}", instruction.to_json()).map_err(|e| e.to_string())?;
+                }
+                for change in event.changes(state.memory_pointer()) {
+                    writeln!(writer, "{}", change.to_json()).map_err(|e| e.to_string())?;
+                }
+            }
+            step += 1;
+            if options.core_dump_path.is_some() {
+                if recent.len() == CORE_TRACE_LENGTH {
+                    recent.pop_front();
+                }
+                recent.push_back(event);
+            }
+            if let (Some(pattern), Token::Output) = (&options.break_on_output, token) {
+                if output_tail.len() == pattern.len() {
+                    output_tail.pop_front();
+                }
+                output_tail.push_back(before);
+                if output_tail.iter().eq(pattern) {
+                    state.io_mut().flush().map_err(|e| e.to_string())?;
+                    let message = format!(
+                        "Breakpoint on output \"{}\" at instruction {}",
+                        pattern.escape_ascii(),
+                        position
+                    );
+                    let message = match locate(position, sources) {
+                        Some(x) => format!("{}: {}", x, message),
+                        None => message,
+                    };
+                    eprintln!("{}", message);
+                    if let Some(path) = &options.core_dump_path {
+                        write_core(path, &state, program, &message, recent)?;
+                    }
+                    breakpoint = true;
+                    break;
+                }
+            }
+        }
+        if let Some(writer) = &mut events {
+            writer.flush().map_err(|e| e.to_string())?;
+        }
+    } else {
+        match run_program(&mut state, program, options.engine) {
+            Err(e) if closed_pipe(options, &e) => return Ok(()),
+            Err(e) => {
+                let _ = state.io_mut().flush();
+                print_report(&state, options, sources, None);
+                return Err(execution_error(e, options, sources));
+            }
+            Ok(()) => (),
+        }
+    }
+    state.io_mut().flush().map_err(|e| e.to_string())?;
+
+    print_report(&state, options, sources, profile.as_ref());
+    if let Some(path) = &options.dump_tape_path {
+        fs::write(path, state.memory()).map_err(|e| format!("{}: {}", path, e))?;
+    }
+    if let Some(path) = &options.expected_tape_path {
+        compare_tape(&state.memory(), path)?;
+    }
+
+    if breakpoint {
+        process::exit(BREAKPOINT_STATUS);
+    }
+    if let Some(index) = options.exit_cell {
+        process::exit(state.cell(index) as i32);
+    }
+    Ok(())
+}
+
+fn run_wide<T: Tape>(
+    options: &Options,
+    sources: &Sources,
+    program: &Program,
+    timings: Timings,
+    tape: T,
+) -> Result<(), String> {
+    if options.sample_interval.is_some()
+        || options.trace.is_some()
+        || options.events.is_some()
+        || options.control_path.is_some()
+        || options.dump_tape_path.is_some()
+        || options.expected_tape_path.is_some()
+        || options.core_dump_path.is_some()
+        || options.break_on_output.is_some()
+    {
+        return Err("Cells wider than 8 bits only support plain runs".to_string());
+    }
+
+    let io = Streams::new(input(options)?, output(options)?);
+    let mut state = State::with_tape(io, tape);
+    configure(&mut state, options, timings)?;
+    match run_program(&mut state, program, options.engine) {
+        Err(e) if closed_pipe(options, &e) => return Ok(()),
+        Err(e) => {
+            let _ = state.io_mut().flush();
+            print_report(&state, options, sources, None);
+            return Err(execution_error(e, options, sources));
+        }
+        Ok(()) => (),
+    }
+    state.io_mut().flush().map_err(|e| e.to_string())?;
+
+    print_report(&state, options, sources, None);
+    if let Some(index) = options.exit_cell {
+        process::exit(state.cell(index).to_byte() as i32);
+    }
+    Ok(())
+}
+
+#[cfg(feature = "bignum")]
+fn run_bignum(
+    options: &Options,
+    sources: &Sources,
+    program: &Program,
+    timings: Timings,
+) -> Result<(), String> {
+    run_wide_cells::<brainfudge::bignum::BigCell>(options, sources, program, timings)
+}
+
+#[cfg(not(feature = "bignum"))]
+fn run_bignum(
+    _options: &Options,
+    _sources: &Sources,
+    _program: &Program,
+    _timings: Timings,
+) -> Result<(), String> {
+    Err("--bignum requires the bignum feature".to_string())
+}
+
+fn test(options: &Options, sources: &Sources, program: &Program) -> Result<(), String> {
+    let assertions = match &options.assertions_path {
+        Some(path) => fs::read(path).map_err(|e| format!("{}: {}", path, e))?,
+        None => {
+            fs::read(Path::new(&options.source_path).with_extension("expect")).unwrap_or_default()
+        }
+    };
+
+    let io = Streams::new(input(options)?, output(options)?);
+    let test = Test {
+        program,
+        options,
+        sources,
+        assertions,
+    };
+    with_state(options, io, test)?;
+    eprintln!("test passed");
+    Ok(())
+}
+
+/// Runs a program for `test`, checking its assertions.
+struct Test<'a> {
+    program: &'a Program<'a>,
+    options: &'a Options,
+    sources: &'a Sources,
+    assertions: Vec<u8>,
+}
+
+impl<I: Io> WithState<I> for Test<'_> {
+    type Output = ();
+
+    fn with<T: Tape>(self, mut state: State<I, T>) -> Result<(), String> {
+        state.set_assertions(&self.assertions);
+        run_program(&mut state, self.program, self.options.engine)
+            .map_err(|e| execution_error(e, self.options, self.sources))?;
+        state.io_mut().flush().map_err(|e| e.to_string())
+    }
+}
+
+fn slice_program(options: &Options, program: &Program) -> Result<(), String> {
+    let target = options
+        .slice_target
+        .ok_or("slice requires --cell <index> or --instruction <position>")?;
+    let tokens =
+        slice(program.tokens(), program.jump_table(), target).map_err(|e| format!("{:?}", e))?;
+
+    println!("{}", detokenize(&tokens));
+    Ok(())
+}
+
+fn annotate_program(options: &Options, sources: &Sources, program: &Program) -> Result<(), String> {
+    if !options.after_run {
+        return Err("annotate requires --after-run".to_string());
+    }
+
+    let io = Streams::new(input(options)?, output(options)?);
+    let annotation = Annotation {
+        program,
+        options,
+        sources,
+    };
+    let counts = with_state(options, io, annotation)?;
+
+    eprint!(
+        "{}",
+        annotate(
+            sources.source(),
+            sources.instructions(),
+            program.tokens(),
+            program.jump_table(),
+            &counts
+        )
+    );
+    Ok(())
+}
+
+/// Runs a program for `annotate`, counting how often every instruction runs.
+struct Annotation<'a> {
+    program: &'a Program<'a>,
+    options: &'a Options,
+    sources: &'a Sources,
+}
+
+impl<I: Io> WithState<I> for Annotation<'_> {
+    type Output = Vec<u64>;
+
+    fn with<T: Tape>(self, mut state: State<I, T>) -> Result<Vec<u64>, String> {
+        let program = self.program;
+        let counts = count_executions(&mut state, program.tokens(), program.jump_table())
+            .map_err(|e| execution_error(e, self.options, self.sources))?;
+        state.io_mut().flush().map_err(|e| e.to_string())?;
+        Ok(counts)
+    }
+}
+
+/// Writes the loop at `--loop-at` to the `-o` file and its span and effects to the same path
+/// with `.json` appended, or both to stdout and stderr without `-o`.
+fn extract_snippet(options: &Options, sources: &Sources, program: &Program) -> Result<(), String> {
+    let position = options
+        .loop_at
+        .ok_or("extract requires --loop-at <position>")?;
+    let snippet = extract_loop(program.tokens(), program.jump_table(), position)
+        .map_err(|e| format!("{:?}", e))?;
+
+    let path = match &options.emit_path {
+        Some(x) => x,
+        None => {
+            println!("{}", snippet.source());
+            eprintln!("{}", snippet.to_json());
+            return Ok(());
+        }
+    };
+    fs::write(path, snippet.source() + "\n").map_err(|e| format!("{}: {}", path, e))?;
+    let metadata_path = format!("{}.json", path);
+    fs::write(&metadata_path, snippet.to_json() + "\n")
+        .map_err(|e| format!("{}: {}", metadata_path, e))?;
+    let location = locate(snippet.start, sources).unwrap_or_default();
+    eprintln!(
+        "extracted instructions {} to {} ({}) into {}",
+        snippet.start, snippet.end, location, path
+    );
+    Ok(())
+}
+
+fn compile(options: &Options, program: &Program) -> Result<(), String> {
+    let generate = match options.target {
+        Some(CompileTarget::C) => to_c,
+        Some(CompileTarget::Rust) => to_rust,
+        Some(CompileTarget::Wat) => to_wat,
+        Some(CompileTarget::X86_64) => to_x86_64,
+        Some(CompileTarget::Python) => to_python,
+        Some(CompileTarget::JavaScript) => to_javascript,
+        None => return Err("compile requires --target <c|rust|wat|x86-64|python|js>".to_string()),
+    };
+    if options.bignum {
+        return Err("Unbounded cells cannot be compiled".to_string());
+    }
+    if options.cell_io != CellIo::Byte {
+        return Err("Only byte cell I/O can be compiled".to_string());
+    }
+    let codegen_options = CodegenOptions {
+        cell_bits: options.cell_bits,
+        eof: match options.lenient {
+            true => EofMode::Zero,
+            false => options.eof,
+        },
+        origin: options.origin,
+    };
+    let code = generate(program.ir(), codegen_options).map_err(|e| format!("{:?}", e))?;
+
+    match &options.emit_path {
+        Some(path) => fs::write(path, code).map_err(|e| format!("{}: {}", path, e)),
+        None => {
+            print!("{}", code);
+            Ok(())
+        }
+    }
+}
+
+fn rewrite_program(options: &Options, program: &Program) -> Result<(), String> {
+    let source = detokenize(&rewrite(program.ir()));
+
+    match &options.emit_path {
+        Some(path) => fs::write(path, source + "\n").map_err(|e| format!("{}: {}", path, e)),
+        None => {
+            println!("{}", source);
+            Ok(())
+        }
+    }
+}
+
+fn format_program(options: &Options, sources: &Sources, program: &Program) -> Result<(), String> {
+    let source = match options.minify {
+        true => detokenize(program.tokens()),
+        false => format(sources.source(), options.width).map_err(|e| format!("{:?}", e))?,
+    };
+
+    match &options.emit_path {
+        Some(path) => fs::write(path, source).map_err(|e| format!("{}: {}", path, e)),
+        None => {
+            print!("{}", source);
+            Ok(())
+        }
+    }
+}
+
+/// Checks that the brackets of a program match without building or running it.
+fn check_program(options: &Options, sources: &Sources) -> Result<(), String> {
+    let aliases = command_aliases(options);
+    let tokens = match options.counts {
+        Some(syntax) => tokenize_with_counts(sources.source(), syntax, &aliases),
+        None => tokenize_with_aliases(sources.source(), &aliases),
+    };
+    let unmatched = unmatched_brackets(&tokens);
+
+    for position in &unmatched {
+        let message = format!("unmatched `{}`", tokens[*position].as_char());
+        let location = locate(*position, sources);
+        match (&options.report_format, location) {
+            (ReportFormat::Json, location) => println!(
+                "{}",
+                diagnostic_json(
+                    "error",
+                    "unmatched-bracket",
+                    *position,
+                    location.as_deref(),
+                    &message
+                )
+            ),
+            (_, Some(location)) => eprintln!("error: {}: {}", location, message),
+            (_, None) => eprintln!("error: instruction {}: {}", position, message),
+        }
+    }
+    match unmatched.len() {
+        0 => Ok(()),
+        1 => Err("1 unmatched bracket".to_string()),
+        count => Err(format!("{} unmatched brackets", count)),
+    }
+}
+
+fn lint_program(options: &Options, sources: &Sources, program: &Program) -> Result<(), String> {
+    let lints = lint(program.tokens(), program.jump_table());
+
+    for x in &lints {
+        let location = locate(x.position(), sources);
+        match (&options.report_format, location) {
+            (ReportFormat::Json, location) => println!(
+                "{}",
+                diagnostic_json(
+                    "warning",
+                    x.kind(),
+                    x.position(),
+                    location.as_deref(),
+                    &x.to_string()
+                )
+            ),
+            (_, Some(location)) => println!("warning: {}: {}", location, x),
+            (_, None) => println!("warning: instruction {}: {}", x.position(), x),
+        }
+    }
+    match lints.len() {
+        0 => Ok(()),
+        1 => Err("1 warning".to_string()),
+        count => Err(format!("{} warnings", count)),
+    }
+}
+
+fn reduce_program(options: &Options, program: &Program) -> Result<(), String> {
+    let check = options
+        .check
+        .as_ref()
+        .ok_or("reduce requires --check <command>")?;
+    let directory = private_directory("brainfudge-reduce")?;
+    let candidate_path = directory.join("candidate.bf");
+    let mut error = None;
+    let mut interesting = |tokens: &[Token]| {
+        let source = detokenize(tokens);
+        let status = fs::write(&candidate_path, source).and_then(|_| {
+            process::Command::new("sh")
+                .arg("-c")
+                .arg(check)
+                .arg("sh")
+                .arg(&candidate_path)
+                .stdout(process::Stdio::null())
+                .stderr(process::Stdio::null())
+                .status()
+        });
+        match status {
+            Ok(x) => x.success(),
+            Err(e) => {
+                error.get_or_insert(e.to_string());
+                false
+            }
+        }
+    };
+
+    if !interesting(program.tokens()) {
+        let _ = fs::remove_dir_all(&directory);
+        return Err(error.unwrap_or("The check does not hold for the original program".to_string()));
+    }
+    let reduced = reduce(program.tokens(), interesting);
+    let _ = fs::remove_dir_all(&directory);
+    if let Some(e) = error {
+        return Err(e);
+    }
+
+    println!("{}", detokenize(&reduced));
+    Ok(())
+}
+
+/// Creates a new directory in the temporary directory that only the current user can access,
+/// so that nobody else can replace the files in it, e.g. with symlinks. A name that is already
+/// taken is never reused.
+fn private_directory(prefix: &str) -> Result<PathBuf, String> {
+    let mut builder = fs::DirBuilder::new();
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+
+    for _ in 0..16 {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |x| x.subsec_nanos());
+        let path = env::temp_dir().join(format!("{}-{}-{}", prefix, process::id(), nanos));
+        match builder.create(&path) {
+            Ok(()) => return Ok(path),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(format!("{}: {}", path.display(), e)),
+        }
+    }
+    Err("No unused name for a temporary directory".to_string())
+}
+
+fn analyze(corpus_path: &str, length: usize, top: usize) -> Result<(), String> {
+    let mut paths = Vec::new();
+    collect_programs(Path::new(corpus_path), &mut paths)?;
+    paths.sort();
+
+    let mut programs = Vec::with_capacity(paths.len());
+    for path in &paths {
+        let source = fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        programs.push(tokenize(source));
+    }
+
+    println!("{} programs", programs.len());
+    for (ngram, count) in ngram_frequencies(programs.iter().map(Vec::as_slice), length)
+        .into_iter()
+        .take(top)
+    {
+        let text = detokenize(&ngram);
+        println!("{:>10} {}", count, text);
+    }
+    Ok(())
+}
+
+fn collect_programs(directory: &Path, paths: &mut Vec<PathBuf>) -> Result<(), String> {
+    let entries = fs::read_dir(directory).map_err(|e| format!("{}: {}", directory.display(), e))?;
+    for entry in entries {
+        let path = entry.map_err(|e| e.to_string())?.path();
+        if path.is_dir() {
+            collect_programs(&path, paths)?;
+        } else if path.extension().is_some_and(|x| x == "bf" || x == "b") {
+            paths.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn input(options: &Options) -> Result<Box<dyn Read>, String> {
+    let input: Box<dyn Read + Send> = match (&options.input, &options.input_stream) {
+        (Some(x), _) => Box::new(std::io::Cursor::new(x.clone())),
+        (None, Some(Stream::Path(path))) => match File::open(path) {
+            Ok(x) => Box::new(x),
+            Err(e) => return Err(format!("{}: {}", path, e)),
+        },
+        (None, Some(Stream::Fd(fd))) => Box::new(open_fd(*fd)?),
+        (None, None) => Box::new(stdin()),
+    };
+    match options.stall_timeout {
+        Some(period) => Ok(Box::new(Watchdog::new(input, period))),
+        None => Ok(input),
+    }
+}
+
+/// The program's output. Files and descriptors are buffered, so callers flush the stream
+/// before they return or exit; stdout flushes itself at every newline and on exit.
+fn output(options: &Options) -> Result<Box<dyn Write>, String> {
+    match &options.output_stream {
+        Some(stream) => Ok(Box::new(BufWriter::new(open_stream(stream)?))),
+        None => Ok(Box::new(stdout())),
+    }
+}
+
+fn open_stream(stream: &Stream) -> Result<Box<dyn Write>, String> {
+    match stream {
+        Stream::Path(path) => match File::create(path) {
+            Ok(x) => Ok(Box::new(x)),
+            Err(e) => Err(format!("{}: {}", path, e)),
+        },
+        Stream::Fd(fd) => Ok(Box::new(open_fd(*fd)?)),
+    }
+}
+
+#[cfg(unix)]
+fn open_fd(fd: i32) -> Result<File, String> {
+    use std::os::unix::io::FromRawFd;
+
+    // SAFETY: The descriptor was handed to the process by the caller and is used by nothing else.
+    Ok(unsafe { File::from_raw_fd(fd) })
+}
+
+#[cfg(not(unix))]
+fn open_fd(_fd: i32) -> Result<File, String> {
+    Err("File descriptors are only supported on Unix".to_string())
+}
+
+fn parse_tape(value: &str) -> Result<TapeKind, String> {
+    match value {
+        "growing" => return Ok(TapeKind::Growing),
+        "grow-left" => return Ok(TapeKind::GrowLeft),
+        _ => (),
+    }
+    let cells = value
+        .strip_prefix("wrap:")
+        .ok_or(format!("Invalid tape for --tape: {}", value))?;
+    match cells.parse() {
+        Ok(x) if x > 0 => Ok(TapeKind::Wrap(x)),
+        _ => Err(format!("Invalid cell count for --tape: {}", cells)),
+    }
+}
+
+fn parse_fd(value: Option<String>, option: &str) -> Result<i32, String> {
+    let value = value.ok_or(format!("Missing value for {}", option))?;
+    value
+        .parse()
+        .map_err(|_| format!("Invalid file descriptor for {}: {}", option, value))
+}
+
+fn is_stdout(stream: &Stream) -> bool {
+    match stream {
+        Stream::Path(path) => path == "/dev/stdout",
+        Stream::Fd(fd) => *fd == 1,
+    }
+}
+
+/// In filter mode a reader that closes the pipe early ends the program quietly, like it ends
+/// other filters in a pipeline.
+fn closed_pipe(options: &Options, error: &ExecutionError) -> bool {
+    options.filter
+        && matches!(error, ExecutionError::OutputError(_, e) if e.kind() == ErrorKind::BrokenPipe)
+}
+
+fn run_jobs(options: &Options, jobs: usize) -> bool {
+    let paths: Vec<_> = std::iter::once(&options.source_path)
+        .chain(&options.additional_source_paths)
+        .collect();
+    let next = AtomicUsize::new(0);
+    let failed = AtomicBool::new(false);
+
+    thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, paths.len()) {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let path = match paths.get(index) {
+                    Some(x) => x,
+                    None => break,
+                };
+                if let Err(e) = run_job(path, options) {
+                    eprintln!("error: {}", e);
+                    failed.store(true, Ordering::Relaxed);
+                }
+            });
+        }
+    });
+
+    !failed.load(Ordering::Relaxed)
+}
+
+fn run_job(source_path: &str, options: &Options) -> Result<(), String> {
+    let source = read_source(source_path, options.program_encoding)
+        .map_err(|e| format!("{}: {}", source_path, e))?;
+    let mut sources = Sources::new();
+    sources.add(source_path, &source);
+    sources.set_instructions(offsets(options, &sources));
+    // Errors that point into the source already start with its path.
+    let in_source = |message: String| match message.starts_with(source_path) {
+        true => message,
+        false => format!("{}: {}", source_path, message),
+    };
+
+    let tokens = tokenize_with_spans(&source, &command_aliases(options), options.counts);
+    let mut program =
+        Program::from_spanned_tokens(tokens).map_err(|e| in_source(program_error(e, &sources)))?;
+    if options.passes != PassManager::default() {
+        program
+            .set_passes(&options.passes)
+            .map_err(|e| in_source(program_error(e, &sources)))?;
+    }
+    let path = Path::new(source_path);
+    let input: Box<dyn Read> = match File::open(path.with_extension("in")) {
+        Ok(x) => Box::new(BufReader::new(x)),
+        Err(_) => Box::new(empty()),
+    };
+    let output_path = path.with_extension("out");
+    let output =
+        File::create(&output_path).map_err(|e| format!("{}: {}", output_path.display(), e))?;
+    let io = Streams::new(input, BufWriter::new(output));
+
+    with_state(
+        options,
+        io,
+        Job {
+            program: &program,
+            options,
+            sources: &sources,
+        },
+    )
+    .map_err(in_source)
+}
 
-    let tokens = tokenize(&source);
-    let jump_table = JumpTable::from(&tokens).unwrap();
-    let mut state = State::new();
+struct Job<'a> {
+    program: &'a Program<'a>,
+    options: &'a Options,
+    sources: &'a Sources,
+}
+
+impl<I: Io> WithState<I> for Job<'_> {
+    type Output = ();
+
+    fn with<T: Tape>(self, mut state: State<I, T>) -> Result<(), String> {
+        run_program(&mut state, self.program, self.options.engine)
+            .map_err(|e| execution_error(e, self.options, self.sources))?;
+        state.io_mut().flush().map_err(|e| e.to_string())
+    }
+}
 
-    while state.can_execute(&tokens) {
-        state
-            .execute_current_instruction(&tokens, &jump_table)
-            .unwrap();
+/// Runs the program to the end with the engine chosen with `--engine`.
+fn run_program<I: Io, T: Tape>(
+    state: &mut State<I, T>,
+    program: &Program,
+    engine: Engine,
+) -> Result<(), ExecutionError> {
+    match engine {
+        Engine::Ir => state.run_ir(program.ir()),
+        Engine::Blocks => state.run_blocks(program.tokens(), program.blocks()),
+        Engine::Tokens => state.run(program.tokens(), program.jump_table()),
     }
 }
 
-fn tokenize(source: &str) -> Vec<Token> {
-    source
-        .chars()
-        .map(|x| Token::parse(&x))
-        .filter(Option::is_some)
-        .map(Option::unwrap)
+/// What to do with the state the options describe, whatever its tape and cells.
+trait WithState<I: Io> {
+    type Output;
+
+    fn with<T: Tape>(self, state: State<I, T>) -> Result<Self::Output, String>;
+}
+
+/// Builds the state the options describe through `configure`, on the tape and cells they
+/// choose, and hands it on.
+fn with_state<I: Io, F: WithState<I>>(options: &Options, io: I, f: F) -> Result<F::Output, String> {
+    if options.bignum {
+        return with_bignum(options, io, f);
+    }
+    match options.cell_bits {
+        16 => with_cells::<u16, _, _>(options, io, f),
+        32 => with_cells::<u32, _, _>(options, io, f),
+        _ => with_cells::<u8, _, _>(options, io, f),
+    }
+}
+
+#[cfg(feature = "bignum")]
+fn with_bignum<I: Io, F: WithState<I>>(
+    options: &Options,
+    io: I,
+    f: F,
+) -> Result<F::Output, String> {
+    with_cells::<brainfudge::bignum::BigCell, _, _>(options, io, f)
+}
+
+#[cfg(not(feature = "bignum"))]
+fn with_bignum<I: Io, F: WithState<I>>(
+    _options: &Options,
+    _io: I,
+    _f: F,
+) -> Result<F::Output, String> {
+    Err("--bignum requires the bignum feature".to_string())
+}
+
+fn with_cells<C: Cell, I: Io, F: WithState<I>>(
+    options: &Options,
+    io: I,
+    f: F,
+) -> Result<F::Output, String> {
+    match options.tape {
+        TapeKind::Growing => with_tape(options, io, VecTape::<C>::default(), f),
+        TapeKind::GrowLeft => with_tape(options, io, BidirectionalTape::<C>::default(), f),
+        TapeKind::Wrap(cells) => with_tape(options, io, CircularTape::<C>::new(cells), f),
+    }
+}
+
+fn with_tape<I: Io, T: Tape, F: WithState<I>>(
+    options: &Options,
+    io: I,
+    tape: T,
+    f: F,
+) -> Result<F::Output, String> {
+    let mut state = State::with_tape(io, tape);
+    configure(&mut state, options, Timings::default())?;
+    f.with(state)
+}
+
+/// Reads a source file, decoding it first when it holds an encoded program.
+fn read_source(path: &str, encoding: Option<ProgramEncoding>) -> Result<Vec<u8>, String> {
+    let source = fs::read(path).map_err(|e| e.to_string())?;
+    match encoding {
+        Some(encoding) => encoding.decode(source).map_err(|e| format!("{:?}", e)),
+        None => Ok(source),
+    }
+}
+
+fn eval(options: &Options, sources: &Sources, program: &Program) -> Result<(), String> {
+    if options.no_input && program.tokens().contains(&Token::Input) {
+        return Err("Program reads input, which --pure does not allow".to_string());
+    }
+    let tape_files = options.dump_tape_path.is_some() || options.expected_tape_path.is_some();
+    if (options.bignum || options.cell_bits != 8) && tape_files {
+        return Err("Cells wider than 8 bits only support plain runs".to_string());
+    }
+
+    let fuel = options.fuel.unwrap_or(DEFAULT_FUEL);
+    // The output of a program on a fresh tape is folded at compile time, which leaves the
+    // run for programs that read input, start on a given tape, write cells in a --cell-io
+    // other than bytes or need more than the fuel.
+    let fresh = !tape_files
+        && !matches!(options.tape, TapeKind::Wrap(_))
+        && options.cell_io == CellIo::Byte
+        && initial_tape(options)?.is_empty()
+        && options.costs.is_none()
+        && Limits {
+            steps: None,
+            ..limits(options)
+        } == Limits::default();
+    let cells = options.passes.cells().unwrap_or(CellKind::Byte);
+    let bytes = match fresh.then(|| evaluate(program.ir(), cells, fuel)) {
+        Some(Evaluation::Finished(x)) => x,
+        _ => {
+            let input: Box<dyn Read> = match (&options.input, &options.input_stream) {
+                (None, None) => Box::new(empty()),
+                _ => input(options)?,
+            };
+            let io = Streams::new(input, Vec::new());
+            let eval = Eval {
+                program,
+                options,
+                sources,
+                fuel,
+            };
+            with_state(options, io, eval)?
+        }
+    };
+
+    let mut io = Streams::new(empty(), output(options)?);
+    io.write_bytes(&bytes).map_err(|e| e.to_string())?;
+    io.flush().map_err(|e| e.to_string())
+}
+
+/// Runs a program for `eval`, which only writes its output once it finishes.
+struct Eval<'a> {
+    program: &'a Program<'a>,
+    options: &'a Options,
+    sources: &'a Sources,
+    fuel: u64,
+}
+
+impl<R: Read> WithState<Streams<R, Vec<u8>>> for Eval<'_> {
+    type Output = Vec<u8>;
+
+    fn with<T: Tape>(self, mut state: State<Streams<R, Vec<u8>>, T>) -> Result<Vec<u8>, String> {
+        state.set_limits(Limits {
+            steps: Some(self.fuel),
+            ..limits(self.options)
+        });
+        run_program(&mut state, self.program, self.options.engine)
+            .map_err(|e| execution_error(e, self.options, self.sources))?;
+
+        let (dump, expected) = (
+            &self.options.dump_tape_path,
+            &self.options.expected_tape_path,
+        );
+        if dump.is_some() || expected.is_some() {
+            let tape: Vec<u8> = state.memory().iter().map(Cell::to_byte).collect();
+            if let Some(path) = dump {
+                fs::write(path, &tape).map_err(|e| format!("{}: {}", path, e))?;
+            }
+            if let Some(path) = expected {
+                compare_tape(&tape, path)?;
+            }
+        }
+        Ok(state.into_output())
+    }
+}
+
+fn write_core<I: Io, T: Tape<Cell = u8>>(
+    path: &str,
+    state: &State<I, T>,
+    program: &Program,
+    error: &str,
+    trace: VecDeque<TraceEvent>,
+) -> Result<(), String> {
+    let core = CoreDump {
+        fingerprint: program.fingerprint(),
+        error: error.to_string(),
+        instruction_pointer: state.instruction_pointer(),
+        memory_pointer: state.memory_pointer(),
+        steps: state.steps(),
+        tape: state.memory(),
+        trace: trace.into(),
+    };
+    let file = File::create(path).map_err(|e| format!("{}: {}", path, e))?;
+    core.write_to(BufWriter::new(file))
+        .map_err(|e| format!("{}: {}", path, e))?;
+    eprintln!("core dumped to {}", path);
+    Ok(())
+}
+
+/// Opens a core dump written by `--core-dump` and answers commands read from stdin.
+fn debug(options: &Options, sources: &Sources, program: &Program) -> Result<(), String> {
+    let path = options.core_path.as_deref().unwrap_or_default();
+    let file = File::open(path).map_err(|e| format!("{}: {}", path, e))?;
+    let core =
+        CoreDump::read_from(BufReader::new(file)).map_err(|e| format!("{}: {:?}", path, e))?;
+    if core.fingerprint != program.fingerprint() {
+        eprintln!("warning: {} was written for a different program", path);
+    }
+
+    print_where(&core, sources);
+    for line in stdin().lock().lines() {
+        let line = line.map_err(|e| e.to_string())?;
+        let numbers: Vec<_> = line.split_whitespace().skip(1).map(str::parse).collect();
+        match (line.split_whitespace().next(), numbers.as_slice()) {
+            (Some("where"), []) => print_where(&core, sources),
+            (Some("trace"), []) => {
+                for event in &core.trace {
+                    println!("{}", event);
+                }
+            }
+            (Some("cell"), [Ok(index)]) => {
+                println!("{}", core.tape.get(*index).copied().unwrap_or_default())
+            }
+            (Some("tape"), [Ok(start), Ok(end)]) => print_cells(&core, *start, *end),
+            (Some("quit"), []) => break,
+            (None, _) => (),
+            _ => eprintln!(
+                "unknown command: {} (where, trace, cell <index>, tape <start> <end>, quit)",
+                line
+            ),
+        }
+    }
+    Ok(())
+}
+
+fn print_where(core: &CoreDump, sources: &Sources) {
+    println!("error: {}", core.error);
+    match locate(core.instruction_pointer, sources) {
+        Some(location) => println!(
+            "stopped at instruction {} ({}) after {} steps",
+            core.instruction_pointer, location, core.steps
+        ),
+        None => println!(
+            "stopped at instruction {} after {} steps",
+            core.instruction_pointer, core.steps
+        ),
+    }
+    println!("memory pointer {}", core.memory_pointer);
+    print_cells(
+        core,
+        core.memory_pointer.saturating_sub(CORE_TAPE_CONTEXT),
+        core.memory_pointer + CORE_TAPE_CONTEXT + 1,
+    );
+}
+
+/// Prints the cells in `start..end`, marking the one under the memory pointer.
+fn print_cells(core: &CoreDump, start: usize, end: usize) {
+    let end = end.min(core.tape.len());
+    let cells: Vec<_> = (start..end)
+        .map(|index| match index == core.memory_pointer {
+            true => format!("[{}]", core.tape[index]),
+            false => core.tape[index].to_string(),
+        })
+        .collect();
+    println!("cells {}..{}: {}", start, end.max(start), cells.join(" "));
+}
+
+/// Prints the warnings and the sampled profile of a run to stderr, or all of its report in the
+/// format given with `--report-format`.
+fn print_report<I: Io, T: Tape>(
+    state: &State<I, T>,
+    options: &Options,
+    sources: &Sources,
+    profile: Option<&SampleProfile>,
+) {
+    let lines = profile.map(|x| profile_lines(x, sources));
+    match options.report_format {
+        ReportFormat::Text => {
+            if let (Some(profile), Some(lines)) = (profile, lines) {
+                eprintln!(
+                    "{} samples (every {} instructions)",
+                    profile.total(),
+                    options.sample_interval.unwrap_or_default()
+                );
+                for (line, count) in lines {
+                    eprintln!(
+                        "  {}: {} ({:.1}%)",
+                        line,
+                        count,
+                        100.0 * count as f64 / profile.total() as f64
+                    );
+                }
+            }
+            if let Some(cost) = state.report().cost {
+                eprintln!("cost: {}", cost);
+            }
+            for (warning, count) in &state.report().warnings {
+                match count {
+                    1 => eprintln!("warning: {}", warning),
+                    _ => eprintln!("warning: {} ({} times)", warning, count),
+                }
+            }
+        }
+        ReportFormat::Json | ReportFormat::Toml => {
+            let mut report = state.report().clone();
+            report.profile = lines.unwrap_or_default();
+            match options.report_format {
+                ReportFormat::Json => eprintln!("{}", report.to_json()),
+                _ => eprint!("{}", report.to_toml()),
+            }
+        }
+    }
+}
+
+/// Sample counts aggregated by source file and line, most frequent first.
+fn profile_lines(profile: &SampleProfile, sources: &Sources) -> Vec<(String, u64)> {
+    profile
+        .aggregate(|position| {
+            sources
+                .locate_instruction(position)
+                .map(|x| (x.file, x.line))
+        })
+        .into_iter()
+        .filter_map(|(x, count)| x.map(|(file, line)| (format!("{}:{}", file, line), count)))
         .collect()
 }
+
+/// Applies the commands read from a file or named pipe while the program runs, until the end
+/// of the file.
+fn read_controls(path: String, controls: Arc<Controls>) {
+    thread::spawn(move || {
+        let file = match File::open(&path) {
+            Ok(x) => x,
+            Err(e) => {
+                eprintln!("warning: {}: {}", path, e);
+                return;
+            }
+        };
+        for line in BufReader::new(file).lines().map_while(Result::ok) {
+            if line.trim().is_empty() {
+                continue;
+            }
+            if let Err(e) = controls.apply(&line) {
+                eprintln!("warning: invalid control command: {:?}", e);
+            }
+        }
+    });
+}
+
+/// The aliases the program was tokenized with, including `=` for assertions in tests.
+fn command_aliases(options: &Options) -> Aliases {
+    let mut aliases = options.aliases.clone();
+    if let Command::Test = options.command {
+        aliases.insert('=', Token::Assert);
+    }
+    aliases
+}
+
+/// The source offset of every instruction of the program.
+fn offsets(options: &Options, sources: &Sources) -> Vec<usize> {
+    let aliases = command_aliases(options);
+    match options.counts {
+        Some(syntax) => token_offsets_with_counts(sources.source(), syntax, &aliases),
+        None if aliases.is_empty() => token_offsets(sources.source()),
+        None => token_offsets_with_aliases(sources.source(), &aliases),
+    }
+}
+
+/// The source file, line and column of the instruction at the given position.
+fn locate(position: usize, sources: &Sources) -> Option<String> {
+    sources.locate_instruction(position).map(|x| x.to_string())
+}
+
+/// Describes an error building the program, pointing at the bracket without a partner.
+fn program_error(error: ProgramError, sources: &Sources) -> String {
+    let message = match error.error() {
+        ProgramError::JumpTable(JumpTableError::TooManyLoopStarts(_)) => {
+            "unmatched `[`".to_string()
+        }
+        ProgramError::JumpTable(JumpTableError::NoMatchingLoopEnd(_)) => {
+            "unmatched `]`".to_string()
+        }
+        e => format!("{:?}", e),
+    };
+    match error.span().and_then(|x| sources.locate(x.offset)) {
+        Some(location) => format!("{}: {}", location, message),
+        None => message,
+    }
+}
+
+/// Describes a runtime error, prefixed with the file, line and column of the instruction.
+fn execution_error(error: ExecutionError, options: &Options, sources: &Sources) -> String {
+    let location = error.position().and_then(|x| locate(x, sources));
+
+    let message = match error {
+        ExecutionError::AssertionFailed(position, expected, actual) => format!(
+            "Assertion failed at instruction {}: expected {}, found {}",
+            position, expected, actual
+        ),
+        ExecutionError::MissingAssertion(position) => format!(
+            "Assertion at instruction {} has no expected value left",
+            position
+        ),
+        ExecutionError::LimitExceeded(_, Limit::Steps(x)) => {
+            format!("Fuel exhausted after {} instructions", x)
+        }
+        ExecutionError::LimitExceeded(position, limit) => {
+            format!("Exceeded the {} at instruction {}", limit, position)
+        }
+        ExecutionError::StalledOnInput(position) => format!(
+            "Stalled on input at instruction {}: nothing arrived for {:?}",
+            position,
+            options.stall_timeout.unwrap_or_default()
+        ),
+        e => format!("{:?}", e),
+    };
+    match location {
+        Some(x) => format!("{}: {}", x, message),
+        None => message,
+    }
+}
+
+fn limits(options: &Options) -> Limits {
+    Limits {
+        steps: options.fuel,
+        cells: options.max_cells,
+        output: options.max_output,
+        time: options.timeout,
+    }
+}
+
+fn initial_tape(options: &Options) -> Result<Vec<u8>, String> {
+    let mut tape = match &options.tape_from_path {
+        Some(path) => fs::read(path).map_err(|e| format!("{}: {}", path, e))?,
+        None => Vec::new(),
+    };
+    if tape.len() < options.arguments.len() {
+        tape.resize(options.arguments.len(), 0);
+    }
+    tape[..options.arguments.len()].copy_from_slice(&options.arguments);
+    Ok(tape)
+}
+
+fn compare_tape(tape: &[u8], expected_tape_path: &str) -> Result<(), String> {
+    let expected = fs::read(expected_tape_path).map_err(|e| e.to_string())?;
+    let length = tape.len().max(expected.len());
+    let differences: Vec<_> = (0..length)
+        .filter_map(|index| {
+            let expected = expected.get(index).copied().unwrap_or(0);
+            let actual = tape.get(index).copied().unwrap_or(0);
+            match expected == actual {
+                true => None,
+                false => Some(format!(
+                    "  {:08x}: expected {:02x}, found {:02x}",
+                    index, expected, actual
+                )),
+            }
+        })
+        .collect();
+
+    match differences.is_empty() {
+        true => Ok(()),
+        false => Err(format!(
+            "Tape does not match {}\n{}",
+            expected_tape_path,
+            differences.join("\n")
+        )),
+    }
+}
+
+fn parse_options(args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut args: Vec<_> = args.collect();
+    // Everything after `--` is passed to the program, including a `--config` of its own.
+    let end = args.iter().position(|x| x == "--").unwrap_or(args.len());
+    let config_path = match args[..end].iter().position(|x| x == "--config") {
+        Some(index) if index + 1 < end => {
+            let path = args.remove(index + 1);
+            args.remove(index);
+            Some(path)
+        }
+        Some(_) => return Err("Missing value for --config".to_string()),
+        None => None,
+    };
+    let (config_args, config_streams) = config_args(config_path.as_deref())?;
+
+    let mut args = args.into_iter().peekable();
+    let command = match args.peek().map(String::as_str) {
+        Some("run") => {
+            args.next();
+            Command::Run
+        }
+        Some("eval") => {
+            args.next();
+            Command::Eval
+        }
+        Some("test") => {
+            args.next();
+            Command::Test
+        }
+        Some("analyze") => {
+            args.next();
+            Command::Analyze
+        }
+        Some("slice") => {
+            args.next();
+            Command::Slice
+        }
+        Some("reduce") => {
+            args.next();
+            Command::Reduce
+        }
+        Some("annotate") => {
+            args.next();
+            Command::Annotate
+        }
+        Some("debug") => {
+            args.next();
+            Command::Debug
+        }
+        Some("extract") => {
+            args.next();
+            Command::Extract
+        }
+        Some("compile") => {
+            args.next();
+            Command::Compile
+        }
+        Some("rewrite") => {
+            args.next();
+            Command::Rewrite
+        }
+        Some("fmt") => {
+            args.next();
+            Command::Fmt
+        }
+        Some("lint") => {
+            args.next();
+            Command::Lint
+        }
+        Some("check") => {
+            args.next();
+            Command::Check
+        }
+        _ => Command::Run,
+    };
+    let mut args = config_args.into_iter().chain(args);
+
+    let mut source_path = None;
+    let mut additional_source_paths = Vec::new();
+    let mut jobs = None;
+    let mut arguments = Vec::new();
+    let mut exit_cell = None;
+    let mut origin = 0;
+    let mut input = None;
+    let mut input_stream = None;
+    let mut output_stream = None;
+    let mut standard_input = false;
+    let mut standard_output = false;
+    let mut lenient = false;
+    let mut eof = EofMode::Error;
+    let mut no_input = false;
+    let mut fuel = None;
+    let mut max_cells = None;
+    let mut max_output = None;
+    let mut timeout = None;
+    let mut stall_timeout = None;
+    let mut expected_tape_path = None;
+    let mut tape_from_path = None;
+    let mut dump_tape_path = None;
+    let mut sample_interval = None;
+    let mut trace = None;
+    let mut control_path = None;
+    let mut assertions_path = None;
+    let mut timings = false;
+    let mut events = None;
+    let mut events_every = 1;
+    let mut aliases = Aliases::new();
+    let mut counts = None;
+    let mut bignum = false;
+    let mut cell_bits = 8;
+    let mut cell_io = CellIo::Byte;
+    let mut corpus_path = None;
+    let mut ngram_length = DEFAULT_NGRAM_LENGTH;
+    let mut top = DEFAULT_TOP;
+    let mut slice_target = None;
+    let mut check = None;
+    let mut loop_at = None;
+    let mut emit_path = None;
+    let mut target = None;
+    let mut after_run = false;
+    let mut core_dump_path = None;
+    let mut core_path = None;
+    let mut filter = false;
+    let mut tape = TapeKind::Growing;
+    let mut engine = Engine::Ir;
+    let mut report_format = ReportFormat::Text;
+    let mut costs = None;
+    let mut passes = PassManager::default();
+    let mut break_on_output = None;
+    let mut bench = None;
+    let mut warmup = DEFAULT_WARMUP;
+    let mut program_encoding = None;
+    let mut minify = false;
+    let mut width = DEFAULT_WIDTH;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--arg" => {
+                let value = args.next().ok_or("Missing value for --arg")?;
+                let value = value
+                    .parse()
+                    .map_err(|_| format!("Invalid byte value for --arg: {}", value))?;
+                arguments.push(value);
+            }
+            "--" => {
+                let guest_arguments: Vec<_> = args.by_ref().collect();
+                let cells = argv::encode(&guest_arguments)
+                    .map_err(|e| format!("Invalid program arguments: {:?}", e))?;
+                arguments.extend(cells);
+            }
+            "--exit-from-cell" => exit_cell = Some(0),
+            _ if arg.starts_with("--exit-from-cell=") => {
+                let value = &arg["--exit-from-cell=".len()..];
+                let index = value
+                    .parse()
+                    .map_err(|_| format!("Invalid cell index for --exit-from-cell: {}", value))?;
+                exit_cell = Some(index);
+            }
+            "--input-str" => {
+                let value = args.next().ok_or("Missing value for --input-str")?;
+                input = Some(unescape(&value)?);
+            }
+            "--input-file" => {
+                let value = args.next().ok_or("Missing value for --input-file")?;
+                standard_input = value == "-";
+                input_stream = (!standard_input).then_some(Stream::Path(value));
+            }
+            "--output-file" => {
+                let value = args.next().ok_or("Missing value for --output-file")?;
+                standard_output = value == "-";
+                output_stream = (!standard_output).then_some(Stream::Path(value));
+            }
+            "--input-fd" => input_stream = Some(Stream::Fd(parse_fd(args.next(), "--input-fd")?)),
+            "--output-fd" => {
+                output_stream = Some(Stream::Fd(parse_fd(args.next(), "--output-fd")?))
+            }
+            "--lenient" => lenient = true,
+            "--pure" => no_input = true,
+            "--timings" => timings = true,
+            "--events" => {
+                let value = args.next().ok_or("Missing value for --events")?;
+                events = Some(Stream::Path(value));
+            }
+            "--events-fd" => events = Some(Stream::Fd(parse_fd(args.next(), "--events-fd")?)),
+            "--events-every" => {
+                let value = args.next().ok_or("Missing value for --events-every")?;
+                events_every = match value.parse() {
+                    Ok(x) if x > 0 => x,
+                    _ => {
+                        return Err(format!(
+                            "Invalid instruction count for --events-every: {}",
+                            value
+                        ))
+                    }
+                };
+            }
+            "--alias" => {
+                let value = args.next().ok_or("Missing value for --alias")?;
+                aliases
+                    .define(&value)
+                    .map_err(|e| format!("Invalid alias: {:?}", e))?;
+            }
+            "--counts" => {
+                let value = args.next().ok_or("Missing value for --counts")?;
+                counts = match value.as_str() {
+                    "prefix" => Some(CountSyntax::Prefix),
+                    "postfix" => Some(CountSyntax::Postfix),
+                    _ => return Err(format!("Invalid syntax for --counts: {}", value)),
+                };
+            }
+            "--aliases" => {
+                let path = args.next().ok_or("Missing value for --aliases")?;
+                let text = fs::read_to_string(&path).map_err(|e| format!("{}: {}", path, e))?;
+                let file_aliases =
+                    Aliases::parse(&text).map_err(|e| format!("{}: {:?}", path, e))?;
+                aliases.extend(file_aliases);
+            }
+            "--bignum" => bignum = true,
+            "--cell-bits" => {
+                let value = args.next().ok_or("Missing value for --cell-bits")?;
+                cell_bits = match value.as_str() {
+                    "8" => 8,
+                    "16" => 16,
+                    "32" => 32,
+                    _ => return Err(format!("Invalid cell width for --cell-bits: {}", value)),
+                };
+            }
+            "--cell-io" => {
+                let value = args.next().ok_or("Missing value for --cell-io")?;
+                cell_io = CellIo::from_name(&value)
+                    .ok_or(format!("Invalid cell I/O mode for --cell-io: {}", value))?;
+            }
+            "--corpus" => corpus_path = Some(args.next().ok_or("Missing value for --corpus")?),
+            "--ngram" => {
+                let value = args.next().ok_or("Missing value for --ngram")?;
+                ngram_length = match value.parse() {
+                    Ok(x) if x > 0 => x,
+                    _ => return Err(format!("Invalid length for --ngram: {}", value)),
+                };
+            }
+            "--cell" => {
+                let value = args.next().ok_or("Missing value for --cell")?;
+                let index = value
+                    .parse()
+                    .map_err(|_| format!("Invalid cell index for --cell: {}", value))?;
+                slice_target = Some(Target::Cell(index));
+            }
+            "--instruction" => {
+                let value = args.next().ok_or("Missing value for --instruction")?;
+                let position = value
+                    .parse()
+                    .map_err(|_| format!("Invalid position for --instruction: {}", value))?;
+                slice_target = Some(Target::Instruction(position));
+            }
+            "--check" => check = Some(args.next().ok_or("Missing value for --check")?),
+            "--loop-at" => {
+                let value = args.next().ok_or("Missing value for --loop-at")?;
+                let position = value
+                    .parse()
+                    .map_err(|_| format!("Invalid position for --loop-at: {}", value))?;
+                loop_at = Some(position);
+            }
+            "-o" => emit_path = Some(args.next().ok_or("Missing value for -o")?),
+            "--target" => {
+                let value = args.next().ok_or("Missing value for --target")?;
+                target = match value.as_str() {
+                    "c" => Some(CompileTarget::C),
+                    "rust" => Some(CompileTarget::Rust),
+                    "wat" => Some(CompileTarget::Wat),
+                    "x86-64" => Some(CompileTarget::X86_64),
+                    "python" => Some(CompileTarget::Python),
+                    "js" => Some(CompileTarget::JavaScript),
+                    _ => return Err(format!("Invalid target for --target: {}", value)),
+                };
+            }
+            "--after-run" => after_run = true,
+            "--core-dump" => {
+                let value = args.next().ok_or("Missing value for --core-dump")?;
+                core_dump_path = Some(value);
+            }
+            "--core" => {
+                let value = args.next().ok_or("Missing value for --core")?;
+                core_path = Some(value);
+            }
+            "--filter" => filter = true,
+            "--engine" => {
+                let value = args.next().ok_or("Missing value for --engine")?;
+                engine = Engine::from_name(&value)
+                    .ok_or_else(|| format!("Unknown engine for --engine: {}", value))?;
+            }
+            "--grow-left" => tape = TapeKind::GrowLeft,
+            "--tape" => {
+                let value = args.next().ok_or("Missing value for --tape")?;
+                tape = parse_tape(&value)?;
+            }
+            _ if arg.starts_with("--tape=") => tape = parse_tape(&arg["--tape=".len()..])?,
+            "--report-format" => {
+                let value = args.next().ok_or("Missing value for --report-format")?;
+                report_format = match value.as_str() {
+                    "text" => ReportFormat::Text,
+                    "json" => ReportFormat::Json,
+                    "toml" => ReportFormat::Toml,
+                    _ => return Err(format!("Invalid format for --report-format: {}", value)),
+                };
+            }
+            "--bench" => {
+                let value = args.next().ok_or("Missing value for --bench")?;
+                let iterations = value
+                    .parse()
+                    .ok()
+                    .filter(|x| *x > 0)
+                    .ok_or(format!("Invalid iteration count for --bench: {}", value))?;
+                bench = Some(iterations);
+            }
+            "--warmup" => {
+                let value = args.next().ok_or("Missing value for --warmup")?;
+                warmup = value
+                    .parse()
+                    .map_err(|_| format!("Invalid iteration count for --warmup: {}", value))?;
+            }
+            "--minify" => minify = true,
+            "--width" => {
+                let value = args.next().ok_or("Missing value for --width")?;
+                width = value
+                    .parse()
+                    .ok()
+                    .filter(|x| *x > 0)
+                    .ok_or(format!("Invalid column count for --width: {}", value))?;
+            }
+            "--program-encoding" => {
+                let value = args.next().ok_or("Missing value for --program-encoding")?;
+                let encoding = ProgramEncoding::from_name(&value).ok_or(format!(
+                    "Invalid encoding for --program-encoding: {}",
+                    value
+                ))?;
+                program_encoding = Some(encoding);
+            }
+            "--costs" => {
+                let value = args.next().ok_or("Missing value for --costs")?;
+                let model = CostModel::parse(&value)
+                    .map_err(|e| format!("Invalid cost model for --costs: {:?}", e))?;
+                costs = Some(model);
+            }
+            "--break-on-output" => {
+                let value = args.next().ok_or("Missing value for --break-on-output")?;
+                let pattern = match value.strip_prefix("0x") {
+                    Some(digits) => parse_hex(digits),
+                    None => unescape(&value).ok(),
+                }
+                .filter(|x| !x.is_empty())
+                .ok_or_else(|| format!("Invalid output for --break-on-output: {}", value))?;
+                break_on_output = Some(pattern);
+            }
+            "-O0" => passes = PassManager::level(0),
+            "-O1" => passes = PassManager::level(1),
+            "-O2" => passes = PassManager::level(2),
+            "--enable-pass" => {
+                let value = args.next().ok_or("Missing value for --enable-pass")?;
+                let pass = Pass::from_name(&value)
+                    .ok_or_else(|| format!("Unknown pass for --enable-pass: {}", value))?;
+                passes.enable(pass);
+            }
+            "--disable-pass" => {
+                let value = args.next().ok_or("Missing value for --disable-pass")?;
+                let pass = Pass::from_name(&value)
+                    .ok_or_else(|| format!("Unknown pass for --disable-pass: {}", value))?;
+                passes.disable(pass);
+            }
+            "--top" => {
+                let value = args.next().ok_or("Missing value for --top")?;
+                top = value
+                    .parse()
+                    .map_err(|_| format!("Invalid count for --top: {}", value))?;
+            }
+            "--assertions" => {
+                assertions_path = Some(args.next().ok_or("Missing value for --assertions")?);
+            }
+            "--trace" => trace = trace.or(Some(None)),
+            "--trace-filter" => {
+                let value = args.next().ok_or("Missing value for --trace-filter")?;
+                let filter = Filter::parse(&value)
+                    .map_err(|e| format!("Invalid trace filter {:?}: {:?}", value, e))?;
+                trace = Some(Some(filter));
+            }
+            "--control" => {
+                control_path = Some(args.next().ok_or("Missing value for --control")?);
+            }
+            "--sample" => {
+                let value = args.next().ok_or("Missing value for --sample")?;
+                let interval = value
+                    .parse()
+                    .map_err(|_| format!("Invalid instruction count for --sample: {}", value))?;
+                sample_interval = Some(interval);
+            }
+            "--origin" => {
+                let value = args.next().ok_or("Missing value for --origin")?;
+                origin = value
+                    .parse()
+                    .map_err(|_| format!("Invalid cell index for --origin: {}", value))?;
+            }
+            "--expect-tape" => {
+                expected_tape_path = Some(args.next().ok_or("Missing value for --expect-tape")?);
+            }
+            "--tape-from" => {
+                tape_from_path = Some(args.next().ok_or("Missing value for --tape-from")?);
+            }
+            "--dump-tape-to" => {
+                dump_tape_path = Some(args.next().ok_or("Missing value for --dump-tape-to")?);
+            }
+            "--eof" => {
+                let value = args.next().ok_or("Missing value for --eof")?;
+                eof = match value.as_str() {
+                    "error" => EofMode::Error,
+                    "0" => EofMode::Zero,
+                    "-1" | "255" => EofMode::MinusOne,
+                    "unchanged" => EofMode::Unchanged,
+                    _ => {
+                        return Err(format!(
+                            "Invalid end of input behavior for --eof: {}",
+                            value
+                        ))
+                    }
+                };
+            }
+            "--fuel" => {
+                let value = args.next().ok_or("Missing value for --fuel")?;
+                let steps = value
+                    .parse()
+                    .map_err(|_| format!("Invalid instruction count for --fuel: {}", value))?;
+                fuel = Some(steps);
+            }
+            "--max-cells" => {
+                let value = args.next().ok_or("Missing value for --max-cells")?;
+                let cells = value
+                    .parse()
+                    .map_err(|_| format!("Invalid cell count for --max-cells: {}", value))?;
+                max_cells = Some(cells);
+            }
+            "--max-output" => {
+                let value = args.next().ok_or("Missing value for --max-output")?;
+                let bytes = value
+                    .parse()
+                    .map_err(|_| format!("Invalid byte count for --max-output: {}", value))?;
+                max_output = Some(bytes);
+            }
+            "--timeout" => {
+                let value = args.next().ok_or("Missing value for --timeout")?;
+                let seconds = value
+                    .parse()
+                    .ok()
+                    .and_then(|x| Duration::try_from_secs_f64(x).ok())
+                    .ok_or_else(|| format!("Invalid duration for --timeout: {}", value))?;
+                timeout = Some(seconds);
+            }
+            "--stall-timeout" => {
+                let value = args.next().ok_or("Missing value for --stall-timeout")?;
+                let seconds = value
+                    .parse()
+                    .ok()
+                    .and_then(|x| Duration::try_from_secs_f64(x).ok())
+                    .ok_or_else(|| format!("Invalid duration for --stall-timeout: {}", value))?;
+                stall_timeout = Some(seconds);
+            }
+            "--jobs" => {
+                let value = args.next().ok_or("Missing value for --jobs")?;
+                let count = value
+                    .parse()
+                    .map_err(|_| format!("Invalid thread count for --jobs: {}", value))?;
+                jobs = Some(count);
+            }
+            _ if arg.starts_with("--") => return Err(format!("Unknown option: {}", arg)),
+            _ if source_path.is_none() => source_path = Some(arg),
+            _ => additional_source_paths.push(arg),
+        }
+    }
+
+    let source_path = match (&command, &corpus_path) {
+        (Command::Analyze, Some(_)) => source_path.unwrap_or_default(),
+        (Command::Analyze, None) => return Err("No corpus directory was given".to_string()),
+        _ => source_path.ok_or("No path the source file was given")?,
+    };
+    if let (Command::Debug, None) = (&command, &core_path) {
+        return Err("No core dump was given with --core".to_string());
+    }
+    // The files of the configuration only replace stdin and stdout where the command line
+    // leaves them to the program.
+    let program_stdin = !filter && !no_input && jobs.is_none();
+    if input.is_none() && input_stream.is_none() && !standard_input && program_stdin {
+        input_stream = config_streams.input.map(Stream::Path);
+    }
+    if output_stream.is_none() && !standard_output && !filter && jobs.is_none() {
+        output_stream = config_streams.output.map(Stream::Path);
+    }
+    if no_input && (input.is_some() || input_stream.is_some()) {
+        return Err("--pure runs programs without input".to_string());
+    }
+    if bignum && cell_io != CellIo::Byte {
+        return Err("--bignum always reads and writes decimal numbers".to_string());
+    }
+    if break_on_output.is_some() && cell_io != CellIo::Byte {
+        return Err("--break-on-output only matches byte output".to_string());
+    }
+    if break_on_output.is_some() && exit_cell.is_some() {
+        return Err(
+            "--break-on-output exits with its own status, not --exit-from-cell".to_string(),
+        );
+    }
+    if jobs.is_some() {
+        if !matches!(command, Command::Run) {
+            return Err("--jobs only applies to the run command".to_string());
+        }
+        if input.is_some() || input_stream.is_some() || output_stream.is_some() {
+            return Err(
+                "--jobs reads and writes the .in and .out files next to each program".to_string(),
+            );
+        }
+        if exit_cell.is_some()
+            || dump_tape_path.is_some()
+            || expected_tape_path.is_some()
+            || trace.is_some()
+            || sample_interval.is_some()
+            || events.is_some()
+            || control_path.is_some()
+            || core_dump_path.is_some()
+            || break_on_output.is_some()
+            || bench.is_some()
+            || timings
+            || !matches!(report_format, ReportFormat::Text)
+        {
+            return Err("--jobs only supports plain runs".to_string());
+        }
+    }
+    if filter {
+        if !matches!(command, Command::Run) || jobs.is_some() {
+            return Err("--filter only applies to running a single program".to_string());
+        }
+        if input.is_some() || input_stream.is_some() || output_stream.is_some() {
+            return Err("--filter always reads stdin and writes stdout".to_string());
+        }
+        // Reports and timings go to stderr, but benchmark results are printed to stdout.
+        if bench.is_some()
+            || events.as_ref().is_some_and(is_stdout)
+            || dump_tape_path.as_deref() == Some("/dev/stdout")
+        {
+            return Err("--filter reserves stdout for program output".to_string());
+        }
+    }
+
+    Ok(Options {
+        command,
+        source_path,
+        additional_source_paths,
+        jobs,
+        arguments,
+        exit_cell,
+        origin,
+        input,
+        input_stream,
+        output_stream,
+        lenient,
+        eof,
+        no_input,
+        fuel,
+        max_cells,
+        max_output,
+        timeout,
+        stall_timeout,
+        expected_tape_path,
+        tape_from_path,
+        dump_tape_path,
+        sample_interval,
+        trace,
+        control_path,
+        assertions_path,
+        timings,
+        events,
+        events_every,
+        aliases,
+        counts,
+        bignum,
+        cell_bits,
+        cell_io,
+        corpus_path,
+        ngram_length,
+        top,
+        slice_target,
+        check,
+        loop_at,
+        emit_path,
+        target,
+        after_run,
+        core_dump_path,
+        core_path,
+        filter,
+        tape,
+        engine,
+        report_format,
+        costs,
+        break_on_output,
+        passes: passes.with_cells(match (bignum, cell_bits) {
+            (true, _) => CellKind::Bignum,
+            (false, 16) => CellKind::U16,
+            (false, 32) => CellKind::U32,
+            _ => CellKind::Byte,
+        }),
+        bench,
+        warmup,
+        program_encoding,
+        minify,
+        width,
+    })
+}
+
+/// The input and output files of the project configuration.
+#[derive(Default)]
+struct ConfigStreams {
+    input: Option<String>,
+    output: Option<String>,
+}
+
+/// Turns the project configuration into command line arguments that precede the given ones,
+/// so that options on the command line take precedence, and the files it reads and writes,
+/// which commands that keep to stdin or stdout ignore.
+fn config_args(path: Option<&str>) -> Result<(Vec<String>, ConfigStreams), String> {
+    let (path, text) = match path {
+        Some(x) => (
+            x,
+            fs::read_to_string(x).map_err(|e| format!("{}: {}", x, e))?,
+        ),
+        None => match fs::read_to_string(CONFIG_FILE) {
+            Ok(text) => (CONFIG_FILE, text),
+            Err(_) => return Ok((Vec::new(), ConfigStreams::default())),
+        },
+    };
+    let config = Config::parse(&text).map_err(|e| format!("{}: {:?}", path, e))?;
+
+    let mut args = Vec::new();
+    if let Some(engine) = config.engine {
+        args.extend(["--engine".to_string(), engine.name().to_string()]);
+    }
+    if config.lenient == Some(true) {
+        args.push("--lenient".to_string());
+    }
+    if let Some(eof) = config.eof {
+        let value = match eof {
+            EofMode::Error => "error",
+            EofMode::Zero => "0",
+            EofMode::MinusOne => "255",
+            EofMode::Unchanged => "unchanged",
+        };
+        args.extend(["--eof".to_string(), value.to_string()]);
+    }
+    match config.cells {
+        Some(CellKind::U16) => args.extend(["--cell-bits".to_string(), "16".to_string()]),
+        Some(CellKind::U32) => args.extend(["--cell-bits".to_string(), "32".to_string()]),
+        Some(CellKind::Bignum) => args.push("--bignum".to_string()),
+        Some(CellKind::Byte) | None => (),
+    }
+    if let Some(fuel) = config.fuel {
+        args.extend(["--fuel".to_string(), fuel.to_string()]);
+    }
+    if let Some(origin) = config.origin {
+        args.extend(["--origin".to_string(), origin.to_string()]);
+    }
+    if let Some(path) = config.aliases {
+        args.extend(["--aliases".to_string(), path]);
+    }
+    if let Some(syntax) = config.counts {
+        let value = match syntax {
+            CountSyntax::Prefix => "prefix",
+            CountSyntax::Postfix => "postfix",
+        };
+        args.extend(["--counts".to_string(), value.to_string()]);
+    }
+    if config.timings == Some(true) {
+        args.push("--timings".to_string());
+    }
+    match config.tape {
+        Some(TapeKind::Growing) => args.push("--tape=growing".to_string()),
+        Some(TapeKind::GrowLeft) => args.push("--tape=grow-left".to_string()),
+        Some(TapeKind::Wrap(cells)) => args.push(format!("--tape=wrap:{}", cells)),
+        None => (),
+    }
+    if let Some(level) = config.optimize {
+        args.push(format!("-O{}", level));
+    }
+    for (option, names) in [
+        ("--enable-pass", config.enable_passes),
+        ("--disable-pass", config.disable_passes),
+    ] {
+        for name in names {
+            if Pass::from_name(&name).is_none() {
+                return Err(format!("{}: Unknown pass: {}", path, name));
+            }
+            args.extend([option.to_string(), name]);
+        }
+    }
+    let streams = ConfigStreams {
+        input: config.input,
+        output: config.output,
+    };
+    Ok((args, streams))
+}
+
+/// Reads bytes written as pairs of hex digits, e.g. `0A` or `0D0A`.
+fn parse_hex(digits: &str) -> Option<Vec<u8>> {
+    if !digits.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|x| u8::from_str_radix(digits.get(x..x + 2)?, 16).ok())
+        .collect()
+}
+
+fn unescape(value: &str) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    let mut chars = value.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buffer = [0; 4];
+            bytes.extend_from_slice(c.encode_utf8(&mut buffer).as_bytes());
+            continue;
+        }
+        match chars.next() {
+            Some('n') => bytes.push(b'\n'),
+            Some('r') => bytes.push(b'\r'),
+            Some('t') => bytes.push(b'\t'),
+            Some('0') => bytes.push(0),
+            Some('\\') => bytes.push(b'\\'),
+            Some('x') => {
+                let digits: String = chars.by_ref().take(2).collect();
+                if digits.len() != 2 || !digits.chars().all(|x| x.is_ascii_hexdigit()) {
+                    return Err(format!("Invalid escape sequence: \\x{}", digits));
+                }
+                bytes.push(u8::from_str_radix(&digits, 16).unwrap());
+            }
+            Some(x) => return Err(format!("Invalid escape sequence: \\{}", x)),
+            None => return Err("Unterminated escape sequence".to_string()),
+        }
+    }
+
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(args: &[&str]) -> Options {
+        parse_options(args.iter().map(|x| x.to_string())).unwrap()
+    }
+
+    fn rejected(args: &[&str]) -> bool {
+        parse_options(args.iter().map(|x| x.to_string())).is_err()
+    }
+
+    #[test]
+    fn config_is_only_read_before_the_program_arguments() {
+        assert!(rejected(&["run", "--config", "--", "a.bf"]));
+        assert!(rejected(&["run", "a.bf", "--config"]));
+
+        let options = options(&["run", "a.bf", "--", "--config", "x.toml"]);
+        assert_eq!(options.arguments, b"\x02\x08--config\x06x.toml");
+    }
+
+    #[test]
+    fn jobs_only_run_plain_programs() {
+        assert!(!rejected(&[
+            "run", "--jobs", "2", "a.bf", "b.bf", "--eof", "0"
+        ]));
+        assert!(rejected(&["check", "--jobs", "2", "a.bf"]));
+        assert!(rejected(&["eval", "--jobs", "2", "a.bf"]));
+        assert!(rejected(&["--jobs", "2", "a.bf", "--input-str", "x"]));
+        assert!(rejected(&[
+            "--jobs",
+            "2",
+            "a.bf",
+            "--dump-tape-to",
+            "t.bin"
+        ]));
+        assert!(rejected(&[
+            "--jobs",
+            "2",
+            "a.bf",
+            "--report-format",
+            "json"
+        ]));
+    }
+
+    #[test]
+    fn cell_io_modes_are_checked() {
+        assert_eq!(
+            options(&["a.bf", "--cell-bits", "16", "--cell-io", "le"]).cell_io,
+            CellIo::LittleEndian
+        );
+        assert!(rejected(&["a.bf", "--cell-io", "utf8"]));
+        assert!(rejected(&["a.bf", "--bignum", "--cell-io", "decimal"]));
+        assert!(rejected(&[
+            "a.bf",
+            "--cell-io",
+            "be",
+            "--break-on-output",
+            "0x41"
+        ]));
+    }
+
+    #[test]
+    fn locate_errors_in_sources_that_are_not_utf8() {
+        let source = b"\xe9\xe9\xe9\xe9\xe9\xe9\xe9\xe9\n\n\n+<<";
+        let mut sources = Sources::new();
+        sources.add("latin1.bf", source);
+
+        for options in [
+            options(&["latin1.bf"]),
+            options(&["latin1.bf", "--alias", "a+"]),
+        ] {
+            sources.set_instructions(offsets(&options, &sources));
+            let program = Program::parse(source).unwrap();
+            let mut state = State::with_io(empty(), Vec::new());
+            let error = state.run_ir(program.ir()).unwrap_err();
+
+            assert_eq!(
+                execution_error(error, &options, &sources),
+                "latin1.bf:4:2: PointerUnderflow(1)"
+            );
+        }
+    }
+
+    #[test]
+    fn unescape_hex_bytes() {
+        assert_eq!(unescape("a\\x41\\x0a\\xfF"), Ok(b"aA\n\xff".to_vec()));
+        assert_eq!(unescape("\\n\\0\\\\"), Ok(b"\n\0\\".to_vec()));
+    }
+
+    #[test]
+    fn unescape_rejects_malformed_escapes() {
+        for value in [
+            "\\x4", "\\x", "\\x+f", "\\x-1", "\\x 4", "\\xg0", "\\xé0", "\\q", "\\",
+        ] {
+            assert!(unescape(value).is_err(), "{}", value);
+        }
+    }
+}
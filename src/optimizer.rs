@@ -0,0 +1,747 @@
+use std::collections::HashMap;
+
+use crate::ir::{CellKind, Instruction, Ir};
+
+/// An optimization over the IR. Each one can be turned off on its own to bisect regressions.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Pass {
+    /// Merges runs of `+`, `-`, `>` or `<` into one `Add` or `Move`.
+    Runs,
+    /// Turns `[-]` and `[+]` into `Clear`.
+    ClearLoops,
+    /// Replaces loops that run a known number of times with copies of their body, up to the
+    /// budget. Only loops whose body adds and moves, returns to the loop cell and steps it by
+    /// one are unrolled.
+    #[cfg(feature = "unroll")]
+    Unroll(UnrollBudget),
+    /// Turns changes to a cell with a known value into `Set` and removes loops that start on
+    /// a cell known to be zero, e.g. right after another loop.
+    Constants,
+}
+
+/// How far `Pass::Unroll` unrolls a loop.
+#[cfg(feature = "unroll")]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UnrollBudget {
+    pub max_trips: u64,
+    /// The most instructions all copies of the body may add up to.
+    pub max_instructions: usize,
+}
+
+/// How far `evaluate` got through a program.
+#[derive(Debug, PartialEq)]
+pub enum Evaluation {
+    /// The program ends after writing the output.
+    Finished(Vec<u8>),
+    /// The program runs longer than the fuel allows.
+    OutOfFuel,
+    /// The instruction at the index depends on more than the program, e.g. it reads input or
+    /// moves left of the first cell, so only a run can tell what it does.
+    Stuck(usize),
+}
+
+/// Runs the enabled passes over the IR in the order they were enabled.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PassManager {
+    passes: Vec<Pass>,
+    cells: Option<CellKind>,
+}
+
+impl Pass {
+    pub const ALL: &'static [Pass] = &[
+        Pass::Runs,
+        Pass::ClearLoops,
+        #[cfg(feature = "unroll")]
+        Pass::Unroll(UnrollBudget::DEFAULT),
+        Pass::Constants,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Runs => "runs",
+            Self::ClearLoops => "clear-loops",
+            #[cfg(feature = "unroll")]
+            Self::Unroll(_) => "unroll",
+            Self::Constants => "constants",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|x| x.name() == name)
+    }
+
+    /// Runs the pass for the given cells, or for cells of any kind.
+    pub fn run(&self, ir: &mut Ir, cells: Option<CellKind>) {
+        match self {
+            Self::Runs => merge_runs(ir),
+            Self::ClearLoops => clear_loops(ir),
+            #[cfg(feature = "unroll")]
+            Self::Unroll(budget) => unroll_loops(ir, cells, *budget),
+            Self::Constants => fold_constants(ir, cells),
+        }
+    }
+}
+
+impl PassManager {
+    /// The passes of an optimization level: none at 0, the passes that rewrite single runs and
+    /// loops at 1, and every pass from 2 up.
+    pub fn level(level: u8) -> Self {
+        let passes = match level {
+            0 => Vec::new(),
+            1 => vec![Pass::Runs, Pass::ClearLoops],
+            _ => Pass::ALL.to_vec(),
+        };
+        Self {
+            passes,
+            cells: None,
+        }
+    }
+
+    /// Optimizes for the given cells. Without it, the passes only rely on what cells of every
+    /// kind agree on, e.g. that a cell holding 3 holds 2 after `-`.
+    pub fn with_cells(mut self, cells: CellKind) -> Self {
+        self.cells = Some(cells);
+        self
+    }
+
+    /// Adds the pass after the enabled ones, unless a pass of the same name is enabled.
+    pub fn enable(&mut self, pass: Pass) {
+        if !self.passes.iter().any(|x| x.name() == pass.name()) {
+            self.passes.push(pass);
+        }
+    }
+
+    /// Removes the pass of the same name, whatever its settings.
+    pub fn disable(&mut self, pass: Pass) {
+        self.passes.retain(|x| x.name() != pass.name());
+    }
+
+    pub fn passes(&self) -> &[Pass] {
+        &self.passes
+    }
+
+    pub fn cells(&self) -> Option<CellKind> {
+        self.cells
+    }
+
+    pub fn run(&self, ir: &mut Ir) {
+        for pass in &self.passes {
+            pass.run(ir, self.cells);
+        }
+    }
+}
+
+#[cfg(feature = "unroll")]
+impl UnrollBudget {
+    pub const DEFAULT: Self = Self {
+        max_trips: 8,
+        max_instructions: 64,
+    };
+}
+
+#[cfg(feature = "unroll")]
+impl Default for UnrollBudget {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl Default for PassManager {
+    fn default() -> Self {
+        Self::level(2)
+    }
+}
+
+fn merge_runs(ir: &mut Ir) {
+    let mut merged: Vec<(Instruction, usize, u64)> = Vec::with_capacity(ir.len());
+
+    for (index, instruction) in ir.instructions().iter().enumerate() {
+        let position = ir.position(index);
+        let steps = ir.steps(index);
+        match (merged.last_mut(), instruction) {
+            (Some((Instruction::Add(x), _, total)), Instruction::Add(y))
+                if x.signum() == y.signum() && x.checked_add(*y).is_some() =>
+            {
+                *x += y;
+                *total += steps;
+            }
+            (Some((Instruction::Move(x), _, total)), Instruction::Move(y))
+                if x.signum() == y.signum() && x.checked_add(*y).is_some() =>
+            {
+                *x += y;
+                *total += steps;
+            }
+            _ => merged.push((*instruction, position, steps)),
+        }
+    }
+
+    ir.replace(merged);
+}
+
+fn clear_loops(ir: &mut Ir) {
+    let mut cleared = Vec::with_capacity(ir.len());
+    let mut index = 0;
+
+    while index < ir.len() {
+        let position = ir.position(index);
+        match ir.instructions()[index..] {
+            [Instruction::JumpIfZero(_), Instruction::Add(step @ (1 | -1)), Instruction::JumpIfNonZero(_), ..]
+                if ir.steps(index) == 1 && ir.steps(index + 1) == 1 =>
+            {
+                // The `[` counts as the first step, and whatever the `]` carries along with it.
+                cleared.push((Instruction::Clear(step), position, ir.steps(index + 2)));
+                index += 3;
+            }
+            _ => {
+                cleared.push((ir.instructions()[index], position, ir.steps(index)));
+                index += 1;
+            }
+        }
+    }
+
+    ir.replace(cleared);
+}
+
+/// The cells with a known value, by their offset from the pointer where nothing was known.
+#[derive(Default)]
+struct KnownCells {
+    values: HashMap<isize, i64>,
+    pointer: isize,
+    /// Whether cells without a value hold 0, as on a fresh tape.
+    blank: bool,
+}
+
+impl KnownCells {
+    fn blank() -> Self {
+        Self {
+            blank: true,
+            ..Self::default()
+        }
+    }
+
+    fn current(&self) -> Option<i64> {
+        let value = self.values.get(&self.pointer).copied();
+        value.or(self.blank.then_some(0))
+    }
+
+    fn set_current(&mut self, value: Option<i64>) {
+        match value {
+            Some(x) => self.values.insert(self.pointer, x),
+            None => self.values.remove(&self.pointer),
+        };
+    }
+
+    /// Follows an instruction that runs as it is.
+    fn run(&mut self, instruction: Instruction, cells: Option<CellKind>) {
+        match instruction {
+            Instruction::Add(x) => {
+                self.set_current(self.current().and_then(|value| add(value, x.into(), cells)))
+            }
+            Instruction::Move(x) => self.pointer = self.pointer.wrapping_add(x),
+            Instruction::Set(x) => self.set_current(Some(x.into())),
+            Instruction::Clear(_) => self.set_current(Some(0)),
+            Instruction::Input => self.set_current(None),
+            Instruction::Output | Instruction::Assert => (),
+            // The body also runs after `]` jumps back, when nothing is known.
+            Instruction::JumpIfZero(_) => self.values.clear(),
+            Instruction::JumpIfNonZero(_) => {
+                self.values.clear();
+                self.set_current(Some(0));
+            }
+        }
+    }
+}
+
+fn fold_constants(ir: &mut Ir, cells: Option<CellKind>) {
+    let mut folded: Vec<(Instruction, usize, u64)> = Vec::with_capacity(ir.len());
+    let mut known = KnownCells::default();
+    let mut index = 0;
+
+    while let Some(instruction) = ir.get(index) {
+        let position = ir.position(index);
+        let steps = ir.steps(index);
+        index += 1;
+        match (*instruction, known.current()) {
+            (Instruction::JumpIfZero(end), Some(0)) => {
+                skip_loop(&mut folded, ir.steps(end));
+                index = end + 1;
+                continue;
+            }
+            (Instruction::Add(x), Some(value)) => {
+                match add(value, x.into(), cells).map(u8::try_from) {
+                    Some(Ok(byte)) => set(&mut folded, byte, position, steps),
+                    _ => folded.push((*instruction, position, steps)),
+                }
+            }
+            (Instruction::Set(x), _) => set(&mut folded, x, position, steps),
+            (Instruction::Clear(step), Some(value)) => {
+                match iterations_to_zero(value, step, cells) {
+                    Some(x) => set(&mut folded, 0, position, steps + 2 * x),
+                    None => folded.push((*instruction, position, steps)),
+                }
+            }
+            _ => folded.push((*instruction, position, steps)),
+        }
+        known.run(*instruction, cells);
+    }
+
+    ir.replace(folded);
+}
+
+/// Evaluates a program on a fresh tape at compile time. Like the `constants` pass it follows
+/// the known values of the cells, but it also follows the jumps, since every value is known
+/// from the start, which folds the whole output of a program that does not read input into
+/// a constant. Steps are counted like `State::run_ir` counts them, up to the fuel.
+pub fn evaluate(ir: &Ir, cells: CellKind, fuel: u64) -> Evaluation {
+    let mut known = KnownCells::blank();
+    let mut output = Vec::new();
+    let mut steps = 0u64;
+    let mut index = 0;
+
+    while let Some(instruction) = ir.get(index) {
+        let value = match known.current() {
+            Some(x) => x,
+            None => return Evaluation::Stuck(index),
+        };
+        let (next, taken) = match *instruction {
+            Instruction::JumpIfZero(end) if value == 0 => (end + 1, ir.steps(end)),
+            Instruction::JumpIfNonZero(start) if value != 0 => (start + 1, ir.steps(start)),
+            Instruction::Clear(step) => match iterations_to_zero(value, step, Some(cells)) {
+                Some(x) => (
+                    index + 1,
+                    ir.steps(index).saturating_add(x.saturating_mul(2)),
+                ),
+                None => return Evaluation::Stuck(index),
+            },
+            Instruction::Move(x) if known.pointer.checked_add(x).is_none_or(|x| x < 0) => {
+                return Evaluation::Stuck(index)
+            }
+            Instruction::Input | Instruction::Assert => return Evaluation::Stuck(index),
+            // Unbounded cells are written as decimal numbers.
+            Instruction::Output if cells.bits().is_none() => return Evaluation::Stuck(index),
+            _ => (index + 1, ir.steps(index)),
+        };
+        steps = match steps.checked_add(taken) {
+            Some(x) if x <= fuel => x,
+            _ => return Evaluation::OutOfFuel,
+        };
+        match *instruction {
+            Instruction::Output => output.push(value as u8),
+            Instruction::JumpIfZero(_) | Instruction::JumpIfNonZero(_) => (),
+            _ => known.run(*instruction, Some(cells)),
+        }
+        index = next;
+    }
+
+    Evaluation::Finished(output)
+}
+
+/// Replaces loops that run a known number of times with copies of their body, when the
+/// body only adds and moves, returns to the loop cell and steps it by one.
+#[cfg(feature = "unroll")]
+fn unroll_loops(ir: &mut Ir, cells: Option<CellKind>, budget: UnrollBudget) {
+    let mut unrolled: Vec<(Instruction, usize, u64)> = Vec::with_capacity(ir.len());
+    let mut known = KnownCells::default();
+    let mut index = 0;
+
+    while let Some(instruction) = ir.get(index) {
+        let trips = match (*instruction, known.current()) {
+            (Instruction::JumpIfZero(end), Some(value)) => {
+                loop_step(&ir.instructions()[index + 1..end])
+                    .and_then(|step| iterations_to_zero(value, step, cells))
+                    .filter(|x| {
+                        *x <= budget.max_trips
+                            && x.saturating_mul((end - index - 1) as u64)
+                                <= budget.max_instructions as u64
+                    })
+                    .map(|x| (x, end))
+            }
+            _ => None,
+        };
+        let (trips, end) = match trips {
+            Some(x) => x,
+            None => {
+                unrolled.push((*instruction, ir.position(index), ir.steps(index)));
+                known.run(*instruction, cells);
+                index += 1;
+                continue;
+            }
+        };
+
+        // The `[` runs once, and every `]` but the last jumps back after the `[`.
+        match trips {
+            0 => skip_loop(&mut unrolled, ir.steps(end)),
+            _ => skip_loop(&mut unrolled, ir.steps(index)),
+        }
+        for trip in 0..trips {
+            for body in index + 1..end {
+                unrolled.push((ir[body], ir.position(body), ir.steps(body)));
+                known.run(ir[body], cells);
+            }
+            if let Some((_, _, x)) = unrolled.last_mut() {
+                *x += match trip + 1 == trips {
+                    true => ir.steps(end),
+                    false => ir.steps(index),
+                };
+            }
+        }
+        index = end + 1;
+    }
+
+    ir.replace(unrolled);
+}
+
+/// The step of the loop cell in a loop body that only adds and moves and returns to the loop
+/// cell, if it is 1 or -1.
+#[cfg(feature = "unroll")]
+fn loop_step(body: &[Instruction]) -> Option<i32> {
+    let mut offset = 0isize;
+    let mut step = 0i32;
+    for instruction in body {
+        match *instruction {
+            Instruction::Add(x) if offset == 0 => step = step.checked_add(x)?,
+            Instruction::Add(_) => (),
+            Instruction::Move(x) => offset = offset.checked_add(x)?,
+            _ => return None,
+        }
+    }
+    (offset == 0 && step.abs() == 1).then_some(step)
+}
+
+/// Drops a loop that starts on a known cell, leaving the steps of the code that still runs
+/// with the instruction before it.
+fn skip_loop(kept: &mut [(Instruction, usize, u64)], steps: u64) {
+    if let Some((_, _, x)) = kept.last_mut() {
+        *x += steps;
+    }
+}
+
+/// Stores a value in the current cell, replacing a value stored right before.
+fn set(folded: &mut Vec<(Instruction, usize, u64)>, value: u8, position: usize, steps: u64) {
+    match folded.last_mut() {
+        Some((x @ Instruction::Set(_), _, total)) => {
+            *x = Instruction::Set(value);
+            *total += steps;
+        }
+        _ => folded.push((Instruction::Set(value), position, steps)),
+    }
+}
+
+/// The value of a known cell after adding `amount`, if it is known for the cells.
+fn add(value: i64, amount: i64, cells: Option<CellKind>) -> Option<i64> {
+    let sum = value.checked_add(amount)?;
+    match cells.map(|x| x.bits()) {
+        // Cells of every kind agree as long as the value stays a byte.
+        None => (0..256).contains(&sum).then_some(sum),
+        Some(Some(bits)) => Some(sum.rem_euclid(1 << bits)),
+        Some(None) => Some(sum),
+    }
+}
+
+/// How many times a clear loop with the step runs on a known cell, if it ends.
+fn iterations_to_zero(value: i64, step: i32, cells: Option<CellKind>) -> Option<u64> {
+    let modulus = cells.and_then(|x| x.bits()).map(|x| 1i64 << x);
+    match (step, modulus) {
+        (-1, _) if value >= 0 => Some(value as u64),
+        (1, _) if value <= 0 => Some(value.unsigned_abs()),
+        (1, Some(x)) => Some((x - value) as u64),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::{tokenize, JumpTable};
+
+    fn optimize(source: &str, passes: &PassManager) -> Ir {
+        let tokens = tokenize(source);
+        let jump_table = JumpTable::from(&tokens).unwrap();
+        let mut ir = Ir::lower(&tokens, &jump_table).unwrap();
+        passes.run(&mut ir);
+        ir
+    }
+
+    fn positions(ir: &Ir) -> Vec<usize> {
+        (0..=ir.len()).map(|x| ir.position(x)).collect()
+    }
+
+    #[test]
+    fn runs_pass_collapses_runs() {
+        let ir = optimize("+++>>--<.[--]", &PassManager::level(1));
+
+        assert_eq!(
+            ir.instructions(),
+            vec![
+                Instruction::Add(3),
+                Instruction::Move(2),
+                Instruction::Add(-2),
+                Instruction::Move(-1),
+                Instruction::Output,
+                Instruction::JumpIfZero(7),
+                Instruction::Add(-2),
+                Instruction::JumpIfNonZero(5),
+            ]
+        );
+        assert_eq!(positions(&ir), vec![0, 3, 5, 7, 8, 9, 10, 12, 13]);
+        assert_eq!(ir.instruction_at(3), Some(1));
+        assert_eq!(ir.instruction_at(4), None);
+        assert_eq!(ir.instruction_at(13), Some(8));
+        assert_eq!(ir.steps(0), 3);
+    }
+
+    #[test]
+    fn clear_loops_pass_clears_cells() {
+        let mut passes = PassManager::default();
+        passes.disable(Pass::Constants);
+        let ir = optimize("+[-]>[+]<[>-]", &passes);
+
+        assert_eq!(
+            ir.instructions(),
+            vec![
+                Instruction::Add(1),
+                Instruction::Clear(-1),
+                Instruction::Move(1),
+                Instruction::Clear(1),
+                Instruction::Move(-1),
+                Instruction::JumpIfZero(8),
+                Instruction::Move(1),
+                Instruction::Add(-1),
+                Instruction::JumpIfNonZero(5),
+            ]
+        );
+        assert_eq!(positions(&ir), vec![0, 1, 4, 5, 8, 9, 10, 11, 12, 13]);
+    }
+
+    #[test]
+    fn constants_pass_folds_known_values_and_removes_dead_loops() {
+        let ir = optimize(",[>+<-]>[-]+++<[.]>.", &PassManager::default());
+
+        assert_eq!(
+            ir.instructions(),
+            vec![
+                Instruction::Input,
+                Instruction::JumpIfZero(6),
+                Instruction::Move(1),
+                Instruction::Add(1),
+                Instruction::Move(-1),
+                Instruction::Add(-1),
+                Instruction::JumpIfNonZero(1),
+                Instruction::Move(1),
+                Instruction::Clear(-1),
+                Instruction::Set(3),
+                Instruction::Move(-1),
+                Instruction::Move(1),
+                Instruction::Output,
+            ]
+        );
+        assert_eq!(ir.steps(9), 3);
+        // The `[` of the removed loop runs once.
+        assert_eq!(ir.steps(10), 2);
+    }
+
+    #[test]
+    fn constants_pass_wraps_at_the_cell_width() {
+        let cases = [
+            (None, vec![Instruction::Add(-1), Instruction::Clear(1)]),
+            (Some(CellKind::Byte), vec![Instruction::Set(0)]),
+            (
+                Some(CellKind::U16),
+                vec![Instruction::Add(-1), Instruction::Set(0)],
+            ),
+            (
+                Some(CellKind::Bignum),
+                vec![Instruction::Add(-1), Instruction::Set(0)],
+            ),
+        ];
+
+        for (cells, folded) in cases {
+            let passes = match cells {
+                Some(x) => PassManager::default().with_cells(x),
+                None => PassManager::default(),
+            };
+            let ir = optimize(",[-]-[+]", &passes);
+            assert_eq!(ir[1], Instruction::Clear(-1), "{:?}", cells);
+            assert_eq!(ir.instructions()[2..], folded, "{:?}", cells);
+        }
+    }
+
+    #[test]
+    fn evaluate_folds_the_output() {
+        let ir = optimize(
+            "++++++[>++++++++<-]>+.+.[-]<[.]>>+++[-]",
+            &PassManager::default(),
+        );
+
+        assert_eq!(
+            evaluate(&ir, CellKind::Byte, 1000),
+            Evaluation::Finished(b"12".to_vec())
+        );
+        assert_eq!(
+            evaluate(&optimize("-.", &PassManager::level(0)), CellKind::U16, 10),
+            Evaluation::Finished(vec![255])
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "runtime")]
+    fn evaluate_counts_steps_like_the_runtime() {
+        use crate::runtime::State;
+
+        for source in ["+++[-]>++[>+++<-]>[.-]<[.]", "++[>+++[>+<-]<-]>>[-]+[+]"] {
+            let ir = optimize(source, &PassManager::default());
+            let mut state = State::with_io(&[][..], Vec::new());
+            state.run_ir(&ir).unwrap();
+            let steps = state.steps();
+
+            assert_eq!(
+                evaluate(&ir, CellKind::Byte, steps),
+                Evaluation::Finished(state.output().clone())
+            );
+            assert_eq!(
+                evaluate(&ir, CellKind::Byte, steps - 1),
+                Evaluation::OutOfFuel
+            );
+        }
+        assert_eq!(
+            evaluate(
+                &optimize("+[]", &PassManager::level(0)),
+                CellKind::Byte,
+                1000
+            ),
+            Evaluation::OutOfFuel
+        );
+    }
+
+    #[test]
+    fn evaluate_stops_where_the_run_decides() {
+        assert_eq!(
+            evaluate(&optimize("+.,", &PassManager::level(0)), CellKind::Byte, 10),
+            Evaluation::Stuck(2)
+        );
+        assert_eq!(
+            evaluate(
+                &optimize(">+<<", &PassManager::level(1)),
+                CellKind::Byte,
+                10
+            ),
+            Evaluation::Stuck(2)
+        );
+        assert_eq!(
+            evaluate(
+                &optimize("+.", &PassManager::level(0)),
+                CellKind::Bignum,
+                10
+            ),
+            Evaluation::Stuck(1)
+        );
+    }
+
+    #[test]
+    fn pass_manager_toggles_passes() {
+        assert_eq!(PassManager::level(0).passes(), []);
+        assert_eq!(
+            PassManager::level(1).passes(),
+            [Pass::Runs, Pass::ClearLoops]
+        );
+        assert_eq!(PassManager::level(2).passes(), Pass::ALL);
+
+        let mut passes = PassManager::default();
+        passes.disable(Pass::Runs);
+        assert_eq!(passes.passes(), &Pass::ALL[1..]);
+
+        let ir = optimize("++[-]", &passes);
+        assert_eq!(
+            ir.instructions(),
+            vec![
+                Instruction::Add(1),
+                Instruction::Add(1),
+                Instruction::Clear(-1)
+            ]
+        );
+
+        passes.enable(Pass::Runs);
+        passes.enable(Pass::Runs);
+        assert_eq!(passes.passes(), [&Pass::ALL[1..], &[Pass::Runs]].concat());
+        assert_eq!(Pass::from_name("clear-loops"), Some(Pass::ClearLoops));
+        assert_eq!(Pass::from_name("inline"), None);
+    }
+
+    #[cfg(feature = "unroll")]
+    fn unroll(source: &str, cells: CellKind, budget: UnrollBudget) -> Vec<Instruction> {
+        let mut passes = PassManager::level(1).with_cells(cells);
+        passes.enable(Pass::Unroll(budget));
+        optimize(source, &passes).instructions().to_vec()
+    }
+
+    #[cfg(feature = "unroll")]
+    #[test]
+    fn unroll_pass_copies_loops_with_known_trips() {
+        let mut passes = PassManager::level(1).with_cells(CellKind::Byte);
+        passes.enable(Pass::Unroll(UnrollBudget::DEFAULT));
+        let ir = optimize(",[-]++[->+<]>.", &passes);
+
+        let body = [
+            Instruction::Add(-1),
+            Instruction::Move(1),
+            Instruction::Add(1),
+            Instruction::Move(-1),
+        ];
+        assert_eq!(
+            ir.instructions(),
+            [
+                &[
+                    Instruction::Input,
+                    Instruction::Clear(-1),
+                    Instruction::Add(2)
+                ],
+                &body[..],
+                &body[..],
+                &[Instruction::Move(1), Instruction::Output],
+            ]
+            .concat()
+        );
+        // The `[` runs once, then every `]` once per trip.
+        assert_eq!(ir.steps(2), 3);
+        assert_eq!(ir.steps(6), 2);
+        assert_eq!(ir.steps(10), 2);
+    }
+
+    #[cfg(feature = "unroll")]
+    #[test]
+    fn unroll_pass_counts_trips_at_the_cell_width() {
+        let source = ",[-]-[->+<]";
+        let budget = UnrollBudget {
+            max_trips: 255,
+            max_instructions: 1020,
+        };
+
+        assert_eq!(unroll(source, CellKind::Byte, budget).len(), 3 + 255 * 4);
+        assert_eq!(unroll(source, CellKind::U16, budget).len(), 9);
+    }
+
+    #[cfg(feature = "unroll")]
+    #[test]
+    fn unroll_pass_keeps_loops_over_budget_or_with_io() {
+        let source = ",[-]+++[->+<]";
+        let few_trips = UnrollBudget {
+            max_trips: 2,
+            ..UnrollBudget::DEFAULT
+        };
+        let few_instructions = UnrollBudget {
+            max_instructions: 11,
+            ..UnrollBudget::DEFAULT
+        };
+
+        assert_eq!(
+            unroll(source, CellKind::Byte, UnrollBudget::DEFAULT).len(),
+            15
+        );
+        assert_eq!(unroll(source, CellKind::Byte, few_trips).len(), 9);
+        assert_eq!(unroll(source, CellKind::Byte, few_instructions).len(), 9);
+        assert_eq!(
+            unroll(",[-]+++[-.]", CellKind::Byte, UnrollBudget::DEFAULT).len(),
+            7
+        );
+    }
+}
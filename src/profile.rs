@@ -0,0 +1,80 @@
+use std::{collections::HashMap, hash::Hash};
+
+pub struct SampleProfile {
+    interval: u64,
+    countdown: u64,
+    samples: HashMap<usize, u64>,
+    total: u64,
+}
+
+impl SampleProfile {
+    pub fn new(interval: u64) -> Self {
+        let interval = interval.max(1);
+        Self {
+            interval,
+            countdown: interval,
+            samples: HashMap::new(),
+            total: 0,
+        }
+    }
+
+    pub fn tick(&mut self, position: usize) {
+        self.countdown -= 1;
+        if self.countdown == 0 {
+            self.countdown = self.interval;
+            *self.samples.entry(position).or_default() += 1;
+            self.total += 1;
+        }
+    }
+
+    pub fn samples(&self) -> &HashMap<usize, u64> {
+        &self.samples
+    }
+
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    pub fn aggregate<K, F>(&self, span_of: F) -> Vec<(K, u64)>
+    where
+        K: Eq + Hash + Ord,
+        F: Fn(usize) -> K,
+    {
+        let mut spans: HashMap<K, u64> = HashMap::new();
+        for (position, count) in &self.samples {
+            *spans.entry(span_of(*position)).or_default() += count;
+        }
+
+        let mut spans: Vec<_> = spans.into_iter().collect();
+        spans.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        spans
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tick_samples_every_interval() {
+        let mut profile = SampleProfile::new(3);
+
+        for position in 0..10 {
+            profile.tick(position);
+        }
+
+        assert_eq!(profile.total(), 3);
+        assert_eq!(profile.samples(), &HashMap::from([(2, 1), (5, 1), (8, 1)]));
+    }
+
+    #[test]
+    fn aggregate_groups_samples_by_span() {
+        let mut profile = SampleProfile::new(1);
+
+        for position in [0, 1, 1, 4, 5, 5, 5] {
+            profile.tick(position);
+        }
+
+        assert_eq!(profile.aggregate(|x| x / 4), vec![(1, 4), (0, 3)]);
+    }
+}
@@ -0,0 +1,301 @@
+use std::{
+    borrow::Cow,
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+};
+
+use crate::{
+    analysis::{self, LoopInfo},
+    block::{BasicBlocks, BasicBlocksError},
+    ir::{Ir, IrError},
+    lexer::{tokenize, JumpTable, JumpTableError, Span, Token},
+    report::Timings,
+};
+
+#[cfg(feature = "optimizer")]
+use crate::optimizer::PassManager;
+
+/// A checked program, ready to run. The tokens are either owned or borrowed from the caller,
+/// e.g. `Program::from_slice` over a buffer that outlives the program.
+pub struct Program<'a> {
+    tokens: Cow<'a, [Token]>,
+    jump_table: JumpTable,
+    blocks: BasicBlocks,
+    ir: Ir,
+    spans: Vec<Span>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ProgramError {
+    JumpTable(JumpTableError),
+    BasicBlocks(BasicBlocksError),
+    Ir(IrError),
+    /// An error in a program built from tokens with spans, with where it is in the source.
+    Located(Box<ProgramError>, Span),
+}
+
+impl ProgramError {
+    /// Where the error is in the source, for programs built from tokens with spans.
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            Self::Located(_, x) => Some(*x),
+            _ => None,
+        }
+    }
+
+    /// The error without where it is.
+    pub fn error(&self) -> &ProgramError {
+        match self {
+            Self::Located(x, _) => x.error(),
+            x => x,
+        }
+    }
+
+    fn located(self, tokens: &[Token], spans: &[Span]) -> Self {
+        let position = match &self {
+            Self::JumpTable(e) => e.position(tokens),
+            Self::BasicBlocks(BasicBlocksError::UndefinedJumpTarget(x))
+            | Self::Ir(IrError::UndefinedJumpTarget(x)) => Some(*x),
+            Self::Located(..) => None,
+        };
+        match position.and_then(|x| spans.get(x)) {
+            Some(span) => Self::Located(Box::new(self), *span),
+            None => self,
+        }
+    }
+}
+
+impl<'a> Program<'a> {
+    pub fn parse(source: impl AsRef<[u8]>) -> Result<Self, ProgramError> {
+        Self::parse_with_timings(source, &mut Timings::default())
+    }
+
+    pub fn parse_with_timings(
+        source: impl AsRef<[u8]>,
+        timings: &mut Timings,
+    ) -> Result<Self, ProgramError> {
+        let tokens = timings.measure("lex", || tokenize(source));
+        Self::from_tokens_with_timings(tokens, timings)
+    }
+
+    pub fn from_tokens(tokens: Vec<Token>) -> Result<Self, ProgramError> {
+        Self::from_tokens_with_timings(tokens, &mut Timings::default())
+    }
+
+    /// Checks a program without copying its tokens.
+    pub fn from_slice(tokens: &'a [Token]) -> Result<Self, ProgramError> {
+        Self::from_tokens_with_timings(tokens, &mut Timings::default())
+    }
+
+    pub fn from_tokens_with_timings(
+        tokens: impl Into<Cow<'a, [Token]>>,
+        timings: &mut Timings,
+    ) -> Result<Self, ProgramError> {
+        let tokens = tokens.into();
+        let (jump_table, blocks, ir) = check(&tokens, timings)?;
+        Ok(Self::from_parts(tokens, jump_table, blocks, ir))
+    }
+
+    /// Checks tokens from `lexer::tokenize_with_spans` and keeps where they are, so that
+    /// errors building or running the program can carry their span. The spans take 24 bytes
+    /// per instruction on 64-bit targets, which is why `parse` goes without them.
+    pub fn from_spanned_tokens(tokens: Vec<(Token, Span)>) -> Result<Self, ProgramError> {
+        Self::from_spanned_tokens_with_timings(tokens, &mut Timings::default())
+    }
+
+    pub fn from_spanned_tokens_with_timings(
+        tokens: Vec<(Token, Span)>,
+        timings: &mut Timings,
+    ) -> Result<Self, ProgramError> {
+        let (tokens, spans): (Vec<_>, Vec<_>) = tokens.into_iter().unzip();
+        let (jump_table, blocks, ir) =
+            check(&tokens, timings).map_err(|e| e.located(&tokens, &spans))?;
+        Ok(Self {
+            spans,
+            ..Self::from_parts(tokens, jump_table, blocks, ir)
+        })
+    }
+
+    pub fn from_parts(
+        tokens: impl Into<Cow<'a, [Token]>>,
+        jump_table: JumpTable,
+        blocks: BasicBlocks,
+        ir: Ir,
+    ) -> Self {
+        Self {
+            tokens: tokens.into(),
+            jump_table,
+            blocks,
+            ir,
+            spans: Vec::new(),
+        }
+    }
+
+    pub fn tokens(&self) -> &[Token] {
+        &self.tokens
+    }
+
+    pub fn jump_table(&self) -> &JumpTable {
+        &self.jump_table
+    }
+
+    pub fn blocks(&self) -> &BasicBlocks {
+        &self.blocks
+    }
+
+    pub fn ir(&self) -> &Ir {
+        &self.ir
+    }
+
+    /// Where every instruction is in the source, empty unless the program was built from
+    /// tokens with spans.
+    pub fn spans(&self) -> &[Span] {
+        &self.spans
+    }
+
+    /// Lowers the program again and optimizes it with `passes` instead of the default ones.
+    #[cfg(feature = "optimizer")]
+    pub fn set_passes(&mut self, passes: &PassManager) -> Result<(), ProgramError> {
+        self.ir = Ir::lower(&self.tokens, &self.jump_table)
+            .map_err(|e| ProgramError::Ir(e).located(&self.tokens, &self.spans))?;
+        passes.run(&mut self.ir);
+        Ok(())
+    }
+
+    pub fn fingerprint(&self) -> u64 {
+        fingerprint(&self.tokens)
+    }
+
+    pub fn loops(&self) -> Vec<LoopInfo> {
+        analysis::loops(&self.tokens, &self.jump_table)
+    }
+}
+
+fn check(
+    tokens: &[Token],
+    timings: &mut Timings,
+) -> Result<(JumpTable, BasicBlocks, Ir), ProgramError> {
+    let jump_table = timings
+        .measure("jump table", || JumpTable::from(tokens))
+        .map_err(ProgramError::JumpTable)?;
+    let blocks = timings
+        .measure("basic blocks", || BasicBlocks::from(tokens, &jump_table))
+        .map_err(ProgramError::BasicBlocks)?;
+    let ir = timings
+        .measure("ir", || Ir::lower(tokens, &jump_table))
+        .map_err(ProgramError::Ir)?;
+    let ir = timings.measure("optimize", || optimized(ir));
+    Ok((jump_table, blocks, ir))
+}
+
+/// The IR after the default passes, or as lowered without the `optimizer` feature.
+#[cfg(feature = "optimizer")]
+pub(crate) fn optimized(mut ir: Ir) -> Ir {
+    PassManager::default().run(&mut ir);
+    ir
+}
+
+#[cfg(not(feature = "optimizer"))]
+pub(crate) fn optimized(ir: Ir) -> Ir {
+    ir
+}
+
+pub fn fingerprint(tokens: &[Token]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    tokens.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::{tokenize_with_spans, Aliases};
+
+    #[test]
+    fn parse_program() {
+        let program = Program::parse("+[-]").unwrap();
+
+        assert_eq!(program.tokens().len(), 4);
+        assert_eq!(program.jump_table().resolve(&1), Some(&3));
+        assert_eq!(program.blocks().len(), 3);
+        #[cfg(feature = "optimizer")]
+        assert_eq!(program.ir().len(), 2);
+    }
+
+    #[cfg(feature = "optimizer")]
+    #[test]
+    fn parse_removes_dead_loops() {
+        use crate::ir::Instruction;
+
+        let program = Program::parse(",[-][.>]+").unwrap();
+
+        assert_eq!(
+            program.ir().instructions(),
+            [
+                Instruction::Input,
+                Instruction::Clear(-1),
+                Instruction::Set(1)
+            ]
+        );
+    }
+
+    #[test]
+    fn program_from_slice_borrows_tokens() {
+        let tokens = tokenize("+[->+<]");
+        let program = Program::from_slice(&tokens).unwrap();
+
+        assert!(std::ptr::eq(program.tokens(), tokens.as_slice()));
+        assert_eq!(program.jump_table().resolve(&1), Some(&6));
+    }
+
+    #[test]
+    fn parse_program_errors_on_unbalanced_loops() {
+        let program = Program::parse("[");
+
+        assert_eq!(
+            program.err(),
+            Some(ProgramError::JumpTable(JumpTableError::TooManyLoopStarts(
+                1
+            )))
+        );
+    }
+
+    #[test]
+    fn spanned_programs_locate_errors() {
+        let tokens = tokenize_with_spans("+\n [[]", &Aliases::new(), None);
+        let error = Program::from_spanned_tokens(tokens).err().unwrap();
+
+        assert_eq!(
+            error.error(),
+            &ProgramError::JumpTable(JumpTableError::TooManyLoopStarts(1))
+        );
+        assert_eq!(error.span().map(|x| (x.line, x.column)), Some((2, 2)));
+
+        let tokens = tokenize_with_spans("+\n [-]", &Aliases::new(), None);
+        let program = Program::from_spanned_tokens(tokens).unwrap();
+        assert_eq!(program.spans()[1].offset, 3);
+    }
+
+    #[test]
+    fn fingerprint_ignores_comments() {
+        let program = Program::parse("+[-] clear").unwrap();
+        let other = Program::parse("+\n[-]").unwrap();
+        let different = Program::parse("+[+]").unwrap();
+
+        assert_eq!(program.fingerprint(), other.fingerprint());
+        assert_ne!(program.fingerprint(), different.fingerprint());
+    }
+
+    #[test]
+    fn parse_records_compile_stages() {
+        let mut timings = Timings::default();
+
+        Program::parse_with_timings("+[-]", &mut timings).unwrap();
+
+        let stages: Vec<_> = timings.stages.iter().map(|(x, _)| *x).collect();
+        assert_eq!(
+            stages,
+            vec!["lex", "jump table", "basic blocks", "ir", "optimize"]
+        );
+    }
+}
@@ -0,0 +1,127 @@
+use crate::lexer::Token;
+
+/// Removes balanced chunks of the program for as long as `interesting` keeps holding for the
+/// remaining tokens, starting with large chunks and halving their size when none can be removed.
+/// Once single tokens cannot be removed either, loops are replaced by their bodies.
+pub fn reduce<F>(tokens: &[Token], mut interesting: F) -> Vec<Token>
+where
+    F: FnMut(&[Token]) -> bool,
+{
+    let mut current = tokens.to_vec();
+    let mut size = (current.len() / 2).max(1);
+
+    loop {
+        let mut removed = false;
+        let mut start = 0;
+        while start + size <= current.len() {
+            if is_balanced(&current[start..start + size]) {
+                let candidate: Vec<_> = current[..start]
+                    .iter()
+                    .chain(&current[start + size..])
+                    .copied()
+                    .collect();
+                if interesting(&candidate) {
+                    current = candidate;
+                    removed = true;
+                    continue;
+                }
+            }
+            start += 1;
+        }
+
+        match (removed, size) {
+            (true, _) => size = size.min(current.len() / 2).max(1),
+            (false, 1) if unwrap_loops(&mut current, &mut interesting) => {
+                size = (current.len() / 2).max(1)
+            }
+            (false, 1) => return current,
+            (false, _) => size /= 2,
+        }
+    }
+}
+
+fn unwrap_loops<F>(current: &mut Vec<Token>, interesting: &mut F) -> bool
+where
+    F: FnMut(&[Token]) -> bool,
+{
+    let mut unwrapped = false;
+    let mut start = 0;
+
+    while start < current.len() {
+        if current[start] == Token::LoopStart {
+            let end = matching_end(current, start);
+            let mut candidate = current.clone();
+            candidate.remove(end);
+            candidate.remove(start);
+            if interesting(&candidate) {
+                *current = candidate;
+                unwrapped = true;
+                continue;
+            }
+        }
+        start += 1;
+    }
+
+    unwrapped
+}
+
+fn matching_end(tokens: &[Token], start: usize) -> usize {
+    let mut depth = 0usize;
+    for (position, token) in tokens.iter().enumerate().skip(start) {
+        match token {
+            Token::LoopStart => depth += 1,
+            Token::LoopEnd => depth -= 1,
+            _ => continue,
+        }
+        if depth == 0 {
+            return position;
+        }
+    }
+    tokens.len() - 1
+}
+
+fn is_balanced(tokens: &[Token]) -> bool {
+    let mut depth = 0usize;
+    for token in tokens {
+        match token {
+            Token::LoopStart => depth += 1,
+            Token::LoopEnd => match depth.checked_sub(1) {
+                Some(x) => depth = x,
+                None => return false,
+            },
+            _ => (),
+        }
+    }
+    depth == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::tokenize;
+
+    #[test]
+    fn reduce_to_interesting_core() {
+        let tokens = tokenize("++>+[-]<<.>>+++[>.<-]");
+        let reduced = reduce(&tokens, |x| x.contains(&Token::Output));
+
+        assert_eq!(reduced, tokenize("."));
+    }
+
+    #[test]
+    fn reduce_keeps_brackets_balanced() {
+        let tokens = tokenize("+[>+[-]<-]");
+        let reduced = reduce(&tokens, |x| {
+            x.iter().filter(|x| **x == Token::LoopStart).count() == 2
+        });
+
+        assert_eq!(reduced, tokenize("[[]]"));
+    }
+
+    #[test]
+    fn reduce_returns_program_when_nothing_can_be_removed() {
+        let tokens = tokenize("+-");
+
+        assert_eq!(reduce(&tokens, |x| x.len() == 2), tokens);
+    }
+}
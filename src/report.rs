@@ -0,0 +1,272 @@
+use std::{
+    fmt::{self, Write},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    limits::Limit,
+    schema::{quote, RUN_REPORT},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Warning {
+    PointerUnderflowClamped(usize),
+    EndOfInputReadAsZero(usize),
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Timings {
+    pub stages: Vec<(&'static str, Duration)>,
+}
+
+/// What happened during a run, for people (`Display` of the parts) and for scripts
+/// (`to_json` and `to_toml`).
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct RunReport {
+    pub steps: u64,
+    pub output_bytes: u64,
+    /// The summed cost of the executed instructions, when a cost model was set.
+    pub cost: Option<u64>,
+    /// The limit that stopped the run, if any.
+    pub limit: Option<Limit>,
+    /// Each distinct warning once, with how often it came up.
+    pub warnings: Vec<(Warning, u64)>,
+    pub timings: Timings,
+    /// Sample counts per source line, e.g. `("program.bf:3", 12)`, most frequent first.
+    pub profile: Vec<(String, u64)>,
+}
+
+impl Warning {
+    pub fn position(&self) -> usize {
+        match self {
+            Self::PointerUnderflowClamped(x) | Self::EndOfInputReadAsZero(x) => *x,
+        }
+    }
+}
+
+impl RunReport {
+    /// Records a warning, counting repeats of one already recorded so that a warning in a
+    /// loop keeps the report small.
+    pub fn warn(&mut self, warning: Warning) {
+        match self.warnings.iter_mut().find(|(x, _)| *x == warning) {
+            Some((_, count)) => *count += 1,
+            None => self.warnings.push((warning, 1)),
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        let mut json = format!(
+            r#"{{"schema_version":{},"steps":{},"output_bytes":{},"cost":"#,
+            RUN_REPORT.version, self.steps, self.output_bytes
+        );
+        match self.cost {
+            Some(cost) => json.push_str(&cost.to_string()),
+            None => json.push_str("null"),
+        }
+        json.push_str(r#","limit":"#);
+        match self.limit {
+            Some(limit) => {
+                let (kind, max) = limit_fields(limit);
+                let _ = write!(json, r#"{{"kind":"{}","max":{}}}"#, kind, max);
+            }
+            None => json.push_str("null"),
+        }
+
+        json.push_str(r#","warnings":["#);
+        for (index, (warning, count)) in self.warnings.iter().enumerate() {
+            let _ = write!(
+                json,
+                r#"{}{{"position":{},"message":{},"count":{}}}"#,
+                if index > 0 { "," } else { "" },
+                warning.position(),
+                quote(&warning.to_string()),
+                count
+            );
+        }
+
+        json.push_str(r#"],"timings":{"#);
+        for (index, (stage, duration)) in self.timings.stages.iter().enumerate() {
+            let _ = write!(
+                json,
+                "{}{}:{}",
+                if index > 0 { "," } else { "" },
+                quote(stage),
+                duration.as_secs_f64()
+            );
+        }
+
+        json.push_str(r#"},"profile":["#);
+        for (index, (line, samples)) in self.profile.iter().enumerate() {
+            let _ = write!(
+                json,
+                r#"{}{{"line":{},"samples":{}}}"#,
+                if index > 0 { "," } else { "" },
+                quote(line),
+                samples
+            );
+        }
+        json.push_str("]}");
+        json
+    }
+
+    pub fn to_toml(&self) -> String {
+        let mut toml = format!(
+            "schema_version = {}\nsteps = {}\noutput_bytes = {}\n",
+            RUN_REPORT.version, self.steps, self.output_bytes
+        );
+        if let Some(cost) = self.cost {
+            let _ = writeln!(toml, "cost = {}", cost);
+        }
+        if let Some(limit) = self.limit {
+            let (kind, max) = limit_fields(limit);
+            let _ = write!(toml, "\n[limit]\nkind = \"{}\"\nmax = {}\n", kind, max);
+        }
+        if !self.timings.stages.is_empty() {
+            toml.push_str("\n[timings]\n");
+            for (stage, duration) in &self.timings.stages {
+                let _ = writeln!(toml, "{} = {:?}", quote(stage), duration.as_secs_f64());
+            }
+        }
+        for (warning, count) in &self.warnings {
+            let _ = write!(
+                toml,
+                "\n[[warnings]]\nposition = {}\nmessage = {}\ncount = {}\n",
+                warning.position(),
+                quote(&warning.to_string()),
+                count
+            );
+        }
+        for (line, samples) in &self.profile {
+            let _ = write!(
+                toml,
+                "\n[[profile]]\nline = {}\nsamples = {}\n",
+                quote(line),
+                samples
+            );
+        }
+        toml
+    }
+}
+
+/// The kind of a limit and its maximum, in seconds for the time limit.
+fn limit_fields(limit: Limit) -> (&'static str, String) {
+    match limit {
+        Limit::Steps(x) => ("steps", x.to_string()),
+        Limit::Cells(x) => ("cells", x.to_string()),
+        Limit::Output(x) => ("output", x.to_string()),
+        Limit::Time(x) => ("time", format!("{:?}", x.as_secs_f64())),
+    }
+}
+
+impl Timings {
+    pub fn measure<T>(&mut self, stage: &'static str, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = f();
+        self.stages.push((stage, start.elapsed()));
+        result
+    }
+
+    pub fn total(&self) -> Duration {
+        self.stages.iter().map(|(_, x)| *x).sum()
+    }
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::PointerUnderflowClamped(x) => {
+                write!(f, "pointer underflow at instruction {} clamped to 0", x)
+            }
+            Self::EndOfInputReadAsZero(x) => {
+                write!(f, "end of input at instruction {} read as 0", x)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Timings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (stage, duration) in &self.stages {
+            writeln!(f, "{:<14} {:>12.3?}", stage, duration)?;
+        }
+        writeln!(f, "{:<14} {:>12.3?}", "total", self.total())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_warning() {
+        assert_eq!(
+            Warning::PointerUnderflowClamped(3).to_string(),
+            "pointer underflow at instruction 3 clamped to 0"
+        );
+        assert_eq!(
+            Warning::EndOfInputReadAsZero(5).to_string(),
+            "end of input at instruction 5 read as 0"
+        );
+    }
+
+    #[test]
+    fn serialize_report() {
+        let report = RunReport {
+            steps: 12,
+            output_bytes: 2,
+            cost: Some(30),
+            limit: Some(Limit::Time(Duration::from_millis(500))),
+            warnings: vec![(Warning::EndOfInputReadAsZero(4), 3)],
+            timings: Timings {
+                stages: vec![("lex", Duration::from_millis(1))],
+            },
+            profile: vec![("a \"b\".bf:3".to_string(), 7)],
+        };
+
+        assert_eq!(
+            report.to_json(),
+            r#"{"schema_version":2,"steps":12,"output_bytes":2,"cost":30,"limit":{"kind":"time","max":0.5},"warnings":[{"position":4,"message":"end of input at instruction 4 read as 0","count":3}],"timings":{"lex":0.001},"profile":[{"line":"a \"b\".bf:3","samples":7}]}"#
+        );
+        assert_eq!(
+            report.to_toml(),
+            "schema_version = 2\n\
+             steps = 12\n\
+             output_bytes = 2\n\
+             cost = 30\n\
+             \n\
+             [limit]\n\
+             kind = \"time\"\n\
+             max = 0.5\n\
+             \n\
+             [timings]\n\
+             \"lex\" = 0.001\n\
+             \n\
+             [[warnings]]\n\
+             position = 4\n\
+             message = \"end of input at instruction 4 read as 0\"\n\
+             count = 3\n\
+             \n\
+             [[profile]]\n\
+             line = \"a \\\"b\\\".bf:3\"\n\
+             samples = 7\n"
+        );
+        assert_eq!(
+            RunReport::default().to_json(),
+            r#"{"schema_version":2,"steps":0,"output_bytes":0,"cost":null,"limit":null,"warnings":[],"timings":{},"profile":[]}"#
+        );
+    }
+
+    #[test]
+    fn timings_record_stages_in_order() {
+        let mut timings = Timings::default();
+
+        assert_eq!(timings.measure("lex", || 1), 1);
+        timings.stages.push(("blocks", Duration::from_millis(2)));
+
+        assert_eq!(timings.stages[0].0, "lex");
+        assert_eq!(timings.stages[1].0, "blocks");
+        assert!(timings.total() >= Duration::from_millis(2));
+        assert!(timings.to_string().ends_with("\n"));
+        assert_eq!(timings.to_string().lines().count(), 3);
+    }
+}
@@ -0,0 +1,460 @@
+use std::{
+    io::{self, Cursor, Read, Write},
+    time::Duration,
+};
+
+use crate::{
+    cell::{Cell, CellIo},
+    config::CellKind,
+    cost::CostModel,
+    io::{Io, Streams},
+    lexer::{tokenize, tokenize_with_aliases, tokenize_with_counts, Aliases, CountSyntax},
+    limits::Limits,
+    program::{Program, ProgramError},
+    report::RunReport,
+    runtime::{EofMode, ExecutionError, State},
+    tape::{BidirectionalTape, CircularTape, Tape, VecTape},
+    watchdog::Watchdog,
+    InterpreterError,
+};
+
+#[cfg(feature = "optimizer")]
+use crate::optimizer::PassManager;
+
+/// The loop that executes a program.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Engine {
+    /// The optimized IR, like the `run` command.
+    #[default]
+    Ir,
+    /// The basic blocks of the tokens.
+    Blocks,
+    /// One token at a time.
+    Tokens,
+}
+
+/// How the tape extends, like `--tape`.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum TapeKind {
+    /// Grows to the right as the pointer moves.
+    #[default]
+    Growing,
+    /// Grows in both directions, so moving left of cell 0 is not an error.
+    GrowLeft,
+    /// A fixed number of cells, at least one, where moving past either end wraps around.
+    Wrap(usize),
+}
+
+/// The settings of a run, one field per flag of the `run` command.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunOptions {
+    pub engine: Engine,
+    pub cells: CellKind,
+    pub tape: TapeKind,
+    /// Bytes written to the first cells before the program starts, like `--args`.
+    pub arguments: Vec<u8>,
+    pub origin: usize,
+    pub eof: EofMode,
+    pub lenient: bool,
+    /// How `,` and `.` move cell values, like `--cell-io`.
+    pub cell_io: CellIo,
+    pub limits: Limits,
+    /// Fails a read that gets nothing for this long with `ExecutionError::StalledOnInput`.
+    /// The stalled read itself keeps blocking a thread of `watchdog::Watchdog` until the
+    /// reader returns.
+    pub stall_timeout: Option<Duration>,
+    /// Charges every executed instruction, see `State::set_costs`.
+    pub costs: Option<CostModel>,
+    pub aliases: Aliases,
+    pub counts: Option<CountSyntax>,
+    #[cfg(feature = "optimizer")]
+    pub passes: PassManager,
+}
+
+enum Input {
+    Bytes(Vec<u8>),
+    Reader(Box<dyn Read + Send>),
+}
+
+/// Collects the output, unless the request passes it on to a writer.
+enum Output {
+    Buffer(Vec<u8>),
+    Writer(Box<dyn Write>),
+}
+
+/// A program with its input, output and options, for running the crate like the binary, e.g.
+/// `execute(RunRequest::new(",[.,]").input("abc").options(options))`.
+pub struct RunRequest {
+    source: Vec<u8>,
+    input: Input,
+    output: Output,
+    options: RunOptions,
+}
+
+/// What a run left behind, also when it stopped with an error.
+pub struct RunOutcome {
+    /// The output, or nothing when it went to the writer of the request.
+    pub output: Vec<u8>,
+    /// The cells up to the last one the program touched. Empty for unbounded cells.
+    pub memory: Vec<u32>,
+    pub report: RunReport,
+    pub result: Result<(), InterpreterError>,
+}
+
+impl Engine {
+    pub const ALL: &'static [Engine] = &[Engine::Ir, Engine::Blocks, Engine::Tokens];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Ir => "ir",
+            Self::Blocks => "blocks",
+            Self::Tokens => "tokens",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|x| x.name() == name)
+    }
+}
+
+impl Default for RunOptions {
+    fn default() -> Self {
+        Self {
+            engine: Engine::default(),
+            cells: CellKind::Byte,
+            tape: TapeKind::default(),
+            arguments: Vec::new(),
+            origin: 0,
+            eof: EofMode::default(),
+            lenient: false,
+            cell_io: CellIo::default(),
+            limits: Limits::default(),
+            stall_timeout: None,
+            costs: None,
+            aliases: Aliases::default(),
+            counts: None,
+            #[cfg(feature = "optimizer")]
+            passes: PassManager::default(),
+        }
+    }
+}
+
+impl RunRequest {
+    /// A request without input that collects the output and uses the default options.
+    pub fn new(source: impl Into<Vec<u8>>) -> Self {
+        Self {
+            source: source.into(),
+            input: Input::Bytes(Vec::new()),
+            output: Output::Buffer(Vec::new()),
+            options: RunOptions::default(),
+        }
+    }
+
+    pub fn input(mut self, input: impl Into<Vec<u8>>) -> Self {
+        self.input = Input::Bytes(input.into());
+        self
+    }
+
+    /// Reads the input from a stream, e.g. a socket, instead of a buffer.
+    pub fn input_reader(mut self, input: impl Read + Send + 'static) -> Self {
+        self.input = Input::Reader(Box::new(input));
+        self
+    }
+
+    /// Writes the output to a stream as the program runs instead of collecting it.
+    pub fn output_writer(mut self, output: impl Write + 'static) -> Self {
+        self.output = Output::Writer(Box::new(output));
+        self
+    }
+
+    pub fn options(mut self, options: RunOptions) -> Self {
+        self.options = options;
+        self
+    }
+}
+
+impl RunOutcome {
+    fn failed(output: Output, error: InterpreterError) -> Self {
+        Self {
+            output: output.into_buffer(),
+            memory: Vec::new(),
+            report: RunReport::default(),
+            result: Err(error),
+        }
+    }
+}
+
+impl Output {
+    fn into_buffer(self) -> Vec<u8> {
+        match self {
+            Self::Buffer(x) => x,
+            Self::Writer(_) => Vec::new(),
+        }
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Buffer(x) => x.write(buffer),
+            Self::Writer(x) => x.write(buffer),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Buffer(x) => x.flush(),
+            Self::Writer(x) => x.flush(),
+        }
+    }
+}
+
+/// Parses and runs the program of a request with its options.
+pub fn execute(request: RunRequest) -> RunOutcome {
+    let RunRequest {
+        source,
+        input,
+        output,
+        options,
+    } = request;
+    let program = match parse(&source, &options) {
+        Ok(x) => x,
+        Err(e) => return RunOutcome::failed(output, InterpreterError::Program(e)),
+    };
+    if options.tape == TapeKind::Wrap(0) {
+        return RunOutcome::failed(output, InterpreterError::EmptyTape);
+    }
+    let mut input = match input {
+        Input::Bytes(x) => Box::new(Cursor::new(x)) as Box<dyn Read + Send>,
+        Input::Reader(x) => x,
+    };
+    if let Some(period) = options.stall_timeout {
+        input = Box::new(Watchdog::new(input, period));
+    }
+
+    match options.cells {
+        CellKind::Byte => run_cells::<u8>(&program, &options, input, output, words),
+        CellKind::U16 => run_cells::<u16>(&program, &options, input, output, words),
+        CellKind::U32 => run_cells::<u32>(&program, &options, input, output, words),
+        CellKind::Bignum => run_bignum(&program, &options, input, output),
+    }
+}
+
+fn parse(source: &[u8], options: &RunOptions) -> Result<Program<'static>, ProgramError> {
+    let tokens = match (options.counts, options.aliases.is_empty()) {
+        (Some(syntax), _) => tokenize_with_counts(source, syntax, &options.aliases),
+        (None, false) => tokenize_with_aliases(source, &options.aliases),
+        (None, true) => tokenize(source),
+    };
+    let program = Program::from_tokens(tokens)?;
+    #[cfg(feature = "optimizer")]
+    let program = {
+        let mut program = program;
+        program.set_passes(&options.passes.clone().with_cells(options.cells))?;
+        program
+    };
+    Ok(program)
+}
+
+fn words<C: Into<u32>>(cells: Vec<C>) -> Vec<u32> {
+    cells.into_iter().map(Into::into).collect()
+}
+
+fn run_cells<C: Cell>(
+    program: &Program,
+    options: &RunOptions,
+    input: Box<dyn Read + Send>,
+    output: Output,
+    memory: fn(Vec<C>) -> Vec<u32>,
+) -> RunOutcome {
+    let io = Streams::new(input, output);
+    match options.tape {
+        TapeKind::Growing => run_on(program, options, io, VecTape::<C>::default(), memory),
+        TapeKind::GrowLeft => run_on(
+            program,
+            options,
+            io,
+            BidirectionalTape::<C>::default(),
+            memory,
+        ),
+        TapeKind::Wrap(cells) => {
+            run_on(program, options, io, CircularTape::<C>::new(cells), memory)
+        }
+    }
+}
+
+fn run_on<T: Tape>(
+    program: &Program,
+    options: &RunOptions,
+    io: Streams<Box<dyn Read + Send>, Output>,
+    tape: T,
+    memory: fn(Vec<T::Cell>) -> Vec<u32>,
+) -> RunOutcome {
+    let mut state = State::with_tape(io, tape);
+    state.write_arguments(&options.arguments);
+    state.set_limits(options.limits);
+    state.set_lenient(options.lenient);
+    state.set_eof(options.eof);
+    state.set_cell_io(options.cell_io);
+    if let Some(costs) = options.costs {
+        state.set_costs(costs);
+    }
+
+    let result = state
+        .set_origin(options.origin)
+        .and_then(|_| match options.engine {
+            Engine::Tokens => state.run(program.tokens(), program.jump_table()),
+            Engine::Blocks => state.run_blocks(program.tokens(), program.blocks()),
+            Engine::Ir => state.run_ir(program.ir()),
+        })
+        .and_then(|_| {
+            let position = state.instruction_pointer();
+            state
+                .io_mut()
+                .flush()
+                .map_err(|e| ExecutionError::OutputError(position, e))
+        });
+
+    RunOutcome {
+        memory: memory(state.memory()),
+        report: state.report().clone(),
+        output: state.into_io().into_output().into_buffer(),
+        result: result.map_err(InterpreterError::Execution),
+    }
+}
+
+#[cfg(feature = "bignum")]
+fn run_bignum(
+    program: &Program,
+    options: &RunOptions,
+    input: Box<dyn Read + Send>,
+    output: Output,
+) -> RunOutcome {
+    run_cells::<crate::bignum::BigCell>(program, options, input, output, |_| Vec::new())
+}
+
+#[cfg(not(feature = "bignum"))]
+fn run_bignum(
+    _program: &Program,
+    _options: &RunOptions,
+    _input: Box<dyn Read + Send>,
+    output: Output,
+) -> RunOutcome {
+    RunOutcome::failed(output, InterpreterError::BignumUnavailable)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer::Token, limits::Limit};
+
+    #[test]
+    fn execute_collects_output_and_memory() {
+        let options = RunOptions {
+            cells: CellKind::U16,
+            arguments: vec![7],
+            eof: EofMode::Zero,
+            ..RunOptions::default()
+        };
+        let outcome = execute(RunRequest::new(",[.,]>-").input("ab").options(options));
+
+        assert!(outcome.result.is_ok());
+        assert_eq!(outcome.output, b"ab");
+        assert_eq!(outcome.memory, vec![0, u16::MAX as u32]);
+        assert_eq!(outcome.report.output_bytes, 2);
+    }
+
+    #[test]
+    fn execute_applies_options() {
+        let mut aliases = Aliases::new();
+        aliases.insert('i', Token::Increment);
+        let options = RunOptions {
+            engine: Engine::Blocks,
+            tape: TapeKind::Wrap(3),
+            aliases,
+            limits: Limits::default().with_output(1),
+            ..RunOptions::default()
+        };
+        let outcome = execute(RunRequest::new("<ii.<.").options(options));
+
+        assert_eq!(outcome.output, [2]);
+        assert_eq!(outcome.memory, vec![0, 0, 2]);
+        assert!(matches!(
+            outcome.result,
+            Err(InterpreterError::Execution(ExecutionError::LimitExceeded(
+                5,
+                Limit::Output(1)
+            )))
+        ));
+    }
+
+    #[test]
+    fn execute_charges_costs() {
+        for engine in [Engine::Ir, Engine::Blocks, Engine::Tokens] {
+            let options = RunOptions {
+                engine,
+                costs: Some(CostModel::parse("+:3 ]:2").unwrap()),
+                ..RunOptions::default()
+            };
+            let outcome = execute(RunRequest::new("++[>+<-]>.").options(options));
+
+            assert_eq!(outcome.report.cost, Some(25));
+        }
+    }
+
+    #[cfg(feature = "bignum")]
+    #[test]
+    fn execute_applies_options_to_unbounded_cells() {
+        let options = RunOptions {
+            cells: CellKind::Bignum,
+            arguments: vec![5],
+            limits: Limits::default().with_steps(6),
+            ..RunOptions::default()
+        };
+        let outcome = execute(RunRequest::new("-.[-]").options(options));
+
+        assert_eq!(outcome.output, b"4\n");
+        assert_eq!(outcome.report.steps, 6);
+        assert!(matches!(
+            outcome.result,
+            Err(InterpreterError::Execution(ExecutionError::LimitExceeded(
+                _,
+                Limit::Steps(6)
+            )))
+        ));
+    }
+
+    #[test]
+    fn execute_moves_cells_in_the_cell_io_mode() {
+        let options = RunOptions {
+            cells: CellKind::U16,
+            cell_io: CellIo::BigEndian,
+            ..RunOptions::default()
+        };
+        let outcome = execute(RunRequest::new(",+.").input([1, 255]).options(options));
+
+        assert_eq!(outcome.output, [2, 0]);
+        assert_eq!(outcome.memory, vec![0x200]);
+        assert!(outcome.result.is_ok());
+    }
+
+    #[test]
+    fn execute_reports_program_errors() {
+        let outcome = execute(RunRequest::new("[").output_writer(Vec::new()));
+
+        assert!(matches!(outcome.result, Err(InterpreterError::Program(_))));
+        assert!(outcome.output.is_empty());
+    }
+
+    #[test]
+    fn execute_rejects_empty_wrapping_tapes() {
+        let options = RunOptions {
+            tape: TapeKind::Wrap(0),
+            ..RunOptions::default()
+        };
+        let outcome = execute(RunRequest::new("+.").options(options));
+
+        assert!(matches!(outcome.result, Err(InterpreterError::EmptyTape)));
+        assert!(outcome.output.is_empty());
+    }
+}
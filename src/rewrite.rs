@@ -0,0 +1,150 @@
+use crate::{
+    ir::{Instruction, Ir},
+    lexer::Token,
+};
+
+/// Turns optimized IR back into tokens, simplifying it further on the way: runs of `+` and `-`
+/// or `>` and `<` cancel out, changes to a cell before it is cleared are dropped, and loops that
+/// start on a cell known to be zero are removed, e.g. at the start of the program or right
+/// after another loop. The result does the same as the program and leaves the same tape,
+/// unless moves that cancel out took the pointer left of the first cell.
+pub fn rewrite(ir: &Ir) -> Vec<Token> {
+    let mut simplified: Vec<Instruction> = Vec::with_capacity(ir.len());
+    // Whether the current cell is known to be zero, and whether every cell still is.
+    let mut zero = true;
+    let mut untouched = true;
+    let mut index = 0;
+
+    while let Some(instruction) = ir.get(index) {
+        index += 1;
+        match *instruction {
+            Instruction::Add(x) => {
+                match simplified.last_mut() {
+                    Some(Instruction::Add(y)) => match y.checked_add(x) {
+                        Some(0) => {
+                            simplified.pop();
+                        }
+                        Some(sum) => *y = sum,
+                        None => simplified.push(Instruction::Add(x)),
+                    },
+                    _ => simplified.push(Instruction::Add(x)),
+                }
+                zero = false;
+                untouched = false;
+            }
+            Instruction::Move(x) => {
+                match simplified.last_mut() {
+                    Some(Instruction::Move(y)) => match y.checked_add(x) {
+                        Some(0) => {
+                            simplified.pop();
+                        }
+                        Some(sum) => *y = sum,
+                        None => simplified.push(Instruction::Move(x)),
+                    },
+                    _ => simplified.push(Instruction::Move(x)),
+                }
+                zero = untouched;
+            }
+            // Cells wrap around, so a clear loop always ends with the cell at zero.
+            Instruction::Set(_) | Instruction::Clear(_) => {
+                let x = match *instruction {
+                    Instruction::Set(x) => x,
+                    _ => 0,
+                };
+                // Whether the cell was zero before dropped changes is not tracked.
+                let mut dropped = false;
+                while let Some(Instruction::Add(_) | Instruction::Set(_)) = simplified.last() {
+                    simplified.pop();
+                    dropped = true;
+                }
+                match (zero && !dropped, x) {
+                    (true, 0) => (),
+                    (true, _) => simplified.push(Instruction::Add(x.into())),
+                    (false, _) => simplified.push(Instruction::Set(x)),
+                }
+                zero = x == 0;
+                untouched &= x == 0;
+            }
+            Instruction::Input => {
+                simplified.push(Instruction::Input);
+                zero = false;
+                untouched = false;
+            }
+            Instruction::JumpIfZero(end) if zero => index = end + 1,
+            Instruction::JumpIfZero(_) => {
+                simplified.push(*instruction);
+                zero = false;
+                untouched = false;
+            }
+            Instruction::JumpIfNonZero(_) => {
+                simplified.push(*instruction);
+                zero = true;
+            }
+            Instruction::Output | Instruction::Assert => simplified.push(*instruction),
+        }
+    }
+
+    let mut tokens = Vec::with_capacity(simplified.len());
+    for instruction in simplified {
+        match instruction {
+            Instruction::Add(x) => tokens.extend(std::iter::repeat_n(
+                match x > 0 {
+                    true => Token::Increment,
+                    false => Token::Decrement,
+                },
+                x.unsigned_abs() as usize,
+            )),
+            Instruction::Move(x) => tokens.extend(std::iter::repeat_n(
+                match x > 0 {
+                    true => Token::PointerIncrement,
+                    false => Token::PointerDecrement,
+                },
+                x.unsigned_abs(),
+            )),
+            Instruction::Set(x) => {
+                tokens.extend([Token::LoopStart, Token::Decrement, Token::LoopEnd]);
+                tokens.extend(std::iter::repeat_n(Token::Increment, x as usize));
+            }
+            Instruction::Input => tokens.push(Token::Input),
+            Instruction::Output => tokens.push(Token::Output),
+            Instruction::Assert => tokens.push(Token::Assert),
+            Instruction::JumpIfZero(_) => tokens.push(Token::LoopStart),
+            Instruction::JumpIfNonZero(_) => tokens.push(Token::LoopEnd),
+            Instruction::Clear(_) => unreachable!(),
+        }
+    }
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        lexer::{detokenize, tokenize, JumpTable},
+        optimizer::{Pass, PassManager},
+    };
+
+    fn rewritten(source: &str) -> String {
+        let tokens = tokenize(source);
+        let jump_table = JumpTable::from(&tokens).unwrap();
+        let mut ir = Ir::lower(&tokens, &jump_table).unwrap();
+        // Without `unroll`, whose copies of loop bodies read back longer than the loop.
+        let mut passes = PassManager::level(1);
+        passes.enable(Pass::Constants);
+        passes.run(&mut ir);
+        detokenize(&rewrite(&ir))
+    }
+
+    #[test]
+    fn rewrite_cancels_runs() {
+        assert_eq!(rewritten("+++--.>><<<,+-"), "+.<,");
+        assert_eq!(rewritten("+>-<"), "+>-<");
+    }
+
+    #[test]
+    fn rewrite_drops_dead_loops_and_overwritten_cells() {
+        assert_eq!(rewritten("[.]>[-]++[>+<-][.]<"), ">++[>+<-]<");
+        assert_eq!(rewritten(",+++[+]+[-]-."), ",[-]-.");
+        assert_eq!(rewritten(",[[-]>[.]]"), ",[[-]>[.]]");
+    }
+}
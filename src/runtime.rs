@@ -1,141 +1,1374 @@
-use crate::lexer::{JumpTable, Token};
-use std::io::{stdin, Error, Read};
+use crate::{
+    block::{BasicBlocks, Terminator},
+    cell::{Cell, CellIo},
+    cost::CostModel,
+    instrument::{LoopHooks, Probes},
+    io::{Io, Null, Stdio, Streams},
+    ir::{Instruction, Ir},
+    lexer::{JumpTable, Span, Token},
+    limits::{Limit, Limits},
+    report::{RunReport, Timings, Warning},
+    tape::{FixedTape, Tape, VecTape},
+    watchdog,
+};
+use std::{
+    collections::VecDeque,
+    fmt,
+    io::{stdin, stdout, Error, ErrorKind, Read, Stdout, Write},
+    time::Instant,
+};
 
-pub struct State {
-    memory: Vec<u8>,
+/// Input bytes a copy loop reads at once.
+const COPY_CHUNK: usize = 8192;
+
+pub struct State<I = Stdio, T = VecTape> {
+    memory: T,
     memory_pointer: usize,
     instruction_pointer: usize,
+    io: I,
+    lenient: bool,
+    eof: EofMode,
+    cell_io: CellIo,
+    assertions: VecDeque<u8>,
+    /// Input that a copy loop read ahead of the program, for the next `,` to read.
+    unread: VecDeque<u8>,
+    limits: Limits,
+    deadline: Option<Instant>,
+    costs: Option<CostModel>,
+    report: RunReport,
 }
 
 #[derive(Debug)]
 pub enum ExecutionError {
     EndOfInstructions,
     PointerUnderflow(usize),
+    PointerOverflow(usize),
     UndefinedJumpTarget(usize),
     InputError(usize, Error),
+    /// No input arrived for the period of a `watchdog::Watchdog`.
+    StalledOnInput(usize),
+    OutputError(usize, Error),
+    AssertionFailed(usize, u8, u8),
+    MissingAssertion(usize),
+    LimitExceeded(usize, Limit),
+    /// An error of a run on a program with spans, with where the instruction is in the source.
+    Located(Box<ExecutionError>, Span),
+}
+
+/// What `,` does at the end of input.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum EofMode {
+    #[default]
+    Error,
+    Zero,
+    /// Writes 255, the byte value of -1.
+    MinusOne,
+    Unchanged,
+}
+
+impl ExecutionError {
+    /// The position of the instruction that failed.
+    pub fn position(&self) -> Option<usize> {
+        match self {
+            Self::EndOfInstructions => None,
+            Self::PointerUnderflow(x)
+            | Self::PointerOverflow(x)
+            | Self::UndefinedJumpTarget(x)
+            | Self::InputError(x, _)
+            | Self::StalledOnInput(x)
+            | Self::OutputError(x, _)
+            | Self::AssertionFailed(x, _, _)
+            | Self::MissingAssertion(x)
+            | Self::LimitExceeded(x, _) => Some(*x),
+            Self::Located(x, _) => x.position(),
+        }
+    }
+
+    /// Where the instruction that failed is in the source, once the error is `located`.
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            Self::Located(_, x) => Some(*x),
+            _ => None,
+        }
+    }
+
+    /// The error without where it happened.
+    pub fn error(&self) -> &ExecutionError {
+        match self {
+            Self::Located(x, _) => x.error(),
+            x => x,
+        }
+    }
+
+    /// Adds where the instruction that failed is, given the spans of the program's
+    /// instructions, e.g. `Program::spans`. Errors without a position stay as they are.
+    pub fn located(self, spans: &[Span]) -> Self {
+        match self.position().and_then(|x| spans.get(x)) {
+            Some(span) if self.span().is_none() => Self::Located(Box::new(self), *span),
+            _ => self,
+        }
+    }
+}
+
+pub type FixedState<const N: usize, I = Stdio> = State<I, FixedTape<N>>;
+
+#[derive(Debug, PartialEq)]
+pub struct CellChange<C = u8> {
+    pub index: usize,
+    pub before: C,
+    pub after: C,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct StateDiff<C = u8> {
+    pub changed_cells: Vec<CellChange<C>>,
+    pub pointer_movement: isize,
+    pub instruction_pointer_delta: isize,
 }
 
 impl State {
     pub fn new() -> Self {
+        Self::with_input(stdin())
+    }
+}
+
+impl<R: Read> State<Streams<R, Stdout>> {
+    pub fn with_input(input: R) -> Self {
+        Self::with_io(input, stdout())
+    }
+}
+
+impl<R: Read, W: Write> State<Streams<R, W>> {
+    pub fn with_io(input: R, output: W) -> Self {
+        Self::with_device(Streams::new(input, output))
+    }
+}
+
+impl<R: Read, W: Write, T: Tape> State<Streams<R, W>, T> {
+    pub fn output(&self) -> &W {
+        self.io.output()
+    }
+
+    pub fn into_output(self) -> W {
+        self.io.into_output()
+    }
+}
+
+impl<I: Io> State<I> {
+    pub fn with_device(io: I) -> Self {
+        Self::with_tape(io, VecTape::new())
+    }
+}
+
+impl<I: Io, T: Tape> State<I, T> {
+    pub fn with_tape(io: I, tape: T) -> Self {
         Self {
-            memory: vec![0],
+            memory: tape,
             memory_pointer: 0,
             instruction_pointer: 0,
+            io,
+            lenient: false,
+            eof: EofMode::Error,
+            cell_io: CellIo::Byte,
+            assertions: VecDeque::new(),
+            unread: VecDeque::new(),
+            limits: Limits::default(),
+            deadline: None,
+            costs: None,
+            report: RunReport::default(),
+        }
+    }
+
+    pub fn set_lenient(&mut self, lenient: bool) {
+        self.lenient = lenient;
+    }
+
+    /// Sets the end of input behavior for when the run is not lenient.
+    pub fn set_eof(&mut self, eof: EofMode) {
+        self.eof = eof;
+    }
+
+    /// Sets how `,` and `.` move cell values, one byte each by default.
+    pub fn set_cell_io(&mut self, cell_io: CellIo) {
+        self.cell_io = cell_io;
+    }
+
+    pub fn set_assertions(&mut self, expected: &[u8]) {
+        self.assertions = expected.iter().copied().collect();
+    }
+
+    /// Caps the resources of the following runs. The time limit counts from this call.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.deadline = limits.time.and_then(|x| Instant::now().checked_add(x));
+        self.limits = limits;
+    }
+
+    pub fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    /// Charges every instruction the following runs execute its cost in the model, adding up
+    /// to `RunReport::cost`. `run_ir` runs the tokens one by one while there is a cost model.
+    pub fn set_costs(&mut self, costs: CostModel) {
+        self.costs = Some(costs);
+        self.report.cost.get_or_insert(0);
+    }
+
+    pub fn set_timings(&mut self, timings: Timings) {
+        self.report.timings = timings;
+    }
+
+    /// Starts the memory pointer at the given cell instead of cell 0, leaving room for moving
+    /// left on tapes that do not extend below 0. Fails if the cell is outside the tape.
+    pub fn set_origin(&mut self, origin: usize) -> Result<(), ExecutionError> {
+        if let Some(max) = self.limits.cells.filter(|x| origin >= *x) {
+            return Err(ExecutionError::LimitExceeded(
+                self.instruction_pointer,
+                Limit::Cells(max),
+            ));
+        }
+        let pointer = isize::try_from(origin)
+            .ok()
+            .and_then(|x| self.memory.move_by(0, x))
+            .ok_or(ExecutionError::PointerOverflow(self.instruction_pointer))?;
+        self.memory_pointer = pointer;
+        Ok(())
+    }
+
+    pub fn report(&self) -> &RunReport {
+        &self.report
+    }
+
+    pub fn io(&self) -> &I {
+        &self.io
+    }
+
+    pub fn io_mut(&mut self) -> &mut I {
+        &mut self.io
+    }
+
+    pub fn into_io(self) -> I {
+        self.io
+    }
+
+    pub fn instruction_pointer(&self) -> usize {
+        self.instruction_pointer
+    }
+
+    /// Number of instructions executed so far, the same for every engine: one step per token
+    /// that runs, where a `]` that repeats its loop continues after the `[` instead of running
+    /// it again.
+    pub fn steps(&self) -> u64 {
+        self.report.steps
+    }
+
+    pub fn memory_pointer(&self) -> usize {
+        self.memory_pointer
+    }
+
+    pub fn memory(&self) -> Vec<T::Cell> {
+        (0..self.memory.len_hint())
+            .map(|index| self.memory.get(index))
+            .collect()
+    }
+
+    pub fn write_arguments(&mut self, arguments: &[u8]) {
+        for (index, argument) in arguments.iter().enumerate() {
+            self.memory.set(index, T::Cell::from_byte(*argument));
+        }
+    }
+
+    pub fn cell(&self, index: usize) -> T::Cell {
+        self.memory.get(index)
+    }
+
+    pub fn snapshot(&self) -> State<Null, T>
+    where
+        T: Clone,
+    {
+        State {
+            memory: self.memory.clone(),
+            memory_pointer: self.memory_pointer,
+            instruction_pointer: self.instruction_pointer,
+            io: Null,
+            lenient: self.lenient,
+            eof: self.eof,
+            cell_io: self.cell_io,
+            assertions: self.assertions.clone(),
+            unread: VecDeque::new(),
+            limits: self.limits,
+            deadline: self.deadline,
+            costs: self.costs,
+            report: self.report.clone(),
+        }
+    }
+
+    pub fn diff<J, U: Tape<Cell = T::Cell>>(&self, other: &State<J, U>) -> StateDiff<T::Cell> {
+        let length = self.memory.len_hint().max(other.memory.len_hint());
+        let changed_cells = (0..length)
+            .map(|index| CellChange {
+                index,
+                before: self.memory.get(index),
+                after: other.memory.get(index),
+            })
+            .filter(|x| x.before != x.after)
+            .collect();
+
+        StateDiff {
+            changed_cells,
+            pointer_movement: other.memory_pointer as isize - self.memory_pointer as isize,
+            instruction_pointer_delta: other.instruction_pointer as isize
+                - self.instruction_pointer as isize,
+        }
+    }
+
+    pub fn can_execute(&self, tokens: &[Token]) -> bool {
+        self.instruction_pointer < tokens.len()
+    }
+
+    pub fn execute_current_instruction(
+        &mut self,
+        tokens: &[Token],
+        jump_table: &JumpTable,
+    ) -> Result<(), ExecutionError> {
+        match tokens.get(self.instruction_pointer) {
+            Some(token) => self.execute(token, jump_table),
+            None => Err(ExecutionError::EndOfInstructions),
+        }
+    }
+
+    pub fn run(&mut self, tokens: &[Token], jump_table: &JumpTable) -> Result<(), ExecutionError> {
+        while let Some(token) = tokens.get(self.instruction_pointer) {
+            self.execute(token, jump_table)?;
+        }
+        Ok(())
+    }
+
+    pub fn run_blocks(
+        &mut self,
+        tokens: &[Token],
+        blocks: &BasicBlocks,
+    ) -> Result<(), ExecutionError> {
+        self.run_blocks_with(tokens, blocks, |_, _| ())
+    }
+
+    /// Runs like `run_blocks`, calling `hook` with the index of the probe whenever execution
+    /// enters a loop body marked by `probes`.
+    pub fn run_probed(
+        &mut self,
+        tokens: &[Token],
+        blocks: &BasicBlocks,
+        probes: &Probes,
+        mut hook: impl FnMut(usize),
+    ) -> Result<(), ExecutionError> {
+        let mut block_probes = vec![None; blocks.len()];
+        for (probe, start) in probes.loop_starts().iter().enumerate() {
+            block_probes[blocks.block_at(start + 1)] = Some(probe);
+        }
+        self.run_blocks_with(tokens, blocks, |_, block| {
+            if let Some(probe) = block_probes[block] {
+                hook(probe);
+            }
+        })
+    }
+
+    /// Runs like `run_blocks`, calling `on_loop_enter` whenever execution reaches the `[` of a
+    /// loop from outside of it and `on_loop_exit` once it leaves the loop again. Loops are
+    /// numbered in source order, like the probes of `Probes::loop_heads`. A run that stops with
+    /// an error does not exit the loops it is in.
+    pub fn run_with_loop_hooks(
+        &mut self,
+        tokens: &[Token],
+        blocks: &BasicBlocks,
+        hooks: &mut impl LoopHooks,
+    ) -> Result<(), ExecutionError> {
+        // The loop of every block that ends with a `[`.
+        let mut loops = vec![None; blocks.len()];
+        let mut count = 0;
+        for (index, loop_id) in loops.iter_mut().enumerate() {
+            if let Terminator::JumpIfZero { .. } = blocks[index].terminator {
+                *loop_id = Some(count);
+                count += 1;
+            }
+        }
+        // The iterations of the loops execution is in, innermost last.
+        let mut iterations = Vec::new();
+
+        self.run_blocks_with(tokens, blocks, |from, to| match blocks[from].terminator {
+            Terminator::JumpIfZero { target, .. } => {
+                let loop_id = loops[from].unwrap();
+                hooks.on_loop_enter(loop_id);
+                match to == target {
+                    true => hooks.on_loop_exit(loop_id, 0),
+                    false => iterations.push(1),
+                }
+            }
+            Terminator::JumpIfNonZero { target, .. } => match to == target {
+                true => {
+                    if let Some(x) = iterations.last_mut() {
+                        *x += 1;
+                    }
+                }
+                false => {
+                    if let Some(x) = iterations.pop() {
+                        hooks.on_loop_exit(loops[target - 1].unwrap(), x);
+                    }
+                }
+            },
+            Terminator::End => (),
+        })
+    }
+
+    /// Runs blocks, calling `enter` with the index of the block that jumped and the index of
+    /// the block it entered.
+    fn run_blocks_with(
+        &mut self,
+        tokens: &[Token],
+        blocks: &BasicBlocks,
+        mut enter: impl FnMut(usize, usize),
+    ) -> Result<(), ExecutionError> {
+        let mut index = blocks.block_at(self.instruction_pointer);
+        // The current cell lives in a local while the blocks run and is only written back to
+        // the tape when the pointer moves, another instruction needs it or the run stops.
+        let mut cell = self.current();
+
+        while let Some(block) = blocks.get(index) {
+            let start = self.instruction_pointer;
+            let from = index;
+            let end = match self.limits.steps {
+                Some(max) => {
+                    let left = usize::try_from(max.saturating_sub(self.report.steps))
+                        .unwrap_or(usize::MAX);
+                    block.end.min(start.saturating_add(left))
+                }
+                None => block.end,
+            };
+            for (position, token) in tokens.iter().enumerate().take(end).skip(start) {
+                if let Err(e) = self.apply_cached(token, position, &mut cell) {
+                    self.instruction_pointer = position;
+                    self.report.steps += (position - start) as u64;
+                    self.charge(&tokens[start..position]);
+                    return Err(e);
+                }
+            }
+            self.instruction_pointer = end;
+            self.report.steps += (end - start) as u64;
+            self.charge(&tokens[start..end]);
+            if end < block.end || block.terminator != Terminator::End {
+                if let Err(e) = self.check_steps(end).and(self.check_deadline(end)) {
+                    self.set_current(cell);
+                    return Err(e);
+                }
+            }
+            index = match block.terminator {
+                Terminator::JumpIfZero {
+                    target,
+                    fallthrough,
+                } => match cell.is_zero() {
+                    true => target,
+                    false => fallthrough,
+                },
+                Terminator::JumpIfNonZero {
+                    target,
+                    fallthrough,
+                } => match cell.is_zero() {
+                    true => fallthrough,
+                    false => target,
+                },
+                Terminator::End => break,
+            };
+            enter(from, index);
+            self.instruction_pointer = blocks[index].start;
+            self.report.steps += 1;
+            self.charge(&tokens[end..end + 1]);
+        }
+        self.set_current(cell);
+        Ok(())
+    }
+
+    /// Runs the lowered program, applying a run of `+`, `-`, `>` or `<` or a clear loop at once.
+    /// Steps, limits and error positions come out as if `run` ran the tokens: where a limit
+    /// stops the run inside an instruction, or a run resumes inside one, the tokens run one by
+    /// one. Without a step limit, the copy loop `[.,]` copies the input in chunks instead of
+    /// byte by byte.
+    pub fn run_ir(&mut self, ir: &Ir) -> Result<(), ExecutionError> {
+        if self.costs.is_some() {
+            // Every instruction is charged its own cost, so the tokens run one by one.
+            while self.instruction_pointer < ir.tokens().len() {
+                self.execute_token(ir)?;
+            }
+            return Ok(());
+        }
+        let mut index = self.run_to_instruction(ir)?;
+
+        while let Some(instruction) = ir.get(index) {
+            let position = self.instruction_pointer;
+            let zero = self.current().is_zero();
+            let (next, steps) = match *instruction {
+                Instruction::Clear(step) => (
+                    index + 1,
+                    self.current()
+                        .iterations_to_zero(step)
+                        .and_then(|x| x.checked_mul(2))
+                        .and_then(|x| x.checked_add(ir.steps(index))),
+                ),
+                Instruction::JumpIfZero(target) if zero => (target + 1, Some(ir.steps(target))),
+                Instruction::JumpIfNonZero(target) if !zero => (target + 1, Some(ir.steps(target))),
+                _ => (index + 1, Some(ir.steps(index))),
+            };
+            let left = match self.limits.steps {
+                Some(max) => max.saturating_sub(self.report.steps),
+                None => u64::MAX,
+            };
+            let steps = match steps {
+                Some(x) if x <= left => x,
+                // The step limit stops the run inside the instruction, or it is a clear loop
+                // that does not end.
+                _ => {
+                    self.execute_token(ir)?;
+                    index = self.run_to_instruction(ir)?;
+                    continue;
+                }
+            };
+            match *instruction {
+                Instruction::Add(x) => {
+                    let mut cell = self.current();
+                    cell.add_signed(x);
+                    self.set_current(cell);
+                }
+                Instruction::Move(x) => self.move_pointer(position, x)?,
+                Instruction::Set(x) => self.set_current(T::Cell::from_byte(x)),
+                Instruction::Clear(_) => self.set_current(T::Cell::default()),
+                Instruction::Input => self.execute_input(position)?,
+                Instruction::Output => self.execute_output(position)?,
+                Instruction::Assert => self.execute_assert(position)?,
+                Instruction::JumpIfZero(target) => {
+                    self.check_deadline(position)?;
+                    if !zero
+                        && T::Cell::BYTE_IO
+                        && self.cell_io == CellIo::Byte
+                        && self.limits.steps.is_none()
+                        && ir.instructions()[index + 1..target]
+                            == [Instruction::Output, Instruction::Input]
+                        && (index..target).all(|x| ir.steps(x) == 1)
+                    {
+                        self.report.steps += 1;
+                        self.copy_input(position)?;
+                        self.report.steps += ir.steps(target) - 1;
+                        index = target + 1;
+                        self.instruction_pointer = ir.position(index);
+                        continue;
+                    }
+                }
+                Instruction::JumpIfNonZero(_) => self.check_deadline(position)?,
+            }
+            self.report.steps += steps;
+            index = next;
+            self.instruction_pointer = ir.position(index);
+        }
+        Ok(())
+    }
+
+    /// Runs tokens one by one until an instruction of `ir` starts at the instruction pointer,
+    /// and returns its index.
+    fn run_to_instruction(&mut self, ir: &Ir) -> Result<usize, ExecutionError> {
+        loop {
+            match ir.instruction_at(self.instruction_pointer) {
+                Some(x) => return Ok(x),
+                None if self.instruction_pointer >= ir.tokens().len() => return Ok(ir.len()),
+                None => self.execute_token(ir)?,
+            }
+        }
+    }
+
+    /// Executes the token of `ir` at the instruction pointer.
+    fn execute_token(&mut self, ir: &Ir) -> Result<(), ExecutionError> {
+        let position = self.instruction_pointer;
+        match (ir.tokens().get(position), ir.jump_table()) {
+            (Some(token), Some(jump_table)) => self.execute(token, jump_table),
+            (Some(_), None) => Err(ExecutionError::UndefinedJumpTarget(position)),
+            (None, _) => Err(ExecutionError::EndOfInstructions),
+        }
+    }
+
+    /// Runs the loop `[.,]` at `position` once it is entered. Bytes are read in chunks and the
+    /// ones up to the first zero byte are written at once, keeping what was read past it for
+    /// the next `,`. Steps, the output limit and the errors come out as if the loop ran
+    /// instruction by instruction, but the time limit is only checked once per chunk.
+    fn copy_input(&mut self, position: usize) -> Result<(), ExecutionError> {
+        let mut buffer = vec![0; COPY_CHUNK];
+
+        loop {
+            self.instruction_pointer = position + 1;
+            self.execute_output(position + 1)?;
+            self.report.steps += 1;
+
+            self.instruction_pointer = position + 2;
+            let room = match self.limits.output {
+                Some(max) => max.saturating_sub(self.report.output_bytes),
+                None => u64::MAX,
+            };
+            let length = usize::try_from(room).unwrap_or(usize::MAX).min(COPY_CHUNK);
+            let read = match length {
+                0 => 0,
+                _ => self.read_ahead(&mut buffer[..length], position + 2)?,
+            };
+            if read == 0 {
+                // At the end of input or the output limit, `,` handles one byte at a time.
+                self.execute_input(position + 2)?;
+                self.report.steps += 1;
+            } else {
+                // Every byte up to the first zero is read by `,` and all but the last are
+                // written by `.`, which writes the last one on the next iteration.
+                let copied = buffer[..read]
+                    .iter()
+                    .position(|x| *x == 0)
+                    .map_or(read, |x| x + 1);
+                for byte in buffer[copied..read].iter().rev() {
+                    self.unread.push_front(*byte);
+                }
+                self.io
+                    .write_bytes(&buffer[..copied - 1])
+                    .map_err(|e| ExecutionError::OutputError(position + 1, e))?;
+                self.report.output_bytes += (copied - 1) as u64;
+                self.set_current(T::Cell::from_byte(buffer[copied - 1]));
+                self.report.steps += (3 * copied - 2) as u64;
+            }
+
+            self.instruction_pointer = position + 3;
+            self.check_deadline(position + 3)?;
+            self.report.steps += 1;
+            if self.current().is_zero() {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Reads input for `copy_input`, first what an earlier copy read ahead.
+    fn read_ahead(&mut self, buffer: &mut [u8], position: usize) -> Result<usize, ExecutionError> {
+        if !self.unread.is_empty() {
+            let length = buffer.len().min(self.unread.len());
+            for (x, byte) in buffer.iter_mut().zip(self.unread.drain(..length)) {
+                *x = byte;
+            }
+            return Ok(length);
+        }
+        match self.io.read_bytes(buffer) {
+            Ok(x) => Ok(x),
+            Err(e) if watchdog::is_stalled(&e) => Err(ExecutionError::StalledOnInput(position)),
+            Err(e) => Err(ExecutionError::InputError(position, e)),
+        }
+    }
+
+    /// Moves the pointer by `delta` cells at once, or one cell at a time when a limit or the end
+    /// of the tape could stop the move on the way.
+    fn move_pointer(&mut self, position: usize, delta: isize) -> Result<(), ExecutionError> {
+        let distance = delta.unsigned_abs();
+        let direct = match delta > 0 {
+            true => self.limits.cells.is_none_or(|max| {
+                self.memory_pointer
+                    .checked_add(distance)
+                    .is_some_and(|x| x < max)
+            }),
+            false => self.memory_pointer >= distance,
+        };
+        if let Some(x) = direct
+            .then(|| self.memory.move_by(self.memory_pointer, delta))
+            .flatten()
+        {
+            self.memory_pointer = x;
+            return Ok(());
+        }
+
+        for step in 0..distance {
+            let result = match delta > 0 {
+                true => self.execute_pointer_increment(position + step),
+                false => self.execute_pointer_decrement(position + step),
+            };
+            if let Err(e) = result {
+                self.instruction_pointer = position + step;
+                self.report.steps += step as u64;
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    fn apply_cached(
+        &mut self,
+        token: &Token,
+        position: usize,
+        cell: &mut T::Cell,
+    ) -> Result<(), ExecutionError> {
+        match token {
+            Token::Increment => cell.increment(),
+            Token::Decrement => cell.decrement(),
+            Token::Output | Token::Assert => {
+                self.set_current(cell.clone());
+                self.apply(token, position)?;
+            }
+            _ => {
+                self.set_current(cell.clone());
+                self.apply(token, position)?;
+                *cell = self.current();
+            }
+        }
+        Ok(())
+    }
+
+    fn execute(&mut self, token: &Token, jump_table: &JumpTable) -> Result<(), ExecutionError> {
+        self.check_steps(self.instruction_pointer)?;
+        match token {
+            Token::LoopStart => {
+                self.check_deadline(self.instruction_pointer)?;
+                self.execute_loop_start(jump_table)?
+            }
+            Token::LoopEnd => {
+                self.check_deadline(self.instruction_pointer)?;
+                self.execute_loop_end(jump_table)?
+            }
+            _ => {
+                self.apply(token, self.instruction_pointer)?;
+                self.instruction_pointer += 1;
+            }
+        }
+        self.report.steps += 1;
+        self.charge(std::slice::from_ref(token));
+        Ok(())
+    }
+
+    /// Adds the cost of instructions that ran to the report when there is a cost model.
+    fn charge(&mut self, tokens: &[Token]) {
+        if let (Some(costs), Some(cost)) = (&self.costs, &mut self.report.cost) {
+            *cost += tokens.iter().map(|x| costs.cost(*x)).sum::<u64>();
+        }
+    }
+
+    fn apply(&mut self, token: &Token, position: usize) -> Result<(), ExecutionError> {
+        match token {
+            Token::Increment => self.execute_increment(),
+            Token::Decrement => self.execute_decrement(),
+            Token::PointerIncrement => return self.execute_pointer_increment(position),
+            Token::PointerDecrement => return self.execute_pointer_decrement(position),
+            Token::Input => return self.execute_input(position),
+            Token::Output => return self.execute_output(position),
+            Token::Assert => return self.execute_assert(position),
+            Token::LoopStart | Token::LoopEnd => {
+                return Err(ExecutionError::UndefinedJumpTarget(position))
+            }
+        }
+
+        Ok(())
+    }
+
+    fn check_steps(&mut self, position: usize) -> Result<(), ExecutionError> {
+        match self.limits.steps {
+            Some(max) if self.report.steps >= max => {
+                Err(self.exceeded(position, Limit::Steps(max)))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn check_deadline(&mut self, position: usize) -> Result<(), ExecutionError> {
+        match (self.deadline, self.limits.time) {
+            (Some(deadline), Some(time)) if Instant::now() >= deadline => {
+                Err(self.exceeded(position, Limit::Time(time)))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Records the limit in the report and returns the error stopping the run.
+    fn exceeded(&mut self, position: usize, limit: Limit) -> ExecutionError {
+        self.report.limit = Some(limit);
+        ExecutionError::LimitExceeded(position, limit)
+    }
+
+    #[cfg(not(feature = "unchecked"))]
+    fn current(&self) -> T::Cell {
+        self.memory.get(self.memory_pointer)
+    }
+
+    #[cfg(not(feature = "unchecked"))]
+    fn set_current(&mut self, value: T::Cell) {
+        self.memory.set(self.memory_pointer, value)
+    }
+
+    #[cfg(feature = "unchecked")]
+    fn current(&self) -> T::Cell {
+        // SAFETY: `memory_pointer` only ever changes through `Tape::move_by`.
+        unsafe { self.memory.get_unchecked(self.memory_pointer) }
+    }
+
+    #[cfg(feature = "unchecked")]
+    fn set_current(&mut self, value: T::Cell) {
+        // SAFETY: see `current`.
+        unsafe { self.memory.set_unchecked(self.memory_pointer, value) }
+    }
+
+    fn execute_increment(&mut self) {
+        let mut value = self.current();
+        value.increment();
+        self.set_current(value);
+    }
+
+    fn execute_decrement(&mut self) {
+        let mut value = self.current();
+        value.decrement();
+        self.set_current(value);
+    }
+
+    fn execute_pointer_increment(&mut self, position: usize) -> Result<(), ExecutionError> {
+        if let Some(max) = self.limits.cells.filter(|x| self.memory_pointer + 1 >= *x) {
+            return Err(self.exceeded(position, Limit::Cells(max)));
+        }
+        match self.memory.move_by(self.memory_pointer, 1) {
+            Some(x) => self.memory_pointer = x,
+            None => return Err(ExecutionError::PointerOverflow(position)),
+        }
+        Ok(())
+    }
+
+    fn execute_pointer_decrement(&mut self, position: usize) -> Result<(), ExecutionError> {
+        let length = self.memory.len_hint();
+        match self.memory.move_by(self.memory_pointer, -1) {
+            Some(x) => {
+                self.memory_pointer = x;
+                // Tapes that grow to the left count against the cell limit as well.
+                let grown = self.memory.len_hint();
+                if let Some(max) = self.limits.cells.filter(|x| grown > length && grown > *x) {
+                    return Err(self.exceeded(position, Limit::Cells(max)));
+                }
+            }
+            None if self.lenient => self.report.warn(Warning::PointerUnderflowClamped(position)),
+            None => return Err(ExecutionError::PointerUnderflow(position)),
+        }
+        Ok(())
+    }
+
+    fn execute_loop_start(&mut self, jump_table: &JumpTable) -> Result<(), ExecutionError> {
+        match self.current().is_zero() {
+            true => match jump_table.resolve(&self.instruction_pointer) {
+                Some(x) => self.instruction_pointer = *x + 1,
+                None => {
+                    return Err(ExecutionError::UndefinedJumpTarget(
+                        self.instruction_pointer,
+                    ))
+                }
+            },
+            false => self.instruction_pointer += 1,
+        }
+        Ok(())
+    }
+
+    fn execute_loop_end(&mut self, jump_table: &JumpTable) -> Result<(), ExecutionError> {
+        match self.current().is_zero() {
+            true => self.instruction_pointer += 1,
+            false => match jump_table.resolve(&self.instruction_pointer) {
+                Some(x) => self.instruction_pointer = *x + 1,
+                None => {
+                    return Err(ExecutionError::UndefinedJumpTarget(
+                        self.instruction_pointer,
+                    ))
+                }
+            },
+        }
+        Ok(())
+    }
+
+    fn execute_input(&mut self, position: usize) -> Result<(), ExecutionError> {
+        let value = match self.unread.pop_front() {
+            Some(x) => Ok(Some(T::Cell::from_byte(x))),
+            None => T::Cell::read(&mut self.io, self.cell_io),
+        };
+        match value {
+            Ok(Some(x)) => self.set_current(x),
+            Ok(None) if self.lenient => {
+                self.report.warn(Warning::EndOfInputReadAsZero(position));
+                self.set_current(T::Cell::default());
+            }
+            Ok(None) => match self.eof {
+                EofMode::Error => {
+                    let error = Error::from(ErrorKind::UnexpectedEof);
+                    return Err(ExecutionError::InputError(position, error));
+                }
+                EofMode::Zero => self.set_current(T::Cell::default()),
+                EofMode::MinusOne => {
+                    let mut value = T::Cell::default();
+                    value.decrement();
+                    self.set_current(value);
+                }
+                EofMode::Unchanged => (),
+            },
+            Err(e) if watchdog::is_stalled(&e) => {
+                return Err(ExecutionError::StalledOnInput(position))
+            }
+            Err(e) => return Err(ExecutionError::InputError(position, e)),
+        }
+        Ok(())
+    }
+
+    fn execute_assert(&mut self, position: usize) -> Result<(), ExecutionError> {
+        let expected = match self.assertions.pop_front() {
+            Some(x) => x,
+            None => return Err(ExecutionError::MissingAssertion(position)),
+        };
+        let actual = self.current();
+        match T::Cell::from_byte(expected) == actual {
+            true => Ok(()),
+            false => Err(ExecutionError::AssertionFailed(
+                position,
+                expected,
+                actual.to_byte(),
+            )),
+        }
+    }
+
+    fn execute_output(&mut self, position: usize) -> Result<(), ExecutionError> {
+        if let Some(max) = self
+            .limits
+            .output
+            .filter(|x| self.report.output_bytes >= *x)
+        {
+            return Err(self.exceeded(position, Limit::Output(max)));
+        }
+        match self.current().write(&mut self.io, self.cell_io) {
+            Ok(x) => {
+                self.report.output_bytes += x;
+                Ok(())
+            }
+            Err(e) => Err(ExecutionError::OutputError(position, e)),
+        }
+    }
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C> StateDiff<C> {
+    pub fn is_empty(&self) -> bool {
+        self.changed_cells.is_empty()
+            && self.pointer_movement == 0
+            && self.instruction_pointer_delta == 0
+    }
+}
+
+impl<C: fmt::Display> fmt::Display for StateDiff<C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "ip {:+}", self.instruction_pointer_delta)?;
+        if self.pointer_movement != 0 {
+            writeln!(f, "pointer {:+}", self.pointer_movement)?;
+        }
+        for change in &self.changed_cells {
+            writeln!(
+                f,
+                "cell[{}] {} -> {}",
+                change.index, change.before, change.after
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        io::Buffer,
+        lexer::{tokenize, tokenize_with_spans, Aliases},
+        program::{optimized, Program},
+        tape::BidirectionalTape,
+    };
+    use std::{io::empty, time::Duration};
+
+    #[test]
+    fn can_execute_true_when_tokens_left() {
+        let state = State::new();
+        assert!(state.can_execute(&[Token::Increment]));
+    }
+
+    #[test]
+    fn can_execute_false_when_no_tokens_left() {
+        let state = State::new();
+        assert!(!state.can_execute(&[]));
+    }
+
+    #[test]
+    fn write_arguments_populates_start_of_tape() {
+        let mut state = State::new();
+
+        state.write_arguments(&[42, 7]);
+
+        assert_eq!(state.memory(), vec![42, 7]);
+        assert_eq!(state.memory_pointer, 0);
+    }
+
+    #[test]
+    fn cell_reads_untouched_cells_as_zero() {
+        let mut state = State::new();
+
+        state.write_arguments(&[3]);
+
+        assert_eq!(state.cell(0), 3);
+        assert_eq!(state.cell(100), 0);
+    }
+
+    #[test]
+    fn diff_lists_effects_of_instruction() {
+        let mut state = State::new();
+        let tokens = [Token::PointerIncrement, Token::Increment];
+        let jump_table = JumpTable::from(&tokens).unwrap();
+
+        let before = state.snapshot();
+        state
+            .execute_current_instruction(&tokens, &jump_table)
+            .unwrap();
+        state
+            .execute_current_instruction(&tokens, &jump_table)
+            .unwrap();
+        let diff = before.diff(&state);
+
+        assert_eq!(
+            diff.changed_cells,
+            vec![CellChange {
+                index: 1,
+                before: 0,
+                after: 1
+            }]
+        );
+        assert_eq!(diff.pointer_movement, 1);
+        assert_eq!(diff.instruction_pointer_delta, 2);
+        assert_eq!(diff.to_string(), "ip +2\npointer +1\ncell[1] 0 -> 1\n");
+    }
+
+    #[test]
+    fn diff_of_identical_states_is_empty() {
+        let state = State::new();
+
+        assert!(state.diff(&state.snapshot()).is_empty());
+    }
+
+    #[test]
+    fn run_executes_until_end_of_instructions() {
+        let mut state = State::new();
+        let tokens = [
+            Token::Increment,
+            Token::Increment,
+            Token::LoopStart,
+            Token::PointerIncrement,
+            Token::Increment,
+            Token::PointerDecrement,
+            Token::Decrement,
+            Token::LoopEnd,
+        ];
+        let jump_table = JumpTable::from(&tokens).unwrap();
+
+        let result = state.run(&tokens, &jump_table);
+
+        assert!(result.is_ok());
+        assert_eq!(state.memory(), vec![0, 2]);
+        assert_eq!(state.instruction_pointer, tokens.len());
+    }
+
+    #[test]
+    fn run_keeps_memory_pointer_within_tape() {
+        let mut state = State::new();
+        let tokens = [
+            Token::PointerIncrement,
+            Token::PointerIncrement,
+            Token::Increment,
+            Token::PointerDecrement,
+            Token::PointerIncrement,
+            Token::PointerIncrement,
+            Token::Decrement,
+        ];
+        let jump_table = JumpTable::from(&tokens).unwrap();
+
+        state.run(&tokens, &jump_table).unwrap();
+
+        assert!(state.memory_pointer < state.memory().len());
+        assert_eq!(state.memory(), vec![0, 0, 1, 255]);
+    }
+
+    #[test]
+    fn run_blocks_matches_run() {
+        let tokens = [
+            Token::Increment,
+            Token::Increment,
+            Token::LoopStart,
+            Token::PointerIncrement,
+            Token::Increment,
+            Token::Increment,
+            Token::LoopStart,
+            Token::PointerIncrement,
+            Token::Increment,
+            Token::PointerDecrement,
+            Token::Decrement,
+            Token::LoopEnd,
+            Token::PointerDecrement,
+            Token::Decrement,
+            Token::LoopEnd,
+            Token::LoopStart,
+            Token::LoopEnd,
+        ];
+        let jump_table = JumpTable::from(&tokens).unwrap();
+        let blocks = BasicBlocks::from(&tokens, &jump_table).unwrap();
+
+        let mut expected = State::new();
+        expected.run(&tokens, &jump_table).unwrap();
+        let mut state = State::new();
+        state.run_blocks(&tokens, &blocks).unwrap();
+
+        assert!(expected.diff(&state).is_empty());
+        assert_eq!(state.memory(), vec![0, 0, 4]);
+    }
+
+    #[test]
+    fn run_blocks_reports_error_position() {
+        let mut state = State::new();
+        let tokens = [
+            Token::Increment,
+            Token::LoopStart,
+            Token::PointerDecrement,
+            Token::LoopEnd,
+        ];
+        let jump_table = JumpTable::from(&tokens).unwrap();
+        let blocks = BasicBlocks::from(&tokens, &jump_table).unwrap();
+
+        let result = state.run_blocks(&tokens, &blocks);
+
+        match result.unwrap_err() {
+            ExecutionError::PointerUnderflow(x) => assert_eq!(x, 2),
+            e => panic!("Unexpected error: {:?}", e),
+        };
+        assert_eq!(state.instruction_pointer, 2);
+        assert_eq!(state.cell(0), 1);
+    }
+
+    #[test]
+    fn steps_count_dispatched_instructions() {
+        let tokens = tokenize("++[>+++[>+<-]<-]>>.");
+        let jump_table = JumpTable::from(&tokens).unwrap();
+        let blocks = BasicBlocks::from(&tokens, &jump_table).unwrap();
+        let mut stepped = State::with_io(empty(), Vec::new());
+        let mut blocked = State::with_io(empty(), Vec::new());
+
+        stepped.run(&tokens, &jump_table).unwrap();
+        blocked.run_blocks(&tokens, &blocks).unwrap();
+
+        assert_eq!(blocked.steps(), 52);
+        assert_eq!(stepped.steps(), 52);
+    }
+
+    #[test]
+    fn costs_charge_executed_instructions() {
+        let tokens = tokenize("++[>+++<-]>.");
+        let jump_table = JumpTable::from(&tokens).unwrap();
+        let blocks = BasicBlocks::from(&tokens, &jump_table).unwrap();
+        let ir = optimized(Ir::lower(&tokens, &jump_table).unwrap());
+        let costs = CostModel::default()
+            .with_cost(Token::Output, 10)
+            .with_cost(Token::LoopStart, 0);
+        let mut states: Vec<_> = (0..3)
+            .map(|_| State::with_io(empty(), Vec::new()))
+            .collect();
+        assert_eq!(states[0].report().cost, None);
+
+        for state in &mut states {
+            state.set_costs(costs);
+        }
+        states[0].run(&tokens, &jump_table).unwrap();
+        states[1].run_blocks(&tokens, &blocks).unwrap();
+        states[2].run_ir(&ir).unwrap();
+
+        for state in &states {
+            assert_eq!(state.steps(), 19);
+            assert_eq!(state.report().cost, Some(27));
         }
     }
 
-    pub fn can_execute(&self, tokens: &[Token]) -> bool {
-        self.instruction_pointer < tokens.len()
+    #[test]
+    fn run_blocks_writes_cached_cell_back() {
+        let mut state = State::with_io(empty(), Vec::new());
+        let tokens = tokenize("+++[>++<-]>.+");
+        let jump_table = JumpTable::from(&tokens).unwrap();
+        let blocks = BasicBlocks::from(&tokens, &jump_table).unwrap();
+
+        assert!(state.run_blocks(&tokens, &blocks).is_ok());
+        assert_eq!(state.output(), &vec![6]);
+        assert_eq!(state.memory(), vec![0, 7]);
     }
 
-    pub fn execute_current_instruction(
-        &mut self,
-        tokens: &[Token],
-        jump_table: &JumpTable,
-    ) -> Result<(), ExecutionError> {
-        if !self.can_execute(tokens) {
-            return Err(ExecutionError::EndOfInstructions);
-        }
+    #[test]
+    fn run_blocks_resumes_in_middle_of_block() {
+        let mut state = State::new();
+        let tokens = [Token::Increment, Token::Increment, Token::Increment];
+        let jump_table = JumpTable::from(&tokens).unwrap();
+        let blocks = BasicBlocks::from(&tokens, &jump_table).unwrap();
 
-        match tokens[self.instruction_pointer] {
-            Token::Increment => self.execute_increment(),
-            Token::Decrement => self.execute_decrement(),
-            Token::PointerIncrement => self.execute_pointer_increment(),
-            Token::PointerDecrement => return self.execute_pointer_decrement(),
-            Token::LoopStart => return self.execute_loop_start(jump_table),
-            Token::LoopEnd => return self.execute_loop_end(jump_table),
-            Token::Input => return self.execute_input(),
-            Token::Output => self.execute_output(),
-        }
+        state
+            .execute_current_instruction(&tokens, &jump_table)
+            .unwrap();
+        state.run_blocks(&tokens, &blocks).unwrap();
 
-        Ok(())
+        assert_eq!(state.cell(0), 3);
+        assert_eq!(state.instruction_pointer, 3);
     }
 
-    fn execute_increment(&mut self) {
-        let (value, _carry) = self.memory[self.memory_pointer].overflowing_add(1);
-        self.memory[self.memory_pointer] = value;
-        self.instruction_pointer += 1;
-    }
+    #[test]
+    fn run_with_loop_hooks_reports_iterations() {
+        struct Events(Vec<String>);
 
-    fn execute_decrement(&mut self) {
-        let (value, _borrow) = self.memory[self.memory_pointer].overflowing_sub(1);
-        self.memory[self.memory_pointer] = value;
-        self.instruction_pointer += 1;
-    }
+        impl LoopHooks for Events {
+            fn on_loop_enter(&mut self, loop_id: usize) {
+                self.0.push(format!("enter {}", loop_id));
+            }
 
-    fn execute_pointer_increment(&mut self) {
-        self.memory_pointer += 1;
-        if self.memory.len() == self.memory_pointer {
-            self.memory.push(0);
+            fn on_loop_exit(&mut self, loop_id: usize, iterations: u64) {
+                self.0
+                    .push(format!("exit {} after {}", loop_id, iterations));
+            }
         }
-        self.instruction_pointer += 1;
-    }
 
-    fn execute_pointer_decrement(&mut self) -> Result<(), ExecutionError> {
-        if self.memory_pointer == 0 {
-            return Err(ExecutionError::PointerUnderflow(self.instruction_pointer));
-        }
-        self.memory_pointer -= 1;
-        self.instruction_pointer += 1;
-        Ok(())
+        let mut state = State::with_io(empty(), Vec::new());
+        let tokens = tokenize("++[>++[-]<-]>[]");
+        let jump_table = JumpTable::from(&tokens).unwrap();
+        let blocks = BasicBlocks::from(&tokens, &jump_table).unwrap();
+        let mut events = Events(Vec::new());
+
+        state
+            .run_with_loop_hooks(&tokens, &blocks, &mut events)
+            .unwrap();
+
+        assert_eq!(
+            events.0,
+            [
+                "enter 0",
+                "enter 1",
+                "exit 1 after 2",
+                "enter 1",
+                "exit 1 after 2",
+                "exit 0 after 2",
+                "enter 2",
+                "exit 2 after 0",
+            ]
+        );
     }
 
-    fn execute_loop_start(&mut self, jump_table: &JumpTable) -> Result<(), ExecutionError> {
-        match self.memory[self.memory_pointer] {
-            0 => match jump_table.resolve(&self.instruction_pointer) {
-                Some(x) => self.instruction_pointer = *x + 1,
-                None => {
-                    return Err(ExecutionError::UndefinedJumpTarget(
-                        self.instruction_pointer,
-                    ))
-                }
-            },
-            _ => self.instruction_pointer += 1,
-        }
-        Ok(())
+    #[test]
+    fn run_ir_matches_run_blocks() {
+        let tokens = tokenize("++++[>+++++<-]>[>++>>+<<<-]>.<<<<");
+        let jump_table = JumpTable::from(&tokens).unwrap();
+        let blocks = BasicBlocks::from(&tokens, &jump_table).unwrap();
+        let ir = optimized(Ir::lower(&tokens, &jump_table).unwrap());
+
+        let mut expected = State::with_io(empty(), Vec::new());
+        expected.set_lenient(true);
+        expected.run_blocks(&tokens, &blocks).unwrap();
+        let mut state = State::with_io(empty(), Vec::new());
+        state.set_lenient(true);
+        state.run_ir(&ir).unwrap();
+
+        assert!(expected.diff(&state).is_empty());
+        assert_eq!(state.steps(), expected.steps());
+        assert_eq!(state.report().warnings, expected.report().warnings);
+        assert_eq!(state.output(), &vec![40]);
     }
 
-    fn execute_loop_end(&mut self, jump_table: &JumpTable) -> Result<(), ExecutionError> {
-        match jump_table.resolve(&self.instruction_pointer) {
-            Some(x) => self.instruction_pointer = *x,
-            None => {
-                return Err(ExecutionError::UndefinedJumpTarget(
-                    self.instruction_pointer,
-                ))
-            }
-        };
-        Ok(())
+    #[test]
+    #[cfg(feature = "optimizer")]
+    fn run_ir_counts_every_iteration_of_clear_loops() {
+        let tokens = tokenize("+++[-]>-[+]");
+        let jump_table = JumpTable::from(&tokens).unwrap();
+        let ir = optimized(Ir::lower(&tokens, &jump_table).unwrap());
+        let mut state = State::with_tape(Null, VecTape::<u16>::default());
+
+        state.run_ir(&ir).unwrap();
+
+        assert_eq!(state.memory(), vec![0, 0]);
+        assert_eq!(state.steps(), 15);
     }
 
-    fn execute_input(&mut self) -> Result<(), ExecutionError> {
-        let mut buffer = [0];
-        match stdin().read_exact(&mut buffer) {
-            Ok(()) => self.memory[self.memory_pointer] = buffer[0],
-            Err(e) => return Err(ExecutionError::InputError(self.instruction_pointer, e)),
+    #[test]
+    fn run_ir_copies_input_like_run_blocks() {
+        let tokens = tokenize(",[.,],.>,[.,]");
+        let jump_table = JumpTable::from(&tokens).unwrap();
+        let blocks = BasicBlocks::from(&tokens, &jump_table).unwrap();
+        let ir = optimized(Ir::lower(&tokens, &jump_table).unwrap());
+        let cases = [
+            ("ab\0cd\0ef", EofMode::Zero, Limits::default()),
+            ("abc", EofMode::Zero, Limits::default()),
+            ("abc", EofMode::Error, Limits::default()),
+            ("abcdef", EofMode::Zero, Limits::default().with_output(2)),
+            ("a\0b", EofMode::Unchanged, Limits::default().with_output(4)),
+        ];
+
+        for (input, eof, limits) in cases {
+            let mut expected = State::with_device(Buffer::new(input));
+            expected.set_eof(eof);
+            expected.set_limits(limits);
+            let expected_result = expected.run_blocks(&tokens, &blocks);
+            let mut state = State::with_device(Buffer::new(input));
+            state.set_eof(eof);
+            state.set_limits(limits);
+            let result = state.run_ir(&ir);
+
+            assert_eq!(format!("{:?}", result), format!("{:?}", expected_result));
+            assert_eq!(state.io().output(), expected.io().output());
+            assert_eq!(state.steps(), expected.steps());
+            assert_eq!(state.instruction_pointer, expected.instruction_pointer);
+            assert!(expected.diff(&state).is_empty());
         }
-        Ok(())
     }
 
-    fn execute_output(&mut self) {
-        print!("{}", self.memory[self.memory_pointer] as char);
-        self.instruction_pointer += 1;
-    }
-}
+    #[test]
+    fn execution_error_points_into_source() {
+        let tokens = tokenize_with_spans("+\n <", &Aliases::new(), None);
+        let program = Program::from_spanned_tokens(tokens).unwrap();
+        let mut state = State::with_device(Null);
 
-#[cfg(test)]
-mod test {
-    use super::*;
+        let error = state.run_ir(program.ir()).unwrap_err();
+        assert_eq!(error.span(), None);
 
-    #[test]
-    fn can_execute_true_when_tokens_left() {
-        let state = State::new();
-        assert_eq!(state.can_execute(&[Token::Increment]), true);
+        let error = error.located(program.spans());
+        assert_eq!(error.span().map(|x| (x.line, x.column)), Some((2, 2)));
+        assert_eq!(error.position(), Some(1));
+        assert!(matches!(error.error(), ExecutionError::PointerUnderflow(1)));
     }
 
     #[test]
-    fn can_execute_false_when_no_tokens_left() {
-        let state = State::new();
-        assert_eq!(state.can_execute(&[]), false);
+    fn run_ir_stops_inside_runs() {
+        let tokens = tokenize("+++>>><<<<");
+        let jump_table = JumpTable::from(&tokens).unwrap();
+        let ir = optimized(Ir::lower(&tokens, &jump_table).unwrap());
+
+        let mut state = State::new();
+        state.set_limits(Limits::default().with_steps(2));
+        match state.run_ir(&ir).unwrap_err() {
+            ExecutionError::LimitExceeded(2, Limit::Steps(2)) => (),
+            e => panic!("Unexpected error: {:?}", e),
+        }
+        assert_eq!(state.cell(0), 2);
+
+        state.set_limits(Limits::default().with_cells(2));
+        match state.run_ir(&ir).unwrap_err() {
+            ExecutionError::LimitExceeded(4, Limit::Cells(2)) => (),
+            e => panic!("Unexpected error: {:?}", e),
+        }
+        assert_eq!(state.memory_pointer(), 1);
+        assert_eq!(state.steps(), 4);
+
+        state.set_limits(Limits::default());
+        match state.run_ir(&ir).unwrap_err() {
+            ExecutionError::PointerUnderflow(9) => (),
+            e => panic!("Unexpected error: {:?}", e),
+        }
+        assert_eq!(state.cell(0), 3);
+        assert_eq!(state.instruction_pointer, 9);
     }
 
     #[test]
@@ -147,10 +1380,10 @@ mod test {
         let result = state.execute_current_instruction(&tokens, &jump_table);
 
         assert!(result.is_err());
-        match result.unwrap_err() {
-            ExecutionError::EndOfInstructions => assert!(true),
-            _ => assert!(false),
-        };
+        assert!(matches!(
+            result.unwrap_err(),
+            ExecutionError::EndOfInstructions
+        ));
     }
 
     #[test]
@@ -162,7 +1395,7 @@ mod test {
         let result = state.execute_current_instruction(&tokens, &jump_table);
 
         assert!(result.is_ok());
-        assert_eq!(state.memory[0], 1);
+        assert_eq!(state.cell(0), 1);
         assert_eq!(state.instruction_pointer, 1);
     }
 
@@ -172,11 +1405,11 @@ mod test {
         let tokens = [Token::Increment];
         let jump_table = JumpTable::from(&tokens).unwrap();
 
-        state.memory[0] = 255;
+        state.memory.set(0, 255);
         let result = state.execute_current_instruction(&tokens, &jump_table);
 
         assert!(result.is_ok());
-        assert_eq!(state.memory[0], 0);
+        assert_eq!(state.cell(0), 0);
         assert_eq!(state.instruction_pointer, 1);
     }
 
@@ -186,11 +1419,11 @@ mod test {
         let tokens = [Token::Decrement];
         let jump_table = JumpTable::from(&tokens).unwrap();
 
-        state.memory[0] = 10;
+        state.memory.set(0, 10);
         let result = state.execute_current_instruction(&tokens, &jump_table);
 
         assert!(result.is_ok());
-        assert_eq!(state.memory[0], 9);
+        assert_eq!(state.cell(0), 9);
         assert_eq!(state.instruction_pointer, 1);
     }
 
@@ -203,7 +1436,7 @@ mod test {
         let result = state.execute_current_instruction(&tokens, &jump_table);
 
         assert!(result.is_ok());
-        assert_eq!(state.memory[0], 255);
+        assert_eq!(state.cell(0), 255);
         assert_eq!(state.instruction_pointer, 1);
     }
 
@@ -217,7 +1450,7 @@ mod test {
 
         assert!(result.is_ok());
         assert_eq!(state.memory_pointer, 1);
-        assert_eq!(state.memory.len(), 2);
+        assert_eq!(state.memory().len(), 2);
         assert_eq!(state.instruction_pointer, 1);
     }
 
@@ -228,12 +1461,12 @@ mod test {
         let jump_table = JumpTable::from(&tokens).unwrap();
 
         state.memory_pointer = 2;
-        state.memory = vec![0; 3];
+        state.memory = VecTape::from(vec![0; 3]);
         let result = state.execute_current_instruction(&tokens, &jump_table);
 
         assert!(result.is_ok());
         assert_eq!(state.memory_pointer, 1);
-        assert_eq!(state.memory.len(), 3);
+        assert_eq!(state.memory().len(), 3);
         assert_eq!(state.instruction_pointer, 1);
     }
 
@@ -248,10 +1481,48 @@ mod test {
         assert!(result.is_err());
         match result.unwrap_err() {
             ExecutionError::PointerUnderflow(x) => assert_eq!(x, 0),
-            _ => assert!(false),
+            e => panic!("Unexpected error: {:?}", e),
         };
     }
 
+    #[test]
+    fn pointer_decrement_clamps_underflow_when_lenient() {
+        let mut state = State::new();
+        let tokens = [Token::PointerDecrement];
+        let jump_table = JumpTable::from(&tokens).unwrap();
+
+        state.set_lenient(true);
+        let result = state.execute_current_instruction(&tokens, &jump_table);
+
+        assert!(result.is_ok());
+        assert_eq!(state.memory_pointer, 0);
+        assert_eq!(state.instruction_pointer, 1);
+        assert_eq!(
+            state.report().warnings,
+            vec![(Warning::PointerUnderflowClamped(0), 1)]
+        );
+    }
+
+    #[test]
+    fn repeated_warnings_are_counted() {
+        let mut state = State::new();
+        let tokens = [
+            Token::Increment,
+            Token::LoopStart,
+            Token::PointerDecrement,
+            Token::LoopEnd,
+        ];
+        let jump_table = JumpTable::from(&tokens).unwrap();
+
+        state.set_lenient(true);
+        state.set_limits(Limits::default().with_steps(30_001));
+        assert!(state.run(&tokens, &jump_table).is_err());
+        assert_eq!(
+            state.report().warnings,
+            vec![(Warning::PointerUnderflowClamped(2), 15_000)]
+        );
+    }
+
     #[test]
     fn loop_start_enters_loop_on_none_zero() {
         let mut state = State::new();
@@ -263,7 +1534,7 @@ mod test {
         ];
         let jump_table = JumpTable::from(&tokens).unwrap();
 
-        state.memory[0] = 1;
+        state.memory.set(0, 1);
         let result = state.execute_current_instruction(&tokens, &jump_table);
 
         assert!(result.is_ok());
@@ -304,12 +1575,138 @@ mod test {
         assert!(result.is_err());
         match result.unwrap_err() {
             ExecutionError::UndefinedJumpTarget(x) => assert_eq!(x, 0),
-            _ => assert!(false),
+            e => panic!("Unexpected error: {:?}", e),
+        };
+    }
+
+    #[test]
+    fn input_reads_byte_into_current() {
+        let mut state = State::with_input("a".as_bytes());
+        let tokens = [Token::Input];
+        let jump_table = JumpTable::from(&tokens).unwrap();
+
+        let result = state.execute_current_instruction(&tokens, &jump_table);
+
+        assert!(result.is_ok());
+        assert_eq!(state.cell(0), b'a');
+        assert_eq!(state.instruction_pointer, 1);
+    }
+
+    #[test]
+    fn input_errors_on_end_of_input() {
+        let mut state = State::with_input("".as_bytes());
+        let tokens = [Token::Input];
+        let jump_table = JumpTable::from(&tokens).unwrap();
+
+        let result = state.execute_current_instruction(&tokens, &jump_table);
+
+        assert!(result.is_err());
+        match result.unwrap_err() {
+            ExecutionError::InputError(x, _) => assert_eq!(x, 0),
+            e => panic!("Unexpected error: {:?}", e),
+        };
+    }
+
+    #[test]
+    fn input_reads_end_of_input_as_zero_when_lenient() {
+        let mut state = State::with_input("".as_bytes());
+        let tokens = [Token::Input];
+        let jump_table = JumpTable::from(&tokens).unwrap();
+
+        state.memory.set(0, 7);
+        state.set_lenient(true);
+        let result = state.execute_current_instruction(&tokens, &jump_table);
+
+        assert!(result.is_ok());
+        assert_eq!(state.cell(0), 0);
+        assert_eq!(state.instruction_pointer, 1);
+        assert_eq!(
+            state.report().warnings,
+            vec![(Warning::EndOfInputReadAsZero(0), 1)]
+        );
+    }
+
+    #[test]
+    fn input_applies_eof_policy() {
+        let tokens = [Token::Input];
+        let jump_table = JumpTable::from(&tokens).unwrap();
+
+        for (eof, expected) in [
+            (EofMode::Zero, 0),
+            (EofMode::MinusOne, 255),
+            (EofMode::Unchanged, 7),
+        ] {
+            let mut state = State::with_input("".as_bytes());
+            state.memory.set(0, 7);
+            state.set_eof(eof);
+
+            assert!(state.run(&tokens, &jump_table).is_ok());
+            assert_eq!(state.cell(0), expected);
+            assert!(state.report().warnings.is_empty());
+        }
+    }
+
+    #[test]
+    fn output_writes_current_byte() {
+        let mut state = State::with_io(empty(), Vec::new());
+        let tokens = [Token::Output];
+        let jump_table = JumpTable::from(&tokens).unwrap();
+
+        state.memory.set(0, 200);
+        let result = state.execute_current_instruction(&tokens, &jump_table);
+
+        assert!(result.is_ok());
+        assert_eq!(state.output(), &vec![200]);
+        assert_eq!(state.instruction_pointer, 1);
+    }
+
+    #[test]
+    fn assert_passes_on_expected_value() {
+        let mut state = State::new();
+        let tokens = [Token::Assert];
+        let jump_table = JumpTable::from(&tokens).unwrap();
+
+        state.memory.set(0, 4);
+        state.set_assertions(&[4]);
+        let result = state.execute_current_instruction(&tokens, &jump_table);
+
+        assert!(result.is_ok());
+        assert_eq!(state.instruction_pointer, 1);
+    }
+
+    #[test]
+    fn assert_errors_on_unexpected_value() {
+        let mut state = State::new();
+        let tokens = [Token::Assert];
+        let jump_table = JumpTable::from(&tokens).unwrap();
+
+        state.set_assertions(&[4]);
+        let result = state.execute_current_instruction(&tokens, &jump_table);
+
+        match result.unwrap_err() {
+            ExecutionError::AssertionFailed(position, expected, actual) => {
+                assert_eq!((position, expected, actual), (0, 4, 0))
+            }
+            e => panic!("Unexpected error: {:?}", e),
         };
     }
 
     #[test]
-    fn loop_end_jumps_back_to_start() {
+    fn assert_errors_without_expected_value() {
+        let mut state = State::new();
+        let tokens = [Token::Assert];
+        let jump_table = JumpTable::from(&tokens).unwrap();
+
+        let result = state.execute_current_instruction(&tokens, &jump_table);
+
+        assert!(matches!(
+            result.unwrap_err(),
+            ExecutionError::MissingAssertion(0)
+        ));
+    }
+
+    #[test]
+    fn loop_end_jumps_back_after_start() {
         let mut state = State::new();
         let tokens = [
             Token::LoopStart,
@@ -319,11 +1716,19 @@ mod test {
         ];
         let jump_table = JumpTable::from(&tokens).unwrap();
 
+        state.write_arguments(&[1]);
         state.instruction_pointer = 2;
         let result = state.execute_current_instruction(&tokens, &jump_table);
 
         assert!(result.is_ok());
-        assert_eq!(state.instruction_pointer, 0);
+        assert_eq!(state.instruction_pointer, 1);
+
+        state.write_arguments(&[0]);
+        state.instruction_pointer = 2;
+        state
+            .execute_current_instruction(&tokens, &jump_table)
+            .unwrap();
+        assert_eq!(state.instruction_pointer, 3);
     }
 
     #[test]
@@ -338,13 +1743,219 @@ mod test {
             Token::Decrement,
         ];
 
+        state.write_arguments(&[1]);
         state.instruction_pointer = 2;
         let result = state.execute_current_instruction(&tokens, &jump_table);
 
         assert!(result.is_err());
         match result.unwrap_err() {
             ExecutionError::UndefinedJumpTarget(x) => assert_eq!(x, 2),
-            _ => assert!(false),
+            e => panic!("Unexpected error: {:?}", e),
+        };
+    }
+
+    #[test]
+    fn fixed_state_runs_program() {
+        let mut state: FixedState<4, _> = State::with_tape(Buffer::new(""), FixedTape::new());
+        let tokens = tokenize("++>+++[-<+>]<.");
+        let jump_table = JumpTable::from(&tokens).unwrap();
+
+        assert!(state.run(&tokens, &jump_table).is_ok());
+        assert_eq!(state.io().output(), &[5]);
+        assert_eq!(state.memory(), vec![5, 0, 0, 0]);
+    }
+
+    #[test]
+    fn fixed_state_errors_on_overflow() {
+        let mut state: FixedState<2, _> = State::with_tape(Null, FixedTape::new());
+        let tokens = tokenize(">>");
+        let jump_table = JumpTable::from(&tokens).unwrap();
+
+        match state.run(&tokens, &jump_table).unwrap_err() {
+            ExecutionError::PointerOverflow(x) => assert_eq!(x, 1),
+            e => panic!("Unexpected error: {:?}", e),
         };
     }
+
+    #[test]
+    fn origin_allows_moving_left() {
+        let mut state: FixedState<4, _> = State::with_tape(Buffer::new(""), FixedTape::new());
+        let tokens = tokenize("<+<++.");
+        let jump_table = JumpTable::from(&tokens).unwrap();
+
+        state.set_origin(2).unwrap();
+
+        assert!(state.run(&tokens, &jump_table).is_ok());
+        assert_eq!(state.memory(), vec![2, 1, 0, 0]);
+        assert!(matches!(
+            state.set_origin(4),
+            Err(ExecutionError::PointerOverflow(_))
+        ));
+    }
+
+    #[test]
+    fn every_engine_counts_steps_alike() {
+        let tokens = tokenize("++[>+++[-]<-]>>+++[<+>-]<[-]+++[-][<]++[>,.<-]>[+]++[>+<-]");
+        let jump_table = JumpTable::from(&tokens).unwrap();
+        let blocks = BasicBlocks::from(&tokens, &jump_table).unwrap();
+        let ir = optimized(Ir::lower(&tokens, &jump_table).unwrap());
+        let mut unlimited = State::with_device(Buffer::new("xy"));
+        unlimited.run(&tokens, &jump_table).unwrap();
+
+        for fuel in 0..=unlimited.steps() + 1 {
+            let mut states: Vec<_> = (0..3)
+                .map(|_| {
+                    let mut state = State::with_device(Buffer::new("xy"));
+                    state.set_limits(Limits::default().with_steps(fuel));
+                    state
+                })
+                .collect();
+            let results = [
+                states[0].run(&tokens, &jump_table),
+                states[1].run_blocks(&tokens, &blocks),
+                states[2].run_ir(&ir),
+            ];
+
+            for (state, result) in states.iter().zip(&results).skip(1) {
+                assert_eq!(format!("{:?}", result), format!("{:?}", results[0]));
+                assert_eq!(state.steps(), states[0].steps());
+                assert_eq!(state.instruction_pointer, states[0].instruction_pointer);
+                assert_eq!(state.io().output(), states[0].io().output());
+                assert!(states[0].diff(state).is_empty());
+            }
+            assert_eq!(states[0].steps(), fuel.min(unlimited.steps()));
+
+            for state in &mut states {
+                state.set_limits(Limits::default());
+            }
+            states[0].run(&tokens, &jump_table).unwrap();
+            states[1].run_blocks(&tokens, &blocks).unwrap();
+            states[2].run_ir(&ir).unwrap();
+            for state in &states {
+                assert_eq!(state.steps(), unlimited.steps());
+                assert!(unlimited.diff(state).is_empty());
+            }
+        }
+    }
+
+    #[test]
+    fn step_limit_stops_both_engines() {
+        let tokens = tokenize("++[>+++<-]>.");
+        let jump_table = JumpTable::from(&tokens).unwrap();
+        let blocks = BasicBlocks::from(&tokens, &jump_table).unwrap();
+        let limits = Limits::default().with_steps(10);
+
+        let mut by_instruction = State::with_io(empty(), Vec::new());
+        by_instruction.set_limits(limits);
+        let mut by_block = State::with_io(empty(), Vec::new());
+        by_block.set_limits(limits);
+
+        for result in [
+            by_instruction.run(&tokens, &jump_table),
+            by_block.run_blocks(&tokens, &blocks),
+        ] {
+            match result.unwrap_err() {
+                ExecutionError::LimitExceeded(_, Limit::Steps(10)) => (),
+                e => panic!("Unexpected error: {:?}", e),
+            }
+        }
+        assert_eq!(by_instruction.steps(), 10);
+        assert_eq!(by_block.steps(), 10);
+        assert_eq!(by_block.memory(), vec![1, 3]);
+    }
+
+    #[test]
+    fn cell_and_output_limits() {
+        let tokens = tokenize(">>");
+        let jump_table = JumpTable::from(&tokens).unwrap();
+        let mut state = State::with_io(empty(), Vec::new());
+        state.set_limits(Limits::default().with_cells(2));
+
+        match state.run(&tokens, &jump_table).unwrap_err() {
+            ExecutionError::LimitExceeded(1, Limit::Cells(2)) => (),
+            e => panic!("Unexpected error: {:?}", e),
+        }
+
+        let tokens = tokenize("...");
+        let jump_table = JumpTable::from(&tokens).unwrap();
+        let mut state = State::with_io(empty(), Vec::new());
+        state.set_limits(Limits::default().with_output(2));
+
+        match state.run(&tokens, &jump_table).unwrap_err() {
+            ExecutionError::LimitExceeded(2, Limit::Output(2)) => (),
+            e => panic!("Unexpected error: {:?}", e),
+        }
+        assert_eq!(state.report().limit, Some(Limit::Output(2)));
+        assert_eq!(state.report().output_bytes, 2);
+        assert_eq!(state.output(), &vec![0, 0]);
+    }
+
+    #[test]
+    fn time_limit_stops_endless_loop() {
+        let tokens = tokenize("+[]");
+        let jump_table = JumpTable::from(&tokens).unwrap();
+        let blocks = BasicBlocks::from(&tokens, &jump_table).unwrap();
+        let mut state = State::with_io(empty(), Vec::new());
+        state.set_limits(Limits::default().with_time(Duration::from_millis(10)));
+
+        match state.run_blocks(&tokens, &blocks).unwrap_err() {
+            ExecutionError::LimitExceeded(_, Limit::Time(_)) => (),
+            e => panic!("Unexpected error: {:?}", e),
+        }
+    }
+
+    #[test]
+    fn wide_cells_count_past_255() {
+        let mut state = State::with_tape(Buffer::new(""), VecTape::<u16>::default());
+        let tokens = tokenize("++++++++[>++++++++[>++++++++<-]<-]>>.");
+        let jump_table = JumpTable::from(&tokens).unwrap();
+        let blocks = BasicBlocks::from(&tokens, &jump_table).unwrap();
+
+        assert!(state.run_blocks(&tokens, &blocks).is_ok());
+        assert_eq!(state.memory(), vec![0, 0, 512]);
+        assert_eq!(state.io().output(), &[0]);
+
+        let tokens = tokenize("-[>+<-]>");
+        let jump_table = JumpTable::from(&tokens).unwrap();
+        let mut state = State::with_tape(Null, VecTape::<u16>::default());
+
+        assert!(state.run(&tokens, &jump_table).is_ok());
+        assert_eq!(state.cell(1), u16::MAX);
+    }
+
+    #[test]
+    fn bidirectional_tape_runs_left_of_the_start() {
+        let tokens = tokenize("+<++<+++[>]");
+        let jump_table = JumpTable::from(&tokens).unwrap();
+        let blocks = BasicBlocks::from(&tokens, &jump_table).unwrap();
+        let mut state = State::with_tape(Null, BidirectionalTape::new());
+
+        assert!(state.run_blocks(&tokens, &blocks).is_ok());
+        assert_eq!(state.memory(), vec![3, 2, 1, 0]);
+        assert_eq!(state.memory_pointer(), 3);
+
+        let tokens = tokenize("<<");
+        let jump_table = JumpTable::from(&tokens).unwrap();
+        let mut state = State::with_tape(Null, BidirectionalTape::new());
+        state.set_limits(Limits::default().with_cells(2));
+
+        match state.run(&tokens, &jump_table).unwrap_err() {
+            ExecutionError::LimitExceeded(1, Limit::Cells(2)) => (),
+            e => panic!("Unexpected error: {:?}", e),
+        }
+    }
+
+    #[cfg(feature = "bignum")]
+    #[test]
+    fn run_on_unbounded_cells() {
+        use crate::bignum::BigCell;
+
+        let mut state = State::with_tape(Buffer::new(""), VecTape::<BigCell>::default());
+        let tokens = tokenize("-.");
+        let jump_table = JumpTable::from(&tokens).unwrap();
+
+        assert!(state.run(&tokens, &jump_table).is_ok());
+        assert_eq!(state.cell(0).to_string(), "-1");
+        assert_eq!(state.io().output(), b"-1\n");
+    }
 }
@@ -0,0 +1,117 @@
+use std::fmt::Write;
+
+/// The layout of a machine-readable document the crate writes, e.g. a run report with
+/// `--report-format json`. Every document holds the version of its schema in a
+/// `schema_version` field, so that tools reading it notice when the layout changes.
+///
+/// Within a version, fields are only ever added, so readers should skip fields they do not
+/// know. Removing or renaming a field, or changing its type or meaning, bumps the version of
+/// that schema, and readers should refuse versions they were not written for.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Schema {
+    pub name: &'static str,
+    pub version: u32,
+}
+
+/// `RunReport::to_json` and `RunReport::to_toml`, including the sample profile.
+pub const RUN_REPORT: Schema = Schema {
+    name: "run-report",
+    version: 2,
+};
+
+/// Every line written by `--events`, from `Event::to_json`.
+pub const TRACE_EVENT: Schema = Schema {
+    name: "trace-event",
+    version: 1,
+};
+
+/// The metadata `extract` writes next to a loop, from `Snippet::to_json`.
+pub const SNIPPET: Schema = Schema {
+    name: "snippet",
+    version: 1,
+};
+
+/// The statistics of `--bench`, from `BenchResult::to_json`.
+pub const BENCH: Schema = Schema {
+    name: "bench",
+    version: 1,
+};
+
+/// The per-tenant totals of `stats::SandboxStats::to_json`.
+pub const SANDBOX_STATS: Schema = Schema {
+    name: "sandbox-stats",
+    version: 1,
+};
+
+/// Every line `lint` and `check` write with `--report-format json`, one per finding.
+pub const DIAGNOSTIC: Schema = Schema {
+    name: "diagnostic",
+    version: 1,
+};
+
+impl Schema {
+    /// Whether a reader written against this schema understands a document of `version`.
+    pub fn can_read(&self, version: u32) -> bool {
+        version == self.version
+    }
+}
+
+/// A string literal that is valid in both JSON and TOML.
+pub fn quote(text: &str) -> String {
+    let mut quoted = String::from('"');
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(quoted, "\\u{:04x}", c as u32);
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// A finding of `lint` or `check` as one line of JSON, with the source location of the
+/// instruction when it is known.
+pub fn diagnostic_json(
+    severity: &str,
+    kind: &str,
+    position: usize,
+    location: Option<&str>,
+    message: &str,
+) -> String {
+    format!(
+        r#"{{"schema_version":{},"severity":{},"kind":{},"position":{},"location":{},"message":{}}}"#,
+        DIAGNOSTIC.version,
+        quote(severity),
+        quote(kind),
+        position,
+        location.map_or("null".to_string(), quote),
+        quote(message)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diagnostics_as_json() {
+        assert_eq!(
+            diagnostic_json(
+                "error",
+                "unmatched-bracket",
+                3,
+                Some("a.bf:1:4"),
+                "unmatched `[`"
+            ),
+            r#"{"schema_version":1,"severity":"error","kind":"unmatched-bracket","position":3,"location":"a.bf:1:4","message":"unmatched `[`"}"#
+        );
+        assert!(RUN_REPORT.can_read(2));
+        assert!(!RUN_REPORT.can_read(1));
+    }
+}
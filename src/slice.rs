@@ -0,0 +1,208 @@
+use std::collections::BTreeSet;
+
+use crate::lexer::{JumpTable, Token};
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Target {
+    /// The value of a cell when the program terminates.
+    Cell(usize),
+    /// The value of the current cell when the instruction at a position executes.
+    Instruction(usize),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum SliceError {
+    UnbalancedLoop(usize),
+    TargetInsideLoop(usize),
+    TargetOutOfRange(usize),
+}
+
+enum Item {
+    Operation {
+        token: Token,
+        offset: isize,
+    },
+    Loop {
+        start: usize,
+        end: usize,
+        offset: isize,
+        touched: BTreeSet<isize>,
+        reads_input: bool,
+    },
+}
+
+/// Computes a backward slice of the program: the instructions that can influence the target,
+/// with pointer movements regenerated between them. Loops are kept or dropped as a whole, and
+/// every instruction reading input is kept so the remaining reads see the same bytes.
+pub fn slice(
+    tokens: &[Token],
+    jump_table: &JumpTable,
+    target: Target,
+) -> Result<Vec<Token>, SliceError> {
+    let (end, target_offset) = match target {
+        Target::Cell(x) => (tokens.len(), Some(x as isize)),
+        Target::Instruction(x) if x < tokens.len() => (x, None),
+        Target::Instruction(x) => return Err(SliceError::TargetOutOfRange(x)),
+    };
+
+    let mut items = Vec::new();
+    let mut offset = 0isize;
+    let mut position = 0;
+    while position < end {
+        let token = tokens[position];
+        match token {
+            Token::PointerIncrement => offset += 1,
+            Token::PointerDecrement => offset -= 1,
+            Token::LoopStart => {
+                let loop_end = *jump_table
+                    .resolve(&position)
+                    .ok_or(SliceError::UnbalancedLoop(position))?;
+                if end < loop_end {
+                    return Err(SliceError::TargetInsideLoop(end));
+                }
+                let (touched, reads_input) = loop_cells(tokens, jump_table, position)?;
+                items.push(Item::Loop {
+                    start: position,
+                    end: loop_end,
+                    offset,
+                    touched,
+                    reads_input,
+                });
+                position = loop_end;
+            }
+            Token::LoopEnd => return Err(SliceError::UnbalancedLoop(position)),
+            _ => items.push(Item::Operation { token, offset }),
+        }
+        position += 1;
+    }
+
+    let mut relevant = BTreeSet::from([target_offset.unwrap_or(offset)]);
+    let mut kept = vec![false; items.len()];
+    for (index, item) in items.iter().enumerate().rev() {
+        match item {
+            Item::Operation {
+                token: Token::Increment | Token::Decrement,
+                offset,
+            } => kept[index] = relevant.contains(offset),
+            Item::Operation {
+                token: Token::Input,
+                offset,
+            } => {
+                kept[index] = true;
+                relevant.remove(offset);
+            }
+            Item::Operation { .. } => (),
+            Item::Loop {
+                offset,
+                touched,
+                reads_input,
+                ..
+            } => {
+                let touched: Vec<_> = touched.iter().map(|x| x + offset).collect();
+                if *reads_input || touched.iter().any(|x| relevant.contains(x)) {
+                    kept[index] = true;
+                    relevant.extend(touched);
+                }
+            }
+        }
+    }
+
+    let mut result = Vec::new();
+    let mut pointer = 0isize;
+    for (item, _) in items.iter().zip(kept).filter(|(_, kept)| *kept) {
+        let offset = match item {
+            Item::Operation { offset, .. } | Item::Loop { offset, .. } => *offset,
+        };
+        let movement = match offset > pointer {
+            true => Token::PointerIncrement,
+            false => Token::PointerDecrement,
+        };
+        result.extend(std::iter::repeat_n(movement, offset.abs_diff(pointer)));
+        pointer = offset;
+        match item {
+            Item::Operation { token, .. } => result.push(*token),
+            Item::Loop { start, end, .. } => result.extend_from_slice(&tokens[*start..=*end]),
+        }
+    }
+    Ok(result)
+}
+
+fn loop_cells(
+    tokens: &[Token],
+    jump_table: &JumpTable,
+    start: usize,
+) -> Result<(BTreeSet<isize>, bool), SliceError> {
+    let end = *jump_table
+        .resolve(&start)
+        .ok_or(SliceError::UnbalancedLoop(start))?;
+    let mut touched = BTreeSet::from([0]);
+    let mut reads_input = false;
+    let mut offset = 0isize;
+
+    for token in &tokens[start + 1..end] {
+        match token {
+            Token::PointerIncrement => offset += 1,
+            Token::PointerDecrement => offset -= 1,
+            Token::Input => reads_input = true,
+            _ => (),
+        }
+        touched.insert(offset);
+    }
+
+    match offset {
+        0 => Ok((touched, reads_input)),
+        _ => Err(SliceError::UnbalancedLoop(start)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::tokenize;
+
+    fn sliced(source: &str, target: Target) -> Result<Vec<Token>, SliceError> {
+        let tokens = tokenize(source);
+        let jump_table = JumpTable::from(&tokens).unwrap();
+        slice(&tokens, &jump_table, target)
+    }
+
+    #[test]
+    fn drop_writes_to_other_cells() {
+        assert_eq!(sliced("+>++>+++.", Target::Cell(1)), Ok(tokenize(">++")));
+        assert_eq!(sliced("+>++>+++.", Target::Cell(3)), Ok(vec![]));
+    }
+
+    #[test]
+    fn keep_loops_feeding_the_target() {
+        assert_eq!(
+            sliced("+++[->++<]>>+<.", Target::Cell(1)),
+            Ok(tokenize("+++[->++<]"))
+        );
+        assert_eq!(sliced(">+++[-]<++", Target::Cell(0)), Ok(tokenize("++")));
+    }
+
+    #[test]
+    fn keep_input_reads() {
+        assert_eq!(sliced(",+>,", Target::Cell(1)), Ok(tokenize(",>,")));
+    }
+
+    #[test]
+    fn slice_before_instruction() {
+        assert_eq!(
+            sliced("+>++<-.>.", Target::Instruction(6)),
+            Ok(tokenize("+-"))
+        );
+        assert_eq!(
+            sliced("+[.]", Target::Instruction(2)),
+            Err(SliceError::TargetInsideLoop(2))
+        );
+    }
+
+    #[test]
+    fn reject_unbalanced_loops() {
+        assert_eq!(
+            sliced("+[>]", Target::Cell(0)),
+            Err(SliceError::UnbalancedLoop(1))
+        );
+    }
+}
@@ -0,0 +1,123 @@
+use std::fmt;
+
+/// Several source files concatenated into one program, remembering where each file starts so
+/// that offsets into the combined source can be reported per file.
+#[derive(Debug, Default)]
+pub struct Sources {
+    source: Vec<u8>,
+    files: Vec<SourceFile>,
+    instructions: Vec<usize>,
+}
+
+#[derive(Debug)]
+struct SourceFile {
+    name: String,
+    start: usize,
+    line_starts: Vec<usize>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Location<'a> {
+    pub file: &'a str,
+    pub line: usize,
+    /// Byte column, starting at 1.
+    pub column: usize,
+}
+
+impl Sources {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, name: impl Into<String>, source: &[u8]) {
+        let start = self.source.len();
+        let line_starts = std::iter::once(0)
+            .chain(
+                source
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, x)| **x == b'\n')
+                    .map(|(x, _)| x + 1),
+            )
+            .collect();
+
+        self.files.push(SourceFile {
+            name: name.into(),
+            start,
+            line_starts,
+        });
+        self.source.extend_from_slice(source);
+    }
+
+    pub fn source(&self) -> &[u8] {
+        &self.source
+    }
+
+    /// Remembers the offset of every instruction, e.g. from `lexer::token_offsets`, so that
+    /// `locate_instruction` finds instructions without tokenizing the source again.
+    pub fn set_instructions(&mut self, offsets: Vec<usize>) {
+        self.instructions = offsets;
+    }
+
+    pub fn instructions(&self) -> &[usize] {
+        &self.instructions
+    }
+
+    /// The location of the instruction at the given position, after `set_instructions`.
+    pub fn locate_instruction(&self, position: usize) -> Option<Location<'_>> {
+        self.locate(*self.instructions.get(position)?)
+    }
+
+    pub fn locate(&self, offset: usize) -> Option<Location<'_>> {
+        if offset >= self.source.len() {
+            return None;
+        }
+        let file = &self.files[self.files.partition_point(|x| x.start <= offset) - 1];
+        let offset = offset - file.start;
+        let line = file.line_starts.partition_point(|x| *x <= offset);
+
+        Some(Location {
+            file: &file.name,
+            line,
+            column: offset - file.line_starts[line - 1] + 1,
+        })
+    }
+}
+
+impl fmt::Display for Location<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.column)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locate_offsets_per_file() {
+        let mut sources = Sources::new();
+        sources.add("part1.bf", b"++\n");
+        sources.add("part2.bf", b"[\n>\n  <-]");
+
+        assert_eq!(sources.source(), b"++\n[\n>\n  <-]");
+        assert_eq!(sources.locate(1).unwrap().to_string(), "part1.bf:1:2");
+        assert_eq!(sources.locate(3).unwrap().to_string(), "part2.bf:1:1");
+        assert_eq!(
+            sources.locate(9),
+            Some(Location {
+                file: "part2.bf",
+                line: 3,
+                column: 3
+            })
+        );
+        assert_eq!(sources.locate(12), None);
+
+        sources.set_instructions(vec![0, 1, 3, 5, 9, 10, 11]);
+        assert_eq!(
+            sources.locate_instruction(4).unwrap().to_string(),
+            "part2.bf:3:3"
+        );
+        assert_eq!(sources.locate_instruction(7), None);
+    }
+}
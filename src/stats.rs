@@ -0,0 +1,156 @@
+use std::{collections::BTreeMap, fmt::Write, ops::AddAssign};
+
+use crate::{
+    request::RunOutcome,
+    schema::{quote, SANDBOX_STATS},
+};
+
+/// What the runs of one tenant added up to.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct TenantStats {
+    pub runs: u64,
+    /// Executed instructions, counted like `RunReport::steps`.
+    pub instructions: u64,
+    pub output_bytes: u64,
+    /// Runs stopped by a limit, e.g. a program that loops until it runs out of fuel.
+    pub limit_violations: u64,
+    /// Runs that failed for any other reason, including programs that do not parse.
+    pub failures: u64,
+}
+
+/// Aggregates the outcomes of `request::execute` per tenant, for servers that run programs
+/// on behalf of many users to watch abuse and capacity. Share it between threads behind a
+/// `Mutex` and publish `to_json` on a metrics endpoint.
+#[derive(Debug, Default)]
+pub struct SandboxStats {
+    tenants: BTreeMap<String, TenantStats>,
+}
+
+impl TenantStats {
+    fn json_fields(&self) -> String {
+        format!(
+            r#""runs":{},"instructions":{},"output_bytes":{},"limit_violations":{},"failures":{}"#,
+            self.runs, self.instructions, self.output_bytes, self.limit_violations, self.failures
+        )
+    }
+}
+
+impl AddAssign for TenantStats {
+    fn add_assign(&mut self, other: Self) {
+        self.runs += other.runs;
+        self.instructions += other.instructions;
+        self.output_bytes += other.output_bytes;
+        self.limit_violations += other.limit_violations;
+        self.failures += other.failures;
+    }
+}
+
+impl SandboxStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a finished run to the stats of its tenant.
+    pub fn record(&mut self, tenant: &str, outcome: &RunOutcome) {
+        let limited = outcome.report.limit.is_some();
+        let run = TenantStats {
+            runs: 1,
+            instructions: outcome.report.steps,
+            output_bytes: outcome.report.output_bytes,
+            limit_violations: limited as u64,
+            failures: (outcome.result.is_err() && !limited) as u64,
+        };
+        match self.tenants.get_mut(tenant) {
+            Some(x) => *x += run,
+            None => {
+                self.tenants.insert(tenant.to_string(), run);
+            }
+        }
+    }
+
+    pub fn tenant(&self, tenant: &str) -> Option<&TenantStats> {
+        self.tenants.get(tenant)
+    }
+
+    /// Every tenant with a recorded run, ordered by name.
+    pub fn tenants(&self) -> impl Iterator<Item = (&str, &TenantStats)> {
+        self.tenants.iter().map(|(name, x)| (name.as_str(), x))
+    }
+
+    /// The stats of all tenants together.
+    pub fn total(&self) -> TenantStats {
+        let mut total = TenantStats::default();
+        for x in self.tenants.values() {
+            total += *x;
+        }
+        total
+    }
+
+    /// Forgets all tenants, e.g. after publishing the stats of a time window.
+    pub fn reset(&mut self) {
+        self.tenants.clear();
+    }
+
+    pub fn to_json(&self) -> String {
+        let mut json = format!(
+            r#"{{"schema_version":{},"tenants":["#,
+            SANDBOX_STATS.version
+        );
+        for (index, (name, stats)) in self.tenants.iter().enumerate() {
+            let _ = write!(
+                json,
+                r#"{}{{"tenant":{},{}}}"#,
+                if index > 0 { "," } else { "" },
+                quote(name),
+                stats.json_fields()
+            );
+        }
+        let _ = write!(json, r#"],"total":{{{}}}}}"#, self.total().json_fields());
+        json
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        limits::Limits,
+        request::{execute, RunOptions, RunRequest},
+    };
+
+    #[test]
+    fn record_runs_per_tenant() {
+        let limited = RunOptions {
+            limits: Limits::default().with_steps(10),
+            ..RunOptions::default()
+        };
+        let mut stats = SandboxStats::new();
+
+        stats.record("bob", &execute(RunRequest::new("+.")));
+        stats.record("bob", &execute(RunRequest::new("+[]").options(limited)));
+        stats.record("amy", &execute(RunRequest::new("[")));
+
+        assert_eq!(
+            stats.tenant("bob"),
+            Some(&TenantStats {
+                runs: 2,
+                instructions: 12,
+                output_bytes: 1,
+                limit_violations: 1,
+                failures: 0,
+            })
+        );
+        assert_eq!(stats.total().failures, 1);
+        assert_eq!(
+            stats.tenants().map(|(x, _)| x).collect::<Vec<_>>(),
+            ["amy", "bob"]
+        );
+        assert_eq!(
+            stats.to_json(),
+            r#"{"schema_version":1,"tenants":[{"tenant":"amy","runs":1,"instructions":0,"output_bytes":0,"limit_violations":0,"failures":1},{"tenant":"bob","runs":2,"instructions":12,"output_bytes":1,"limit_violations":1,"failures":0}],"total":{"runs":3,"instructions":12,"output_bytes":1,"limit_violations":1,"failures":1}}"#
+        );
+
+        stats.reset();
+        assert_eq!(stats.total(), TenantStats::default());
+    }
+}
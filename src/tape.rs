@@ -0,0 +1,566 @@
+use std::collections::{HashMap, VecDeque};
+#[cfg(feature = "mmap")]
+use std::{fs::File, io};
+
+#[cfg(feature = "mmap")]
+use memmap2::MmapMut;
+
+use crate::cell::Cell;
+
+const CHUNK_SIZE: usize = 4096;
+
+pub trait Tape {
+    type Cell: Cell;
+
+    fn get(&self, index: usize) -> Self::Cell;
+    fn set(&mut self, index: usize, value: Self::Cell);
+    fn move_by(&mut self, index: usize, delta: isize) -> Option<usize>;
+    fn len_hint(&self) -> usize;
+
+    /// # Safety
+    ///
+    /// `index` must be 0 or a position returned by `move_by`.
+    unsafe fn get_unchecked(&self, index: usize) -> Self::Cell {
+        self.get(index)
+    }
+
+    /// # Safety
+    ///
+    /// `index` must be 0 or a position returned by `move_by`.
+    unsafe fn set_unchecked(&mut self, index: usize, value: Self::Cell) {
+        self.set(index, value)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VecTape<C = u8> {
+    cells: Vec<C>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FixedTape<const N: usize> {
+    cells: [u8; N],
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ChunkedTape {
+    chunks: Vec<Option<Box<[u8; CHUNK_SIZE]>>>,
+    length: usize,
+}
+
+/// Grows in both directions: moving left of the first cell prepends a cell instead of failing,
+/// so indices count from the leftmost cell reached so far.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BidirectionalTape<C = u8> {
+    cells: VecDeque<C>,
+    origin: usize,
+}
+
+/// A fixed number of cells arranged in a ring: moving past either end wraps around to the
+/// other, like the 30,000 cells of the original implementation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CircularTape<C = u8> {
+    cells: Vec<C>,
+}
+
+/// Only stores cells that are not zero, for programs that touch cells far apart.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SparseTape<C = u8> {
+    cells: HashMap<usize, C>,
+    length: usize,
+}
+
+#[cfg(feature = "mmap")]
+pub struct MmapTape {
+    map: MmapMut,
+}
+
+impl VecTape {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+/// A tape of wider cells, e.g. `VecTape::<u16>::default()`.
+impl<C: Cell> Default for VecTape<C> {
+    fn default() -> Self {
+        Self {
+            cells: vec![C::default()],
+        }
+    }
+}
+
+impl<C: Cell> From<Vec<C>> for VecTape<C> {
+    fn from(mut cells: Vec<C>) -> Self {
+        if cells.is_empty() {
+            cells.push(C::default());
+        }
+        Self { cells }
+    }
+}
+
+impl<C: Cell> Tape for VecTape<C> {
+    type Cell = C;
+
+    fn get(&self, index: usize) -> C {
+        self.cells.get(index).cloned().unwrap_or_default()
+    }
+
+    fn set(&mut self, index: usize, value: C) {
+        if index >= self.cells.len() {
+            self.cells.resize(index + 1, C::default());
+        }
+        self.cells[index] = value;
+    }
+
+    fn move_by(&mut self, index: usize, delta: isize) -> Option<usize> {
+        let index = index.checked_add_signed(delta)?;
+        if index >= self.cells.len() {
+            self.cells.resize(index + 1, C::default());
+        }
+        Some(index)
+    }
+
+    fn len_hint(&self) -> usize {
+        self.cells.len()
+    }
+
+    unsafe fn get_unchecked(&self, index: usize) -> C {
+        debug_assert!(index < self.cells.len());
+        // SAFETY: `move_by` grows the tape before returning a position.
+        unsafe { self.cells.get_unchecked(index).clone() }
+    }
+
+    unsafe fn set_unchecked(&mut self, index: usize, value: C) {
+        debug_assert!(index < self.cells.len());
+        // SAFETY: see `get_unchecked`.
+        unsafe { *self.cells.get_unchecked_mut(index) = value }
+    }
+}
+
+impl<const N: usize> FixedTape<N> {
+    pub const fn new() -> Self {
+        assert!(N > 0, "A fixed tape needs at least one cell");
+        Self { cells: [0; N] }
+    }
+}
+
+impl<const N: usize> Default for FixedTape<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Tape for FixedTape<N> {
+    type Cell = u8;
+
+    fn get(&self, index: usize) -> u8 {
+        self.cells.get(index).copied().unwrap_or(0)
+    }
+
+    fn set(&mut self, index: usize, value: u8) {
+        if let Some(cell) = self.cells.get_mut(index) {
+            *cell = value;
+        }
+    }
+
+    fn move_by(&mut self, index: usize, delta: isize) -> Option<usize> {
+        index.checked_add_signed(delta).filter(|index| *index < N)
+    }
+
+    fn len_hint(&self) -> usize {
+        N
+    }
+
+    unsafe fn get_unchecked(&self, index: usize) -> u8 {
+        debug_assert!(index < N);
+        // SAFETY: `move_by` only returns positions inside the array.
+        unsafe { *self.cells.get_unchecked(index) }
+    }
+
+    unsafe fn set_unchecked(&mut self, index: usize, value: u8) {
+        debug_assert!(index < N);
+        // SAFETY: see `get_unchecked`.
+        unsafe { *self.cells.get_unchecked_mut(index) = value }
+    }
+}
+
+impl ChunkedTape {
+    pub fn new() -> Self {
+        Self {
+            chunks: Vec::new(),
+            length: 1,
+        }
+    }
+
+    pub fn allocated_chunks(&self) -> usize {
+        self.chunks.iter().filter(|x| x.is_some()).count()
+    }
+}
+
+impl Tape for ChunkedTape {
+    type Cell = u8;
+
+    fn get(&self, index: usize) -> u8 {
+        match self.chunks.get(index / CHUNK_SIZE) {
+            Some(Some(chunk)) => chunk[index % CHUNK_SIZE],
+            _ => 0,
+        }
+    }
+
+    fn set(&mut self, index: usize, value: u8) {
+        let chunk = index / CHUNK_SIZE;
+        if chunk >= self.chunks.len() {
+            self.chunks.resize(chunk + 1, None);
+        }
+        let chunk = self.chunks[chunk].get_or_insert_with(|| Box::new([0; CHUNK_SIZE]));
+        chunk[index % CHUNK_SIZE] = value;
+        self.length = self.length.max(index + 1);
+    }
+
+    fn move_by(&mut self, index: usize, delta: isize) -> Option<usize> {
+        let index = index.checked_add_signed(delta)?;
+        self.length = self.length.max(index + 1);
+        Some(index)
+    }
+
+    fn len_hint(&self) -> usize {
+        self.length
+    }
+}
+
+impl BidirectionalTape {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<C> BidirectionalTape<C> {
+    /// The index of the cell the tape started with.
+    pub fn origin(&self) -> usize {
+        self.origin
+    }
+}
+
+impl<C: Cell> Default for BidirectionalTape<C> {
+    fn default() -> Self {
+        Self {
+            cells: VecDeque::from([C::default()]),
+            origin: 0,
+        }
+    }
+}
+
+impl<C: Cell> Tape for BidirectionalTape<C> {
+    type Cell = C;
+
+    fn get(&self, index: usize) -> C {
+        self.cells.get(index).cloned().unwrap_or_default()
+    }
+
+    fn set(&mut self, index: usize, value: C) {
+        if index >= self.cells.len() {
+            self.cells.resize(index + 1, C::default());
+        }
+        self.cells[index] = value;
+    }
+
+    fn move_by(&mut self, index: usize, delta: isize) -> Option<usize> {
+        match index.checked_add_signed(delta) {
+            Some(index) => {
+                if index >= self.cells.len() {
+                    self.cells.resize(index + 1, C::default());
+                }
+                Some(index)
+            }
+            None => {
+                let missing = delta.unsigned_abs() - index;
+                self.cells.reserve(missing);
+                for _ in 0..missing {
+                    self.cells.push_front(C::default());
+                }
+                self.origin += missing;
+                Some(0)
+            }
+        }
+    }
+
+    fn len_hint(&self) -> usize {
+        self.cells.len()
+    }
+}
+
+impl<C: Cell> CircularTape<C> {
+    pub fn new(cells: usize) -> Self {
+        assert!(cells > 0, "A circular tape needs at least one cell");
+        Self {
+            cells: vec![C::default(); cells],
+        }
+    }
+}
+
+impl<C: Cell> Tape for CircularTape<C> {
+    type Cell = C;
+
+    fn get(&self, index: usize) -> C {
+        self.cells.get(index).cloned().unwrap_or_default()
+    }
+
+    fn set(&mut self, index: usize, value: C) {
+        if let Some(cell) = self.cells.get_mut(index) {
+            *cell = value;
+        }
+    }
+
+    fn move_by(&mut self, index: usize, delta: isize) -> Option<usize> {
+        let length = self.cells.len() as i128;
+        Some((index as i128 + delta as i128).rem_euclid(length) as usize)
+    }
+
+    fn len_hint(&self) -> usize {
+        self.cells.len()
+    }
+
+    unsafe fn get_unchecked(&self, index: usize) -> C {
+        debug_assert!(index < self.cells.len());
+        // SAFETY: `move_by` only returns positions inside the ring.
+        unsafe { self.cells.get_unchecked(index).clone() }
+    }
+
+    unsafe fn set_unchecked(&mut self, index: usize, value: C) {
+        debug_assert!(index < self.cells.len());
+        // SAFETY: see `get_unchecked`.
+        unsafe { *self.cells.get_unchecked_mut(index) = value }
+    }
+}
+
+impl SparseTape {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<C> SparseTape<C> {
+    pub fn stored_cells(&self) -> usize {
+        self.cells.len()
+    }
+}
+
+impl<C> Default for SparseTape<C> {
+    fn default() -> Self {
+        Self {
+            cells: HashMap::new(),
+            length: 1,
+        }
+    }
+}
+
+impl<C: Cell> Tape for SparseTape<C> {
+    type Cell = C;
+
+    fn get(&self, index: usize) -> C {
+        self.cells.get(&index).cloned().unwrap_or_default()
+    }
+
+    fn set(&mut self, index: usize, value: C) {
+        if value.is_zero() {
+            self.cells.remove(&index);
+        } else {
+            self.cells.insert(index, value);
+        }
+        self.length = self.length.max(index + 1);
+    }
+
+    fn move_by(&mut self, index: usize, delta: isize) -> Option<usize> {
+        let index = index.checked_add_signed(delta)?;
+        self.length = self.length.max(index + 1);
+        Some(index)
+    }
+
+    fn len_hint(&self) -> usize {
+        self.length
+    }
+}
+
+#[cfg(feature = "mmap")]
+impl MmapTape {
+    pub fn anonymous(cells: usize) -> io::Result<Self> {
+        Ok(Self {
+            map: MmapMut::map_anon(cells.max(1))?,
+        })
+    }
+
+    /// Maps the first `cells` bytes of `file` as the tape, growing the file if it is shorter.
+    pub fn from_file(file: &File, cells: usize) -> io::Result<Self> {
+        let cells = cells.max(1) as u64;
+        if file.metadata()?.len() < cells {
+            file.set_len(cells)?;
+        }
+        // SAFETY: The tape assumes no other process modifies the file while it is mapped.
+        let map = unsafe { MmapMut::map_mut(file)? };
+        Ok(Self { map })
+    }
+
+    pub fn flush(&self) -> io::Result<()> {
+        self.map.flush()
+    }
+}
+
+#[cfg(feature = "mmap")]
+impl Tape for MmapTape {
+    type Cell = u8;
+
+    fn get(&self, index: usize) -> u8 {
+        self.map.get(index).copied().unwrap_or(0)
+    }
+
+    fn set(&mut self, index: usize, value: u8) {
+        if let Some(cell) = self.map.get_mut(index) {
+            *cell = value;
+        }
+    }
+
+    fn move_by(&mut self, index: usize, delta: isize) -> Option<usize> {
+        index
+            .checked_add_signed(delta)
+            .filter(|index| *index < self.map.len())
+    }
+
+    fn len_hint(&self) -> usize {
+        self.map.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vec_tape_grows_on_move() {
+        let mut tape = VecTape::new();
+
+        assert_eq!(tape.move_by(0, 3), Some(3));
+        assert_eq!(tape.len_hint(), 4);
+        assert_eq!(tape.move_by(3, -4), None);
+    }
+
+    #[test]
+    fn vec_tape_grows_on_set() {
+        let mut tape = VecTape::new();
+
+        tape.set(2, 7);
+
+        assert_eq!(tape.get(2), 7);
+        assert_eq!(tape.get(10), 0);
+        assert_eq!(tape.len_hint(), 3);
+    }
+
+    #[test]
+    fn vec_tape_holds_wide_cells() {
+        let mut tape = VecTape::<u16>::default();
+
+        tape.set(1, 1000);
+
+        assert_eq!(tape.get(1), 1000);
+        assert_eq!(tape.get(0), 0);
+        assert_eq!(tape.move_by(1, 2), Some(3));
+        assert_eq!(tape.len_hint(), 4);
+    }
+
+    #[test]
+    fn fixed_tape_rejects_moves_out_of_bounds() {
+        let mut tape = FixedTape::<3>::new();
+
+        assert_eq!(tape.move_by(0, 2), Some(2));
+        assert_eq!(tape.move_by(2, 1), None);
+        assert_eq!(tape.move_by(0, -1), None);
+        assert_eq!(tape.len_hint(), 3);
+    }
+
+    #[test]
+    fn fixed_tape_ignores_sets_out_of_bounds() {
+        let mut tape = FixedTape::<2>::new();
+
+        tape.set(1, 5);
+        tape.set(2, 5);
+
+        assert_eq!(tape.get(1), 5);
+        assert_eq!(tape.get(2), 0);
+    }
+
+    #[test]
+    fn chunked_tape_allocates_touched_chunks_only() {
+        let mut tape = ChunkedTape::new();
+
+        assert_eq!(
+            tape.move_by(0, 3 * CHUNK_SIZE as isize),
+            Some(3 * CHUNK_SIZE)
+        );
+        assert_eq!(tape.allocated_chunks(), 0);
+
+        tape.set(3 * CHUNK_SIZE, 9);
+
+        assert_eq!(tape.get(3 * CHUNK_SIZE), 9);
+        assert_eq!(tape.get(CHUNK_SIZE), 0);
+        assert_eq!(tape.allocated_chunks(), 1);
+        assert_eq!(tape.len_hint(), 3 * CHUNK_SIZE + 1);
+    }
+
+    #[test]
+    fn bidirectional_tape_grows_to_the_left() {
+        let mut tape = BidirectionalTape::new();
+
+        tape.set(0, 4);
+        assert_eq!(tape.move_by(0, -2), Some(0));
+        tape.set(0, 1);
+
+        assert_eq!(tape.origin(), 2);
+        assert_eq!(tape.get(2), 4);
+        assert_eq!(tape.move_by(0, 4), Some(4));
+        assert_eq!(tape.len_hint(), 5);
+        assert_eq!(tape.get(0), 1);
+    }
+
+    #[test]
+    fn circular_tape_wraps_around() {
+        let mut tape = CircularTape::new(3);
+
+        tape.set(2, 6u8);
+        tape.set(3, 1);
+
+        assert_eq!(tape.move_by(0, -1), Some(2));
+        assert_eq!(tape.move_by(2, 1), Some(0));
+        assert_eq!(tape.move_by(1, 7), Some(2));
+        assert_eq!(tape.get(2), 6);
+        assert_eq!(tape.get(3), 0);
+        assert_eq!(tape.len_hint(), 3);
+    }
+
+    #[test]
+    fn sparse_tape_stores_non_zero_cells_only() {
+        let mut tape = SparseTape::new();
+
+        assert_eq!(tape.move_by(0, 1 << 40), Some(1 << 40));
+        tape.set(1 << 40, 3);
+        tape.set(5, 1);
+        tape.set(5, 0);
+
+        assert_eq!(tape.get(1 << 40), 3);
+        assert_eq!(tape.get(5), 0);
+        assert_eq!(tape.stored_cells(), 1);
+        assert_eq!(tape.len_hint(), (1 << 40) + 1);
+        assert_eq!(tape.move_by(0, -1), None);
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn mmap_tape_is_bounded() {
+        let mut tape = MmapTape::anonymous(2).unwrap();
+
+        tape.set(1, 4);
+
+        assert_eq!(tape.get(1), 4);
+        assert_eq!(tape.move_by(1, 1), None);
+        assert_eq!(tape.len_hint(), 2);
+    }
+}
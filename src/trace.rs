@@ -0,0 +1,286 @@
+use std::{fmt, ops::Range};
+
+use crate::{
+    lexer::{JumpTable, Token},
+    schema::TRACE_EVENT,
+};
+
+#[derive(Debug, PartialEq)]
+pub struct TraceEvent {
+    pub position: usize,
+    pub token: Token,
+    pub memory_pointer: usize,
+    pub before: u8,
+    pub after: u8,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Event {
+    Instruction {
+        step: u64,
+        position: usize,
+        token: Token,
+    },
+    PointerMoved {
+        from: usize,
+        to: usize,
+    },
+    CellChanged {
+        index: usize,
+        before: u8,
+        after: u8,
+    },
+    Output(u8),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Filter {
+    InstructionRange(Range<usize>),
+    CellChanged(usize),
+    LoopAt(usize),
+    All(Vec<Filter>),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum FilterError {
+    Empty,
+    InvalidNumber(String),
+    UnknownFilter(String),
+}
+
+impl Filter {
+    pub fn parse(expression: &str) -> Result<Self, FilterError> {
+        let mut filters = expression
+            .split(" and ")
+            .map(|x| Self::parse_single(x.trim()))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        match filters.len() {
+            1 => Ok(filters.remove(0)),
+            _ => Ok(Self::All(filters)),
+        }
+    }
+
+    fn parse_single(expression: &str) -> Result<Self, FilterError> {
+        let words: Vec<_> = expression.split_whitespace().collect();
+
+        match words.as_slice() {
+            [] => Err(FilterError::Empty),
+            ["ip", "in", range] => {
+                let (start, end) = range
+                    .split_once("..")
+                    .ok_or_else(|| FilterError::UnknownFilter(expression.to_string()))?;
+                Ok(Self::InstructionRange(number(start)?..number(end)?))
+            }
+            [cell, "changed"] => {
+                let index = cell
+                    .strip_prefix("cell[")
+                    .and_then(|x| x.strip_suffix(']'))
+                    .ok_or_else(|| FilterError::UnknownFilter(expression.to_string()))?;
+                Ok(Self::CellChanged(number(index)?))
+            }
+            ["loop", "at", position] => Ok(Self::LoopAt(number(position)?)),
+            _ => Err(FilterError::UnknownFilter(expression.to_string())),
+        }
+    }
+
+    pub fn matches(&self, event: &TraceEvent, jump_table: &JumpTable) -> bool {
+        match self {
+            Self::InstructionRange(range) => range.contains(&event.position),
+            Self::CellChanged(index) => {
+                event.memory_pointer == *index && event.before != event.after
+            }
+            Self::LoopAt(start) => {
+                event.position == *start || jump_table.resolve(&event.position) == Some(start)
+            }
+            Self::All(filters) => filters.iter().all(|x| x.matches(event, jump_table)),
+        }
+    }
+}
+
+fn number(value: &str) -> Result<usize, FilterError> {
+    value
+        .parse()
+        .map_err(|_| FilterError::InvalidNumber(value.to_string()))
+}
+
+impl Event {
+    pub fn to_json(&self) -> String {
+        match self {
+            Self::Instruction {
+                step,
+                position,
+                token,
+            } => format!(
+                r#"{{"schema_version":{},"event":"instruction","step":{},"position":{},"instruction":"{}"}}"#,
+                TRACE_EVENT.version,
+                step,
+                position,
+                token.as_char()
+            ),
+            Self::PointerMoved { from, to } => {
+                format!(
+                    r#"{{"schema_version":{},"event":"pointer","from":{},"to":{}}}"#,
+                    TRACE_EVENT.version, from, to
+                )
+            }
+            Self::CellChanged {
+                index,
+                before,
+                after,
+            } => format!(
+                r#"{{"schema_version":{},"event":"cell","index":{},"before":{},"after":{}}}"#,
+                TRACE_EVENT.version, index, before, after
+            ),
+            Self::Output(byte) => format!(
+                r#"{{"schema_version":{},"event":"output","byte":{}}}"#,
+                TRACE_EVENT.version, byte
+            ),
+        }
+    }
+}
+
+impl TraceEvent {
+    /// Lists the state changes caused by the traced instruction, given where the memory
+    /// pointer ended up afterwards.
+    pub fn changes(&self, memory_pointer: usize) -> Vec<Event> {
+        let mut events = Vec::new();
+        if self.memory_pointer != memory_pointer {
+            events.push(Event::PointerMoved {
+                from: self.memory_pointer,
+                to: memory_pointer,
+            });
+        }
+        if self.before != self.after {
+            events.push(Event::CellChanged {
+                index: self.memory_pointer,
+                before: self.before,
+                after: self.after,
+            });
+        }
+        if self.token == Token::Output {
+            events.push(Event::Output(self.after));
+        }
+        events
+    }
+}
+
+impl fmt::Display for TraceEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:>8} {} ptr={} cell={}",
+            self.position,
+            self.token.as_char(),
+            self.memory_pointer,
+            self.after
+        )?;
+        if self.before != self.after {
+            write!(f, " (was {})", self.before)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(position: usize, memory_pointer: usize, before: u8, after: u8) -> TraceEvent {
+        TraceEvent {
+            position,
+            token: Token::Increment,
+            memory_pointer,
+            before,
+            after,
+        }
+    }
+
+    #[test]
+    fn parse_filters() {
+        assert_eq!(
+            Filter::parse("ip in 100..200"),
+            Ok(Filter::InstructionRange(100..200))
+        );
+        assert_eq!(Filter::parse("cell[3] changed"), Ok(Filter::CellChanged(3)));
+        assert_eq!(Filter::parse("loop at 57"), Ok(Filter::LoopAt(57)));
+        assert_eq!(
+            Filter::parse("ip in 0..10 and cell[0] changed"),
+            Ok(Filter::All(vec![
+                Filter::InstructionRange(0..10),
+                Filter::CellChanged(0)
+            ]))
+        );
+    }
+
+    #[test]
+    fn parse_invalid_filters() {
+        assert_eq!(Filter::parse(""), Err(FilterError::Empty));
+        assert_eq!(
+            Filter::parse("ip in a..2"),
+            Err(FilterError::InvalidNumber("a".to_string()))
+        );
+        assert_eq!(
+            Filter::parse("cell 3 changed"),
+            Err(FilterError::UnknownFilter("cell 3 changed".to_string()))
+        );
+    }
+
+    #[test]
+    fn filters_match_events() {
+        let jump_table = JumpTable::from(&[Token::LoopStart, Token::LoopEnd]).unwrap();
+
+        assert!(Filter::InstructionRange(1..3).matches(&event(2, 0, 0, 0), &jump_table));
+        assert!(!Filter::InstructionRange(1..3).matches(&event(3, 0, 0, 0), &jump_table));
+        assert!(Filter::CellChanged(1).matches(&event(0, 1, 0, 1), &jump_table));
+        assert!(!Filter::CellChanged(1).matches(&event(0, 1, 1, 1), &jump_table));
+        assert!(Filter::LoopAt(0).matches(&event(1, 0, 0, 0), &jump_table));
+        assert!(!Filter::LoopAt(1).matches(&event(2, 0, 0, 0), &jump_table));
+    }
+
+    #[test]
+    fn display_event() {
+        assert_eq!(
+            event(12, 3, 4, 5).to_string(),
+            "      12 + ptr=3 cell=5 (was 4)"
+        );
+    }
+
+    #[test]
+    fn events_as_json() {
+        let instruction = Event::Instruction {
+            step: 4,
+            position: 2,
+            token: Token::LoopStart,
+        };
+
+        assert_eq!(
+            instruction.to_json(),
+            r#"{"schema_version":1,"event":"instruction","step":4,"position":2,"instruction":"["}"#
+        );
+        assert_eq!(
+            Event::PointerMoved { from: 1, to: 0 }.to_json(),
+            r#"{"schema_version":1,"event":"pointer","from":1,"to":0}"#
+        );
+        assert_eq!(
+            Event::Output(65).to_json(),
+            r#"{"schema_version":1,"event":"output","byte":65}"#
+        );
+    }
+
+    #[test]
+    fn changes_of_traced_instruction() {
+        assert_eq!(
+            event(0, 3, 1, 2).changes(3),
+            vec![Event::CellChanged {
+                index: 3,
+                before: 1,
+                after: 2
+            }]
+        );
+        assert_eq!(
+            event(0, 3, 1, 1).changes(4),
+            vec![Event::PointerMoved { from: 3, to: 4 }]
+        );
+    }
+}
@@ -0,0 +1,168 @@
+use crate::lexer::{JumpTable, Token};
+
+/// Receives the instructions of a program in source order, with loops as nested spans.
+pub trait Visitor {
+    /// Called for every instruction except loop brackets.
+    fn visit_instruction(&mut self, _position: usize, _token: Token) {}
+
+    /// Called at `[` with the position of the matching `]`, before the body is visited.
+    fn enter_loop(&mut self, _start: usize, _end: usize) {}
+
+    /// Called at `]`, after the body was visited.
+    fn exit_loop(&mut self, _start: usize, _end: usize) {}
+}
+
+/// Produces a new program from an existing one. Returning `None` keeps the original code.
+pub trait Rewriter {
+    /// Called at `[` with the position of the matching `]`. A replacement is emitted instead of
+    /// the whole loop, and the body is not visited.
+    fn rewrite_loop(
+        &mut self,
+        _tokens: &[Token],
+        _start: usize,
+        _end: usize,
+    ) -> Option<Vec<Token>> {
+        None
+    }
+
+    /// Called for every instruction outside replaced loops, except loop brackets.
+    fn rewrite_instruction(&mut self, _position: usize, _token: Token) -> Option<Vec<Token>> {
+        None
+    }
+}
+
+pub fn walk(tokens: &[Token], jump_table: &JumpTable, visitor: &mut impl Visitor) {
+    let mut open = Vec::new();
+
+    for (position, token) in tokens.iter().enumerate() {
+        match token {
+            Token::LoopStart => {
+                if let Some(end) = jump_table.resolve(&position) {
+                    visitor.enter_loop(position, *end);
+                    open.push(position);
+                }
+            }
+            Token::LoopEnd => {
+                if let Some(start) = open.pop() {
+                    visitor.exit_loop(start, position);
+                }
+            }
+            _ => visitor.visit_instruction(position, *token),
+        }
+    }
+}
+
+pub fn rewrite(
+    tokens: &[Token],
+    jump_table: &JumpTable,
+    rewriter: &mut impl Rewriter,
+) -> Vec<Token> {
+    let mut result = Vec::with_capacity(tokens.len());
+    let mut position = 0;
+
+    while position < tokens.len() {
+        let token = tokens[position];
+        match token {
+            Token::LoopStart => {
+                let replacement = jump_table
+                    .resolve(&position)
+                    .and_then(|end| Some((*end, rewriter.rewrite_loop(tokens, position, *end)?)));
+                if let Some((end, replacement)) = replacement {
+                    result.extend(replacement);
+                    position = end + 1;
+                    continue;
+                }
+                result.push(token);
+            }
+            Token::LoopEnd => result.push(token),
+            _ => match rewriter.rewrite_instruction(position, token) {
+                Some(replacement) => result.extend(replacement),
+                None => result.push(token),
+            },
+        }
+        position += 1;
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::tokenize;
+
+    #[derive(Default)]
+    struct Spans {
+        depth: usize,
+        events: Vec<String>,
+    }
+
+    impl Visitor for Spans {
+        fn visit_instruction(&mut self, position: usize, token: Token) {
+            self.events.push(format!(
+                "{}{}@{}",
+                " ".repeat(self.depth),
+                token.as_char(),
+                position
+            ));
+        }
+
+        fn enter_loop(&mut self, start: usize, end: usize) {
+            self.events
+                .push(format!("{}loop {}..{}", " ".repeat(self.depth), start, end));
+            self.depth += 1;
+        }
+
+        fn exit_loop(&mut self, _start: usize, _end: usize) {
+            self.depth -= 1;
+        }
+    }
+
+    #[test]
+    fn walk_visits_nested_loops() {
+        let tokens = tokenize("+[>[-]<]");
+        let jump_table = JumpTable::from(&tokens).unwrap();
+        let mut spans = Spans::default();
+
+        walk(&tokens, &jump_table, &mut spans);
+
+        assert_eq!(
+            spans.events,
+            ["+@0", "loop 1..7", " >@2", " loop 3..5", "  -@4", " <@6"]
+        );
+    }
+
+    /// Replaces clear loops and counts every output.
+    struct ClearsAndOutputs;
+
+    impl Rewriter for ClearsAndOutputs {
+        fn rewrite_loop(
+            &mut self,
+            tokens: &[Token],
+            start: usize,
+            end: usize,
+        ) -> Option<Vec<Token>> {
+            match &tokens[start + 1..end] {
+                [Token::Decrement] => Some(Vec::new()),
+                _ => None,
+            }
+        }
+
+        fn rewrite_instruction(&mut self, _position: usize, token: Token) -> Option<Vec<Token>> {
+            match token {
+                Token::Output => Some(tokenize(".>+<")),
+                _ => None,
+            }
+        }
+    }
+
+    #[test]
+    fn rewrite_replaces_loops_and_instructions() {
+        let tokens = tokenize("[-]+[.[-]]");
+        let jump_table = JumpTable::from(&tokens).unwrap();
+
+        let rewritten = rewrite(&tokens, &jump_table, &mut ClearsAndOutputs);
+
+        assert_eq!(rewritten, tokenize("+[.>+<]"));
+    }
+}
@@ -0,0 +1,187 @@
+use std::{
+    error, fmt,
+    io::{Error, ErrorKind, Read, Result},
+    sync::mpsc::{self, Receiver, RecvTimeoutError},
+    thread,
+    time::Duration,
+};
+
+const CHUNK_SIZE: usize = 4096;
+
+/// Reads input on a watchdog thread. A program only stops making progress while it waits for
+/// input, so a read that gets nothing for the whole period fails with a `Stalled` error, which
+/// the runtime reports as `ExecutionError::StalledOnInput`, instead of hanging forever.
+///
+/// The thread reads at most one chunk ahead of the program. It stops at the end of the input,
+/// or at the first read that returns after the `Watchdog` was dropped. A read that never
+/// returns, which is what a stall usually is, keeps the thread blocked until the input is
+/// closed or the process exits, so long-lived callers should use inputs that end.
+pub struct Watchdog {
+    chunks: Receiver<Result<Vec<u8>>>,
+    chunk: Vec<u8>,
+    offset: usize,
+    period: Duration,
+}
+
+/// The payload of the `ErrorKind::TimedOut` errors raised by `Watchdog`. Time-outs of the
+/// input itself, e.g. a socket with a read timeout, stay ordinary input errors.
+#[derive(Debug)]
+pub struct Stalled;
+
+impl fmt::Display for Stalled {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "no input within the watchdog period")
+    }
+}
+
+impl error::Error for Stalled {}
+
+/// Whether the error is a time-out raised by `Watchdog`.
+pub fn is_stalled(error: &Error) -> bool {
+    error.kind() == ErrorKind::TimedOut && error.get_ref().is_some_and(|x| x.is::<Stalled>())
+}
+
+impl Watchdog {
+    pub fn new(mut input: impl Read + Send + 'static, period: Duration) -> Self {
+        let (sender, chunks) = mpsc::sync_channel(1);
+        thread::spawn(move || loop {
+            let mut chunk = vec![0; CHUNK_SIZE];
+            let result = match input.read(&mut chunk) {
+                Ok(length) => {
+                    chunk.truncate(length);
+                    Ok(chunk)
+                }
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => Err(e),
+            };
+            let done = !matches!(&result, Ok(x) if !x.is_empty());
+            if sender.send(result).is_err() || done {
+                break;
+            }
+        });
+
+        Self {
+            chunks,
+            chunk: Vec::new(),
+            offset: 0,
+            period,
+        }
+    }
+}
+
+impl Read for Watchdog {
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize> {
+        if self.offset == self.chunk.len() {
+            self.chunk = match self.chunks.recv_timeout(self.period) {
+                Ok(x) => x?,
+                Err(RecvTimeoutError::Timeout) => {
+                    return Err(Error::new(ErrorKind::TimedOut, Stalled))
+                }
+                Err(RecvTimeoutError::Disconnected) => Vec::new(),
+            };
+            self.offset = 0;
+        }
+
+        let length = buffer.len().min(self.chunk.len() - self.offset);
+        buffer[..length].copy_from_slice(&self.chunk[self.offset..self.offset + length]);
+        self.offset += length;
+        Ok(length)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        program::Program,
+        runtime::{EofMode, ExecutionError, State},
+    };
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    /// Blocks until the sender is dropped.
+    struct Blocking(Receiver<u8>);
+
+    impl Read for Blocking {
+        fn read(&mut self, buffer: &mut [u8]) -> Result<usize> {
+            match self.0.recv() {
+                Ok(x) => {
+                    buffer[0] = x;
+                    Ok(1)
+                }
+                Err(_) => Ok(0),
+            }
+        }
+    }
+
+    #[test]
+    fn reads_input_through_the_watchdog() {
+        let program = Program::parse(",[.,]").unwrap();
+        let input = Watchdog::new(&b"abc"[..], Duration::from_secs(10));
+        let mut state = State::with_io(input, Vec::new());
+
+        state.set_eof(EofMode::Zero);
+        state.run_ir(program.ir()).unwrap();
+
+        assert_eq!(state.output(), b"abc");
+    }
+
+    #[test]
+    fn reads_at_most_one_chunk_ahead() {
+        struct Endless(Arc<AtomicUsize>);
+
+        impl Read for Endless {
+            fn read(&mut self, buffer: &mut [u8]) -> Result<usize> {
+                self.0.fetch_add(1, Ordering::SeqCst);
+                Ok(buffer.len())
+            }
+        }
+
+        let reads = Arc::new(AtomicUsize::new(0));
+        let mut input = Watchdog::new(Endless(Arc::clone(&reads)), Duration::from_secs(10));
+        let mut buffer = [0; 16];
+
+        input.read_exact(&mut buffer).unwrap();
+        thread::sleep(Duration::from_millis(50));
+
+        // The chunk being read, one waiting in the channel and one the thread holds.
+        assert!(reads.load(Ordering::SeqCst) <= 3);
+    }
+
+    #[test]
+    fn stalled_input_stops_the_program() {
+        let (sender, receiver) = mpsc::channel();
+        let program = Program::parse("+.,").unwrap();
+        let input = Watchdog::new(Blocking(receiver), Duration::from_millis(20));
+        let mut state = State::with_io(input, Vec::new());
+
+        let result = state.run_ir(program.ir());
+
+        assert!(matches!(result, Err(ExecutionError::StalledOnInput(2))));
+        assert_eq!(state.output(), &[1]);
+        drop(sender);
+    }
+
+    #[test]
+    fn other_time_outs_are_input_errors() {
+        struct TimingOut;
+
+        impl Read for TimingOut {
+            fn read(&mut self, _buffer: &mut [u8]) -> Result<usize> {
+                Err(Error::from(ErrorKind::TimedOut))
+            }
+        }
+
+        let program = Program::parse("+.,").unwrap();
+        let mut state = State::with_io(TimingOut, Vec::new());
+
+        let result = state.run_ir(program.ir());
+
+        assert!(matches!(
+            result,
+            Err(ExecutionError::InputError(2, e)) if e.kind() == ErrorKind::TimedOut
+        ));
+    }
+}
@@ -0,0 +1,91 @@
+use std::{env, fs, path::PathBuf, process::Command};
+
+/// A directory of its own for every test, since tests run in parallel.
+fn scratch(name: &str) -> PathBuf {
+    let path = env::temp_dir().join(format!("brainfudge-{}-{}", name, std::process::id()));
+    fs::create_dir_all(&path).unwrap();
+    path
+}
+
+fn brainfudge() -> Command {
+    Command::new(env!("CARGO_BIN_EXE_brainfudge"))
+}
+
+#[test]
+fn break_on_output_exits_with_its_own_status() {
+    let dir = scratch("break");
+    let program = dir.join("program.bf");
+    let tape = dir.join("tape.bin");
+    fs::write(&program, "+++.>++.").unwrap();
+
+    let output = brainfudge()
+        .arg(&program)
+        .args(["--break-on-output", "0x03", "--dump-tape-to"])
+        .arg(&tape)
+        .output()
+        .unwrap();
+
+    assert_eq!(output.status.code(), Some(3));
+    assert_eq!(output.stdout, [3]);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Breakpoint on output"));
+    assert_eq!(fs::read(&tape).unwrap(), [3]);
+
+    let output = brainfudge()
+        .arg(&program)
+        .args(["--break-on-output", "0x04"])
+        .output()
+        .unwrap();
+
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(output.stdout, [3, 2]);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn buffered_output_files_are_written_before_exiting() {
+    let dir = scratch("output-file");
+    let program = dir.join("program.bf");
+    let output_file = dir.join("out.bin");
+    fs::write(&program, "+++.>++.<").unwrap();
+
+    let output = brainfudge()
+        .arg(&program)
+        .args(["--exit-from-cell", "--output-file"])
+        .arg(&output_file)
+        .output()
+        .unwrap();
+
+    assert_eq!(output.status.code(), Some(3));
+    assert_eq!(fs::read(&output_file).unwrap(), [3, 2]);
+
+    let output = brainfudge()
+        .arg(&program)
+        .args(["--cell-bits", "16", "--exit-from-cell", "--output-file"])
+        .arg(&output_file)
+        .output()
+        .unwrap();
+
+    assert_eq!(output.status.code(), Some(3));
+    assert_eq!(fs::read(&output_file).unwrap(), [3, 2]);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn diagnostics_start_with_their_severity() {
+    let dir = scratch("diagnostics");
+    let program = dir.join("program.bf");
+    fs::write(&program, "[+-").unwrap();
+
+    let output = brainfudge().arg("check").arg(&program).output().unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let first = stderr.lines().next().unwrap();
+    assert!(first.starts_with("error: "));
+    assert!(first.ends_with("program.bf:1:1: unmatched `[`"));
+
+    fs::write(&program, "+-").unwrap();
+    let output = brainfudge().arg("lint").arg(&program).output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.starts_with("warning: "));
+    assert!(stdout.contains("program.bf:1:1: `+-` cancel each other out"));
+    fs::remove_dir_all(dir).unwrap();
+}